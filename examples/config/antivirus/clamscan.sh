@@ -1,5 +0,0 @@
-#!/bin/bash
-
-echo $1 | clamscan -
-
-exit $?
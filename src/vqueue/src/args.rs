@@ -36,6 +36,35 @@ pub enum Commands {
         #[clap(subcommand)]
         command: MessageCommand,
     },
+    /// Ask the running server to reload its configuration and vsl rules
+    Reload,
+    /// Manage the DKIM keyset of a domain
+    Dkim {
+        /// Domain of the keyset, the one of the server if not set
+        #[clap(short, long, value_parser)]
+        domain: Option<String>,
+        /// Operation on the keyset
+        #[clap(subcommand)]
+        command: DkimCommand,
+    },
+}
+
+/// Operations on a DKIM keyset
+#[derive(Clone, clap::Subcommand)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum DkimCommand {
+    /// Generate a new key in the keyset and print the DNS record to publish
+    Rotate {
+        /// Selector of the new key
+        #[clap(value_parser)]
+        selector: String,
+        /// Signing algorithm of the new key (rsa-sha256 or ed25519-sha256)
+        #[clap(short, long, value_parser, default_value = "rsa-sha256")]
+        algorithm: vsmtp_dkim::SigningAlgorithm,
+        /// Delay before the new key is used, to let the DNS record propagate
+        #[clap(long, value_parser = vsmtp_config::re::humantime::parse_duration, default_value = "2d")]
+        delay: std::time::Duration,
+    },
 }
 
 ///
@@ -126,6 +155,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn arg_reload() {
+        assert_eq!(
+            Args {
+                config: None,
+                command: Commands::Reload
+            },
+            <Args as clap::StructOpt>::try_parse_from(&["", "reload"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn arg_dkim_rotate() {
+        assert_eq!(
+            Args {
+                config: None,
+                command: Commands::Dkim {
+                    domain: None,
+                    command: DkimCommand::Rotate {
+                        selector: "2022-09".to_string(),
+                        algorithm: vsmtp_dkim::SigningAlgorithm::RsaSha256,
+                        delay: std::time::Duration::from_secs(2 * 24 * 60 * 60),
+                    }
+                }
+            },
+            <Args as clap::StructOpt>::try_parse_from(&["", "dkim", "rotate", "2022-09"]).unwrap()
+        );
+
+        assert_eq!(
+            Args {
+                config: None,
+                command: Commands::Dkim {
+                    domain: Some("example.com".to_string()),
+                    command: DkimCommand::Rotate {
+                        selector: "ed".to_string(),
+                        algorithm: vsmtp_dkim::SigningAlgorithm::Ed25519Sha256,
+                        delay: std::time::Duration::ZERO,
+                    }
+                }
+            },
+            <Args as clap::StructOpt>::try_parse_from(&[
+                "",
+                "dkim",
+                "--domain",
+                "example.com",
+                "rotate",
+                "ed",
+                "--algorithm",
+                "ed25519-sha256",
+                "--delay",
+                "0s"
+            ])
+            .unwrap()
+        );
+    }
+
     #[test]
     fn arg_show_message() {
         assert_eq!(
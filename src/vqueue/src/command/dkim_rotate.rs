@@ -0,0 +1,111 @@
+use vsmtp_common::re::anyhow;
+use vsmtp_config::{Config, DkimKeySet};
+use vsmtp_dkim::SigningAlgorithm;
+
+pub fn dkim_rotate<OUT: std::io::Write>(
+    config: &Config,
+    domain: Option<&str>,
+    selector: &str,
+    signing_algorithm: SigningAlgorithm,
+    delay: std::time::Duration,
+    output: &mut OUT,
+) -> anyhow::Result<()> {
+    let (sdid, dkim) = match domain {
+        Some(domain) => (
+            domain,
+            config
+                .server
+                .r#virtual
+                .get(domain)
+                .ok_or_else(|| anyhow::anyhow!("no virtual server for the domain '{domain}'"))?
+                .dkim
+                .as_ref(),
+        ),
+        None => (config.server.domain.as_str(), config.server.dkim.as_ref()),
+    };
+
+    let keyset_dirpath = dkim
+        .and_then(|dkim| dkim.keyset.as_ref())
+        .map(|keyset| &keyset.path)
+        .ok_or_else(|| anyhow::anyhow!("no dkim keyset is configured for the domain '{sdid}'"))?;
+
+    let not_before = std::time::SystemTime::now() + delay;
+    let record = DkimKeySet::read(keyset_dirpath)?.rotate(
+        keyset_dirpath,
+        selector,
+        signing_algorithm,
+        not_before,
+    )?;
+
+    writeln!(
+        output,
+        "The key '{selector}' will be used from {}, publish this record before:",
+        vsmtp_config::re::humantime::format_rfc3339_seconds(not_before)
+    )?;
+    // a TXT record is made of strings of 255 characters at most
+    writeln!(
+        output,
+        "{selector}._domainkey.{sdid}. IN TXT ( {} )",
+        record
+            .as_bytes()
+            .chunks(255)
+            .map(|chunk| format!("\"{}\"", String::from_utf8_lossy(chunk)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    )?;
+    writeln!(output, "The server must be reloaded to load the new key.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate() {
+        let keyset_dirpath = std::path::PathBuf::from("./tmp/dkim_rotate");
+        let _ = std::fs::remove_dir_all(&keyset_dirpath);
+        std::fs::create_dir_all(&keyset_dirpath).unwrap();
+
+        let mut config = Config::default();
+        config.server.domain = "example.com".to_string();
+
+        let mut output = vec![];
+        assert!(dkim_rotate(
+            &config,
+            None,
+            "foo",
+            SigningAlgorithm::RsaSha256,
+            std::time::Duration::ZERO,
+            &mut output
+        )
+        .is_err());
+
+        config.server.dkim = Some(vsmtp_config::field::FieldDkim {
+            private_key: None,
+            dual_private_key: None,
+            keyset: Some(vsmtp_config::field::SecretFile {
+                inner: DkimKeySet::load(&keyset_dirpath).unwrap(),
+                path: keyset_dirpath.clone(),
+            }),
+        });
+
+        dkim_rotate(
+            &config,
+            None,
+            "foo",
+            SigningAlgorithm::Ed25519Sha256,
+            std::time::Duration::ZERO,
+            &mut output,
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("foo._domainkey.example.com. IN TXT ( \"v=DKIM1; k=ed25519; p="));
+        assert_eq!(
+            DkimKeySet::read(&keyset_dirpath).unwrap().keys[0].selector,
+            "foo"
+        );
+    }
+}
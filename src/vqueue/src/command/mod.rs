@@ -1,10 +1,14 @@
-use crate::{Commands, MessageCommand};
+use crate::{Commands, DkimCommand, MessageCommand};
 use vsmtp_common::{
     queue::Queue,
-    re::{anyhow, strum},
+    re::{
+        anyhow::{self, Context},
+        strum,
+    },
 };
 use vsmtp_config::Config;
 
+mod dkim_rotate;
 mod queue_show;
 mod msg_command {
     pub mod r#move;
@@ -75,7 +79,55 @@ pub fn execute(command: Commands, config: &Config) -> anyhow::Result<()> {
             ),
             MessageCommand::ReRun {} => unimplemented!(),
         },
+        Commands::Reload => reload(&config.pid_filepath()),
+        Commands::Dkim { domain, command } => match command {
+            DkimCommand::Rotate {
+                selector,
+                algorithm,
+                delay,
+            } => dkim_rotate::dkim_rotate(
+                config,
+                domain.as_deref(),
+                &selector,
+                algorithm,
+                delay,
+                &mut std::io::stdout(),
+            ),
+        },
+    }
+}
+
+/// read the pid written at `pid_filepath`, and check it is the one of a running server.
+fn server_pid(pid_filepath: &std::path::Path) -> anyhow::Result<vsmtp_common::re::libc::pid_t> {
+    let pid = std::fs::read_to_string(pid_filepath)
+        .with_context(|| format!("cannot read pid file '{}'", pid_filepath.display()))?
+        .trim()
+        .parse::<vsmtp_common::re::libc::pid_t>()
+        .with_context(|| format!("invalid pid file '{}'", pid_filepath.display()))?;
+
+    // NOTE: the pid file of a server which did not stop cleanly is stale,
+    // and its pid can be reused by another process.
+    let name = std::fs::read_to_string(format!("/proc/{pid}/comm")).with_context(|| {
+        format!(
+            "no process with the pid {pid} of '{}'",
+            pid_filepath.display()
+        )
+    })?;
+
+    if name.trim() != "vsmtp" {
+        anyhow::bail!(
+            "the process {pid} of '{}' is not vsmtp but '{}'",
+            pid_filepath.display(),
+            name.trim()
+        );
     }
+
+    Ok(pid)
+}
+
+/// send `SIGHUP` to the server whose pid is written at `pid_filepath`
+fn reload(pid_filepath: &std::path::Path) -> anyhow::Result<()> {
+    vsmtp_common::libc_abstraction::kill(server_pid(pid_filepath)?, vsmtp_common::re::libc::SIGHUP)
 }
 
 #[cfg(test)]
@@ -117,11 +169,36 @@ mod tests {
         assert!(get_message_path("foobar", &std::path::PathBuf::from("./tmp")).is_err());
     }
 
+    #[test]
+    fn reload_without_server() {
+        let mut config = Config::default();
+        config.server.queues.dirpath = "./tmp/reload_without_server".into();
+
+        assert!(execute(Commands::Reload, &config).is_err());
+    }
+
+    #[test]
+    fn reload_stale_pid_file() {
+        let pid_filepath = std::path::PathBuf::from("./tmp/reload_stale_pid_file.pid");
+        std::fs::create_dir_all("./tmp").unwrap();
+
+        // NOTE: the pid of the test, which is not a server.
+        std::fs::write(&pid_filepath, std::process::id().to_string()).unwrap();
+        assert!(server_pid(&pid_filepath)
+            .unwrap_err()
+            .to_string()
+            .contains("is not vsmtp"));
+
+        std::fs::remove_file(pid_filepath).unwrap();
+    }
+
     fn get_mail(msg_id: &str) -> MailContext {
         MailContext {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::now(),
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: "testserver.com".to_string(),
@@ -137,7 +214,14 @@ mod tests {
                     email_status: EmailTransferStatus::Waiting {
                         timestamp: std::time::SystemTime::now(),
                     },
+                    notify: None,
+                    original_recipient: None,
                 }],
+                declared_size: None,
+                dsn_return: None,
+                envelop_id: None,
+                null_reverse_path: false,
+                smtp_utf8: false,
             },
             metadata: Some(MessageMetadata {
                 timestamp: std::time::SystemTime::now(),
@@ -60,6 +60,8 @@ mod tests {
                 connection: ConnectionContext {
                     timestamp: std::time::SystemTime::now(),
                     credentials: None,
+                    verify_query: None,
+                    client_certificate: None,
                     is_authenticated: false,
                     is_secured: false,
                     server_name: "testserver.com".to_string(),
@@ -75,7 +77,14 @@ mod tests {
                         email_status: EmailTransferStatus::Waiting {
                             timestamp: std::time::SystemTime::now(),
                         },
+                        notify: None,
+                        original_recipient: None,
                     }],
+                    declared_size: None,
+                    dsn_return: None,
+                    envelop_id: None,
+                    null_reverse_path: false,
+                    smtp_utf8: false,
                 },
                 metadata: Some(MessageMetadata {
                     timestamp: std::time::SystemTime::now(),
@@ -15,6 +15,6 @@ mod args;
 mod command;
 mod model;
 
-pub use args::{Args, Commands, MessageCommand, MessageShowFormat};
+pub use args::{Args, Commands, DkimCommand, MessageCommand, MessageShowFormat};
 pub use command::execute;
 pub(crate) use model::{QueueContent, QueueEntry};
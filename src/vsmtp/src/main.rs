@@ -45,6 +45,7 @@ fn bind_sockets(addr: &[std::net::SocketAddr]) -> anyhow::Result<Vec<std::net::T
 fn try_main() -> anyhow::Result<()> {
     let args = <Args as clap::StructOpt>::parse();
 
+    let config_path = args.config.as_ref().map(std::path::PathBuf::from);
     let config = args.config.as_ref().map_or_else(
         || Ok(Config::default()),
         |config| {
@@ -102,7 +103,7 @@ fn try_main() -> anyhow::Result<()> {
 
     vsmtp::tracing_subscriber::initialize(&args, &config);
 
-    start_runtime(config, sockets, args.timeout.map(|t| t.0)).map_err(|e| {
+    start_runtime(config, config_path, sockets, args.timeout.map(|t| t.0)).map_err(|e| {
         log::error!("vSMTP terminating error: '{e}'");
         e
     })
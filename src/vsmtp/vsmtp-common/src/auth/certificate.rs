@@ -0,0 +1,288 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+
+/// Identity of a client which presented a trusted certificate during the TLS handshake.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ClientCertificate {
+    /// distinguished name of the subject, formatted as in RFC 4514, ex: `CN=relay.example.com,O=viridIT,C=FR`
+    pub subject: String,
+    /// alternative names of the subject, ex: `DNS:relay.example.com` or `IP:192.168.1.254`
+    pub subject_alt_names: Vec<String>,
+}
+
+use std::fmt::Write;
+use x509_parser::{
+    der_parser::asn1_rs::{Tag, ToDer},
+    prelude::{FromDer, GeneralName, X509Certificate},
+    x509::{AttributeTypeAndValue, X509Name},
+};
+
+/// Escape the special characters of an attribute value,
+/// see <https://datatracker.ietf.org/doc/html/rfc4514#section-2.4>
+fn escape_value(value: &str) -> String {
+    let last = value.chars().count().saturating_sub(1);
+    let mut out = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '"' | '+' | ',' | ';' | '<' | '>' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\0' => out.push_str("\\00"),
+            ' ' | '#' if i == 0 => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' if i == last => out.push_str("\\ "),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `type=value` of an attribute, see <https://datatracker.ietf.org/doc/html/rfc4514#section-2.3>
+fn attribute_to_string(attribute: &AttributeTypeAndValue<'_>) -> anyhow::Result<String> {
+    let oid = attribute.attr_type().to_id_string();
+    let key = match oid.as_str() {
+        "2.5.4.3" => "CN",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "0.9.2342.19200300.100.1.1" => "UID",
+        "0.9.2342.19200300.100.1.25" => "DC",
+        // the value of an attribute without a short name is written as its hex encoded BER
+        _ => {
+            let ber = attribute
+                .attr_value()
+                .to_der_vec()
+                .map_err(|e| anyhow::anyhow!("invalid attribute in distinguished name: {e}"))?;
+            let mut out = format!("{oid}=#");
+            for b in ber {
+                write!(out, "{b:02x}")?;
+            }
+            return Ok(out);
+        }
+    };
+
+    let value = attribute.attr_value();
+    let value = match value.tag() {
+        Tag::BmpString => String::from_utf16_lossy(
+            &value
+                .data
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]))
+                .collect::<Vec<_>>(),
+        ),
+        _ => attribute.as_str().map_or_else(
+            |_| String::from_utf8_lossy(value.data).to_string(),
+            ToString::to_string,
+        ),
+    };
+
+    Ok(format!("{key}={}", escape_value(&value)))
+}
+
+/// String representation of a distinguished name,
+/// see <https://datatracker.ietf.org/doc/html/rfc4514#section-2.1>
+fn name_to_string(name: &X509Name<'_>) -> anyhow::Result<String> {
+    let mut rdns = name
+        .iter()
+        .map(|rdn| {
+            Ok(rdn
+                .iter()
+                .map(attribute_to_string)
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .join("+"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    // the last RDN of the sequence is written first
+    rdns.reverse();
+    Ok(rdns.join(","))
+}
+
+fn general_name_to_string(name: &GeneralName<'_>) -> Option<String> {
+    match name {
+        GeneralName::RFC822Name(email) => Some(format!("email:{email}")),
+        GeneralName::DNSName(dns) => Some(format!("DNS:{dns}")),
+        GeneralName::URI(uri) => Some(format!("URI:{uri}")),
+        GeneralName::IPAddress(ip) => match ip.len() {
+            4 => Some(format!(
+                "IP:{}",
+                std::net::Ipv4Addr::from(<[u8; 4]>::try_from(*ip).ok()?)
+            )),
+            16 => Some(format!(
+                "IP:{}",
+                std::net::Ipv6Addr::from(<[u8; 16]>::try_from(*ip).ok()?)
+            )),
+            _ => None,
+        },
+        // otherName, x400Address, directoryName, ediPartyName & registeredID are ignored
+        _ => None,
+    }
+}
+
+impl ClientCertificate {
+    /// Extract the subject and its alternative names of a DER encoded X.509 certificate.
+    ///
+    /// # Errors
+    ///
+    /// * the certificate is not valid DER
+    /// * the certificate does not follow the structure of RFC 5280
+    pub fn from_der(der: &[u8]) -> anyhow::Result<Self> {
+        let (_, certificate) = X509Certificate::from_der(der)
+            .map_err(|e| anyhow::anyhow!("invalid certificate: {e}"))?;
+
+        let subject_alt_names = certificate
+            .subject_alternative_name()
+            .map_err(|e| anyhow::anyhow!("invalid subject alternative name: {e}"))?
+            .map(|extension| {
+                extension
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(general_name_to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            subject: name_to_string(certificate.subject())?,
+            subject_alt_names,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// decode the first certificate of a pem file
+    fn pem_to_der(pem: &str) -> Vec<u8> {
+        base64::decode(
+            pem.lines()
+                .skip(1)
+                .take_while(|l| !l.starts_with("-----END"))
+                .collect::<String>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn from_der() {
+        let der = pem_to_der(include_str!(
+            "../../../vsmtp-test/src/template/certs/client/client.crt"
+        ));
+
+        assert_eq!(
+            ClientCertificate::from_der(&der).unwrap(),
+            ClientCertificate {
+                subject: "CN=relay.example.com,O=viridIT,C=FR".to_string(),
+                subject_alt_names: vec![
+                    "DNS:relay.example.com".to_string(),
+                    "email:postmaster@example.com".to_string(),
+                    "IP:192.168.1.254".to_string(),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn server_certificate() {
+        let der = pem_to_der(include_str!(
+            "../../../vsmtp-test/src/template/certs/certificate.crt"
+        ));
+
+        assert_eq!(
+            ClientCertificate::from_der(&der).unwrap(),
+            ClientCertificate {
+                subject: "CN=testserver.com".to_string(),
+                subject_alt_names: vec![
+                    "DNS:testserver.com".to_string(),
+                    "DNS:second.testserver.com".to_string(),
+                    "DNS:localhost".to_string(),
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn error() {
+        assert!(ClientCertificate::from_der(&[]).is_err());
+        assert!(ClientCertificate::from_der(&[0x30, 0x82, 0x01]).is_err());
+        assert!(ClientCertificate::from_der(&[0x30, 0x03, 0x30, 0x01, 0x02]).is_err());
+        // indefinite length is not allowed in DER
+        assert!(ClientCertificate::from_der(&[0x30, 0x80, 0x30, 0x80, 0x00, 0x00]).is_err());
+    }
+
+    /// `SEQUENCE OF SET OF SEQUENCE { OID, UTF8String }`, short forms only
+    fn name_der(rdns: &[&[(&[u8], &str)]]) -> Vec<u8> {
+        let tlv =
+            |tag: u8, value: &[u8]| [&[tag, u8::try_from(value.len()).unwrap()], value].concat();
+        tlv(
+            0x30,
+            &rdns
+                .iter()
+                .flat_map(|rdn| {
+                    tlv(
+                        0x31,
+                        &rdn.iter()
+                            .flat_map(|(oid, value)| {
+                                tlv(
+                                    0x30,
+                                    &[tlv(0x06, oid), tlv(0x0C, value.as_bytes())].concat(),
+                                )
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn format_name(rdns: &[&[(&[u8], &str)]]) -> String {
+        let der = name_der(rdns);
+        name_to_string(&X509Name::from_der(&der).unwrap().1).unwrap()
+    }
+
+    const CN: &[u8] = &[0x55, 0x04, 0x03];
+    const O: &[u8] = &[0x55, 0x04, 0x0A];
+
+    #[test]
+    fn name_escaping() {
+        assert_eq!(format_name(&[&[(O, "b")], &[(CN, "a")]]), "CN=a,O=b");
+        assert_eq!(format_name(&[&[(CN, "a,O=b")]]), "CN=a\\,O=b");
+        assert_eq!(format_name(&[&[(CN, "a, O=b")]]), "CN=a\\, O=b");
+        assert_eq!(format_name(&[&[(CN, "a"), (O, "b")]]), "CN=a+O=b");
+        assert_eq!(format_name(&[&[(CN, "a+O=b")]]), "CN=a\\+O=b");
+        assert_eq!(
+            format_name(&[&[(CN, " #x\\y\"<>; ")]]),
+            "CN=\\ #x\\\\y\\\"\\<\\>\\;\\ "
+        );
+        // no short name for `emailAddress`
+        assert_eq!(
+            format_name(&[&[(
+                &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x01],
+                "a@b"
+            )]]),
+            "1.2.840.113549.1.9.1=#0c03614062"
+        );
+    }
+}
@@ -14,6 +14,8 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+use super::Mechanism;
+
 /// The credentials send by the client, not necessarily the right one
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize, strum::Display)]
 #[strum(serialize_all = "PascalCase")]
@@ -35,4 +37,171 @@ pub enum Credentials {
         /// [ email / 1*255TCHAR ]
         token: String,
     },
+    /// the server will query the salted keys of a SCRAM mechanism,
+    /// see [`super::ScramSecrets`]
+    ScramQuery {
+        ///
+        authid: String,
+        /// the SCRAM mechanism used by the client
+        mechanism: Mechanism,
+    },
+    /// verify the token sent by the `OAUTHBEARER` or `XOAUTH2` mechanisms
+    BearerToken {
+        /// user of the token, empty if the client did not provide it with `OAUTHBEARER`
+        authid: String,
+        /// OAuth 2.0 bearer token, see <https://datatracker.ietf.org/doc/html/rfc6750>
+        token: String,
+    },
+    /// the client presented a trusted certificate during the TLS handshake,
+    /// see [`super::ClientCertificate`]
+    External {
+        /// identity requested by the client, empty to derive it from the certificate
+        authid: String,
+    },
+}
+
+/// value of the `auth=` key, `Bearer <token>`
+fn bearer_token<'a>(mut kvpairs: impl Iterator<Item = &'a str>) -> anyhow::Result<String> {
+    let auth = kvpairs
+        .find_map(|kv| kv.strip_prefix("auth="))
+        .ok_or_else(|| anyhow::anyhow!("missing `auth` key"))?;
+
+    match auth.split_once(' ') {
+        Some((scheme, token))
+            if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() =>
+        {
+            Ok(token.trim().to_string())
+        }
+        _ => anyhow::bail!("`auth` value is not a bearer token"),
+    }
+}
+
+impl Credentials {
+    /// Parse the (base64 decoded) response of the client for `OAUTHBEARER` or `XOAUTH2`.
+    ///
+    /// # Errors
+    ///
+    /// * the payload is not valid utf8
+    /// * the payload does not follow the format of the mechanism
+    /// * the mechanism does not use a bearer token
+    pub fn from_bearer_payload(mechanism: Mechanism, payload: &[u8]) -> anyhow::Result<Self> {
+        let payload = std::str::from_utf8(payload)?;
+
+        match mechanism {
+            // gs2-header kvsep *(key=value kvsep) kvsep
+            // see https://datatracker.ietf.org/doc/html/rfc7628#section-3.1
+            Mechanism::OAuthBearer => {
+                let (gs2_header, kvpairs) = payload
+                    .split_once('\x01')
+                    .ok_or_else(|| anyhow::anyhow!("missing gs2 header"))?;
+
+                let authid = match gs2_header.splitn(3, ',').collect::<Vec<_>>()[..] {
+                    ["n" | "y", "", ""] => String::new(),
+                    ["n" | "y", authzid, ""] => authzid
+                        .strip_prefix("a=")
+                        .ok_or_else(|| anyhow::anyhow!("invalid authzid: '{authzid}'"))?
+                        .replace("=2C", ",")
+                        .replace("=3D", "="),
+                    _ => anyhow::bail!("invalid gs2 header: '{gs2_header}'"),
+                };
+
+                Ok(Self::BearerToken {
+                    authid,
+                    token: bearer_token(kvpairs.split('\x01'))?,
+                })
+            }
+            // "user=" user "\x01auth=Bearer " token "\x01\x01"
+            Mechanism::XOAuth2 => Ok(Self::BearerToken {
+                authid: payload
+                    .split('\x01')
+                    .find_map(|kv| kv.strip_prefix("user="))
+                    .ok_or_else(|| anyhow::anyhow!("missing `user` key"))?
+                    .to_string(),
+                token: bearer_token(payload.split('\x01'))?,
+            }),
+            otherwise => anyhow::bail!("mechanism '{otherwise}' does not use a bearer token"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oauthbearer() {
+        assert_eq!(
+            Credentials::from_bearer_payload(
+                Mechanism::OAuthBearer,
+                b"n,a=user@example.com,\x01host=server.example.com\x01port=143\x01auth=Bearer vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==\x01\x01"
+            )
+            .unwrap(),
+            Credentials::BearerToken {
+                authid: "user@example.com".to_string(),
+                token: "vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==".to_string()
+            }
+        );
+
+        assert_eq!(
+            Credentials::from_bearer_payload(
+                Mechanism::OAuthBearer,
+                b"n,,\x01auth=bearer token\x01\x01"
+            )
+            .unwrap(),
+            Credentials::BearerToken {
+                authid: String::new(),
+                token: "token".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn xoauth2() {
+        assert_eq!(
+            Credentials::from_bearer_payload(
+                Mechanism::XOAuth2,
+                b"user=someuser@example.com\x01auth=Bearer ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg\x01\x01"
+            )
+            .unwrap(),
+            Credentials::BearerToken {
+                authid: "someuser@example.com".to_string(),
+                token: "ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn error() {
+        for (mechanism, payload) in [
+            (Mechanism::OAuthBearer, &b"n,a=user@example.com,"[..]),
+            (
+                Mechanism::OAuthBearer,
+                b"p=tls-unique,,\x01auth=Bearer token\x01\x01",
+            ),
+            (
+                Mechanism::OAuthBearer,
+                b"n,user@example.com,\x01auth=Bearer token\x01\x01",
+            ),
+            (
+                Mechanism::OAuthBearer,
+                b"n,,\x01auth=Basic dXNlcjpwYXNz\x01\x01",
+            ),
+            (
+                Mechanism::OAuthBearer,
+                b"n,,\x01host=server.example.com\x01\x01",
+            ),
+            (Mechanism::XOAuth2, b"auth=Bearer token\x01\x01"),
+            (Mechanism::XOAuth2, b"user=foo\x01auth=Bearer \x01\x01"),
+            (
+                Mechanism::XOAuth2,
+                b"user=\xff\x01auth=Bearer token\x01\x01",
+            ),
+            (Mechanism::Plain, b"\0user\0pass"),
+        ] {
+            assert!(
+                Credentials::from_bearer_payload(mechanism, payload).is_err(),
+                "{payload:?}"
+            );
+        }
+    }
 }
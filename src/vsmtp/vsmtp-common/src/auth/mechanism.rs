@@ -41,14 +41,33 @@ pub enum Mechanism {
     /// Common
     /// See <https://datatracker.ietf.org/doc/html/rfc4505>
     Anonymous,
+    /// Salted Challenge Response Authentication Mechanism
+    /// See <https://datatracker.ietf.org/doc/html/rfc5802>
+    #[strum(serialize = "SCRAM-SHA-1")]
+    ScramSha1,
+    /// `SCRAM-SHA-1` with channel binding
+    #[strum(serialize = "SCRAM-SHA-1-PLUS")]
+    ScramSha1Plus,
+    /// See <https://datatracker.ietf.org/doc/html/rfc7677>
+    #[strum(serialize = "SCRAM-SHA-256")]
+    ScramSha256,
+    /// `SCRAM-SHA-256` with channel binding
+    #[strum(serialize = "SCRAM-SHA-256-PLUS")]
+    ScramSha256Plus,
+    /// OAuth 2.0 bearer token
+    /// See <https://datatracker.ietf.org/doc/html/rfc7628>
+    #[strum(serialize = "OAUTHBEARER")]
+    OAuthBearer,
+    /// Predecessor of `OAUTHBEARER`, still used by webmails
+    /// See <https://developers.google.com/gmail/imap/xoauth2-protocol>
+    #[strum(serialize = "XOAUTH2")]
+    XOAuth2,
+    /// Identity established by the TLS client certificate
+    /// See <https://datatracker.ietf.org/doc/html/rfc4422#appendix-A>
+    External,
     /*
-    - EXTERNAL
     - SECURID
     - DIGEST-MD5
-    - SCRAM-SHA-1
-    - SCRAM-SHA-1-PLUS
-    - SCRAM-SHA-256
-    - SCRAM-SHA-256-PLUS
     - SAML20
     - OPENID20
     - GSSAPI
@@ -89,7 +108,15 @@ impl Mechanism {
     #[must_use]
     pub const fn client_first(self) -> bool {
         match self {
-            Self::Plain | Self::Anonymous => true,
+            Self::Plain
+            | Self::Anonymous
+            | Self::ScramSha1
+            | Self::ScramSha1Plus
+            | Self::ScramSha256
+            | Self::ScramSha256Plus
+            | Self::OAuthBearer
+            | Self::XOAuth2
+            | Self::External => true,
             Self::Login | Self::CramMd5 => false,
         }
     }
@@ -98,9 +125,39 @@ impl Mechanism {
     #[must_use]
     pub const fn must_be_under_tls(self) -> bool {
         match self {
-            Self::Plain | Self::Login | Self::CramMd5 | Self::Anonymous => true,
+            Self::Plain
+            | Self::Login
+            | Self::CramMd5
+            | Self::Anonymous
+            | Self::ScramSha1
+            | Self::ScramSha1Plus
+            | Self::ScramSha256
+            | Self::ScramSha256Plus
+            | Self::OAuthBearer
+            | Self::XOAuth2
+            | Self::External => true,
         }
     }
+
+    /// Does this mechanism bind the authentication to the TLS connection,
+    /// see <https://datatracker.ietf.org/doc/html/rfc5056>
+    #[must_use]
+    pub const fn channel_binding(self) -> bool {
+        matches!(self, Self::ScramSha1Plus | Self::ScramSha256Plus)
+    }
+
+    /// Does this mechanism rely on the TLS session itself, and so cannot be
+    /// used on a clair connection even if `enable_dangerous_mechanism_in_clair` is set
+    #[must_use]
+    pub const fn requires_tls_session(self) -> bool {
+        self.channel_binding() || matches!(self, Self::External)
+    }
+
+    /// Is this mechanism implemented by vSMTP instead of the SASL backend
+    #[must_use]
+    pub const fn is_native(self) -> bool {
+        matches!(self, Self::OAuthBearer | Self::XOAuth2 | Self::External)
+    }
 }
 
 #[cfg(test)]
@@ -114,6 +171,13 @@ mod tests {
         assert_eq!(Mechanism::Login.to_string(), "LOGIN");
         assert_eq!(Mechanism::CramMd5.to_string(), "CRAM-MD5");
         assert_eq!(Mechanism::Anonymous.to_string(), "ANONYMOUS");
+        assert_eq!(Mechanism::ScramSha1.to_string(), "SCRAM-SHA-1");
+        assert_eq!(Mechanism::ScramSha1Plus.to_string(), "SCRAM-SHA-1-PLUS");
+        assert_eq!(Mechanism::ScramSha256.to_string(), "SCRAM-SHA-256");
+        assert_eq!(Mechanism::ScramSha256Plus.to_string(), "SCRAM-SHA-256-PLUS");
+        assert_eq!(Mechanism::OAuthBearer.to_string(), "OAUTHBEARER");
+        assert_eq!(Mechanism::XOAuth2.to_string(), "XOAUTH2");
+        assert_eq!(Mechanism::External.to_string(), "EXTERNAL");
     }
 
     #[test]
@@ -144,7 +208,7 @@ mod tests {
             );
         }
 
-        for i in <Mechanism as strum::IntoEnumIterator>::iter() {
+        for i in <Mechanism as strum::IntoEnumIterator>::iter().filter(|m| !m.is_native()) {
             assert!(
                 supported_by_backend
                     .get(&format!("{}", i))
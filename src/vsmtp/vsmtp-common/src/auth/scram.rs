@@ -0,0 +1,149 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+use super::Mechanism;
+
+/// The salted keys of a user for a SCRAM mechanism, stored instead of a password.
+///
+/// The textual representation is the one of the `authPassword` LDAP attribute,
+/// see <https://datatracker.ietf.org/doc/html/rfc5803#section-3> :
+/// `SCRAM-SHA-256$<iteration count>:<salt>$<StoredKey>:<ServerKey>`,
+/// where the salt and the keys are encoded in base64.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramSecrets {
+    /// `SCRAM-SHA-1` or `SCRAM-SHA-256`
+    pub mechanism: Mechanism,
+    /// iteration count of the key derivation function.
+    pub iterations: u32,
+    /// salt, in base64.
+    pub salt: String,
+    /// `StoredKey := H(HMAC(SaltedPassword, "Client Key"))`, in base64.
+    pub stored_key: String,
+    /// `ServerKey := HMAC(SaltedPassword, "Server Key")`, in base64.
+    pub server_key: String,
+}
+
+impl ScramSecrets {
+    /// Can those keys be used to authenticate a client with this mechanism ?
+    #[must_use]
+    pub const fn can_be_used_with(&self, mechanism: Mechanism) -> bool {
+        matches!(
+            (self.mechanism, mechanism),
+            (
+                Mechanism::ScramSha1,
+                Mechanism::ScramSha1 | Mechanism::ScramSha1Plus
+            ) | (
+                Mechanism::ScramSha256,
+                Mechanism::ScramSha256 | Mechanism::ScramSha256Plus
+            )
+        )
+    }
+}
+
+impl std::str::FromStr for ScramSecrets {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, (iterations, salt), (stored_key, server_key)) =
+            match s.splitn(3, '$').collect::<Vec<_>>()[..] {
+                [scheme, info, value] => match (info.split_once(':'), value.split_once(':')) {
+                    (Some(info), Some(value)) => (scheme, info, value),
+                    _ => anyhow::bail!("ill-formed SCRAM secrets '{s}'"),
+                },
+                _ => anyhow::bail!("ill-formed SCRAM secrets '{s}'"),
+            };
+
+        let (mechanism, key_length) = match scheme {
+            "SCRAM-SHA-1" => (Mechanism::ScramSha1, 20),
+            "SCRAM-SHA-256" => (Mechanism::ScramSha256, 32),
+            _ => anyhow::bail!("unsupported SCRAM scheme '{scheme}'"),
+        };
+
+        let iterations = iterations.parse::<u32>()?;
+        anyhow::ensure!(iterations != 0, "the iteration count must not be 0");
+
+        base64::decode(salt)?;
+        for key in [stored_key, server_key] {
+            anyhow::ensure!(
+                base64::decode(key)?.len() == key_length,
+                "the keys of {mechanism} must be {key_length} bytes long"
+            );
+        }
+
+        Ok(Self {
+            mechanism,
+            iterations,
+            salt: salt.to_string(),
+            stored_key: stored_key.to_string(),
+            server_key: server_key.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for ScramSecrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}${}:{}${}:{}",
+            self.mechanism, self.iterations, self.salt, self.stored_key, self.server_key
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let secrets = "SCRAM-SHA-256$4096:W22ZaJ0SNY7soEsUEjb6gQ==$WG5d8oPm3OtcPnkdi4Uo7BkeZkBFzpcXkuLmtbsT4qY=:wfPLwcE6nTWhTAmQ7tl2KeoiWGPlZqQxSrmfPwDl2dU=";
+
+        let parsed = secrets.parse::<ScramSecrets>().unwrap();
+        assert_eq!(
+            parsed,
+            ScramSecrets {
+                mechanism: Mechanism::ScramSha256,
+                iterations: 4096,
+                salt: "W22ZaJ0SNY7soEsUEjb6gQ==".to_string(),
+                stored_key: "WG5d8oPm3OtcPnkdi4Uo7BkeZkBFzpcXkuLmtbsT4qY=".to_string(),
+                server_key: "wfPLwcE6nTWhTAmQ7tl2KeoiWGPlZqQxSrmfPwDl2dU=".to_string(),
+            }
+        );
+        assert_eq!(parsed.to_string(), secrets);
+
+        assert!(parsed.can_be_used_with(Mechanism::ScramSha256));
+        assert!(parsed.can_be_used_with(Mechanism::ScramSha256Plus));
+        assert!(!parsed.can_be_used_with(Mechanism::ScramSha1));
+        assert!(!parsed.can_be_used_with(Mechanism::Plain));
+    }
+
+    #[test]
+    fn error() {
+        for i in [
+            "",
+            "pencil",
+            "SCRAM-SHA-512$4096:W22ZaJ0SNY7soEsUEjb6gQ==$a:b",
+            "SCRAM-SHA-1$4096:QSXCR+Q6sek8bf92",
+            "SCRAM-SHA-1$0:QSXCR+Q6sek8bf92$6dlGYMOdZcOPutkcNY8U2g7vK9Y=:D+CSWLOshSulAsxiupA+qs2/fTE=",
+            "SCRAM-SHA-1$4096:QSXCR+Q6sek8bf92$6dlGYMOdZcOPutkcNY8U2g7vK9Y=",
+            "SCRAM-SHA-1$4096:QSXCR+Q6sek8bf92$6dlGYMOdZcOPutkcNY8U2g7vK9Y=:!!",
+            // keys of SCRAM-SHA-1 used with SCRAM-SHA-256
+            "SCRAM-SHA-256$4096:QSXCR+Q6sek8bf92$6dlGYMOdZcOPutkcNY8U2g7vK9Y=:D+CSWLOshSulAsxiupA+qs2/fTE=",
+        ] {
+            assert!(i.parse::<ScramSecrets>().is_err(), "{i}");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::CodeID;
+
+/// What should be returned to the sender in case of failure, see the `RET` parameter
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.3>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DsnReturn {
+    /// the full message
+    Full,
+    /// only the headers of the message
+    Headers,
+}
+
+impl std::str::FromStr for DsnReturn {
+    type Err = CodeID;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "FULL" => Ok(Self::Full),
+            "HDRS" => Ok(Self::Headers),
+            _ => Err(CodeID::SyntaxErrorParams),
+        }
+    }
+}
+
+/// Conditions under which a notification should be sent for a recipient, see the `NOTIFY`
+/// parameter <https://datatracker.ietf.org/doc/html/rfc3461#section-4.1>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    /// a notification must never be sent
+    Never,
+    /// a notification must be sent on the following events
+    Some {
+        /// the message has been delivered or relayed
+        success: bool,
+        /// the delivery has failed
+        failure: bool,
+        /// the delivery has been delayed
+        delay: bool,
+    },
+}
+
+impl Default for NotifyOn {
+    /// When the parameter is not provided, only failures are notified.
+    fn default() -> Self {
+        Self::Some {
+            success: false,
+            failure: true,
+            delay: false,
+        }
+    }
+}
+
+impl std::str::FromStr for NotifyOn {
+    type Err = CodeID;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("NEVER") {
+            return Ok(Self::Never);
+        }
+
+        let (mut success, mut failure, mut delay) = (false, false, false);
+        for keyword in s.split(',') {
+            let flag = match keyword.to_ascii_uppercase().as_str() {
+                "SUCCESS" => &mut success,
+                "FAILURE" => &mut failure,
+                "DELAY" => &mut delay,
+                _ => return Err(CodeID::SyntaxErrorParams),
+            };
+            if *flag {
+                return Err(CodeID::SyntaxErrorParams);
+            }
+            *flag = true;
+        }
+
+        Ok(Self::Some {
+            success,
+            failure,
+            delay,
+        })
+    }
+}
+
+impl NotifyOn {
+    /// Should a notification be sent when the message has been delivered or relayed ?
+    #[must_use]
+    pub const fn on_success(&self) -> bool {
+        matches!(self, Self::Some { success: true, .. })
+    }
+
+    /// Should a notification be sent when the delivery has failed ?
+    #[must_use]
+    pub const fn on_failure(&self) -> bool {
+        matches!(self, Self::Some { failure: true, .. })
+    }
+
+    /// Should a notification be sent when the delivery has been delayed ?
+    #[must_use]
+    pub const fn on_delay(&self) -> bool {
+        matches!(self, Self::Some { delay: true, .. })
+    }
+}
+
+/// Original address of a recipient, provided by the `ORCPT` parameter
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.2>
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OriginalRecipient {
+    /// type of the address, "rfc822" in most cases
+    pub addr_type: String,
+    /// the address, decoded from xtext
+    pub mailbox: String,
+}
+
+impl std::str::FromStr for OriginalRecipient {
+    type Err = CodeID;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(';') {
+            Some((addr_type, mailbox))
+                if !addr_type.is_empty()
+                    && addr_type
+                        .bytes()
+                        .all(|c| c.is_ascii_alphanumeric() || c == b'-') =>
+            {
+                Ok(Self {
+                    addr_type: addr_type.to_string(),
+                    mailbox: decode_xtext(mailbox)?,
+                })
+            }
+            _ => Err(CodeID::SyntaxErrorParams),
+        }
+    }
+}
+
+impl std::fmt::Display for OriginalRecipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}; {}", self.addr_type, self.mailbox)
+    }
+}
+
+/// Decode a string encoded as xtext
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4>
+///
+/// # Errors
+///
+/// * the input contains characters out of the xtext range
+/// * an hexchar is ill-formed
+pub fn decode_xtext(input: &str) -> Result<String, CodeID> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut bytes = input.bytes();
+
+    while let Some(c) = bytes.next() {
+        match c {
+            b'+' => {
+                let hex = [
+                    bytes.next().ok_or(CodeID::SyntaxErrorParams)?,
+                    bytes.next().ok_or(CodeID::SyntaxErrorParams)?,
+                ];
+                if !hex
+                    .iter()
+                    .all(|c| c.is_ascii_digit() || (b'A'..=b'F').contains(c))
+                {
+                    return Err(CodeID::SyntaxErrorParams);
+                }
+                output.push(
+                    u8::from_str_radix(
+                        std::str::from_utf8(&hex).map_err(|_| CodeID::SyntaxErrorParams)?,
+                        16,
+                    )
+                    .map_err(|_| CodeID::SyntaxErrorParams)?,
+                );
+            }
+            b'!'..=b'~' if c != b'=' => output.push(c),
+            _ => return Err(CodeID::SyntaxErrorParams),
+        }
+    }
+
+    String::from_utf8(output).map_err(|_| CodeID::SyntaxErrorParams)
+}
+
+/// Encode a string as xtext
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4>
+#[must_use]
+pub fn encode_xtext(input: &str) -> String {
+    input.bytes().fold(String::new(), |mut output, c| {
+        match c {
+            b'!'..=b'~' if c != b'+' && c != b'=' => output.push(char::from(c)),
+            _ => {
+                const HEX: &[u8; 16] = b"0123456789ABCDEF";
+                output.push('+');
+                output.push(char::from(HEX[usize::from(c >> 4)]));
+                output.push(char::from(HEX[usize::from(c & 0xF)]));
+            }
+        }
+        output
+    })
+}
@@ -14,6 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+use crate::dsn::DsnReturn;
 use crate::rcpt::Rcpt;
 use crate::Address;
 
@@ -26,6 +27,27 @@ pub struct Envelop {
     pub mail_from: Address,
     /// a list of recipients received using the RCPT TO command.
     pub rcpt: Vec<Rcpt>,
+    /// size of the message declared by the client using the `SIZE` parameter
+    /// of the MAIL FROM command, see <https://datatracker.ietf.org/doc/html/rfc1870>
+    #[serde(default)]
+    pub declared_size: Option<usize>,
+    /// what should be returned to the sender in a delivery status notification, using the `RET`
+    /// parameter of the MAIL FROM command, see <https://datatracker.ietf.org/doc/html/rfc3461>
+    #[serde(default)]
+    pub dsn_return: Option<DsnReturn>,
+    /// identifier of the envelop given by the client using the `ENVID` parameter
+    /// of the MAIL FROM command, see <https://datatracker.ietf.org/doc/html/rfc3461>
+    #[serde(default)]
+    pub envelop_id: Option<String>,
+    /// the `SMTPUTF8` parameter has been used in the MAIL FROM command,
+    /// see <https://datatracker.ietf.org/doc/html/rfc6531>
+    #[serde(default)]
+    pub smtp_utf8: bool,
+    /// the message is sent with the null reverse-path `<>`, as the delivery status notifications,
+    /// see <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.5>
+    /// `mail_from` then only identifies the local sender.
+    #[serde(default)]
+    pub null_reverse_path: bool,
 }
 
 impl Default for Envelop {
@@ -34,6 +56,11 @@ impl Default for Envelop {
             helo: String::default(),
             mail_from: Address::new_unchecked("default@domain.com".to_string()),
             rcpt: vec![],
+            declared_size: None,
+            dsn_return: None,
+            envelop_id: None,
+            smtp_utf8: false,
+            null_reverse_path: false,
         }
     }
 }
@@ -50,6 +77,8 @@ pub mod test {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::now(),
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: "testserver.com".to_string(),
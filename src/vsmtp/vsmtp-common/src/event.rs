@@ -14,7 +14,11 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::{auth::Mechanism, Address, CodeID};
+use crate::{
+    auth::Mechanism,
+    dsn::{decode_xtext, DsnReturn, NotifyOn, OriginalRecipient},
+    Address, CodeID,
+};
 
 /// See "SMTP Service Extension for 8-bit MIME Transport"
 /// <https://datatracker.ietf.org/doc/html/rfc6152>
@@ -24,7 +28,9 @@ pub enum MimeBodyType {
     SevenBit,
     ///
     EightBitMime,
-    // Binary, // TODO: https://datatracker.ietf.org/doc/html/rfc3030
+    /// See "SMTP Service Extensions for Transmission of Large and Binary MIME Messages"
+    /// <https://datatracker.ietf.org/doc/html/rfc3030>
+    Binary,
 }
 
 impl std::str::FromStr for MimeBodyType {
@@ -34,6 +40,7 @@ impl std::str::FromStr for MimeBodyType {
         match s {
             "7BIT" => Ok(Self::SevenBit),
             "8BITMIME" => Ok(Self::EightBitMime),
+            "BINARYMIME" => Ok(Self::Binary),
             _ => Err(CodeID::SyntaxErrorParams),
         }
     }
@@ -58,17 +65,47 @@ pub enum Event {
     ///
     /// 3rd argument is an xtext of the identity of the submitter,
     /// "<>" meaning not enough unknown or insufficiently authenticated
-    MailCmd(Option<Address>, Option<MimeBodyType>, Option<String>),
+    ///
+    /// 4th argument is the size of the message declared by the client.
+    /// See "SMTP Service Extension for Message Size Declaration"
+    /// <https://datatracker.ietf.org/doc/html/rfc1870>
+    ///
+    /// 5th and 6th arguments are the `RET` and `ENVID` parameters (the latter decoded from xtext).
+    /// See "SMTP Service Extension for Delivery Status Notifications (DSNs)"
+    /// <https://datatracker.ietf.org/doc/html/rfc3461>
+    ///
+    /// 7th argument is true if the `SMTPUTF8` parameter is used.
+    /// See "SMTP Extension for Internationalized Email"
+    /// <https://datatracker.ietf.org/doc/html/rfc6531>
+    MailCmd(
+        Option<Address>,
+        Option<MimeBodyType>,
+        Option<String>,
+        Option<usize>,
+        Option<DsnReturn>,
+        Option<String>,
+        bool,
+    ),
     /// This command is used to identify an individual recipient of the mail
     /// data; multiple recipients are specified by multiple uses of this
     /// command.
     /// Syntax = `"RCPT TO:" ( "<Postmaster@" Domain ">" / "<Postmaster>" /
     /// Forward-path ) [SP Rcpt-parameters] CRLF`
-    RcptCmd(Address),
+    ///
+    /// 2nd and 3rd arguments are the `NOTIFY` and `ORCPT` parameters.
+    /// See "SMTP Service Extension for Delivery Status Notifications (DSNs)"
+    /// <https://datatracker.ietf.org/doc/html/rfc3461>
+    RcptCmd(Address, Option<NotifyOn>, Option<OriginalRecipient>),
     /// This command causes the mail data to be appended to the mail data
     /// buffer.
     /// Syntax = `"DATA" CRLF`
     DataCmd,
+    /// This command is used to send a chunk of the mail data, of the given size
+    /// in octets. The second argument is true if this is the last chunk.
+    /// See "SMTP Service Extensions for Transmission of Large and Binary MIME Messages"
+    /// <https://datatracker.ietf.org/doc/html/rfc3030>
+    /// Syntax = `"BDAT" SP chunk-size [ SP end-marker ] CRLF`
+    BdatCmd(usize, bool),
     /// "RSET\r\n"
     /// This command specifies that the current mail transaction will be
     /// aborted. Any stored sender, recipients, and mail data MUST be
@@ -112,13 +149,10 @@ pub enum Event {
     Auth(Mechanism, Option<Vec<u8>>),
     //
     // Authenticated TURN for On-Demand Mail Relay // https://datatracker.ietf.org/doc/html/rfc2645
-    // Chunking // https://datatracker.ietf.org/doc/html/rfc3030
     // Delivery status notification // https://datatracker.ietf.org/doc/html/rfc3461
     // https://en.wikipedia.org/wiki/Variable_envelope_return_path
     // Extended version of remote message queue starting command TURN
     // https://datatracker.ietf.org/doc/html/rfc1985
-    // Command pipelining // https://datatracker.ietf.org/doc/html/rfc2920
-    // Message size declaration // https://datatracker.ietf.org/doc/html/rfc1870
 }
 
 impl Event {
@@ -128,7 +162,21 @@ impl Event {
     /// # Errors
     pub fn parse_cmd(input: &str) -> Result<Self, CodeID> {
         // 88 = 80 - "\r\n".len() + (SMTPUTF8 ? 10 : 0)
-        if input.len() > 88 || input.is_empty() {
+        // + 26 if the SIZE parameter is used (RFC 1870 section 6)
+        // + 100 if the RET or ENVID parameters are used (RFC 3461 section 4)
+        // + 500 if the NOTIFY or ORCPT parameters are used (RFC 3461 section 4)
+        let upper = input.to_ascii_uppercase();
+        let max_length = [
+            (" SIZE=", 26),
+            (" RET=", 100),
+            (" ENVID=", 100),
+            (" NOTIFY=", 500),
+            (" ORCPT=", 500),
+        ]
+        .iter()
+        .filter(|(keyword, _)| upper.contains(keyword))
+        .fold(88, |max, (_, extra)| max + extra);
+        if input.len() > max_length || input.is_empty() {
             return Err(CodeID::UnrecognizedCommand);
         }
 
@@ -168,6 +216,7 @@ impl Event {
             ("HELP", [help_value]) => Ok(Self::HelpCmd(Some((*help_value).to_string()))),
 
             ("DATA", []) => Ok(Self::DataCmd),
+            ("BDAT", args) => Self::parse_arg_bdat(args),
             ("QUIT", []) => Ok(Self::QuitCmd),
             ("RSET", []) => Ok(Self::RsetCmd),
             ("NOOP", [..]) => Ok(Self::NoopCmd),
@@ -237,6 +286,10 @@ impl Event {
         fn parse_esmtp_args(path: String, args: &[&str]) -> Result<Event, CodeID> {
             let mut bitmime = None;
             let mut auth_mailbox = None;
+            let mut size = None;
+            let mut dsn_return = None;
+            let mut envelop_id = None;
+            let mut smtp_utf8 = false;
 
             for arg in args {
                 if let Some(raw) = arg.strip_prefix("BODY=") {
@@ -245,15 +298,40 @@ impl Event {
                     } else {
                         return Err(CodeID::SyntaxErrorParams);
                     }
-                } else if *arg == "SMTPUTF8" {
-                    // TODO: ?
-                    // do we want to set a flag in the envelope to force utf8 in the deliver/relay ?
+                } else if arg.eq_ignore_ascii_case("SMTPUTF8") {
+                    if smtp_utf8 {
+                        return Err(CodeID::SyntaxErrorParams);
+                    }
+                    smtp_utf8 = true;
                 } else if let Some(mailbox) = arg.strip_prefix("AUTH=") {
                     if auth_mailbox.is_none() {
                         auth_mailbox = Some(mailbox.to_string());
                     } else {
                         return Err(CodeID::SyntaxErrorParams);
                     }
+                } else if let Some(raw) = strip_prefix_ignore_case(arg, "SIZE=") {
+                    // size-value ::= 1*20DIGIT
+                    if size.is_none() && !raw.is_empty() && raw.bytes().all(|c| c.is_ascii_digit())
+                    {
+                        // a size overflowing `usize` is saturated, and rejected as too large.
+                        size = Some(raw.parse().unwrap_or(usize::MAX));
+                    } else {
+                        return Err(CodeID::SyntaxErrorParams);
+                    }
+                } else if let Some(raw) = strip_prefix_ignore_case(arg, "RET=") {
+                    if dsn_return.is_none() {
+                        dsn_return = Some(<DsnReturn as std::str::FromStr>::from_str(raw)?);
+                    } else {
+                        return Err(CodeID::SyntaxErrorParams);
+                    }
+                } else if let Some(raw) = strip_prefix_ignore_case(arg, "ENVID=") {
+                    // the decoded value must not exceed 100 characters
+                    match decode_xtext(raw)? {
+                        id if envelop_id.is_none() && !id.is_empty() && id.len() <= 100 => {
+                            envelop_id = Some(id);
+                        }
+                        _ => return Err(CodeID::SyntaxErrorParams),
+                    }
                 } else {
                     return Err(CodeID::ParameterUnimplemented);
                 }
@@ -267,6 +345,10 @@ impl Event {
                 },
                 bitmime,
                 auth_mailbox,
+                size,
+                dsn_return,
+                envelop_id,
+                smtp_utf8,
             ))
         }
 
@@ -299,15 +381,34 @@ impl Event {
 
         // TODO: parse "<Postmaster@" Domain ">" / "<Postmaster>"
 
-        #[allow(clippy::missing_const_for_fn)]
         fn parse_esmtp_args(path: String, args: &[&str]) -> Result<Event, CodeID> {
-            if args.is_empty() {
-                Ok(Event::RcptCmd(
-                    Address::try_from(path).map_err(|_| CodeID::SyntaxErrorParams)?,
-                ))
-            } else {
-                Err(CodeID::ParameterUnimplemented)
+            let mut notify = None;
+            let mut original_recipient = None;
+
+            for arg in args {
+                if let Some(raw) = strip_prefix_ignore_case(arg, "NOTIFY=") {
+                    if notify.is_none() {
+                        notify = Some(<NotifyOn as std::str::FromStr>::from_str(raw)?);
+                    } else {
+                        return Err(CodeID::SyntaxErrorParams);
+                    }
+                } else if let Some(raw) = strip_prefix_ignore_case(arg, "ORCPT=") {
+                    if original_recipient.is_none() {
+                        original_recipient =
+                            Some(<OriginalRecipient as std::str::FromStr>::from_str(raw)?);
+                    } else {
+                        return Err(CodeID::SyntaxErrorParams);
+                    }
+                } else {
+                    return Err(CodeID::ParameterUnimplemented);
+                }
             }
+
+            Ok(Event::RcptCmd(
+                Address::try_from(path).map_err(|_| CodeID::SyntaxErrorParams)?,
+                notify,
+                original_recipient,
+            ))
         }
 
         match args {
@@ -328,6 +429,24 @@ impl Event {
         }
     }
 
+    fn parse_arg_bdat(args: &[&str]) -> Result<Self, CodeID> {
+        let (size, last) = match args {
+            [size] => (size, false),
+            [size, end_marker] if end_marker.eq_ignore_ascii_case("LAST") => (size, true),
+            _ => return Err(CodeID::SyntaxErrorParams),
+        };
+
+        // chunk-size ::= 1*DIGIT
+        if size.is_empty() || !size.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(CodeID::SyntaxErrorParams);
+        }
+
+        Ok(Self::BdatCmd(
+            size.parse().map_err(|_| CodeID::SyntaxErrorParams)?,
+            last,
+        ))
+    }
+
     fn parse_arg_auth(mechanism: &str, initial_response: Option<&str>) -> Result<Self, CodeID> {
         Ok(Self::Auth(
             Mechanism::try_from(mechanism).map_err(|_| CodeID::AuthMechNotSupported)?,
@@ -353,3 +472,11 @@ impl Event {
         }
     }
 }
+
+/// esmtp keywords are case-insensitive, but their values may not be.
+fn strip_prefix_ignore_case<'a>(input: &'a str, prefix: &str) -> Option<&'a str> {
+    match input.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => Some(&input[prefix.len()..]),
+        _ => None,
+    }
+}
@@ -82,6 +82,9 @@ pub type ReplyOrCodeID = Either<CodeID, Reply>;
 /// envelop of a transaction
 pub mod envelop;
 
+/// delivery status notification parameters
+pub mod dsn;
+
 /// parsed command of the client
 pub mod event;
 
@@ -111,11 +114,15 @@ pub mod utils;
 
 /// Data related to ESMTP Authentication
 pub mod auth {
+    mod certificate;
     mod credentials;
     mod mechanism;
+    mod scram;
 
+    pub use certificate::ClientCertificate;
     pub use credentials::Credentials;
     pub use mechanism::Mechanism;
+    pub use scram::ScramSecrets;
 }
 
 mod r#trait {
@@ -126,6 +133,8 @@ pub use r#trait::mail_parser::{MailParser, MailParserOnFly, ParserOutcome};
 
 #[cfg(test)]
 mod tests {
+    mod dsn;
+
     mod event;
 
     mod libc_abstraction;
@@ -143,6 +152,7 @@ pub mod re {
     pub use strum;
     pub use tokio;
     pub use vsmtp_rsasl;
+    pub use x509_parser;
 }
 
 #[doc(hidden)]
@@ -239,3 +239,98 @@ pub fn getpwuid(uid: libc::uid_t) -> anyhow::Result<std::path::PathBuf> {
         .to_str()?
         .into())
 }
+
+/// Send a signal to a process
+///
+/// # Errors
+///
+/// see kill(2) ERRORS
+pub fn kill(pid: libc::pid_t, signal: libc::c_int) -> anyhow::Result<()> {
+    #[allow(unsafe_code)]
+    match unsafe { libc::kill(pid, signal) } {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "kill: '{}'",
+            std::io::Error::last_os_error()
+        )),
+    }
+}
+
+/// Create an inotify instance, the events are read from the returned file
+///
+/// # Errors
+///
+/// see `inotify_init1(2)` ERRORS
+pub fn inotify_init() -> anyhow::Result<std::fs::File> {
+    #[allow(unsafe_code)]
+    match unsafe { libc::inotify_init1(libc::IN_CLOEXEC) } {
+        -1 => Err(anyhow::anyhow!(
+            "inotify_init1: '{}'",
+            std::io::Error::last_os_error()
+        )),
+        #[allow(unsafe_code)]
+        // the descriptor has just been created, the file is its only owner.
+        fd => Ok(unsafe { std::os::unix::io::FromRawFd::from_raw_fd(fd) }),
+    }
+}
+
+/// Watch the events of `@mask` on `@path`, returns the watch descriptor
+///
+/// # Errors
+///
+/// * `@path` cannot be convert to `CString`
+/// * see `inotify_add_watch(2)` ERRORS
+pub fn inotify_add_watch(
+    inotify: &std::fs::File,
+    path: &std::path::Path,
+    mask: u32,
+) -> anyhow::Result<i32> {
+    let path = std::ffi::CString::new(path.to_string_lossy().as_bytes())?;
+    #[allow(unsafe_code)]
+    match unsafe {
+        libc::inotify_add_watch(
+            std::os::unix::io::AsRawFd::as_raw_fd(inotify),
+            path.as_ptr(),
+            mask,
+        )
+    } {
+        -1 => Err(anyhow::anyhow!(
+            "inotify_add_watch: '{}'",
+            std::io::Error::last_os_error()
+        )),
+        watch => Ok(watch),
+    }
+}
+
+/// Stop watching the events of `@watch`, an `IN_IGNORED` event is generated
+///
+/// # Errors
+///
+/// see `inotify_rm_watch(2)` ERRORS
+pub fn inotify_rm_watch(inotify: &std::fs::File, watch: i32) -> anyhow::Result<()> {
+    #[allow(unsafe_code)]
+    match unsafe { libc::inotify_rm_watch(std::os::unix::io::AsRawFd::as_raw_fd(inotify), watch) } {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "inotify_rm_watch: '{}'",
+            std::io::Error::last_os_error()
+        )),
+    }
+}
+
+/// Acquire an exclusive lock on `@file`, waiting for the current holder to release it.
+/// The lock is released when the file is closed
+///
+/// # Errors
+///
+/// see `flock(2)` ERRORS
+pub fn flock_exclusive(file: &std::fs::File) -> anyhow::Result<()> {
+    #[allow(unsafe_code)]
+    match unsafe { libc::flock(std::os::unix::io::AsRawFd::as_raw_fd(file), libc::LOCK_EX) } {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "flock: '{}'",
+            std::io::Error::last_os_error()
+        )),
+    }
+}
@@ -16,7 +16,11 @@ use anyhow::Context;
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::{auth::Credentials, envelop::Envelop, status::Status};
+use crate::{
+    auth::{ClientCertificate, Credentials},
+    envelop::Envelop,
+    status::Status,
+};
 
 /// average size of a mail
 pub const MAIL_CAPACITY: usize = 10_000_000; // 10MB
@@ -50,6 +54,12 @@ pub struct ConnectionContext {
     pub timestamp: std::time::SystemTime,
     /// credentials of the client.
     pub credentials: Option<Credentials>,
+    /// argument of the last VRFY or EXPN command sent by the client.
+    #[serde(default)]
+    pub verify_query: Option<String>,
+    /// trusted certificate presented by the client during the TLS handshake.
+    #[serde(default)]
+    pub client_certificate: Option<ClientCertificate>,
     /// server's domain of the connection. (from config.server.domain or sni)
     pub server_name: String,
     /// server socket used for this connection.
@@ -119,7 +119,7 @@ impl MessageBody {
                 .write(true)
                 .truncate(true)
                 .open(&mails_eml)?;
-            std::io::Write::write_all(&mut file, self.raw.to_string().as_bytes())?;
+            std::io::Write::write_all(&mut file, &self.raw.to_bytes())?;
         }
         if let Some(parsed) = &self.parsed {
             let mails_json = mails.join(format!("{message_id}.json"));
@@ -147,11 +147,18 @@ impl MessageBody {
             format!("{id}.eml").into(),
         ]);
 
-        let content = tokio::fs::read_to_string(&message_filepath)
+        let content = tokio::fs::read(&message_filepath)
             .await
             .with_context(|| format!("Cannot read file '{}'", message_filepath.display()))?;
 
-        Self::try_from(content.as_str())
+        match String::from_utf8(content) {
+            Ok(content) => Self::try_from(content.as_str()),
+            // NOTE: message received with `BODY=BINARYMIME`
+            Err(binary) => Ok(Self {
+                raw: RawBody::from_bytes(binary.into_bytes())?,
+                parsed: None,
+            }),
+        }
     }
 
     /// get the value of an header, return None if it does not exists or when the body is empty.
@@ -217,6 +224,20 @@ impl MessageBody {
         }
     }
 
+    /// insert a header before the header field at `index`.
+    pub fn insert_header(&mut self, index: usize, name: &str, value: &str) {
+        self.raw.insert_header(index, name, value);
+        // NOTE: the parsed part is built again when needed.
+        self.parsed = None;
+    }
+
+    /// replace the value of the `occurrence`-th (starting at 1) header named `name`,
+    /// or remove it if `value` is `None`.
+    pub fn change_header(&mut self, name: &str, occurrence: usize, value: Option<&str>) {
+        self.raw.change_header(name, occurrence, value);
+        self.parsed = None;
+    }
+
     /// prepend a header to the header section.
     ///
     /// push front
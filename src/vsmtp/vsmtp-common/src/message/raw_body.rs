@@ -20,6 +20,9 @@
 pub struct RawBody {
     headers: Vec<String>,
     body: Option<String>,
+    /// body which is not valid utf8, received with `BODY=BINARYMIME`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    binary: Option<Vec<u8>>,
 }
 
 impl RawBody {
@@ -29,6 +32,7 @@ impl RawBody {
         Self {
             headers,
             body: Some(body),
+            binary: None,
         }
     }
 
@@ -38,9 +42,52 @@ impl RawBody {
         Self {
             headers,
             body: None,
+            binary: None,
         }
     }
 
+    /// Create an instance from the octets of a message, without dot-stuffing.
+    ///
+    /// The body is kept as is if it is not valid utf8 (see `BODY=BINARYMIME`).
+    ///
+    /// # Errors
+    ///
+    /// * the header section is not valid utf8
+    pub fn from_bytes(mut bytes: Vec<u8>) -> anyhow::Result<Self> {
+        let body = if bytes.starts_with(b"\r\n") {
+            let body = bytes.split_off(2);
+            bytes.clear();
+            body
+        } else if let Some(i) = bytes.windows(4).position(|w| w == b"\r\n\r\n") {
+            let body = bytes.split_off(i + 4);
+            bytes.truncate(i);
+            body
+        } else {
+            if bytes.ends_with(b"\r\n") {
+                bytes.truncate(bytes.len() - 2);
+            }
+            vec![]
+        };
+
+        let headers = if bytes.is_empty() {
+            vec![]
+        } else {
+            String::from_utf8(bytes)?
+                .split("\r\n")
+                .map(str::to_string)
+                .collect()
+        };
+
+        Ok(match String::from_utf8(body) {
+            Ok(body) => Self::new(headers, body),
+            Err(binary) => Self {
+                headers,
+                body: None,
+                binary: Some(binary.into_bytes()),
+            },
+        })
+    }
+
     /// Return an iterator over the headers field
     pub fn headers_lines(&self) -> impl Iterator<Item = &str> {
         self.headers.iter().map(String::as_str)
@@ -58,6 +105,30 @@ impl RawBody {
         &self.body
     }
 
+    /// Return the octets of the body, valid utf8 or not.
+    #[must_use]
+    pub fn body_bytes(&self) -> Option<&[u8]> {
+        self.body
+            .as_ref()
+            .map(String::as_bytes)
+            .or(self.binary.as_deref())
+    }
+
+    /// Return the octets of the whole message, without lossy conversion.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for i in &self.headers {
+            out.extend_from_slice(i.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(b"\r\n");
+        if let Some(body) = self.body_bytes() {
+            out.extend_from_slice(body);
+        }
+        out
+    }
+
     ///
     // TODO: make it lazy if possible
     #[must_use]
@@ -143,8 +214,60 @@ impl RawBody {
         // TODO: handle folding ?
         self.headers.splice(..0, headers);
     }
+
+    /// return the range of lines of each header field, folded lines included.
+    fn header_fields(&self) -> Vec<std::ops::Range<usize>> {
+        let mut fields: Vec<std::ops::Range<usize>> = vec![];
+        for (idx, header) in self.headers.iter().enumerate() {
+            match fields.last_mut() {
+                Some(last) if header.starts_with(' ') || header.starts_with('\t') => {
+                    last.end = idx + 1;
+                }
+                _ => fields.push(idx..idx + 1),
+            }
+        }
+        fields
+    }
+
+    /// insert a header before the header field at `index`,
+    /// or at the end of the header section if `index` is out of range.
+    pub fn insert_header(&mut self, index: usize, name: &str, value: &str) {
+        let position = self
+            .header_fields()
+            .get(index)
+            .map_or(self.headers.len(), |field| field.start);
+
+        self.headers.insert(position, format!("{name}: {value}"));
+    }
+
+    /// replace the value of the `occurrence`-th (starting at 1) header named `name`,
+    /// or remove it if `value` is `None`.
+    pub fn change_header(&mut self, name: &str, occurrence: usize, value: Option<&str>) {
+        let field = self
+            .header_fields()
+            .into_iter()
+            .filter(|field| {
+                self.headers[field.start]
+                    .split_once(':')
+                    .map_or(false, |(key, _)| key.eq_ignore_ascii_case(name))
+            })
+            .nth(occurrence.saturating_sub(1));
+
+        if let Some(field) = field {
+            match value {
+                Some(value) => {
+                    self.headers
+                        .splice(field, [format!("{name}: {value}")]);
+                }
+                None => {
+                    self.headers.drain(field);
+                }
+            }
+        }
+    }
 }
 
+// NOTE: a binary body is converted lossily, use [`RawBody::to_bytes`] to get the exact content.
 impl std::fmt::Display for RawBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for i in &self.headers {
@@ -154,7 +277,80 @@ impl std::fmt::Display for RawBody {
         f.write_str("\r\n")?;
         if let Some(body) = &self.body {
             f.write_str(body)?;
+        } else if let Some(binary) = &self.binary {
+            f.write_str(&String::from_utf8_lossy(binary))?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_bytes() {
+        let raw =
+            RawBody::from_bytes(b"From: a@b\r\nTo: b@c\r\n\r\nhello\r\n.\r\n".to_vec()).unwrap();
+
+        assert_eq!(
+            raw.headers_lines().collect::<Vec<_>>(),
+            ["From: a@b", "To: b@c"]
+        );
+        assert_eq!(raw.body().as_deref(), Some("hello\r\n.\r\n"));
+        assert_eq!(
+            raw.to_bytes(),
+            b"From: a@b\r\nTo: b@c\r\n\r\nhello\r\n.\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn from_bytes_binary() {
+        let message = b"Content-Type: application/octet-stream\r\n\r\n\x00\xff\xfe\r\n".to_vec();
+        let raw = RawBody::from_bytes(message.clone()).unwrap();
+
+        assert_eq!(raw.body(), &None);
+        assert_eq!(raw.body_bytes(), Some(&b"\x00\xff\xfe\r\n"[..]));
+        assert_eq!(raw.to_bytes(), message);
+    }
+
+    #[test]
+    fn from_bytes_no_body() {
+        let raw = RawBody::from_bytes(b"From: a@b\r\n".to_vec()).unwrap();
+        assert_eq!(raw.headers_lines().collect::<Vec<_>>(), ["From: a@b"]);
+        assert_eq!(raw.body().as_deref(), Some(""));
+
+        let raw = RawBody::from_bytes(b"\r\nbody only\r\n".to_vec()).unwrap();
+        assert_eq!(raw.headers_lines().count(), 0);
+        assert_eq!(raw.body().as_deref(), Some("body only\r\n"));
+    }
+
+    #[test]
+    fn insert_and_change_header() {
+        let mut raw = RawBody::new_empty(vec![
+            "From: a@b".to_string(),
+            "X-Spam: yes".to_string(),
+            "Subject: hello".to_string(),
+            " world".to_string(),
+            "X-Spam: no".to_string(),
+        ]);
+
+        raw.insert_header(2, "X-Milter", "inserted");
+        raw.change_header("x-spam", 2, Some("maybe"));
+        raw.change_header("Subject", 1, None);
+        raw.change_header("X-Unknown", 1, None);
+
+        assert_eq!(
+            raw.headers_lines().collect::<Vec<_>>(),
+            ["From: a@b", "X-Spam: yes", "X-Milter: inserted", "x-spam: maybe"]
+        );
+
+        raw.insert_header(42, "X-Last", "appended");
+        assert_eq!(raw.headers_lines().last(), Some("X-Last: appended"));
+    }
+
+    #[test]
+    fn from_bytes_invalid_headers() {
+        assert!(RawBody::from_bytes(b"From: \xff\r\n\r\nbody\r\n".to_vec()).is_err());
+    }
+}
@@ -15,6 +15,7 @@
  *
 */
 use crate::{
+    dsn::{NotifyOn, OriginalRecipient},
     transfer::{EmailTransferStatus, Transfer},
     Address,
 };
@@ -28,6 +29,14 @@ pub struct Rcpt {
     pub transfer_method: Transfer,
     /// delivery status of the email bound to this recipient.
     pub email_status: EmailTransferStatus,
+    /// conditions under which a delivery status notification must be sent,
+    /// using the `NOTIFY` parameter of the RCPT TO command, see <https://datatracker.ietf.org/doc/html/rfc3461>
+    #[serde(default)]
+    pub notify: Option<NotifyOn>,
+    /// original address of the recipient, using the `ORCPT` parameter
+    /// of the RCPT TO command, see <https://datatracker.ietf.org/doc/html/rfc3461>
+    #[serde(default)]
+    pub original_recipient: Option<OriginalRecipient>,
 }
 
 impl Rcpt {
@@ -41,6 +50,8 @@ impl Rcpt {
             email_status: EmailTransferStatus::Waiting {
                 timestamp: std::time::SystemTime::now(),
             },
+            notify: None,
+            original_recipient: None,
         }
     }
 
@@ -53,6 +64,8 @@ impl Rcpt {
             email_status: EmailTransferStatus::Waiting {
                 timestamp: std::time::SystemTime::now(),
             },
+            notify: None,
+            original_recipient: None,
         }
     }
 }
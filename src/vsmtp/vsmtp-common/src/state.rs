@@ -43,6 +43,10 @@ pub enum StateSMTP {
     NegotiationTLS,
     /// After receiving AUTH command
     Authenticate(Mechanism, Option<Vec<u8>>),
+    /// After receiving VRFY command
+    Vrfy,
+    /// After receiving EXPN command
+    Expn,
     /// After receiving MAIL FROM command
     #[strum(serialize = "mail")]
     MailFrom,
@@ -51,6 +55,8 @@ pub enum StateSMTP {
     RcptTo,
     /// After receiving DATA command
     Data,
+    /// After receiving a BDAT command without the LAST parameter
+    Bdat,
     /// Before write on disk
     PreQ,
     /// After receiving QUIT command
@@ -0,0 +1,63 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    dsn::{decode_xtext, encode_xtext, NotifyOn},
+    CodeID,
+};
+
+#[test]
+fn xtext() {
+    assert_eq!(decode_xtext("foo"), Ok("foo".to_string()));
+    assert_eq!(
+        decode_xtext("e+3Dmc2@example.com"),
+        Ok("e=mc2@example.com".to_string())
+    );
+    assert_eq!(decode_xtext("a+2Bb+20c"), Ok("a+b c".to_string()));
+
+    assert_eq!(decode_xtext("a+2bb"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(decode_xtext("a+2"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(decode_xtext("a=b"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(decode_xtext("a b"), Err(CodeID::SyntaxErrorParams));
+
+    assert_eq!(encode_xtext("e=mc2@example.com"), "e+3Dmc2@example.com");
+    assert_eq!(encode_xtext("a+b c"), "a+2Bb+20c");
+    assert_eq!(
+        decode_xtext(&encode_xtext("ünïcode+=")),
+        Ok("ünïcode+=".to_string())
+    );
+}
+
+#[test]
+fn notify() {
+    let default = NotifyOn::default();
+    assert!(default.on_failure());
+    assert!(!default.on_success());
+    assert!(!default.on_delay());
+
+    assert!(!NotifyOn::Never.on_failure());
+    assert!(!NotifyOn::Never.on_success());
+    assert!(!NotifyOn::Never.on_delay());
+
+    let all = "success,Failure,DELAY".parse::<NotifyOn>().unwrap();
+    assert!(all.on_failure() && all.on_success() && all.on_delay());
+
+    assert_eq!("".parse::<NotifyOn>(), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(
+        "NEVER,DELAY".parse::<NotifyOn>(),
+        Err(CodeID::SyntaxErrorParams)
+    );
+}
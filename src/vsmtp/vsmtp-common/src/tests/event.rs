@@ -16,6 +16,7 @@
 */
 use crate::{
     auth::Mechanism,
+    dsn::{DsnReturn, NotifyOn, OriginalRecipient},
     event::{Event, MimeBodyType},
     CodeID,
 };
@@ -164,7 +165,11 @@ fn command_mail_from() {
         Ok(Event::MailCmd(
             Some(addr!("valid@reverse.path.com")),
             None,
-            None
+            None,
+            None,
+            None,
+            None,
+            false
         ))
     );
     assert_eq!(
@@ -172,12 +177,16 @@ fn command_mail_from() {
         Ok(Event::MailCmd(
             Some(addr!("valid2@reverse.path.com")),
             None,
-            None
+            None,
+            None,
+            None,
+            None,
+            false
         ))
     );
     assert_eq!(
         Event::parse_cmd("MaIl From:   <>  "),
-        Ok(Event::MailCmd(None, None, None))
+        Ok(Event::MailCmd(None, None, None, None, None, None, false))
     );
     // assert_eq!(
     //     Event::parse_cmd("MaIl From:   <local.part@[127.0.0.1]>  "),
@@ -188,7 +197,11 @@ fn command_mail_from() {
         Ok(Event::MailCmd(
             Some(addr!("\"john..doe\"@example.org")),
             None,
-            None
+            None,
+            None,
+            None,
+            None,
+            false
         ))
     );
     assert_eq!(
@@ -210,7 +223,11 @@ fn command_mail_from_8bitmime() {
         Ok(Event::MailCmd(
             Some(addr!("ned@ymir.claremont.edu")),
             Some(MimeBodyType::EightBitMime),
-            None
+            None,
+            None,
+            None,
+            None,
+            false
         ))
     );
 
@@ -219,7 +236,11 @@ fn command_mail_from_8bitmime() {
         Ok(Event::MailCmd(
             Some(addr!("ned@ymir.claremont.edu")),
             Some(MimeBodyType::SevenBit),
-            None
+            None,
+            None,
+            None,
+            None,
+            false
         ))
     );
 
@@ -245,6 +266,26 @@ fn command_mail_from_8bitmime() {
     );
 }
 
+#[test]
+fn command_mail_from_binarymime() {
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> BODY=BINARYMIME"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            Some(MimeBodyType::Binary),
+            None,
+            None,
+            None,
+            None,
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> BODY=BINARY"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+}
+
 #[test]
 fn command_mail_from_international() {
     assert_eq!(
@@ -252,7 +293,11 @@ fn command_mail_from_international() {
         Ok(Event::MailCmd(
             Some(addr!("ned@ymir.claremont.edu")),
             None,
-            None
+            None,
+            None,
+            None,
+            None,
+            true
         ))
     );
     assert_eq!(
@@ -261,7 +306,31 @@ fn command_mail_from_international() {
     );
     assert_eq!(
         Event::parse_cmd("MAIL FROM:<用户@例子.广告> SMTPUTF8"),
-        Ok(Event::MailCmd(Some(addr!("用户@例子.广告")), None, None))
+        Ok(Event::MailCmd(
+            Some(addr!("用户@例子.广告")),
+            None,
+            None,
+            None,
+            None,
+            None,
+            true
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> smtputf8 SMTPUTF8"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<用户@例子.广告>"),
+        Ok(Event::MailCmd(
+            Some(addr!("用户@例子.广告")),
+            None,
+            None,
+            None,
+            None,
+            None,
+            false
+        ))
     );
 }
 
@@ -272,7 +341,11 @@ fn command_mail_from_auth() {
         Ok(Event::MailCmd(
             Some(addr!("e=mc2@example.com")),
             None,
-            Some("e+3Dmc2@example.com".to_string())
+            Some("e+3Dmc2@example.com".to_string()),
+            None,
+            None,
+            None,
+            false
         ))
     );
     assert_eq!(
@@ -280,7 +353,11 @@ fn command_mail_from_auth() {
         Ok(Event::MailCmd(
             Some(addr!("ned@ymir.claremont.edu")),
             None,
-            Some("<>".to_string())
+            Some("<>".to_string()),
+            None,
+            None,
+            None,
+            false
         ))
     );
     assert_eq!(
@@ -289,17 +366,98 @@ fn command_mail_from_auth() {
     );
 }
 
+#[test]
+fn command_mail_from_size() {
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> SIZE=500000"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            None,
+            None,
+            Some(500_000),
+            None,
+            None,
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> BODY=8BITMIME SIZE=0"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            Some(MimeBodyType::EightBitMime),
+            None,
+            Some(0),
+            None,
+            None,
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd(
+            "MAIL FROM:<a.very.long.local.part.for.testing@a.very.long.domain.example.com> SIZE=12345678901234567890"
+        ),
+        Ok(Event::MailCmd(
+            Some(addr!(
+                "a.very.long.local.part.for.testing@a.very.long.domain.example.com"
+            )),
+            None,
+            None,
+            Some(12_345_678_901_234_567_890), None, None, false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> size=123"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            None,
+            None,
+            Some(123),
+            None,
+            None,
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> SIZE=99999999999999999999"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            None,
+            None,
+            Some(usize::MAX),
+            None,
+            None,
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> SIZE="),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> SIZE=+10"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> SIZE=ten"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> SIZE=10 SIZE=10"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+}
+
 #[test]
 fn command_rcpt_to() {
     // TODO: RCPT TO:<@hosta.int,@jkl.org:userc@d.bar.org>
 
     assert_eq!(
         Event::parse_cmd("RcPt To:<valid@forward.path.com>"),
-        Ok(Event::RcptCmd(addr!("valid@forward.path.com")))
+        Ok(Event::RcptCmd(addr!("valid@forward.path.com"), None, None))
     );
     assert_eq!(
         Event::parse_cmd("rCpT TO: <valid2@forward.path.com>"),
-        Ok(Event::RcptCmd(addr!("valid2@forward.path.com")))
+        Ok(Event::RcptCmd(addr!("valid2@forward.path.com"), None, None))
     );
     assert_eq!(
         Event::parse_cmd("RCPT TO:   <>  "),
@@ -311,7 +469,11 @@ fn command_rcpt_to() {
     // );
     assert_eq!(
         Event::parse_cmd("rcpt to:   <\"john..doe\"@example.org>  "),
-        Ok(Event::RcptCmd(addr!("\"john..doe\"@example.org")))
+        Ok(Event::RcptCmd(
+            addr!("\"john..doe\"@example.org"),
+            None,
+            None
+        ))
     );
     assert_eq!(
         Event::parse_cmd("RCPT TO:   <ibm@com>  extra_arg "),
@@ -333,7 +495,94 @@ fn command_rcpt_to() {
 fn command_rcpt_to_international() {
     assert_eq!(
         Event::parse_cmd("RCPT TO:<用户@例子.广告>"),
-        Ok(Event::RcptCmd(addr!("用户@例子.广告")))
+        Ok(Event::RcptCmd(addr!("用户@例子.广告"), None, None))
+    );
+}
+
+#[test]
+fn command_mail_from_dsn() {
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> RET=HDRS ENVID=QQ314159"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            None,
+            None,
+            None,
+            Some(DsnReturn::Headers),
+            Some("QQ314159".to_string()),
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> ret=full envid=a+2Bb"),
+        Ok(Event::MailCmd(
+            Some(addr!("ned@ymir.claremont.edu")),
+            None,
+            None,
+            None,
+            Some(DsnReturn::Full),
+            Some("a+b".to_string()),
+            false
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> RET=BODY"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> RET=FULL RET=HDRS"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> ENVID=a+2"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("MAIL FROM:<ned@ymir.claremont.edu> ENVID="),
+        Err(CodeID::SyntaxErrorParams)
+    );
+}
+
+#[test]
+fn command_rcpt_to_dsn() {
+    assert_eq!(
+        Event::parse_cmd("RCPT TO:<bob@example.com> NOTIFY=SUCCESS,DELAY"),
+        Ok(Event::RcptCmd(
+            addr!("bob@example.com"),
+            Some(NotifyOn::Some {
+                success: true,
+                failure: false,
+                delay: true
+            }),
+            None
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("RCPT TO:<bob@example.com> notify=never ORCPT=rfc822;Bob+40example.com"),
+        Ok(Event::RcptCmd(
+            addr!("bob@example.com"),
+            Some(NotifyOn::Never),
+            Some(OriginalRecipient {
+                addr_type: "rfc822".to_string(),
+                mailbox: "Bob@example.com".to_string()
+            })
+        ))
+    );
+    assert_eq!(
+        Event::parse_cmd("RCPT TO:<bob@example.com> NOTIFY=NEVER,SUCCESS"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("RCPT TO:<bob@example.com> NOTIFY=FAILURE,FAILURE"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("RCPT TO:<bob@example.com> ORCPT=bob@example.com"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("RCPT TO:<bob@example.com> NOTIFY=FAILURE NOTIFY=DELAY"),
+        Err(CodeID::SyntaxErrorParams)
     );
 }
 
@@ -347,6 +596,28 @@ fn command_data() {
     );
 }
 
+#[test]
+fn command_bdat() {
+    assert_eq!(Event::parse_cmd("BDAT 86"), Ok(Event::BdatCmd(86, false)));
+    assert_eq!(Event::parse_cmd("bdat 0 LAST"), Ok(Event::BdatCmd(0, true)));
+    assert_eq!(
+        Event::parse_cmd("BDAT 1000 last"),
+        Ok(Event::BdatCmd(1000, true))
+    );
+    assert_eq!(Event::parse_cmd("BDAT"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(Event::parse_cmd("BDAT -1"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(Event::parse_cmd("BDAT +1"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(Event::parse_cmd("BDAT ten"), Err(CodeID::SyntaxErrorParams));
+    assert_eq!(
+        Event::parse_cmd("BDAT 10 FIRST"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+    assert_eq!(
+        Event::parse_cmd("BDAT 10 LAST foo"),
+        Err(CodeID::SyntaxErrorParams)
+    );
+}
+
 #[test]
 fn command_quit() {
     assert_eq!(Event::parse_cmd("QuIt"), Ok(Event::QuitCmd));
@@ -15,7 +15,8 @@
  *
 */
 use crate::libc_abstraction::{
-    chown, fork, if_indextoname, if_nametoindex, setgid, setsid, setuid, ForkResult,
+    chown, flock_exclusive, fork, if_indextoname, if_nametoindex, inotify_add_watch, inotify_init,
+    inotify_rm_watch, kill, setgid, setsid, setuid, ForkResult,
 };
 
 #[test]
@@ -92,3 +93,79 @@ fn test_chown_file() {
 
     std::fs::remove_file(file_to_create).unwrap();
 }
+
+#[test]
+fn test_kill() {
+    // signal 0 only checks the existence of the process
+    assert!(kill(i32::try_from(std::process::id()).unwrap(), 0).is_ok());
+    assert!(kill(i32::MAX, 0).is_err());
+}
+
+#[test]
+fn test_inotify() {
+    let inotify = inotify_init().unwrap();
+
+    assert!(inotify_add_watch(
+        &inotify,
+        std::path::Path::new("./no_such_file_exist"),
+        libc::IN_MODIFY
+    )
+    .is_err());
+
+    let file_to_watch = "./inotify_watched";
+    std::fs::write(file_to_watch, "").unwrap();
+
+    let watch = inotify_add_watch(
+        &inotify,
+        std::path::Path::new(file_to_watch),
+        libc::IN_MODIFY,
+    )
+    .unwrap();
+    std::fs::write(file_to_watch, "modified").unwrap();
+
+    // struct inotify_event { int wd; uint32_t mask; ... }
+    let mut event = [0; 1024];
+    assert!(std::io::Read::read(&mut &inotify, &mut event).unwrap() >= 16);
+    assert_eq!(i32::from_ne_bytes(event[0..4].try_into().unwrap()), watch);
+    assert_eq!(
+        u32::from_ne_bytes(event[4..8].try_into().unwrap()) & libc::IN_MODIFY,
+        libc::IN_MODIFY
+    );
+
+    assert!(inotify_rm_watch(&inotify, watch).is_ok());
+    assert!(inotify_rm_watch(&inotify, watch).is_err());
+
+    std::fs::remove_file(file_to_watch).unwrap();
+}
+
+#[test]
+fn test_flock_exclusive() {
+    let filepath = "./flock_exclusive";
+    let open = || {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(filepath)
+            .unwrap()
+    };
+
+    let first = open();
+    flock_exclusive(&first).unwrap();
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let waiting = std::thread::spawn(move || {
+        let second = open();
+        flock_exclusive(&second).unwrap();
+        tx.send(()).unwrap();
+    });
+
+    // the second lock is acquired only once the first file is closed.
+    assert!(rx
+        .recv_timeout(std::time::Duration::from_millis(100))
+        .is_err());
+    drop(first);
+    rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+    waiting.join().unwrap();
+
+    std::fs::remove_file(filepath).unwrap();
+}
@@ -106,6 +106,26 @@ impl Address {
         &self.full[self.at_sign + 1..]
     }
 
+    /// does the address contains only ascii characters ?
+    /// if not, the `SMTPUTF8` extension is required to transfer it, see <https://datatracker.ietf.org/doc/html/rfc6531>
+    #[must_use]
+    pub fn is_ascii(&self) -> bool {
+        self.full.is_ascii()
+    }
+
+    /// convert the domain of the address to its ascii form (A-labels), the local part is kept as is.
+    /// see <https://datatracker.ietf.org/doc/html/rfc5890>
+    ///
+    /// # Errors
+    ///
+    /// * the domain is not a valid internationalized domain name
+    pub fn with_ascii_domain(&self) -> anyhow::Result<Self> {
+        let domain = idna::domain_to_ascii(self.domain())
+            .map_err(|e| anyhow::anyhow!("'{}' is not a valid domain: {:?}", self.domain(), e))?;
+
+        Self::try_from(format!("{}@{domain}", self.local_part()))
+    }
+
     /// create a new address without verifying the syntax.
     ///
     /// # Panics
@@ -150,4 +170,23 @@ mod tests {
             r#""hello@domain.com""#
         );
     }
+
+    #[test]
+    fn international() {
+        let address = Address::try_from("用户@例子.广告").unwrap();
+        assert!(!address.is_ascii());
+        assert_eq!(address.local_part(), "用户");
+        assert_eq!(address.domain(), "例子.广告");
+
+        let downgraded = address.with_ascii_domain().unwrap();
+        assert_eq!(downgraded.full(), "用户@xn--fsqu00a.xn--4rr70v");
+        assert!(!downgraded.is_ascii());
+
+        let address = Address::try_from("john@bücher.example").unwrap();
+        let downgraded = address.with_ascii_domain().unwrap();
+        assert_eq!(downgraded.full(), "john@xn--bcher-kva.example");
+        assert!(downgraded.is_ascii());
+
+        assert!(Address::try_from("hello@domain.com").unwrap().is_ascii());
+    }
 }
@@ -116,4 +116,30 @@ pub enum CodeID {
     Timeout,
     ///
     TooManyRecipients,
+    //
+    // Size extension
+    //
+    /// The size of the message (declared with `SIZE` or received after `DATA`)
+    /// exceeds `server.smtp.message_size_max`
+    MessageSizeExceeded,
+    //
+    // Chunking extension
+    //
+    /// The header section of the message received with `BDAT` is not valid utf8
+    InvalidMessageContent,
+    //
+    // SMTPUTF8 extension
+    //
+    /// A non-ascii address is used without the `SMTPUTF8` parameter
+    Utf8AddressNotPermitted,
+    //
+    // VRFY / EXPN
+    //
+    /// The `vrfy` or `expn` stage did not answer the command, see RFC 5321 section 3.5.3
+    CannotVerifyUser,
+    //
+    // Greylisting
+    //
+    /// The recipient is greylisted, the client must retry later (RFC 6647)
+    Greylisted,
 }
@@ -17,9 +17,9 @@
 use super::{wants::WantsValidate, with::Builder};
 use crate::{
     config::field::{
-        FieldApp, FieldAppLogs, FieldAppVSL, FieldServer, FieldServerInterfaces, FieldServerLogs,
-        FieldServerQueues, FieldServerSMTP, FieldServerSMTPError, FieldServerSMTPTimeoutClient,
-        FieldServerSystem, FieldServerSystemThreadPool,
+        FieldApp, FieldAppLogs, FieldAppVSL, FieldGreylist, FieldServer, FieldServerInterfaces,
+        FieldServerLogs, FieldServerQueues, FieldServerSMTP, FieldServerSMTPError,
+        FieldServerSMTPTimeoutClient, FieldServerSystem, FieldServerSystemThreadPool,
     },
     Config,
 };
@@ -82,6 +82,7 @@ impl Builder<WantsValidate> {
                 tls: srv_tls.tls,
                 smtp: FieldServerSMTP {
                     rcpt_count_max: smtp_opt.rcpt_count_max,
+                    message_size_max: smtp_opt.message_size_max,
                     disable_ehlo: smtp_opt.disable_ehlo,
                     required_extension: smtp_opt.required_extension,
                     error: FieldServerSMTPError {
@@ -102,6 +103,9 @@ impl Builder<WantsValidate> {
                 dns: dns.config,
                 r#virtual: virtual_entries.r#virtual,
                 dkim: None,
+                dmarc: None,
+                tls_rpt: None,
+                greylist: FieldGreylist::default(),
             },
             app: FieldApp {
                 dirpath: app.dirpath,
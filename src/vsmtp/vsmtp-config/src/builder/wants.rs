@@ -84,6 +84,7 @@ pub struct WantsServerSMTPConfig1 {
 pub struct WantsServerSMTPConfig2 {
     pub(crate) parent: WantsServerSMTPConfig1,
     pub(super) rcpt_count_max: usize,
+    pub(super) message_size_max: usize,
     pub(super) disable_ehlo: bool,
     pub(super) required_extension: Vec<String>,
 }
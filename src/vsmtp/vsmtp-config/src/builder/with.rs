@@ -355,6 +355,8 @@ impl Builder<WantsServerTLSConfig> {
                         path: private_key.into(),
                     },
                     cipher_suite: FieldServerTls::default_cipher_suite(),
+                    client_ca: None,
+                    sender_security_level: FieldServerTls::default_sender_security_level(),
                 }),
             },
         })
@@ -385,11 +387,25 @@ impl Builder<WantsServerSMTPConfig1> {
     pub fn with_rcpt_count_and_default(
         self,
         rcpt_count_max: usize,
+    ) -> Builder<WantsServerSMTPConfig2> {
+        self.with_rcpt_count_and_message_size_max(
+            rcpt_count_max,
+            FieldServerSMTP::default_message_size_max(),
+        )
+    }
+
+    ///
+    #[must_use]
+    pub fn with_rcpt_count_and_message_size_max(
+        self,
+        rcpt_count_max: usize,
+        message_size_max: usize,
     ) -> Builder<WantsServerSMTPConfig2> {
         Builder::<WantsServerSMTPConfig2> {
             state: WantsServerSMTPConfig2 {
                 parent: self.state,
                 rcpt_count_max,
+                message_size_max,
                 disable_ehlo: FieldServerSMTP::default_disable_ehlo(),
                 required_extension: FieldServerSMTP::default_required_extension(),
             },
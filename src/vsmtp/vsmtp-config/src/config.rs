@@ -93,6 +93,13 @@ pub mod field {
         /// see [`FieldDkim`]
         // TODO: should not be an Option<> and should be under #[cfg(feature = "dkim")]
         pub dkim: Option<FieldDkim>,
+        /// see [`FieldDmarc`]
+        pub dmarc: Option<FieldDmarc>,
+        /// see [`FieldTlsRpt`]
+        pub tls_rpt: Option<FieldTlsRpt>,
+        /// see [`FieldGreylist`]
+        #[serde(default)]
+        pub greylist: FieldGreylist,
         /// see [`FieldServerVirtual`]
         #[serde(default)]
         pub r#virtual: std::collections::BTreeMap<String, FieldServerVirtual>,
@@ -102,8 +109,72 @@ pub mod field {
     #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
     #[serde(deny_unknown_fields)]
     pub struct FieldDkim {
-        /// The private key used to sign the mail.
-        pub private_key: SecretFile<rsa::RsaPrivateKey>,
+        /// The private key used to sign the mail, RSA or Ed25519.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub private_key: Option<SecretFile<vsmtp_dkim::PrivateKey>>,
+        /// An optional second key, to sign the mail twice (usually an Ed25519 key
+        /// alongside a RSA one, as advised by RFC 8463).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub dual_private_key: Option<SecretFile<vsmtp_dkim::PrivateKey>>,
+        /// A directory containing a keyset, to rotate the keys (see [`crate::DkimKeySet`]),
+        /// used instead of `private_key`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub keyset: Option<SecretFile<crate::LoadedDkimKeySet>>,
+    }
+
+    /// Readonly configuration for the dmarc aggregate reports.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldDmarc {
+        /// The results of the evaluations are sent to the domains requesting it with a clock with this period.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldDmarc::default_report_interval")]
+        pub report_interval: std::time::Duration,
+    }
+
+    /// Readonly configuration for the SMTP TLS reports (RFC 8460).
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldTlsRpt {
+        /// The results of the outgoing sessions are reported with a clock with this period.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldTlsRpt::default_report_interval")]
+        pub report_interval: std::time::Duration,
+        /// Write the reports in this directory instead of sending them to the
+        /// addresses published in the `_smtp._tls` record of the domains.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub dirpath: Option<std::path::PathBuf>,
+    }
+
+    /// Readonly configuration of the greylisting, used by `greylist()` in vsl.
+    #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct FieldGreylist {
+        /// A client retrying before this delay is greylisted again.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldGreylist::default_delay")]
+        pub delay: std::time::Duration,
+        /// A client must retry within this window after the first attempt,
+        /// or it is greylisted from the start.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldGreylist::default_retry_window")]
+        pub retry_window: std::time::Duration,
+        /// The entries which passed the greylisting are forgotten if they are
+        /// not seen again during this time.
+        #[serde(with = "humantime_serde")]
+        #[serde(default = "FieldGreylist::default_lifetime")]
+        pub lifetime: std::time::Duration,
+        /// A client is not greylisted anymore once this number of triplets passed
+        /// the greylisting, `0` disables the auto-whitelisting.
+        #[serde(default = "FieldGreylist::default_auto_whitelist")]
+        pub auto_whitelist: u32,
+        /// The length of the prefix of the ipv4 networks of the clients,
+        /// the mail exchangers of a pool can retry from different addresses.
+        #[serde(default = "FieldGreylist::default_ipv4_prefix")]
+        pub ipv4_prefix: u8,
+        /// The length of the prefix of the ipv6 networks of the clients.
+        #[serde(default = "FieldGreylist::default_ipv6_prefix")]
+        pub ipv6_prefix: u8,
     }
 
     /// The field related to the privileges used by `vSMTP`.
@@ -294,6 +365,9 @@ pub mod field {
         /// Policy of security for the TLS connection.
         #[serde(default = "FieldServerVirtualTls::default_sender_security_level")]
         pub sender_security_level: TlsSecurityLevel,
+        /// Certificate authorities trusted to sign the certificate of the clients
+        /// connecting with the sni of this entry, overriding the root one.
+        pub client_ca: Option<SecretFile<Vec<rustls::Certificate>>>,
     }
 
     /// The policy of security for the TLS connection.
@@ -310,6 +384,10 @@ pub mod field {
             /// port
             port: u16,
         },
+        /// MTA-STS protocol (RFC 8461), the policy published by the recipient's domain over HTTPS
+        /// is fetched and cached. In `enforce` mode, the mail exchangers must match the policy and
+        /// the connection **MUST BE UNDER TLS** with a valid certificate.
+        MtaSts,
     }
 
     #[doc(hidden)]
@@ -353,6 +431,15 @@ pub mod field {
         pub certificate: SecretFile<rustls::Certificate>,
         /// Private key to use for the TLS connection.
         pub private_key: SecretFile<rustls::PrivateKey>,
+        /// Certificate authorities trusted to sign the certificate of the clients.
+        ///
+        /// If set, the clients are requested a certificate during the handshake (mutual TLS),
+        /// which is not mandatory but used by the `EXTERNAL` authentication mechanism.
+        pub client_ca: Option<SecretFile<Vec<rustls::Certificate>>>,
+        /// Policy of security for the TLS connection of the **OUTGOING SIDE**,
+        /// used for the senders of the root domain.
+        #[serde(default = "FieldServerTls::default_sender_security_level")]
+        pub sender_security_level: TlsSecurityLevel,
     }
 
     /// Configuration of the client's error handling.
@@ -425,6 +512,12 @@ pub mod field {
         /// Maximum number of recipients received in the envelop, extra recipient will produce an [`CodeID::TooManyRecipients`].
         #[serde(default = "FieldServerSMTP::default_rcpt_count_max")]
         pub rcpt_count_max: usize,
+        /// Maximum size in bytes of a message, advertised with the `SIZE` extension.
+        ///
+        /// A `MAIL FROM` declaring a bigger size, or a message exceeding this size after the `DATA` command,
+        /// will produce an [`CodeID::MessageSizeExceeded`].
+        #[serde(default = "FieldServerSMTP::default_message_size_max")]
+        pub message_size_max: usize,
         /// Disable the `EHLO` keywords, and thus the SMTP extension.
         ///
         /// default: `false`
@@ -16,11 +16,11 @@
 */
 
 use crate::config::field::{
-    Config, FieldApp, FieldAppLogs, FieldAppVSL, FieldQueueDelivery, FieldQueueWorking,
-    FieldServer, FieldServerDNS, FieldServerInterfaces, FieldServerLogs, FieldServerQueues,
-    FieldServerSMTP, FieldServerSMTPAuth, FieldServerSMTPError, FieldServerSMTPTimeoutClient,
-    FieldServerSystem, FieldServerSystemThreadPool, FieldServerTls, FieldServerVirtualTls,
-    ResolverOptsWrapper, TlsSecurityLevel,
+    Config, FieldApp, FieldAppLogs, FieldAppVSL, FieldDmarc, FieldGreylist, FieldQueueDelivery,
+    FieldQueueWorking, FieldServer, FieldServerDNS, FieldServerInterfaces, FieldServerLogs,
+    FieldServerQueues, FieldServerSMTP, FieldServerSMTPAuth, FieldServerSMTPError,
+    FieldServerSMTPTimeoutClient, FieldServerSystem, FieldServerSystemThreadPool, FieldServerTls,
+    FieldServerVirtualTls, FieldTlsRpt, ResolverOptsWrapper, TlsSecurityLevel,
 };
 use vsmtp_common::{auth::Mechanism, collection, re::strum, CodeID, Reply, ReplyCode};
 
@@ -49,6 +49,9 @@ impl Default for FieldServer {
             dns: FieldServerDNS::default(),
             r#virtual: std::collections::BTreeMap::default(),
             dkim: None,
+            dmarc: None,
+            tls_rpt: None,
+            greylist: FieldGreylist::default(),
         }
     }
 }
@@ -63,6 +66,57 @@ impl FieldServer {
     }
 }
 
+impl FieldDmarc {
+    pub(crate) const fn default_report_interval() -> std::time::Duration {
+        std::time::Duration::from_secs(24 * 60 * 60)
+    }
+}
+
+impl FieldTlsRpt {
+    pub(crate) const fn default_report_interval() -> std::time::Duration {
+        std::time::Duration::from_secs(24 * 60 * 60)
+    }
+}
+
+impl Default for FieldGreylist {
+    fn default() -> Self {
+        Self {
+            delay: Self::default_delay(),
+            retry_window: Self::default_retry_window(),
+            lifetime: Self::default_lifetime(),
+            auto_whitelist: Self::default_auto_whitelist(),
+            ipv4_prefix: Self::default_ipv4_prefix(),
+            ipv6_prefix: Self::default_ipv6_prefix(),
+        }
+    }
+}
+
+impl FieldGreylist {
+    pub(crate) const fn default_delay() -> std::time::Duration {
+        std::time::Duration::from_secs(5 * 60)
+    }
+
+    pub(crate) const fn default_retry_window() -> std::time::Duration {
+        std::time::Duration::from_secs(2 * 24 * 60 * 60)
+    }
+
+    pub(crate) const fn default_lifetime() -> std::time::Duration {
+        std::time::Duration::from_secs(35 * 24 * 60 * 60)
+    }
+
+    pub(crate) const fn default_auto_whitelist() -> u32 {
+        5
+    }
+
+    pub(crate) const fn default_ipv4_prefix() -> u8 {
+        24
+    }
+
+    pub(crate) const fn default_ipv6_prefix() -> u8 {
+        64
+    }
+}
+
 impl Default for FieldServerSystem {
     fn default() -> Self {
         Self {
@@ -157,6 +211,10 @@ impl FieldServerLogs {
 }
 
 impl FieldServerTls {
+    pub(crate) const fn default_sender_security_level() -> TlsSecurityLevel {
+        TlsSecurityLevel::May
+    }
+
     pub(crate) fn default_cipher_suite() -> Vec<rustls::CipherSuite> {
         vec![
             // TLS1.3 suites
@@ -274,6 +332,7 @@ impl Default for FieldServerSMTP {
     fn default() -> Self {
         Self {
             rcpt_count_max: Self::default_rcpt_count_max(),
+            message_size_max: Self::default_message_size_max(),
             disable_ehlo: Self::default_disable_ehlo(),
             required_extension: Self::default_required_extension(),
             error: FieldServerSMTPError::default(),
@@ -289,6 +348,10 @@ impl FieldServerSMTP {
         1000
     }
 
+    pub(crate) const fn default_message_size_max() -> usize {
+        20_000_000 // 20MB
+    }
+
     pub(crate) const fn default_disable_ehlo() -> bool {
         false
     }
@@ -301,6 +364,7 @@ impl FieldServerSMTP {
     }
 
     // TODO: should be const and compile time checked
+    #[allow(clippy::too_many_lines)]
     pub(crate) fn default_smtp_codes() -> std::collections::BTreeMap<CodeID, Reply> {
         let codes: std::collections::BTreeMap<CodeID, Reply> = collection! {
             CodeID::Greetings => Reply::new(
@@ -394,6 +458,21 @@ impl FieldServerSMTP {
             CodeID::TooManyRecipients => Reply::new(
                 ReplyCode::Code{ code: 452 }, "Requested action not taken: too many recipients"
             ),
+            CodeID::MessageSizeExceeded => Reply::new(
+                ReplyCode::Enhanced{ code: 552, enhanced: "5.3.4".to_string() }, "Message size exceeds fixed maximum message size"
+            ),
+            CodeID::InvalidMessageContent => Reply::new(
+                ReplyCode::Enhanced{ code: 554, enhanced: "5.6.0".to_string() }, "Message header section is not valid utf8"
+            ),
+            CodeID::Utf8AddressNotPermitted => Reply::new(
+                ReplyCode::Enhanced{ code: 553, enhanced: "5.6.7".to_string() }, "Non-ASCII addresses require the SMTPUTF8 parameter"
+            ),
+            CodeID::CannotVerifyUser => Reply::new(
+                ReplyCode::Enhanced{ code: 252, enhanced: "2.1.5".to_string() }, "Cannot VRFY user, but will accept message and attempt delivery"
+            ),
+            CodeID::Greylisted => Reply::new(
+                ReplyCode::Enhanced{ code: 451, enhanced: "4.7.1".to_string() }, "Greylisted, please try again later"
+            ),
         };
 
         assert!(
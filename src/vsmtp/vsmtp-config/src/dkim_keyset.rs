@@ -0,0 +1,320 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use vsmtp_common::re::anyhow::{self, Context};
+use vsmtp_dkim::{PrivateKey, SigningAlgorithm};
+
+/// A key of a [`DkimKeySet`], used to sign the messages between `not_before` and `not_after`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DkimKey {
+    /// The public key is published at `{selector}._domainkey.{sdid}`.
+    pub selector: String,
+    /// Path of the private key, relative to the directory of the keyset.
+    pub private_key: std::path::PathBuf,
+    /// The key is not used before this date.
+    #[serde(with = "humantime_serde")]
+    pub not_before: std::time::SystemTime,
+    /// The key is not used after this date.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub not_after: Option<std::time::SystemTime>,
+}
+
+/// The DKIM keys of a domain, listed in the `keyset.toml` file of a directory.
+///
+/// The active key is the most recent one whose validity window contains the
+/// current date. The keyset is loaded with the configuration, a new key added
+/// (see [`DkimKeySet::rotate`]) is used once the configuration is reloaded.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DkimKeySet {
+    /// The keys of the set.
+    #[serde(default, rename = "key")]
+    pub keys: Vec<DkimKey>,
+}
+
+impl DkimKeySet {
+    const MANIFEST: &'static str = "keyset.toml";
+
+    /// Read the keyset stored in `dirpath`, a missing manifest is an empty keyset.
+    ///
+    /// # Errors
+    ///
+    /// * the manifest cannot be read.
+    /// * the manifest is not a valid keyset.
+    pub fn read(dirpath: &std::path::Path) -> anyhow::Result<Self> {
+        let manifest = dirpath.join(Self::MANIFEST);
+        match std::fs::read_to_string(&manifest) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("invalid dkim keyset '{}'", manifest.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("cannot read dkim keyset '{}'", manifest.display()))
+            }
+        }
+    }
+
+    /// Write the manifest of the keyset in `dirpath`.
+    ///
+    /// # Errors
+    ///
+    /// * the manifest cannot be written.
+    pub fn write(&self, dirpath: &std::path::Path) -> anyhow::Result<()> {
+        let manifest = dirpath.join(Self::MANIFEST);
+        let tmp = dirpath.join(format!(".{}.tmp", Self::MANIFEST));
+
+        std::fs::write(&tmp, toml::to_string(self)?)
+            .with_context(|| format!("cannot write dkim keyset '{}'", tmp.display()))?;
+        // the server may read the manifest at the same time
+        std::fs::rename(&tmp, &manifest)
+            .with_context(|| format!("cannot write dkim keyset '{}'", manifest.display()))
+    }
+
+    /// Get the key to use at the date `now`.
+    #[must_use]
+    pub fn active_key(&self, now: std::time::SystemTime) -> Option<&DkimKey> {
+        self.active_index(now).map(|index| &self.keys[index])
+    }
+
+    fn active_index(&self, now: std::time::SystemTime) -> Option<usize> {
+        self.keys
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| key.not_before <= now && key.not_after.map_or(true, |end| now < end))
+            .max_by_key(|(_, key)| key.not_before)
+            .map(|(index, _)| index)
+    }
+
+    /// Read the keyset stored in `dirpath` and all its private keys.
+    ///
+    /// # Errors
+    ///
+    /// * the keyset cannot be read.
+    /// * a private key cannot be read.
+    pub fn load(dirpath: &std::path::Path) -> anyhow::Result<LoadedDkimKeySet> {
+        let keyset = Self::read(dirpath)?;
+        let private_keys = keyset
+            .keys
+            .iter()
+            .map(|key| PrivateKey::read_pem_file(dirpath.join(&key.private_key)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(LoadedDkimKeySet {
+            keyset,
+            private_keys,
+        })
+    }
+
+    /// Generate a new key named `{selector}.pem` in `dirpath`, used from `not_before`,
+    /// and add it to the keyset. The previous keys are kept in the keyset, but
+    /// are no longer used after `not_before`.
+    ///
+    /// Return the DNS TXT record to publish before `not_before`.
+    ///
+    /// # Errors
+    ///
+    /// * the selector is already in the keyset.
+    /// * the key cannot be generated or written.
+    /// * the manifest cannot be written.
+    pub fn rotate(
+        &mut self,
+        dirpath: &std::path::Path,
+        selector: &str,
+        signing_algorithm: SigningAlgorithm,
+        not_before: std::time::SystemTime,
+    ) -> anyhow::Result<String> {
+        if self.keys.iter().any(|key| key.selector == selector) {
+            anyhow::bail!("the selector '{selector}' is already in the dkim keyset");
+        }
+
+        let filename = std::path::PathBuf::from(format!("{selector}.pem"));
+        let filepath = dirpath.join(&filename);
+
+        let pem = PrivateKey::generate_pem(signing_algorithm)?;
+        {
+            use std::io::Write;
+            use std::os::unix::fs::OpenOptionsExt;
+
+            std::fs::OpenOptions::new()
+                .create_new(true)
+                .write(true)
+                .mode(0o600)
+                .open(&filepath)
+                .and_then(|mut file| file.write_all(pem.as_bytes()))
+                .with_context(|| format!("cannot write dkim key '{}'", filepath.display()))?;
+        }
+
+        let record = PrivateKey::read_pem_file(&filepath)?.dns_record()?;
+
+        // the manifest does not store the sub-seconds
+        let not_before = std::time::UNIX_EPOCH
+            + std::time::Duration::from_secs(
+                not_before
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            );
+
+        self.keys.push(DkimKey {
+            selector: selector.to_string(),
+            private_key: filename,
+            not_before,
+            not_after: None,
+        });
+        self.write(dirpath)?;
+
+        Ok(record)
+    }
+}
+
+/// A [`DkimKeySet`] with its private keys, see [`DkimKeySet::load`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedDkimKeySet {
+    keyset: DkimKeySet,
+    private_keys: Vec<PrivateKey>,
+}
+
+impl LoadedDkimKeySet {
+    /// Get the selector and the private key to use at the date `now`.
+    #[must_use]
+    pub fn active_key(&self, now: std::time::SystemTime) -> Option<(&str, &PrivateKey)> {
+        self.keyset.active_index(now).map(|index| {
+            (
+                self.keyset.keys[index].selector.as_str(),
+                &self.private_keys[index],
+            )
+        })
+    }
+
+    /// Get the private key published with `selector`.
+    #[must_use]
+    pub fn get(&self, selector: &str) -> Option<&PrivateKey> {
+        self.keyset
+            .keys
+            .iter()
+            .position(|key| key.selector == selector)
+            .map(|index| &self.private_keys[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> std::time::SystemTime {
+        humantime_serde::re::humantime::parse_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn active_key() {
+        let keyset = toml::from_str::<DkimKeySet>(
+            r#"
+[[key]]
+selector = "2022-06"
+private_key = "2022-06.pem"
+not_before = "2022-06-01T00:00:00Z"
+
+[[key]]
+selector = "2022-09"
+private_key = "2022-09.pem"
+not_before = "2022-09-01T00:00:00Z"
+not_after = "2022-12-01T00:00:00Z"
+"#,
+        )
+        .unwrap();
+
+        assert!(keyset.active_key(date("2022-01-01T00:00:00Z")).is_none());
+        assert_eq!(
+            keyset
+                .active_key(date("2022-07-01T00:00:00Z"))
+                .unwrap()
+                .selector,
+            "2022-06"
+        );
+        assert_eq!(
+            keyset
+                .active_key(date("2022-10-01T00:00:00Z"))
+                .unwrap()
+                .selector,
+            "2022-09"
+        );
+        assert_eq!(
+            keyset
+                .active_key(date("2023-01-01T00:00:00Z"))
+                .unwrap()
+                .selector,
+            "2022-06"
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let dirpath = std::path::PathBuf::from("./tmp/dkim_keyset_rotate");
+        let _ = std::fs::remove_dir_all(&dirpath);
+        std::fs::create_dir_all(&dirpath).unwrap();
+
+        let mut keyset = DkimKeySet::read(&dirpath).unwrap();
+        assert!(keyset.keys.is_empty());
+
+        let record = keyset
+            .rotate(
+                &dirpath,
+                "first",
+                SigningAlgorithm::Ed25519Sha256,
+                date("2022-06-01T00:00:00Z"),
+            )
+            .unwrap();
+        assert!(record.starts_with("v=DKIM1; k=ed25519; p="));
+
+        keyset
+            .rotate(
+                &dirpath,
+                "second",
+                SigningAlgorithm::RsaSha256,
+                date("2022-09-01T00:00:00Z"),
+            )
+            .unwrap();
+        assert!(keyset
+            .rotate(
+                &dirpath,
+                "second",
+                SigningAlgorithm::RsaSha256,
+                date("2022-09-01T00:00:00Z"),
+            )
+            .is_err());
+
+        assert_eq!(DkimKeySet::read(&dirpath).unwrap(), keyset);
+
+        let loaded = DkimKeySet::load(&dirpath).unwrap();
+
+        let (selector, private_key) = loaded.active_key(date("2022-07-01T00:00:00Z")).unwrap();
+        assert_eq!(selector, "first");
+        assert_eq!(
+            private_key.signing_algorithm(),
+            SigningAlgorithm::Ed25519Sha256
+        );
+
+        let (selector, private_key) = loaded.active_key(date("2022-10-01T00:00:00Z")).unwrap();
+        assert_eq!(selector, "second");
+        assert_eq!(private_key.signing_algorithm(), SigningAlgorithm::RsaSha256);
+        assert_eq!(loaded.get("second"), Some(private_key));
+        assert!(loaded.get("third").is_none());
+    }
+}
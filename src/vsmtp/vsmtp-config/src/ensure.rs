@@ -31,6 +31,46 @@ fn mech_list_to_code(list: &[Mechanism]) -> String {
     )
 }
 
+/// a dkim configuration signs either with its private keys, or with its keyset.
+fn ensure_dkim(config: &Config) -> anyhow::Result<()> {
+    for (domain, dkim) in std::iter::once((&config.server.domain, config.server.dkim.as_ref()))
+        .chain(
+            config
+                .server
+                .r#virtual
+                .iter()
+                .map(|(domain, entry)| (domain, entry.dkim.as_ref())),
+        )
+        .filter_map(|(domain, dkim)| dkim.map(|dkim| (domain, dkim)))
+    {
+        anyhow::ensure!(
+            dkim.private_key.is_some() != dkim.keyset.is_some(),
+            "the dkim configuration of '{domain}' must set either `private_key` or `keyset`"
+        );
+        anyhow::ensure!(
+            dkim.dual_private_key.is_none() || dkim.private_key.is_some(),
+            "the dkim configuration of '{domain}' sets `dual_private_key` without `private_key`"
+        );
+    }
+
+    Ok(())
+}
+
+fn ensure_greylist(config: &Config) -> anyhow::Result<()> {
+    let greylist = &config.server.greylist;
+
+    anyhow::ensure!(
+        greylist.ipv4_prefix <= 32 && greylist.ipv6_prefix <= 128,
+        "the greylisting prefixes must be valid network prefixes (ipv4 <= 32, ipv6 <= 128)"
+    );
+    anyhow::ensure!(
+        greylist.delay < greylist.retry_window,
+        "the greylisting delay must be shorter than the retry window"
+    );
+
+    Ok(())
+}
+
 impl Config {
     pub(crate) fn ensure(mut config: Self) -> anyhow::Result<Self> {
         anyhow::ensure!(
@@ -46,6 +86,9 @@ impl Config {
             "Worker threads cannot be set to 0"
         );
 
+        ensure_greylist(&config)?;
+        ensure_dkim(&config)?;
+
         {
             let auth_mechanism_list: Option<(Vec<Mechanism>, Vec<Mechanism>)> = config
                 .server
@@ -54,6 +97,8 @@ impl Config {
                 .as_ref()
                 .map(|auth| auth.mechanisms.iter().partition(|m| m.must_be_under_tls()));
 
+            let size = format!("SIZE {}\r\n", config.server.smtp.message_size_max);
+
             config.server.smtp.codes.insert(
                 CodeID::EhloPain,
                 Reply::new(
@@ -71,7 +116,13 @@ impl Config {
                                     .as_ref()
                                     .map_or(false, |auth| auth.enable_dangerous_mechanism_in_clair)
                                 {
-                                    mech_list_to_code(&[secured.clone(), plain.clone()].concat())
+                                    // NOTE: channel binding and client certificates cannot be used without tls.
+                                    let plain = plain
+                                        .iter()
+                                        .filter(|m| !m.requires_tls_session())
+                                        .copied()
+                                        .collect::<Vec<_>>();
+                                    mech_list_to_code(&[secured.clone(), plain].concat())
                                 } else {
                                     mech_list_to_code(secured)
                                 }
@@ -79,6 +130,11 @@ impl Config {
                             .unwrap_or_default(),
                         "STARTTLS\r\n",
                         "8BITMIME\r\n",
+                        &size,
+                        "PIPELINING\r\n",
+                        "CHUNKING\r\n",
+                        "BINARYMIME\r\n",
+                        "DSN\r\n",
                         "SMTPUTF8\r\n",
                     ]
                     .concat(),
@@ -94,9 +150,18 @@ impl Config {
                         "\r\n",
                         &auth_mechanism_list
                             .as_ref()
-                            .map(|(must_be_secured, _)| mech_list_to_code(must_be_secured))
+                            .map(|(must_be_secured, others)| {
+                                mech_list_to_code(
+                                    &[must_be_secured.clone(), others.clone()].concat(),
+                                )
+                            })
                             .unwrap_or_default(),
                         "8BITMIME\r\n",
+                        &size,
+                        "PIPELINING\r\n",
+                        "CHUNKING\r\n",
+                        "BINARYMIME\r\n",
+                        "DSN\r\n",
                         "SMTPUTF8\r\n",
                     ]
                     .concat(),
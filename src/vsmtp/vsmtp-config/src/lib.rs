@@ -80,16 +80,18 @@ pub mod builder {
 
 mod config;
 mod default;
+mod dkim_keyset;
 mod ensure;
 mod rustls_helper;
 mod trust_dns_helper;
 mod virtual_tls;
 
 pub use config::{field, Config};
+pub use dkim_keyset::{DkimKey, DkimKeySet, LoadedDkimKeySet};
 
 // pub use log4rs_helper::get_log4rs_config;
-pub use rustls_helper::get_rustls_config;
-pub use trust_dns_helper::{build_resolvers, Resolvers};
+pub use rustls_helper::{get_rustls_config, verify_client_certificate};
+pub use trust_dns_helper::{build_resolvers, resolver_config, Resolvers};
 
 /// Re-exported dependencies
 pub mod re {
@@ -152,6 +154,13 @@ impl Config {
             .map(Self::ensure)
             .map_err(anyhow::Error::new)?
     }
+
+    /// Path of the file containing the pid of the running server,
+    /// used to send it signals (reloading the configuration with `SIGHUP`).
+    #[must_use]
+    pub fn pid_filepath(&self) -> std::path::PathBuf {
+        self.server.queues.dirpath.join("vsmtp.pid")
+    }
 }
 
 #[doc(hidden)]
@@ -16,7 +16,8 @@
 */
 use vsmtp_common::re::anyhow;
 
-pub fn from_string(input: &str) -> anyhow::Result<rustls::Certificate> {
+/// read all the certificates of a pem file, in order.
+pub fn chain_from_string(input: &str) -> anyhow::Result<Vec<rustls::Certificate>> {
     let path = std::path::Path::new(&input);
     anyhow::ensure!(
         path.exists(),
@@ -29,9 +30,16 @@ pub fn from_string(input: &str) -> anyhow::Result<rustls::Certificate> {
         .map(rustls::Certificate)
         .collect::<Vec<_>>();
 
-    pem.first()
-        .cloned()
-        .ok_or_else(|| anyhow::anyhow!("certificate path is valid but empty: '{}'", path.display()))
+    anyhow::ensure!(
+        !pem.is_empty(),
+        "certificate path is valid but empty: '{}'",
+        path.display()
+    );
+    Ok(pem)
+}
+
+pub fn from_string(input: &str) -> anyhow::Result<rustls::Certificate> {
+    Ok(chain_from_string(input)?.remove(0))
 }
 
 // TODO: should be used only for debug build
@@ -116,4 +124,30 @@ mod tests {
     fn not_valid_path() {
         serde_json::from_str::<S>(r#"{"v": "foobar"}"#).unwrap_err();
     }
+
+    #[test]
+    fn chain() {
+        let _droppable = std::fs::DirBuilder::new().create("./tmp");
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open("./tmp/chain.crt")
+            .unwrap();
+        file.write_all(
+            [
+                get_tls_file::get_client_certificate(),
+                get_tls_file::get_client_ca(),
+            ]
+            .concat()
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let chain =
+            serde_json::from_str::<SecretFile<Vec<rustls::Certificate>>>(r#""./tmp/chain.crt""#)
+                .unwrap();
+        assert_eq!(chain.inner.len(), 2);
+    }
 }
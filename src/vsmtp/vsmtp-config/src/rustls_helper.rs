@@ -59,6 +59,75 @@ fn to_supported_cipher_suite(
         .collect::<Vec<_>>()
 }
 
+fn to_root_cert_store<'a>(
+    certificates: impl Iterator<Item = &'a rustls::Certificate>,
+) -> anyhow::Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+    for i in certificates {
+        roots
+            .add(i)
+            .map_err(|e| anyhow::anyhow!("invalid client certificate authority: {e}"))?;
+    }
+    Ok(roots)
+}
+
+/// the client certificate is requested if a certificate authority is configured,
+/// the chain is verified against all of them, and again after the handshake
+/// with [`verify_client_certificate`] when the sni is known.
+fn get_client_cert_verifier(
+    config: &FieldServerTls,
+    virtual_entries: &std::collections::BTreeMap<String, FieldServerVirtual>,
+) -> anyhow::Result<std::sync::Arc<dyn rustls::server::ClientCertVerifier>> {
+    let client_ca = config
+        .client_ca
+        .iter()
+        .chain(
+            virtual_entries
+                .values()
+                .filter_map(|entry| entry.tls.as_ref()?.client_ca.as_ref()),
+        )
+        .flat_map(|ca| ca.inner.iter())
+        .collect::<Vec<_>>();
+
+    if client_ca.is_empty() {
+        return Ok(rustls::server::NoClientAuth::new());
+    }
+
+    Ok(rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(
+        to_root_cert_store(client_ca.into_iter())?,
+    ))
+}
+
+/// Verify the certificate chain presented by a client against the certificate
+/// authorities of the virtual entry matching `sni`, or the root ones otherwise.
+///
+/// # Errors
+///
+/// * no certificate authority is configured for this sni
+/// * the chain is not signed by the certificate authorities
+#[doc(hidden)]
+pub fn verify_client_certificate(
+    config: &FieldServerTls,
+    virtual_entries: &std::collections::BTreeMap<String, FieldServerVirtual>,
+    sni: Option<&str>,
+    chain: &[rustls::Certificate],
+) -> anyhow::Result<()> {
+    let client_ca = sni
+        .and_then(|sni| virtual_entries.get(sni)?.tls.as_ref()?.client_ca.as_ref())
+        .or(config.client_ca.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("no client certificate authority configured"))?;
+
+    let (end_entity, intermediates) = chain
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("empty certificate chain"))?;
+
+    rustls::server::AllowAnyAuthenticatedClient::new(to_root_cert_store(client_ca.inner.iter())?)
+        .verify_client_cert(end_entity, intermediates, std::time::SystemTime::now())
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    Ok(())
+}
+
 #[doc(hidden)]
 pub fn get_rustls_config(
     config: &FieldServerTls,
@@ -85,7 +154,7 @@ pub fn get_rustls_config(
         .with_kx_groups(&rustls::ALL_KX_GROUPS)
         .with_protocol_versions(protocol_version)
         .map_err(|e| anyhow::anyhow!("cannot initialize tls config: '{e}'"))?
-        .with_client_cert_verifier(rustls::server::NoClientAuth::new())
+        .with_client_cert_verifier(get_client_cert_verifier(config, virtual_entries)?)
         .with_cert_resolver(std::sync::Arc::new(CertResolver {
             sni_resolver: virtual_entries.iter().fold(
                 anyhow::Ok(rustls::server::ResolvesServerCertUsingSni::new()),
@@ -16,11 +16,18 @@
 */
 mod root_example {
     mod antivirus;
+    mod dmarc;
+    mod greylist;
+    mod ldap;
     mod logging;
+    mod milter;
     mod minimal;
+    mod mtls;
+    mod oauth;
     mod secured;
     mod simple;
     mod tls;
+    mod tls_rpt;
 }
 
 mod validate;
@@ -0,0 +1,54 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{field::FieldGreylist, Config};
+
+#[test]
+fn parse() {
+    let toml = include_str!("../../../../../../examples/config/greylist.toml");
+
+    let mut config = Config::builder()
+        .with_version_str(">=1.0.0, <2.0.0")
+        .unwrap()
+        .with_hostname()
+        .with_default_system()
+        .with_ipv4_localhost()
+        .with_default_logs_settings()
+        .with_default_delivery()
+        .without_tls_support()
+        .with_default_smtp_options()
+        .with_default_smtp_error_handler()
+        .with_default_smtp_codes()
+        .without_auth()
+        .with_default_app()
+        .with_vsl("./examples/config/greylist/main.vsl")
+        .with_default_app_logs()
+        .with_system_dns()
+        .without_virtual_entries()
+        .validate()
+        .unwrap();
+
+    config.server.greylist = FieldGreylist {
+        delay: std::time::Duration::from_secs(10 * 60),
+        retry_window: std::time::Duration::from_secs(24 * 60 * 60),
+        lifetime: std::time::Duration::from_secs(30 * 24 * 60 * 60),
+        auto_whitelist: 10,
+        ipv4_prefix: 24,
+        ipv6_prefix: 64,
+    };
+
+    pretty_assertions::assert_eq!(Config::from_toml(toml).unwrap(), config);
+}
@@ -0,0 +1,56 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::Config;
+use vsmtp_common::auth::Mechanism;
+
+#[test]
+fn parse() {
+    let toml = include_str!("../../../../../../examples/config/oauth.toml");
+
+    pretty_assertions::assert_eq!(
+        Config::from_toml(toml).unwrap(),
+        Config::builder()
+            .with_version_str(">=1.0.0, <2.0.0")
+            .unwrap()
+            .with_server_name("testserver.com")
+            .with_default_system()
+            .with_ipv4_localhost()
+            .with_default_logs_settings()
+            .with_default_delivery()
+            .with_safe_tls_config(
+                "../../../examples/config/tls/certificate.crt",
+                "../../../examples/config/tls/private_key.key"
+            )
+            .unwrap()
+            .with_default_smtp_options()
+            .with_default_smtp_error_handler()
+            .with_default_smtp_codes()
+            .with_auth(
+                false,
+                false,
+                vec![Mechanism::OAuthBearer, Mechanism::XOAuth2],
+                -1
+            )
+            .with_default_app()
+            .with_vsl("./examples/config/oauth/main.vsl")
+            .with_default_app_logs()
+            .with_system_dns()
+            .without_virtual_entries()
+            .validate()
+            .unwrap()
+    );
+}
@@ -14,7 +14,10 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::Config;
+use crate::{
+    field::{FieldDkim, FieldGreylist, SecretFile, TlsSecurityLevel},
+    Config, DkimKeySet,
+};
 use vsmtp_common::{auth::Mechanism, CodeID};
 
 fn get_mechanism_from_config(config: &Config, tls: bool) -> Vec<Mechanism> {
@@ -94,3 +97,136 @@ fn auth_mechanism() {
         [Mechanism::Login, Mechanism::Plain, Mechanism::CramMd5]
     );
 }
+
+#[test]
+fn dane_with_system_dns() {
+    let mut config = Config::builder()
+        .with_current_version()
+        .with_hostname()
+        .with_default_system()
+        .with_ipv4_localhost()
+        .with_default_logs_settings()
+        .with_default_delivery()
+        .with_safe_tls_config(
+            "../../../examples/config/tls/certificate.crt",
+            "../../../examples/config/tls/private_key.key",
+        )
+        .unwrap()
+        .with_default_smtp_options()
+        .with_default_smtp_error_handler()
+        .with_default_smtp_codes()
+        .without_auth()
+        .with_default_app()
+        .with_default_vsl_settings()
+        .with_default_app_logs()
+        .with_system_dns()
+        .without_virtual_entries()
+        .validate()
+        .unwrap();
+
+    // the TLSA records are validated by the name servers, not by the resolver.
+    config.server.tls.as_mut().unwrap().sender_security_level = TlsSecurityLevel::Dane { port: 25 };
+    assert!(Config::ensure(config).is_ok());
+}
+
+#[test]
+fn greylist_delay_and_prefixes() {
+    let mut config = Config::builder()
+        .with_current_version()
+        .with_hostname()
+        .with_default_system()
+        .with_ipv4_localhost()
+        .with_default_logs_settings()
+        .with_default_delivery()
+        .without_tls_support()
+        .with_default_smtp_options()
+        .with_default_smtp_error_handler()
+        .with_default_smtp_codes()
+        .without_auth()
+        .with_default_app()
+        .with_default_vsl_settings()
+        .with_default_app_logs()
+        .with_system_dns()
+        .without_virtual_entries()
+        .validate()
+        .unwrap();
+    assert!(Config::ensure(config.clone()).is_ok());
+
+    config.server.greylist.delay = config.server.greylist.retry_window;
+    assert!(Config::ensure(config.clone()).is_err());
+
+    config.server.greylist = FieldGreylist {
+        ipv6_prefix: 129,
+        ..FieldGreylist::default()
+    };
+    assert!(Config::ensure(config).is_err());
+}
+
+#[test]
+fn dkim_private_key_or_keyset() {
+    let mut config = Config::default();
+
+    let dirpath = std::path::PathBuf::from("./tmp/dkim_private_key_or_keyset");
+    std::fs::create_dir_all(&dirpath).unwrap();
+    let private_key = dirpath.join("static.pem");
+    std::fs::write(
+        &private_key,
+        vsmtp_dkim::PrivateKey::generate_pem(vsmtp_dkim::SigningAlgorithm::Ed25519Sha256).unwrap(),
+    )
+    .unwrap();
+
+    let private_key = SecretFile {
+        inner: vsmtp_dkim::PrivateKey::read_pem_file(&private_key).unwrap(),
+        path: private_key,
+    };
+    let keyset = SecretFile {
+        inner: DkimKeySet::load(&dirpath).unwrap(),
+        path: dirpath,
+    };
+
+    for (dkim, is_valid) in [
+        (
+            FieldDkim {
+                private_key: Some(private_key.clone()),
+                dual_private_key: Some(private_key.clone()),
+                keyset: None,
+            },
+            true,
+        ),
+        (
+            FieldDkim {
+                private_key: None,
+                dual_private_key: None,
+                keyset: Some(keyset.clone()),
+            },
+            true,
+        ),
+        (
+            FieldDkim {
+                private_key: None,
+                dual_private_key: None,
+                keyset: None,
+            },
+            false,
+        ),
+        (
+            FieldDkim {
+                private_key: Some(private_key.clone()),
+                dual_private_key: None,
+                keyset: Some(keyset.clone()),
+            },
+            false,
+        ),
+        (
+            FieldDkim {
+                private_key: None,
+                dual_private_key: Some(private_key.clone()),
+                keyset: Some(keyset.clone()),
+            },
+            false,
+        ),
+    ] {
+        config.server.dkim = Some(dkim);
+        assert_eq!(Config::ensure(config.clone()).is_ok(), is_valid);
+    }
+}
@@ -18,15 +18,17 @@ use crate::{
     field::{FieldServerDNS, ResolverOptsWrapper},
     Config,
 };
-use trust_dns_resolver::{config::ResolverConfig, error::ResolveError, TokioAsyncResolver};
+use trust_dns_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    error::ResolveError,
+    TokioAsyncResolver,
+};
 
 #[doc(hidden)]
 pub type Resolvers = std::collections::HashMap<String, TokioAsyncResolver>;
 
-fn resolver_opts_from_config(
-    config: &ResolverOptsWrapper,
-) -> trust_dns_resolver::config::ResolverOpts {
-    let mut opts = trust_dns_resolver::config::ResolverOpts::default();
+fn resolver_opts_from_config(config: &ResolverOptsWrapper) -> ResolverOpts {
+    let mut opts = ResolverOpts::default();
 
     opts.timeout = config.timeout;
     opts.attempts = config.attempts;
@@ -67,18 +69,27 @@ pub fn build_resolvers(config: &Config) -> Result<Resolvers, ResolveError> {
     Ok(resolvers)
 }
 
-fn build_dns_from_config(config: &FieldServerDNS) -> Result<TokioAsyncResolver, ResolveError> {
+/// the name servers and the options of a dns configuration.
+#[doc(hidden)]
+pub fn resolver_config(
+    config: &FieldServerDNS,
+) -> Result<(ResolverConfig, ResolverOpts), ResolveError> {
     match &config {
-        FieldServerDNS::System => TokioAsyncResolver::tokio_from_system_conf(),
+        FieldServerDNS::System => Ok(trust_dns_resolver::system_conf::read_system_conf()?),
         FieldServerDNS::Google { options } => {
-            TokioAsyncResolver::tokio(ResolverConfig::google(), resolver_opts_from_config(options))
+            Ok((ResolverConfig::google(), resolver_opts_from_config(options)))
         }
-        FieldServerDNS::CloudFlare { options } => TokioAsyncResolver::tokio(
+        FieldServerDNS::CloudFlare { options } => Ok((
             ResolverConfig::cloudflare(),
             resolver_opts_from_config(options),
-        ),
+        )),
         FieldServerDNS::Custom { config, options } => {
-            TokioAsyncResolver::tokio(config.clone(), resolver_opts_from_config(options))
+            Ok((config.clone(), resolver_opts_from_config(options)))
         }
     }
 }
+
+fn build_dns_from_config(config: &FieldServerDNS) -> Result<TokioAsyncResolver, ResolveError> {
+    let (config, options) = resolver_config(config)?;
+    TokioAsyncResolver::tokio(config, options)
+}
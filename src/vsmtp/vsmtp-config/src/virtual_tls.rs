@@ -46,17 +46,40 @@ impl<'de> serde::Deserialize<'de> for SecretFile<rustls::Certificate> {
     }
 }
 
-impl<'de> serde::Deserialize<'de> for SecretFile<rsa::RsaPrivateKey> {
+impl<'de> serde::Deserialize<'de> for SecretFile<Vec<rustls::Certificate>> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         let s = <String as serde::Deserialize>::deserialize(deserializer)?;
         Ok(Self {
-            inner: <rsa::RsaPrivateKey as rsa::pkcs8::DecodePrivateKey>::read_pkcs8_pem_file(&s)
-                .or_else(|_| {
-                    <rsa::RsaPrivateKey as rsa::pkcs1::DecodeRsaPrivateKey>::read_pkcs1_pem_file(&s)
-                })
+            inner: tls_certificate::chain_from_string(&s).map_err(serde::de::Error::custom)?,
+            path: s.into(),
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SecretFile<vsmtp_dkim::PrivateKey> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(Self {
+            inner: vsmtp_dkim::PrivateKey::read_pem_file(&s).map_err(serde::de::Error::custom)?,
+            path: s.into(),
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SecretFile<crate::LoadedDkimKeySet> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Ok(Self {
+            inner: crate::DkimKeySet::load(std::path::Path::new(&s))
                 .map_err(serde::de::Error::custom)?,
             path: s.into(),
         })
@@ -82,6 +105,37 @@ impl FieldServerVirtualTls {
                 path: private_key.into(),
             },
             sender_security_level: Self::default_sender_security_level(),
+            client_ca: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::field::SecretFile;
+    use vsmtp_common::re::serde_json;
+    use vsmtp_dkim::{PrivateKey, SigningAlgorithm};
+
+    #[derive(Debug, serde::Deserialize)]
+    struct S {
+        v: SecretFile<PrivateKey>,
+    }
+
+    #[test]
+    fn dkim_ed25519_ok() {
+        let _droppable = std::fs::DirBuilder::new().create("./tmp");
+
+        std::fs::write(
+            "./tmp/dkim_ed25519_key",
+            PrivateKey::generate_pem(SigningAlgorithm::Ed25519Sha256).unwrap(),
+        )
+        .unwrap();
+
+        let s = serde_json::from_str::<S>(r#"{"v": "./tmp/dkim_ed25519_key"}"#).unwrap();
+        assert_eq!(
+            s.v.inner.signing_algorithm(),
+            SigningAlgorithm::Ed25519Sha256
+        );
+        assert_eq!(s.v.path, std::path::PathBuf::from("./tmp/dkim_ed25519_key"));
+    }
+}
@@ -0,0 +1,861 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::tls_rpt::{Failure, ResultType, TLSA_MISMATCH};
+use trust_dns_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    error::ResolveErrorKind,
+    name_server::{NameServer, TokioConnection, TokioConnectionProvider},
+    proto::{
+        op::{Edns, Message, MessageType, OpCode, Query, ResponseCode},
+        rr::{
+            rdata::{
+                tlsa::{CertUsage, Matching, Selector, TLSA},
+                MX,
+            },
+            Name, RData, RecordType,
+        },
+        xfer::{DnsHandle, DnsRequest, DnsRequestOptions, FirstAnswer},
+    },
+    TokioHandle,
+};
+use vsmtp_common::re::{
+    anyhow::{self, Context},
+    lettre::{
+        self,
+        transport::smtp::{
+            client::{AsyncSmtpConnection, TlsParameters},
+            extension::ClientId,
+        },
+    },
+    x509_parser::prelude::{FromDer, X509Certificate},
+};
+use vsmtp_config::field::FieldServerDNS;
+
+/// The DNSSEC status of an answer, see RFC 4035 section 4.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// the answer has been validated.
+    Secure,
+    /// the answer comes from an unsigned zone, or no record exists.
+    Insecure,
+}
+
+/// A stub resolver relying on the DNSSEC validation of its name servers.
+///
+/// The validated answers are flagged with the AD bit, see RFC 6840 section 5.7.
+/// The name servers prove that the unsigned zones are insecure, and answer `SERVFAIL`
+/// for the records failing the validation, see RFC 7672 section 2.1.1
+#[derive(Debug)]
+pub struct Resolver {
+    name_servers: Vec<NameServer<TokioConnection, TokioConnectionProvider>>,
+}
+
+impl Resolver {
+    /// Create a resolver querying the name servers of `config`.
+    #[must_use]
+    pub fn new(config: &ResolverConfig, mut options: ResolverOpts) -> Self {
+        // NOTE: the signatures are checked by the name servers.
+        options.validate = false;
+
+        Self {
+            name_servers: config
+                .name_servers()
+                .iter()
+                .map(|name_server| NameServer::new(name_server.clone(), options, TokioHandle))
+                .collect(),
+        }
+    }
+
+    /// Create a resolver querying the name servers of a dns configuration.
+    ///
+    /// # Errors
+    ///
+    /// * the configuration of the system could not be read
+    pub fn from_config(dns: &FieldServerDNS) -> anyhow::Result<Self> {
+        let (config, options) = vsmtp_config::resolver_config(dns)?;
+        Ok(Self::new(&config, options))
+    }
+
+    /// Query the records of `name`, the answer being empty if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// * the name servers are not reachable
+    /// * the answer failed the DNSSEC validation (`SERVFAIL`)
+    async fn lookup(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> anyhow::Result<(Security, Vec<RData>)> {
+        let mut message = Message::new();
+        message
+            .add_query(Query::query(Name::from_ascii(name)?, record_type))
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Query)
+            .set_recursion_desired(true)
+            .set_authentic_data(true);
+        let mut edns = Edns::new();
+        edns.set_max_payload(1232);
+        edns.set_dnssec_ok(true);
+        message.set_edns(edns);
+
+        // NOTE: a failure of the validation is reported over the other errors.
+        let mut error = None;
+        // the name servers over TCP are tried after the truncated answers.
+        for name_server in &self.name_servers {
+            let request = DnsRequest::new(message.clone(), DnsRequestOptions::default());
+
+            match name_server.clone().send(request).first_answer().await {
+                Ok(response) if response.truncated() => {
+                    error.get_or_insert_with(|| anyhow::anyhow!("the answer is truncated"));
+                }
+                Ok(response) => {
+                    return Ok((
+                        if response.authentic_data() {
+                            Security::Secure
+                        } else {
+                            Security::Insecure
+                        },
+                        response
+                            .answers()
+                            .iter()
+                            .filter(|record| record.record_type() == record_type)
+                            .filter_map(|record| record.data().cloned())
+                            .collect(),
+                    ))
+                }
+                Err(failure) => match failure.kind() {
+                    // NOTE: the AD bit of the negative answers is not kept by the resolver,
+                    //       the name does not use DANE in both cases.
+                    ResolveErrorKind::NoRecordsFound {
+                        response_code: ResponseCode::NXDomain | ResponseCode::NoError,
+                        ..
+                    } => return Ok((Security::Insecure, vec![])),
+                    ResolveErrorKind::NoRecordsFound {
+                        response_code: ResponseCode::ServFail,
+                        ..
+                    } => {
+                        error = Some(
+                            anyhow::Error::new(failure).context(Failure(ResultType::DnssecInvalid)),
+                        );
+                    }
+                    _ => {
+                        error.get_or_insert_with(|| anyhow::Error::new(failure));
+                    }
+                },
+            }
+        }
+
+        Err(error.unwrap_or_else(|| anyhow::anyhow!("no name server configured")))
+    }
+}
+
+/// Fetch the MX records of `domain` sorted by preference, with the security of the answer.
+///
+/// # Errors
+///
+/// * the lookup failed, see [`Resolver::lookup`]
+pub async fn lookup_mx(resolver: &Resolver, domain: &str) -> anyhow::Result<(Security, Vec<MX>)> {
+    let query = format!("{}.", domain.trim_end_matches('.'));
+    let (security, records) = resolver
+        .lookup(&query, RecordType::MX)
+        .await
+        .with_context(|| format!("failed to lookup the MX records of '{domain}'"))?;
+
+    let mut records = records
+        .into_iter()
+        .filter_map(|rdata| match rdata {
+            RData::MX(mx) => Some(mx),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    records.sort_by_key(MX::preference);
+
+    Ok((security, records))
+}
+
+/// Fetch the TLSA records of a mail exchanger, published at `_{port}._tcp.{host}`.
+///
+/// Returns `None` if the host does not use DANE, or the usable records otherwise.
+/// An empty set of usable records still requires the connection to be encrypted,
+/// without authentication of the server, see RFC 7672 section 2.2.
+///
+/// # Errors
+///
+/// * the lookup failed, the host must not be used, see RFC 7672 section 2.2
+pub async fn lookup_tlsa(
+    resolver: &Resolver,
+    host: &str,
+    port: u16,
+) -> anyhow::Result<Option<Vec<TLSA>>> {
+    let query = format!("_{port}._tcp.{}.", host.trim_end_matches('.'));
+
+    let (security, records) = resolver
+        .lookup(&query, RecordType::TLSA)
+        .await
+        .with_context(|| format!("failed to lookup the TLSA records at '{query}'"))?;
+
+    let records = records
+        .into_iter()
+        .filter_map(|rdata| match rdata {
+            RData::TLSA(tlsa) => Some(tlsa),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    // the records of an unsigned zone cannot be trusted, the host does not use DANE.
+    if security == Security::Insecure || records.is_empty() {
+        return Ok(None);
+    }
+
+    // only the DANE-TA and DANE-EE usages are used for SMTP, see RFC 7672 section 3.1.3
+    Ok(Some(
+        records
+            .into_iter()
+            .filter(|record| {
+                matches!(
+                    record.cert_usage(),
+                    CertUsage::TrustAnchor | CertUsage::DomainIssued
+                ) && matches!(record.selector(), Selector::Full | Selector::Spki)
+                    && matches!(
+                        record.matching(),
+                        Matching::Raw | Matching::Sha256 | Matching::Sha512
+                    )
+            })
+            .collect(),
+    ))
+}
+
+/// Extract the `SubjectPublicKeyInfo` of a DER encoded X.509 certificate.
+fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    X509Certificate::from_der(certificate)
+        .ok()
+        .map(|(_, certificate)| certificate.tbs_certificate.subject_pki.raw)
+}
+
+/// Does the DER encoded `certificate` match the TLSA record ?
+fn matches(record: &TLSA, certificate: &[u8]) -> bool {
+    let data = match record.selector() {
+        Selector::Full => certificate,
+        Selector::Spki => match subject_public_key_info(certificate) {
+            Some(spki) => spki,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    match record.matching() {
+        Matching::Raw => data == record.cert_data(),
+        Matching::Sha256 => {
+            ring::digest::digest(&ring::digest::SHA256, data).as_ref() == record.cert_data()
+        }
+        Matching::Sha512 => {
+            ring::digest::digest(&ring::digest::SHA512, data).as_ref() == record.cert_data()
+        }
+        _ => false,
+    }
+}
+
+/// Authenticate the certificate chain of the server with its TLSA records.
+struct DaneVerifier {
+    records: Vec<TLSA>,
+}
+
+impl rustls::client::ServerCertVerifier for DaneVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &rustls::ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        now: std::time::SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        // no usable records, the connection is only encrypted.
+        if self.records.is_empty() {
+            return Ok(rustls::client::ServerCertVerified::assertion());
+        }
+
+        // DANE-EE, the names and validity dates of the certificate are ignored,
+        // see RFC 7672 section 3.1.1
+        if self
+            .records
+            .iter()
+            .filter(|record| record.cert_usage() == CertUsage::DomainIssued)
+            .any(|record| matches(record, &end_entity.0))
+        {
+            return Ok(rustls::client::ServerCertVerified::assertion());
+        }
+
+        // DANE-TA, the trust anchor must be in the chain presented by the server,
+        // see RFC 7672 section 3.1.2
+        // NOTE: the chain is not exposed by lettre 0.10, the hosts only publishing
+        //       DANE-TA records do not match until the intermediates are available.
+        for trust_anchor in intermediates.iter().filter(|certificate| {
+            self.records
+                .iter()
+                .filter(|record| record.cert_usage() == CertUsage::TrustAnchor)
+                .any(|record| matches(record, &certificate.0))
+        }) {
+            let mut roots = rustls::RootCertStore::empty();
+            if roots.add(trust_anchor).is_ok()
+                && rustls::client::WebPkiVerifier::new(roots, None)
+                    .verify_server_cert(
+                        end_entity,
+                        intermediates,
+                        server_name,
+                        &mut std::iter::empty(),
+                        &[],
+                        now,
+                    )
+                    .is_ok()
+            {
+                return Ok(rustls::client::ServerCertVerified::assertion());
+            }
+        }
+
+        Err(rustls::Error::General(TLSA_MISMATCH.to_string()))
+    }
+}
+
+/// Send a message to a mail exchanger, authenticated with its TLSA records.
+///
+/// The connection is never downgraded to plain text.
+///
+/// # Errors
+///
+/// * the connection failed, or the server does not support STARTTLS
+/// * the certificate does not match the records
+/// * the message requires SMTPUTF8 or 8BITMIME, which the server does not support
+/// * the server rejected the transaction
+pub async fn send(
+    records: Vec<TLSA>,
+    host: &str,
+    port: u16,
+    hello_name: &str,
+    envelop: &lettre::address::Envelope,
+    content: &[u8],
+) -> anyhow::Result<()> {
+    let host = host.trim_end_matches('.');
+    let hello_name = ClientId::Domain(hello_name.to_string());
+
+    let mut connection = AsyncSmtpConnection::connect_tokio1(
+        (host, port),
+        Some(std::time::Duration::from_secs(5 * 60)),
+        &hello_name,
+        None,
+        None,
+    )
+    .await
+    .with_context(|| format!("failed to connect to '{host}:{port}'"))?;
+
+    if !connection.can_starttls() {
+        connection.abort().await;
+        return Err(
+            anyhow::Error::new(Failure(ResultType::StartTlsNotSupported)).context(format!(
+                "'{host}' publishes TLSA records but does not support STARTTLS"
+            )),
+        );
+    }
+
+    // NOTE: lettre does not accept a custom certificate verifier, the certificate is
+    //       authenticated once the handshake is done, before the transaction is started.
+    connection
+        .starttls(
+            TlsParameters::builder(host.to_string())
+                .dangerous_accept_invalid_certs(true)
+                .build_rustls()?,
+            &hello_name,
+        )
+        .await
+        .with_context(|| format!("tls handshake with '{host}' failed"))?;
+
+    let verified = rustls::client::ServerCertVerifier::verify_server_cert(
+        &DaneVerifier { records },
+        &rustls::Certificate(connection.peer_certificate()?),
+        &[],
+        &rustls::ServerName::try_from(host)?,
+        &mut std::iter::empty(),
+        &[],
+        std::time::SystemTime::now(),
+    );
+    if let Err(error) = verified {
+        connection.abort().await;
+        return Err(anyhow::Error::new(error)
+            .context(format!("the certificate of '{host}' is not authenticated")));
+    }
+
+    match connection.send(envelop, content).await {
+        Ok(_) => {
+            // the message has been accepted, the result of QUIT does not matter.
+            let _ = connection.quit().await;
+            Ok(())
+        }
+        Err(error) => {
+            connection.abort().await;
+            Err(error.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{dns_serving, Validation};
+    use trust_dns_resolver::proto::rr::Record;
+    use vsmtp_common::re::tokio;
+    use vsmtp_config::re::rustls_pemfile;
+
+    const CERTIFICATE: &str = include_str!("../../vsmtp-test/src/template/certs/certificate.crt");
+    const PRIVATE_KEY: &str =
+        include_str!("../../vsmtp-test/src/template/certs/private_key.rsa.key");
+
+    fn chain() -> Vec<rustls::Certificate> {
+        rustls_pemfile::certs(&mut CERTIFICATE.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect()
+    }
+
+    fn tlsa(usage: CertUsage, selector: Selector, matching: Matching, hex: &str) -> TLSA {
+        TLSA::new(
+            usage,
+            selector,
+            matching,
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect(),
+        )
+    }
+
+    fn verify(records: Vec<TLSA>, now: std::time::SystemTime) -> Result<(), rustls::Error> {
+        let chain = chain();
+        rustls::client::ServerCertVerifier::verify_server_cert(
+            &DaneVerifier { records },
+            &chain[0],
+            &chain[1..],
+            &rustls::ServerName::try_from("testserver.com").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            now,
+        )
+        .map(|_| ())
+    }
+
+    fn in_2020() -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000)
+    }
+
+    #[test]
+    fn spki() {
+        let chain = chain();
+        let spki = subject_public_key_info(&chain[0].0).unwrap();
+        // the public key info is the 7th element of the tbsCertificate, see RFC 5280 section 4.1
+        let (_, certificate) = X509Certificate::from_der(&chain[0].0).unwrap();
+        assert_eq!(spki, certificate.public_key().raw);
+
+        assert!(matches(
+            &tlsa(
+                CertUsage::DomainIssued,
+                Selector::Spki,
+                Matching::Raw,
+                &spki
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>()
+            ),
+            &chain[0].0
+        ));
+        assert!(subject_public_key_info(&chain[0].0[..64]).is_none());
+    }
+
+    #[test]
+    fn dane_ee() {
+        // the certificate has expired, the validity dates are ignored.
+        let now = std::time::SystemTime::now();
+
+        verify(
+            vec![tlsa(
+                CertUsage::DomainIssued,
+                Selector::Full,
+                Matching::Sha256,
+                "dbeb64fe9e0c839e2b754b152be398cb96ab767617a985a5db4b1c478038b043",
+            )],
+            now,
+        )
+        .unwrap();
+        verify(
+            vec![tlsa(
+                CertUsage::DomainIssued,
+                Selector::Spki,
+                Matching::Sha256,
+                "fae03a4995f695dcce166882b542617d3bf0a7845eb63079517917544e3ccc92",
+            )],
+            now,
+        )
+        .unwrap();
+        // no usable records, only the encryption is required.
+        verify(vec![], now).unwrap();
+
+        assert!(verify(
+            vec![tlsa(
+                CertUsage::DomainIssued,
+                Selector::Spki,
+                Matching::Sha256,
+                "dbeb64fe9e0c839e2b754b152be398cb96ab767617a985a5db4b1c478038b043",
+            )],
+            now,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn dane_ta() {
+        let intermediate = vec![
+            tlsa(
+                CertUsage::TrustAnchor,
+                Selector::Full,
+                Matching::Sha256,
+                "b5216506b9b531a856adc73b59570a44f6772df0e27d0b0d1d14f5fc4e9177ed",
+            ),
+            tlsa(
+                CertUsage::TrustAnchor,
+                Selector::Spki,
+                Matching::Sha512,
+                "cda1d709c7b380fc62adcc07fa36a586ba03780cccb6bff8252f1d583ce5223092fa08f9ff45bb209959d1b88c2b3bd68d8d9747ce6b3334e9268a1c272112ae",
+            ),
+        ];
+
+        for record in &intermediate {
+            verify(vec![record.clone()], in_2020()).unwrap();
+        }
+
+        // the chain is validated, with the dates of the certificate.
+        assert!(verify(intermediate.clone(), std::time::SystemTime::now()).is_err());
+        // the end entity is not a trust anchor.
+        assert!(verify(
+            vec![tlsa(
+                CertUsage::TrustAnchor,
+                Selector::Full,
+                Matching::Sha256,
+                "dbeb64fe9e0c839e2b754b152be398cb96ab767617a985a5db4b1c478038b043",
+            )],
+            in_2020(),
+        )
+        .is_err());
+    }
+
+    fn dane_ee_record() -> TLSA {
+        tlsa(
+            CertUsage::DomainIssued,
+            Selector::Full,
+            Matching::Sha256,
+            "dbeb64fe9e0c839e2b754b152be398cb96ab767617a985a5db4b1c478038b043",
+        )
+    }
+
+    async fn resolver_serving(validation: Validation) -> Resolver {
+        let name = |name: &str| Name::from_ascii(name).unwrap();
+        let records = vec![
+            Record::from_rdata(
+                name("_25._tcp.mx.test."),
+                3600,
+                RData::TLSA(dane_ee_record()),
+            ),
+            Record::from_rdata(
+                name("test."),
+                3600,
+                RData::MX(MX::new(20, name("mx2.test."))),
+            ),
+            Record::from_rdata(
+                name("test."),
+                3600,
+                RData::MX(MX::new(10, name("mx.test."))),
+            ),
+        ];
+
+        Resolver::new(
+            &dns_serving(records, validation).await,
+            ResolverOpts::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn lookup_secure() {
+        let resolver = resolver_serving(Validation::Secure).await;
+
+        assert_eq!(
+            lookup_tlsa(&resolver, "mx.test", 25).await.unwrap(),
+            Some(vec![dane_ee_record()])
+        );
+        // the host does not publish TLSA records.
+        assert_eq!(lookup_tlsa(&resolver, "mx2.test", 25).await.unwrap(), None);
+
+        let (security, records) = lookup_mx(&resolver, "test").await.unwrap();
+        assert_eq!(security, Security::Secure);
+        assert_eq!(
+            records
+                .iter()
+                .map(|mx| mx.exchange().to_ascii())
+                .collect::<Vec<_>>(),
+            ["mx.test.", "mx2.test."]
+        );
+    }
+
+    #[tokio::test]
+    async fn lookup_unsigned_zone() {
+        let resolver = resolver_serving(Validation::Insecure).await;
+
+        // the records of an unsigned zone are not used.
+        assert_eq!(lookup_tlsa(&resolver, "mx.test", 25).await.unwrap(), None);
+
+        let (security, records) = lookup_mx(&resolver, "test").await.unwrap();
+        assert_eq!(security, Security::Insecure);
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn lookup_bogus() {
+        let resolver = resolver_serving(Validation::Bogus).await;
+
+        let error = lookup_tlsa(&resolver, "mx.test", 25).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<Failure>(),
+            Some(&Failure(ResultType::DnssecInvalid))
+        );
+        assert!(lookup_mx(&resolver, "test").await.is_err());
+    }
+
+    /// a mail exchanger supporting STARTTLS, replying `ehlo` to the EHLO command once
+    /// encrypted and `mail` to the MAIL FROM command.
+    /// returns the commands received after the handshake, and the content of the message.
+    async fn serve(
+        listener: tokio::net::TcpListener,
+        ehlo: &'static str,
+        mail: &'static str,
+    ) -> anyhow::Result<(Vec<String>, String)> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        let (stream, _) = listener.accept().await?;
+        let mut stream = tokio::io::BufReader::new(stream);
+        for reply in ["220 localhost\r\n", "250-localhost\r\n250 STARTTLS\r\n"] {
+            stream.write_all(reply.as_bytes()).await?;
+            stream.read_line(&mut String::new()).await?;
+        }
+        stream.write_all(b"220 go ahead\r\n").await?;
+
+        let acceptor = tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(
+            rustls::ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(
+                    chain(),
+                    rustls::PrivateKey(
+                        rustls_pemfile::rsa_private_keys(&mut PRIVATE_KEY.as_bytes())?.remove(0),
+                    ),
+                )?,
+        ));
+        let mut stream = tokio::io::BufReader::new(acceptor.accept(stream.into_inner()).await?);
+
+        let mut commands = vec![];
+        let mut data = String::new();
+        loop {
+            // NOTE: the client closes the connection without a TLS close_notify.
+            let mut line = String::new();
+            if !matches!(stream.read_line(&mut line).await, Ok(1..)) {
+                break;
+            }
+            commands.push(line.trim_end().to_string());
+
+            let reply = match line.get(..4) {
+                Some("EHLO") => ehlo,
+                Some("MAIL") => mail,
+                Some("DATA") => {
+                    stream.write_all(b"354 Start mail input\r\n").await?;
+                    while !data.ends_with("\r\n.\r\n") {
+                        if stream.read_line(&mut data).await? == 0 {
+                            anyhow::bail!("connection closed");
+                        }
+                    }
+                    "250 Ok\r\n"
+                }
+                Some("QUIT") => "221 bye\r\n",
+                _ => "250 Ok\r\n",
+            };
+            stream.write_all(reply.as_bytes()).await?;
+        }
+
+        Ok((commands, data))
+    }
+
+    async fn send_to_local_server(
+        records: Vec<TLSA>,
+        ehlo: &'static str,
+        mail: &'static str,
+        to: &str,
+        content: &[u8],
+    ) -> (anyhow::Result<()>, Vec<String>, String) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(listener, ehlo, mail));
+
+        let result = send(
+            records,
+            "localhost",
+            port,
+            "client.com",
+            &lettre::address::Envelope::new(
+                Some("a@client.com".parse().unwrap()),
+                vec![to.parse().unwrap()],
+            )
+            .unwrap(),
+            content,
+        )
+        .await;
+
+        let (commands, data) = server.await.unwrap().unwrap_or_default();
+        (result, commands, data)
+    }
+
+    fn mail_from(commands: &[String]) -> Option<&str> {
+        commands
+            .iter()
+            .find(|command| command.starts_with("MAIL"))
+            .map(String::as_str)
+    }
+
+    #[tokio::test]
+    async fn send_authenticated() {
+        let (result, commands, data) = send_to_local_server(
+            vec![dane_ee_record()],
+            "250-localhost\r\n250 8BITMIME\r\n",
+            "250 Ok\r\n",
+            "b@localhost",
+            b"Subject: hello\r\n\r\n.world\r\n",
+        )
+        .await;
+
+        result.unwrap();
+        assert_eq!(mail_from(&commands), Some("MAIL FROM:<a@client.com>"));
+        // the same framing as the other lettre transports.
+        assert_eq!(data, "Subject: hello\r\n\r\n..world\r\n\r\n.\r\n");
+    }
+
+    #[tokio::test]
+    async fn send_mismatch() {
+        let (result, commands, data) = send_to_local_server(
+            vec![tlsa(
+                CertUsage::DomainIssued,
+                Selector::Full,
+                Matching::Sha256,
+                "b5216506b9b531a856adc73b59570a44f6772df0e27d0b0d1d14f5fc4e9177ed",
+            )],
+            "250-localhost\r\n250 8BITMIME\r\n",
+            "250 Ok\r\n",
+            "b@localhost",
+            b"Subject: hello\r\n\r\n.world\r\n",
+        )
+        .await;
+
+        // only the EHLO is sent before the certificate is authenticated.
+        assert_eq!(
+            crate::tls_rpt::result_type(&result.unwrap_err()),
+            Some(ResultType::TlsaInvalid)
+        );
+        assert_eq!(mail_from(&commands), None);
+        assert!(data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn send_8bitmime() {
+        let content = "Subject: café\r\n\r\nhello\r\n".as_bytes();
+
+        let (result, commands, _) = send_to_local_server(
+            vec![dane_ee_record()],
+            "250-localhost\r\n250 8BITMIME\r\n",
+            "250 Ok\r\n",
+            "b@localhost",
+            content,
+        )
+        .await;
+        result.unwrap();
+        assert_eq!(
+            mail_from(&commands),
+            Some("MAIL FROM:<a@client.com> BODY=8BITMIME")
+        );
+
+        // the message is not sent to a server without 8BITMIME.
+        let (result, commands, _) = send_to_local_server(
+            vec![dane_ee_record()],
+            "250 localhost\r\n",
+            "250 Ok\r\n",
+            "b@localhost",
+            content,
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("8BITMIME"));
+        assert_eq!(mail_from(&commands), None);
+    }
+
+    #[tokio::test]
+    async fn send_smtp_utf8() {
+        let content = b"Subject: hello\r\n\r\nworld\r\n";
+
+        let (result, commands, _) = send_to_local_server(
+            vec![dane_ee_record()],
+            "250-localhost\r\n250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "bébé@localhost",
+            content,
+        )
+        .await;
+        result.unwrap();
+        assert_eq!(
+            mail_from(&commands),
+            Some("MAIL FROM:<a@client.com> SMTPUTF8")
+        );
+
+        // the server does not support SMTPUTF8, the transaction is not started.
+        let (result, commands, _) = send_to_local_server(
+            vec![dane_ee_record()],
+            "250-localhost\r\n250 8BITMIME\r\n",
+            "250 Ok\r\n",
+            "bébé@localhost",
+            content,
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("SMTPUTF8"));
+        assert_eq!(mail_from(&commands), None);
+
+        // the server rejects the non-ascii addresses.
+        let (result, _, _) = send_to_local_server(
+            vec![dane_ee_record()],
+            "250-localhost\r\n250 SMTPUTF8\r\n",
+            "553 5.6.7 non-ascii addresses are not permitted\r\n",
+            "bébé@localhost",
+            content,
+        )
+        .await;
+        assert!(result
+            .unwrap_err()
+            .downcast_ref::<lettre::transport::smtp::Error>()
+            .unwrap()
+            .is_permanent());
+    }
+}
@@ -26,9 +26,20 @@
 #![warn(clippy::nursery)]
 #![warn(clippy::cargo)]
 
+/// SMTP MTA Strict Transport Security (MTA-STS)
+/// see <https://datatracker.ietf.org/doc/html/rfc8461>
+pub mod mta_sts;
+
+/// SMTP security via opportunistic DNS-Based Authentication of Named Entities (DANE)
+/// see <https://datatracker.ietf.org/doc/html/rfc7672>
+pub mod dane;
+
+/// SMTP TLS Reporting (TLS-RPT)
+/// see <https://datatracker.ietf.org/doc/html/rfc8460>
+pub mod tls_rpt;
+
 /// a few helpers to create systems that will deliver emails.
 pub mod transport {
-    use trust_dns_resolver::TokioAsyncResolver;
     use vsmtp_common::re::anyhow::Context;
     use vsmtp_common::re::lettre;
     use vsmtp_common::{mail_context::MessageMetadata, rcpt::Rcpt, re::anyhow, Address};
@@ -44,7 +55,7 @@ pub mod transport {
             metadata: &MessageMetadata,
             from: &Address,
             to: Vec<Rcpt>,
-            content: &str,
+            content: &[u8],
         ) -> Vec<Rcpt>;
     }
 
@@ -68,20 +79,20 @@ pub mod transport {
             _: &MessageMetadata,
             _: &Address,
             to: Vec<Rcpt>,
-            _: &str,
+            _: &[u8],
         ) -> Vec<Rcpt> {
             to
         }
     }
 
-    /// build a transport using opportunistic tls and toml specified certificates.
+    /// build a transport using opportunistic (or required) tls and toml specified certificates.
     /// TODO: resulting transport should be cached.
     fn build_transport(
         config: &Config,
-        // will be used for tlsa record resolving.
-        _: &TokioAsyncResolver,
         from: &vsmtp_common::Address,
         target: &str,
+        port: u16,
+        tls_required: bool,
     ) -> anyhow::Result<lettre::AsyncSmtpTransport<lettre::Tokio1Executor>> {
         let tls_builder =
             lettre::transport::smtp::client::TlsParameters::builder(target.to_string());
@@ -129,10 +140,12 @@ pub mod transport {
                 .hello_name(lettre::transport::smtp::extension::ClientId::Domain(
                     from.domain().to_string(),
                 ))
-                .port(lettre::transport::smtp::SMTP_PORT)
-                .tls(lettre::transport::smtp::client::Tls::Opportunistic(
-                    tls_parameters,
-                ))
+                .port(port)
+                .tls(if tls_required {
+                    lettre::transport::smtp::client::Tls::Required(tls_parameters)
+                } else {
+                    lettre::transport::smtp::client::Tls::Opportunistic(tls_parameters)
+                })
                 .build(),
         )
     }
@@ -140,7 +153,97 @@ pub mod transport {
 
 #[cfg(test)]
 pub mod test {
+    use trust_dns_resolver::{
+        config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+        proto::{
+            op::{Message, MessageType, ResponseCode},
+            rr::Record,
+            serialize::binary::{BinDecodable, BinEncodable},
+        },
+        TokioAsyncResolver,
+    };
     use vsmtp_common::mail_context::ConnectionContext;
+    use vsmtp_common::re::tokio;
+
+    /// the DNSSEC status of the answers of the dns server of the tests.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Validation {
+        /// the answers are flagged with the AD bit.
+        Secure,
+        /// the answers are not flagged, as for an unsigned zone.
+        Insecure,
+        /// every query is answered with `SERVFAIL`.
+        Bogus,
+    }
+
+    /// serve `records` with a dns server listening on localhost, and create a resolver
+    /// querying it. the names without records are answered with `NXDOMAIN`.
+    ///
+    /// # Panics
+    pub async fn resolver_serving(
+        records: Vec<Record>,
+        options: ResolverOpts,
+    ) -> TokioAsyncResolver {
+        TokioAsyncResolver::tokio(dns_serving(records, Validation::Insecure).await, options)
+            .unwrap()
+    }
+
+    /// serve `records` with a dns server listening on localhost, validating the answers
+    /// as a recursive name server would, and return the configuration to query it.
+    ///
+    /// # Panics
+    pub async fn dns_serving(records: Vec<Record>, validation: Validation) -> ResolverConfig {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let mut buffer = [0; 4096];
+            while let Ok((size, from)) = socket.recv_from(&mut buffer).await {
+                let query = match Message::from_bytes(&buffer[..size]) {
+                    Ok(query) => query,
+                    Err(_) => continue,
+                };
+
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_op_code(query.op_code())
+                    .set_recursion_desired(query.recursion_desired())
+                    .set_recursion_available(true)
+                    .set_authentic_data(validation == Validation::Secure)
+                    .add_queries(query.queries().to_vec());
+
+                for question in query.queries() {
+                    let mut known = false;
+                    for record in records
+                        .iter()
+                        .filter(|record| record.name() == question.name())
+                    {
+                        known = true;
+                        if record.record_type() == question.query_type() {
+                            response.add_answer(record.clone());
+                        }
+                    }
+                    if !known {
+                        response.set_response_code(ResponseCode::NXDomain);
+                    }
+                }
+                if validation == Validation::Bogus {
+                    response.take_answers();
+                    response.set_response_code(ResponseCode::ServFail);
+                }
+
+                let _ = socket.send_to(&response.to_bytes().unwrap(), from).await;
+            }
+        });
+
+        ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&["127.0.0.1".parse().unwrap()], port, true),
+        )
+    }
 
     /// create an empty email context for testing purposes.
     ///
@@ -151,6 +254,8 @@ pub mod test {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::now(),
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: "testserver.com".to_string(),
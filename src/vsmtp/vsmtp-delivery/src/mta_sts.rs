@@ -0,0 +1,494 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use trust_dns_resolver::TokioAsyncResolver;
+use vsmtp_common::re::{
+    anyhow::{self, Context},
+    log, serde_json, tokio,
+};
+
+/// The mode of a [`Policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The sender must not deliver to a mail exchanger failing the policy.
+    Enforce,
+    /// The failures are reported, but the message is delivered anyway.
+    Testing,
+    /// The domain does not have an active policy.
+    None,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "enforce" => Ok(Self::Enforce),
+            "testing" => Ok(Self::Testing),
+            "none" => Ok(Self::None),
+            otherwise => anyhow::bail!("invalid mode: '{otherwise}'"),
+        }
+    }
+}
+
+/// The MTA-STS policy of a domain.
+/// see <https://datatracker.ietf.org/doc/html/rfc8461#section-3.2>
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Policy {
+    /// Mode of the policy.
+    pub mode: Mode,
+    /// Patterns of the mail exchangers allowed to receive the messages of the domain.
+    pub mx: Vec<String>,
+    /// Lifetime of the policy in seconds.
+    pub max_age: u64,
+}
+
+impl std::str::FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut version = None;
+        let mut mode = None;
+        let mut mx = vec![];
+        let mut max_age = None;
+
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("invalid line: '{line}'"))?;
+
+            match (key.trim(), value.trim()) {
+                ("version", value) => version = Some(value),
+                ("mode", value) => mode = Some(value.parse::<Mode>()?),
+                ("mx", value) => mx.push(value.to_ascii_lowercase()),
+                ("max_age", value) => {
+                    max_age = Some(
+                        value
+                            .parse::<u64>()
+                            .with_context(|| format!("invalid max_age: '{value}'"))?,
+                    );
+                }
+                // unknown keys are ignored
+                _ => {}
+            }
+        }
+
+        if version != Some("STSv1") {
+            anyhow::bail!("unsupported version: '{}'", version.unwrap_or_default());
+        }
+        let mode = mode.ok_or_else(|| anyhow::anyhow!("missing mode"))?;
+        if mode != Mode::None && mx.is_empty() {
+            anyhow::bail!("missing mx");
+        }
+
+        Ok(Self {
+            mode,
+            mx,
+            max_age: max_age
+                .ok_or_else(|| anyhow::anyhow!("missing max_age"))?
+                // a policy is valid one year at most
+                .min(31_557_600),
+        })
+    }
+}
+
+impl Policy {
+    /// Does the mail exchanger `host` match one of the `mx` patterns of the policy.
+    ///
+    /// A pattern `*.example.com` matches `mail.example.com` but not `example.com`
+    /// nor `foo.mail.example.com`.
+    #[must_use]
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        self.mx.iter().any(|pattern| {
+            pattern.strip_prefix("*.").map_or_else(
+                || *pattern == host,
+                |suffix| {
+                    host.split_once('.')
+                        .map_or(false, |(label, rest)| !label.is_empty() && rest == suffix)
+                },
+            )
+        })
+    }
+}
+
+/// Get the `id` of the policy in a `_mta-sts` TXT record, `None` if the record is not valid.
+/// see <https://datatracker.ietf.org/doc/html/rfc8461#section-3.1>
+#[must_use]
+pub fn parse_txt_record(record: &str) -> Option<String> {
+    let mut fields = record.split(';').map(str::trim).filter(|f| !f.is_empty());
+
+    if fields.next() != Some("v=STSv1") {
+        return None;
+    }
+
+    fields
+        .filter_map(|field| field.split_once('='))
+        .find(|(key, _)| key.trim() == "id")
+        .map(|(_, id)| id.trim().to_string())
+        .filter(|id| {
+            !id.is_empty() && id.len() <= 32 && id.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Retrieve the body of the policy of a domain.
+#[async_trait::async_trait]
+pub trait PolicyFetcher: Send + Sync {
+    /// Fetch the policy of `domain`, published at `https://mta-sts.{domain}/.well-known/mta-sts.txt`
+    async fn fetch(&self, domain: &str) -> anyhow::Result<String>;
+}
+
+/// Fetch the policies over HTTPS, validating the certificate with the webpki roots.
+pub struct HttpsPolicyFetcher;
+
+impl HttpsPolicyFetcher {
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+    /// the policies are small, avoid reading an endless response.
+    const MAX_SIZE: usize = 64 * 1024;
+
+    async fn get(host: &str) -> anyhow::Result<Vec<u8>> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_rustls::rustls;
+
+        let mut root_store = rustls::RootCertStore::empty();
+        root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+
+        let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(root_store)
+                .with_no_client_auth(),
+        ));
+
+        let stream = tokio::net::TcpStream::connect((host, 443)).await?;
+        let mut stream = connector
+            .connect(rustls::ServerName::try_from(host)?, stream)
+            .await?;
+
+        // HTTP/1.0 avoids the chunked transfer encoding
+        stream
+            .write_all(
+                format!("GET /.well-known/mta-sts.txt HTTP/1.0\r\nHost: {host}\r\n\r\n").as_bytes(),
+            )
+            .await?;
+
+        let mut response = vec![];
+        let mut buffer = [0; 4096];
+        loop {
+            match stream.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(n) => response.extend_from_slice(&buffer[..n]),
+                // the server may close the connection without a `close_notify`
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            if response.len() > Self::MAX_SIZE {
+                anyhow::bail!("the response is larger than {} bytes", Self::MAX_SIZE);
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait::async_trait]
+impl PolicyFetcher for HttpsPolicyFetcher {
+    async fn fetch(&self, domain: &str) -> anyhow::Result<String> {
+        let host = format!("mta-sts.{domain}");
+        let response = tokio::time::timeout(Self::TIMEOUT, Self::get(&host))
+            .await
+            .with_context(|| format!("timeout while fetching the policy at '{host}'"))??;
+
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut parsed = httparse::Response::new(&mut headers);
+        let offset = match parsed.parse(&response)? {
+            httparse::Status::Complete(offset) => offset,
+            httparse::Status::Partial => anyhow::bail!("incomplete response from '{host}'"),
+        };
+
+        // NOTE: the redirections must not be followed, see RFC 8461 section 3.3
+        if parsed.code != Some(200) {
+            anyhow::bail!(
+                "'{host}' responded with the status code {}",
+                parsed.code.unwrap_or_default()
+            );
+        }
+
+        if !parsed.headers.iter().any(|header| {
+            header.name.eq_ignore_ascii_case("content-type")
+                && std::str::from_utf8(header.value)
+                    .map_or(false, |value| value.trim().starts_with("text/plain"))
+        }) {
+            anyhow::bail!("the policy of '{host}' is not `text/plain`");
+        }
+
+        Ok(String::from_utf8(response[offset..].to_vec())?)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedPolicy {
+    id: String,
+    fetched_at: std::time::SystemTime,
+    policy: Policy,
+}
+
+impl CachedPolicy {
+    fn read(cache_dirpath: &std::path::Path, domain: &str) -> Option<Self> {
+        std::fs::read_to_string(cache_dirpath.join(format!("{domain}.json")))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+    }
+
+    fn write(&self, cache_dirpath: &std::path::Path, domain: &str) -> anyhow::Result<()> {
+        std::fs::create_dir_all(cache_dirpath)?;
+
+        let tmp = cache_dirpath.join(format!(".{domain}.json.tmp"));
+        std::fs::write(&tmp, serde_json::to_string(self)?)?;
+        std::fs::rename(&tmp, cache_dirpath.join(format!("{domain}.json")))?;
+
+        Ok(())
+    }
+
+    fn is_expired(&self, now: std::time::SystemTime) -> bool {
+        self.fetched_at + std::time::Duration::from_secs(self.policy.max_age) <= now
+    }
+}
+
+/// Get the MTA-STS policy of `domain`, using the cache stored in `cache_dirpath`.
+/// `None` if the domain does not have a policy.
+/// see <https://datatracker.ietf.org/doc/html/rfc8461#section-5.1>
+pub async fn get_policy(
+    resolver: &TokioAsyncResolver,
+    fetcher: &dyn PolicyFetcher,
+    cache_dirpath: &std::path::Path,
+    domain: &str,
+) -> Option<Policy> {
+    let policy_id = match resolver.txt_lookup(format!("_mta-sts.{domain}.")).await {
+        Ok(records) => {
+            let mut ids = records
+                .iter()
+                .filter_map(|record| parse_txt_record(&record.to_string()))
+                .collect::<Vec<_>>();
+
+            // multiple records are treated as no record
+            if ids.len() == 1 {
+                ids.pop()
+            } else {
+                None
+            }
+        }
+        Err(error) => {
+            log::debug!("no MTA-STS record found for '{domain}': {error}");
+            None
+        }
+    };
+
+    refresh_policy(
+        policy_id.as_deref(),
+        fetcher,
+        cache_dirpath,
+        domain,
+        std::time::SystemTime::now(),
+    )
+    .await
+}
+
+/// Get the policy of `domain` from the cache, fetching it again if the `policy_id`
+/// published in the DNS changed. A cached policy is used until it expires if
+/// the record disappears or if the fetch fails.
+pub async fn refresh_policy(
+    policy_id: Option<&str>,
+    fetcher: &dyn PolicyFetcher,
+    cache_dirpath: &std::path::Path,
+    domain: &str,
+    now: std::time::SystemTime,
+) -> Option<Policy> {
+    let cached = CachedPolicy::read(cache_dirpath, domain).filter(|cached| !cached.is_expired(now));
+
+    let policy_id = match (policy_id, &cached) {
+        (None, _) => return cached.map(|cached| cached.policy),
+        (Some(id), Some(cached)) if cached.id == id => return Some(cached.policy.clone()),
+        (Some(id), _) => id,
+    };
+
+    match fetcher
+        .fetch(domain)
+        .await
+        .and_then(|body| body.parse::<Policy>())
+    {
+        Ok(policy) => {
+            let entry = CachedPolicy {
+                id: policy_id.to_string(),
+                fetched_at: now,
+                policy,
+            };
+            if let Err(error) = entry.write(cache_dirpath, domain) {
+                log::warn!("failed to cache the MTA-STS policy of '{domain}': {error}");
+            }
+            Some(entry.policy)
+        }
+        Err(error) => {
+            log::warn!("failed to fetch the MTA-STS policy of '{domain}': {error}");
+            cached.map(|cached| cached.policy)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!(
+            "version: STSv1\r\nmode: enforce\r\nmx: mail.example.com\r\nmx: *.example.net\r\nmx: backupmx.example.com\r\nmax_age: 604800\r\n"
+                .parse::<Policy>()
+                .unwrap(),
+            Policy {
+                mode: Mode::Enforce,
+                mx: vec![
+                    "mail.example.com".to_string(),
+                    "*.example.net".to_string(),
+                    "backupmx.example.com".to_string()
+                ],
+                max_age: 604_800,
+            }
+        );
+
+        assert_eq!(
+            "version: STSv1\nmode: none\nmax_age: 86400\n"
+                .parse::<Policy>()
+                .unwrap()
+                .mode,
+            Mode::None
+        );
+
+        assert!("version: STSv2\nmode: enforce\nmx: a.com\nmax_age: 1\n"
+            .parse::<Policy>()
+            .is_err());
+        assert!("version: STSv1\nmode: enforce\nmax_age: 1\n"
+            .parse::<Policy>()
+            .is_err());
+        assert!("version: STSv1\nmode: strict\nmx: a.com\nmax_age: 1\n"
+            .parse::<Policy>()
+            .is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let policy = Policy {
+            mode: Mode::Enforce,
+            mx: vec!["mail.example.com".to_string(), "*.example.net".to_string()],
+            max_age: 1,
+        };
+
+        assert!(policy.matches("mail.example.com"));
+        assert!(policy.matches("MAIL.example.com."));
+        assert!(policy.matches("mx1.example.net"));
+        assert!(!policy.matches("example.net"));
+        assert!(!policy.matches("foo.mx1.example.net"));
+        assert!(!policy.matches("mail.example.org"));
+    }
+
+    #[test]
+    fn txt_record() {
+        assert_eq!(
+            parse_txt_record("v=STSv1; id=20160831085700Z;"),
+            Some("20160831085700Z".to_string())
+        );
+        assert_eq!(parse_txt_record("v=STSv1; id=;"), None);
+        assert_eq!(parse_txt_record("v=spf1 -all"), None);
+    }
+
+    struct Stub(std::sync::Mutex<Vec<anyhow::Result<String>>>);
+
+    #[async_trait::async_trait]
+    impl PolicyFetcher for Stub {
+        async fn fetch(&self, _: &str) -> anyhow::Result<String> {
+            self.0.lock().unwrap().remove(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn cache() {
+        let cache_dirpath =
+            std::env::temp_dir().join(format!("vsmtp-mta-sts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dirpath);
+
+        let now = std::time::SystemTime::now();
+        let fetcher = Stub(std::sync::Mutex::new(vec![
+            Ok("version: STSv1\nmode: testing\nmx: a.com\nmax_age: 3600\n".to_string()),
+            Err(anyhow::anyhow!("unreachable")),
+            Ok("version: STSv1\nmode: enforce\nmx: b.com\nmax_age: 3600\n".to_string()),
+        ]));
+
+        // no record and nothing in the cache
+        assert_eq!(
+            refresh_policy(None, &fetcher, &cache_dirpath, "example.com", now).await,
+            None
+        );
+
+        let first = refresh_policy(Some("1"), &fetcher, &cache_dirpath, "example.com", now)
+            .await
+            .unwrap();
+        assert_eq!(first.mode, Mode::Testing);
+
+        // same id, the cache is used
+        assert_eq!(
+            refresh_policy(Some("1"), &fetcher, &cache_dirpath, "example.com", now).await,
+            Some(first.clone())
+        );
+        // no record, the cache is still valid
+        assert_eq!(
+            refresh_policy(None, &fetcher, &cache_dirpath, "example.com", now).await,
+            Some(first.clone())
+        );
+        // new id but the fetch failed
+        assert_eq!(
+            refresh_policy(Some("2"), &fetcher, &cache_dirpath, "example.com", now).await,
+            Some(first)
+        );
+
+        let second = refresh_policy(Some("2"), &fetcher, &cache_dirpath, "example.com", now)
+            .await
+            .unwrap();
+        assert_eq!(second.mode, Mode::Enforce);
+
+        // the cached policy expired
+        assert_eq!(
+            refresh_policy(
+                None,
+                &fetcher,
+                &cache_dirpath,
+                "example.com",
+                now + std::time::Duration::from_secs(3600)
+            )
+            .await,
+            None
+        );
+
+        std::fs::remove_dir_all(cache_dirpath).unwrap();
+    }
+}
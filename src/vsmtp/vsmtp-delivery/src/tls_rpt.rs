@@ -0,0 +1,721 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use vsmtp_common::re::{
+    anyhow::{self, Context},
+    lettre, log, serde_json,
+};
+use vsmtp_config::Config;
+
+/// The type of failure of a session, see RFC 8460 section 4.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResultType {
+    /// The receiving MTA did not support STARTTLS.
+    #[serde(rename = "starttls-not-supported")]
+    StartTlsNotSupported,
+    /// The certificate did not match the host name of the receiving MTA.
+    CertificateHostMismatch,
+    /// The certificate has expired.
+    CertificateExpired,
+    /// The certificate is not signed by a trusted authority.
+    CertificateNotTrusted,
+    /// A failure not matching the other categories.
+    ValidationFailure,
+    /// None of the TLSA records matched the certificate.
+    TlsaInvalid,
+    /// The TLSA records could not be validated with DNSSEC.
+    DnssecInvalid,
+    /// The MTA-STS policy could not be fetched.
+    StsPolicyFetchError,
+    /// The MTA-STS policy is invalid.
+    StsPolicyInvalid,
+    /// The certificate of the MTA-STS policy host could not be validated.
+    StsWebpkiInvalid,
+}
+
+/// A failure of the TLS negotiation, attached as a context to the errors of the transports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure(pub ResultType);
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tls negotiation failed: {}",
+            serde_json::to_value(self.0)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default()
+        )
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// the message of the error returned when the certificate does not match the TLSA records.
+pub(crate) const TLSA_MISMATCH: &str = "the certificate does not match the TLSA records";
+
+fn certificate_result_type(error: &rustls::Error) -> ResultType {
+    match error {
+        rustls::Error::General(message) if message == TLSA_MISMATCH => ResultType::TlsaInvalid,
+        // NOTE: the errors of the certificate verifier are formatted as `invalid peer certificate: {webpki::Error:?}`
+        rustls::Error::InvalidCertificateData(message) if message.ends_with("CertExpired") => {
+            ResultType::CertificateExpired
+        }
+        rustls::Error::InvalidCertificateData(message)
+            if message.ends_with("CertNotValidForName") =>
+        {
+            ResultType::CertificateHostMismatch
+        }
+        rustls::Error::InvalidCertificateData(message) if message.ends_with("UnknownIssuer") => {
+            ResultType::CertificateNotTrusted
+        }
+        _ => ResultType::ValidationFailure,
+    }
+}
+
+/// Find the failure of the TLS negotiation in the causes of `error`,
+/// or `None` if the session did not fail because of TLS.
+#[must_use]
+pub fn result_type(error: &anyhow::Error) -> Option<ResultType> {
+    if let Some(Failure(result_type)) = error.downcast_ref::<Failure>() {
+        return Some(*result_type);
+    }
+
+    error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<rustls::Error>().or_else(|| {
+            cause
+                .downcast_ref::<std::io::Error>()
+                .and_then(|io| io.get_ref()?.downcast_ref::<rustls::Error>())
+        }) {
+            return Some(certificate_result_type(error));
+        }
+
+        cause
+            .downcast_ref::<lettre::transport::smtp::Error>()
+            .filter(|error| error.is_client() && error.to_string().contains("STARTTLS"))
+            .map(|_| ResultType::StartTlsNotSupported)
+    })
+}
+
+/// The type of a [`Policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyType {
+    /// The TLSA records of the mail exchanger (DANE).
+    Tlsa,
+    /// The MTA-STS policy of the domain.
+    Sts,
+    /// No policy applied, TLS was required by the configuration.
+    NoPolicyFound,
+}
+
+/// The policy applied to a session, see RFC 8460 section 4.4
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Policy {
+    /// the type of the policy
+    pub policy_type: PolicyType,
+    /// the policy, the TLSA records or the lines of the MTA-STS policy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policy_string: Vec<String>,
+    /// the mail exchangers allowed by a MTA-STS policy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mx_host: Vec<String>,
+}
+
+impl Policy {
+    /// The policy of the sessions where no policy was found.
+    #[must_use]
+    pub const fn none() -> Self {
+        Self {
+            policy_type: PolicyType::NoPolicyFound,
+            policy_string: vec![],
+            mx_host: vec![],
+        }
+    }
+}
+
+impl From<&crate::mta_sts::Policy> for Policy {
+    fn from(policy: &crate::mta_sts::Policy) -> Self {
+        let mode = match policy.mode {
+            crate::mta_sts::Mode::Enforce => "enforce",
+            crate::mta_sts::Mode::Testing => "testing",
+            crate::mta_sts::Mode::None => "none",
+        };
+
+        Self {
+            policy_type: PolicyType::Sts,
+            policy_string: std::iter::once("version: STSv1".to_string())
+                .chain(std::iter::once(format!("mode: {mode}")))
+                .chain(policy.mx.iter().map(|mx| format!("mx: {mx}")))
+                .chain(std::iter::once(format!("max_age: {}", policy.max_age)))
+                .collect(),
+            mx_host: policy.mx.clone(),
+        }
+    }
+}
+
+/// The outcome of an outgoing session, recorded to produce the reports.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SessionResult {
+    /// when the session happened, in seconds since the unix epoch
+    pub timestamp: u64,
+    /// the domain of the recipients
+    pub policy_domain: String,
+    /// the policy applied to the session
+    pub policy: Policy,
+    /// the host name of the mail exchanger
+    pub receiving_mx_hostname: String,
+    /// the failure of the session, `None` if it succeeded
+    pub result: Option<ResultType>,
+}
+
+/// the results are stored in one file per policy domain.
+const EXTENSION: &str = "jsonl";
+/// the results are set aside in this file until their report is written.
+const TAKEN: &str = "taken";
+
+impl SessionResult {
+    /// Append the result to the file of its policy domain in `dirpath`.
+    ///
+    /// # Errors
+    ///
+    /// * the policy domain is not a valid file name
+    /// * the file cannot be written
+    pub fn store(&self, dirpath: &std::path::Path) -> anyhow::Result<()> {
+        if self.policy_domain.is_empty()
+            || self.policy_domain.starts_with('.')
+            || self.policy_domain.contains(['/', '\\'])
+        {
+            anyhow::bail!("invalid policy domain '{}'", self.policy_domain);
+        }
+
+        std::fs::create_dir_all(dirpath)
+            .with_context(|| format!("cannot create '{}'", dirpath.display()))?;
+
+        let path = dirpath.join(format!("{}.{EXTENSION}", self.policy_domain));
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+
+        std::io::Write::write_all(
+            &mut std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("cannot open '{}'", path.display()))?,
+            line.as_bytes(),
+        )
+        .with_context(|| format!("cannot write to '{}'", path.display()))
+    }
+
+    /// Set aside all the results stored in `dirpath`, and return them grouped by policy domain.
+    ///
+    /// The results are kept until [`SessionResult::remove_taken`] is called for their
+    /// policy domain, and are returned again by the next call otherwise.
+    ///
+    /// # Errors
+    ///
+    /// * the directory or a file cannot be read
+    pub fn take_all(dirpath: &std::path::Path) -> anyhow::Result<Vec<(String, Vec<Self>)>> {
+        if !dirpath.exists() {
+            return Ok(vec![]);
+        }
+
+        for entry in std::fs::read_dir(dirpath)
+            .with_context(|| format!("cannot read '{}'", dirpath.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(std::ffi::OsStr::to_str) != Some(EXTENSION) {
+                continue;
+            }
+
+            // NOTE: the file is moved first, the results stored meanwhile go to a new file.
+            let taken = path.with_extension(format!("{EXTENSION}.{TAKEN}"));
+            if taken.exists() {
+                let content = std::fs::read(&path)
+                    .with_context(|| format!("cannot read '{}'", path.display()))?;
+                std::io::Write::write_all(
+                    &mut std::fs::OpenOptions::new()
+                        .append(true)
+                        .open(&taken)
+                        .with_context(|| format!("cannot open '{}'", taken.display()))?,
+                    &content,
+                )
+                .with_context(|| format!("cannot write to '{}'", taken.display()))?;
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("cannot remove '{}'", path.display()))?;
+            } else {
+                std::fs::rename(&path, &taken)
+                    .with_context(|| format!("cannot move '{}'", path.display()))?;
+            }
+        }
+
+        let mut out = vec![];
+        for entry in std::fs::read_dir(dirpath)
+            .with_context(|| format!("cannot read '{}'", dirpath.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(std::ffi::OsStr::to_str) != Some(TAKEN) {
+                continue;
+            }
+
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("cannot read '{}'", path.display()))?;
+
+            let results = content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<Self>(line).ok())
+                .collect::<Vec<_>>();
+
+            if let Some(first) = results.first() {
+                out.push((first.policy_domain.clone(), results));
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Remove the results of `policy_domain` returned by [`SessionResult::take_all`],
+    /// once they have been reported.
+    ///
+    /// # Errors
+    ///
+    /// * the file cannot be removed
+    pub fn remove_taken(dirpath: &std::path::Path, policy_domain: &str) -> anyhow::Result<()> {
+        let path = dirpath.join(format!("{policy_domain}.{EXTENSION}.{TAKEN}"));
+        match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(error).with_context(|| format!("cannot remove '{}'", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Record the outcome of a session if the reports are enabled with `server.tls_rpt`.
+///
+/// The failures are recorded when they are caused by the TLS negotiation, and the
+/// successes only when TLS was required, the transports not reporting whether
+/// an opportunistic session was encrypted.
+pub fn record(
+    config: &Config,
+    policy_domain: &str,
+    policy: &Policy,
+    receiving_mx_hostname: &str,
+    outcome: &anyhow::Result<()>,
+    tls_required: bool,
+) {
+    if config.server.tls_rpt.is_none() {
+        return;
+    }
+
+    let result = outcome.as_ref().err().and_then(result_type);
+    if result.is_none() && (outcome.is_err() || !tls_required) {
+        return;
+    }
+
+    if let Err(error) = (SessionResult {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs()),
+        policy_domain: policy_domain.trim_end_matches('.').to_string(),
+        policy: policy.clone(),
+        receiving_mx_hostname: receiving_mx_hostname.trim_end_matches('.').to_string(),
+        result,
+    })
+    .store(&config.app.dirpath.join("tls-rpt"))
+    {
+        log::warn!("failed to store the tls session result: {error}");
+    }
+}
+
+/// Extract the reporting addresses of a `_smtp._tls` TXT record, see RFC 8460 section 3
+#[must_use]
+pub fn parse_txt_record(record: &str) -> Option<Vec<String>> {
+    let mut fields = record.split(';').map(str::trim);
+    if fields.next()? != "v=TLSRPTv1" {
+        return None;
+    }
+
+    fields
+        .filter_map(|field| field.split_once('='))
+        .find(|(key, _)| key.trim() == "rua")
+        .map(|(_, rua)| {
+            rua.split(',')
+                .map(str::trim)
+                .filter(|uri| !uri.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|rua| !rua.is_empty())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct DateRange {
+    start_datetime: String,
+    end_datetime: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ReportedPolicy {
+    policy_type: PolicyType,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    policy_string: Vec<String>,
+    policy_domain: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    mx_host: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Summary {
+    total_successful_session_count: usize,
+    total_failure_session_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct FailureDetails {
+    result_type: ResultType,
+    receiving_mx_hostname: String,
+    failed_session_count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct PolicyReport {
+    policy: ReportedPolicy,
+    summary: Summary,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failure_details: Vec<FailureDetails>,
+}
+
+/// An aggregate report of the TLS sessions, see RFC 8460 section 4
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Report {
+    /// the name of the organization sending the report
+    pub organization_name: String,
+    date_range: DateRange,
+    /// the address to contact the organization
+    pub contact_info: String,
+    /// a unique identifier of the report
+    pub report_id: String,
+    policies: Vec<PolicyReport>,
+    #[serde(skip)]
+    policy_domain: String,
+    #[serde(skip)]
+    begin: u64,
+    #[serde(skip)]
+    end: u64,
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    time::OffsetDateTime::from_unix_timestamp(i64::try_from(timestamp).unwrap_or(i64::MAX))
+        .ok()
+        .and_then(|date| {
+            date.format(&time::format_description::well_known::Rfc3339)
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+impl Report {
+    /// Create the report of the sessions of a policy domain, or `None` if there is
+    /// no session.
+    #[must_use]
+    pub fn new(
+        organization_name: impl Into<String>,
+        contact_info: impl Into<String>,
+        report_id: impl Into<String>,
+        results: Vec<SessionResult>,
+    ) -> Option<Self> {
+        let begin = results.iter().map(|r| r.timestamp).min()?;
+        let end = results.iter().map(|r| r.timestamp).max()?;
+        let policy_domain = results.first()?.policy_domain.clone();
+
+        let mut policies = Vec::<PolicyReport>::new();
+        for result in results {
+            let index = policies
+                .iter()
+                .position(|report| {
+                    report.policy.policy_type == result.policy.policy_type
+                        && report.policy.policy_string == result.policy.policy_string
+                        && report.policy.mx_host == result.policy.mx_host
+                })
+                .unwrap_or_else(|| {
+                    policies.push(PolicyReport {
+                        policy: ReportedPolicy {
+                            policy_type: result.policy.policy_type,
+                            policy_string: result.policy.policy_string.clone(),
+                            policy_domain: policy_domain.clone(),
+                            mx_host: result.policy.mx_host.clone(),
+                        },
+                        summary: Summary {
+                            total_successful_session_count: 0,
+                            total_failure_session_count: 0,
+                        },
+                        failure_details: vec![],
+                    });
+                    policies.len() - 1
+                });
+            let report = &mut policies[index];
+
+            let result_type = if let Some(result_type) = result.result {
+                result_type
+            } else {
+                report.summary.total_successful_session_count += 1;
+                continue;
+            };
+
+            report.summary.total_failure_session_count += 1;
+            match report.failure_details.iter_mut().find(|details| {
+                details.result_type == result_type
+                    && details.receiving_mx_hostname == result.receiving_mx_hostname
+            }) {
+                Some(details) => details.failed_session_count += 1,
+                None => report.failure_details.push(FailureDetails {
+                    result_type,
+                    receiving_mx_hostname: result.receiving_mx_hostname,
+                    failed_session_count: 1,
+                }),
+            }
+        }
+
+        Some(Self {
+            organization_name: organization_name.into(),
+            date_range: DateRange {
+                start_datetime: format_timestamp(begin),
+                end_datetime: format_timestamp(end),
+            },
+            contact_info: contact_info.into(),
+            report_id: report_id.into(),
+            policies,
+            policy_domain,
+            begin,
+            end,
+        })
+    }
+
+    /// The domain of the recipients of the sessions reported.
+    #[must_use]
+    pub fn policy_domain(&self) -> &str {
+        &self.policy_domain
+    }
+
+    /// The name of the file of the report, see RFC 8460 section 5.1
+    #[must_use]
+    pub fn filename(&self) -> String {
+        format!(
+            "{}!{}!{}!{}.json",
+            self.organization_name, self.policy_domain, self.begin, self.end
+        )
+    }
+}
+
+/// Serialize the report with the JSON schema of RFC 8460 section 4.4
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| std::fmt::Error)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn txt_record() {
+        assert_eq!(
+            parse_txt_record("v=TLSRPTv1;rua=mailto:reports@example.com, https://reporting.example.com/v1/tlsrpt"),
+            Some(vec![
+                "mailto:reports@example.com".to_string(),
+                "https://reporting.example.com/v1/tlsrpt".to_string()
+            ])
+        );
+        assert_eq!(parse_txt_record("v=TLSRPTv1; rua="), None);
+        assert_eq!(parse_txt_record("v=spf1 -all"), None);
+    }
+
+    #[test]
+    fn classification() {
+        let tlsa = anyhow::Error::new(Failure(ResultType::StartTlsNotSupported))
+            .context("no STARTTLS")
+            .context("failed to send");
+        assert_eq!(result_type(&tlsa), Some(ResultType::StartTlsNotSupported));
+
+        let handshake = anyhow::Error::new(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificateData(
+                "invalid peer certificate: CertExpired".to_string(),
+            ),
+        ))
+        .context("tls handshake failed");
+        assert_eq!(
+            result_type(&handshake),
+            Some(ResultType::CertificateExpired)
+        );
+
+        assert_eq!(
+            result_type(&anyhow::Error::new(rustls::Error::General(
+                TLSA_MISMATCH.to_string()
+            ))),
+            Some(ResultType::TlsaInvalid)
+        );
+        assert_eq!(result_type(&anyhow::anyhow!("connection refused")), None);
+    }
+
+    fn session(timestamp: u64, host: &str, result: Option<ResultType>) -> SessionResult {
+        SessionResult {
+            timestamp,
+            policy_domain: "example.com".to_string(),
+            policy: Policy::from(&crate::mta_sts::Policy {
+                mode: crate::mta_sts::Mode::Enforce,
+                mx: vec!["*.example.com".to_string()],
+                max_age: 86_400,
+            }),
+            receiving_mx_hostname: host.to_string(),
+            result,
+        }
+    }
+
+    #[test]
+    fn report() {
+        let report = Report::new(
+            "testserver.com",
+            "noreply-tlsrpt@testserver.com",
+            "report_test",
+            vec![
+                session(1_000, "mx1.example.com", None),
+                session(
+                    2_000,
+                    "mx1.example.com",
+                    Some(ResultType::CertificateExpired),
+                ),
+                session(
+                    3_000,
+                    "mx1.example.com",
+                    Some(ResultType::CertificateExpired),
+                ),
+                SessionResult {
+                    policy: Policy::none(),
+                    ..session(4_000, "mx2.example.com", None)
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            report.filename(),
+            "testserver.com!example.com!1000!4000.json"
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&report.to_string()).unwrap(),
+            serde_json::json!({
+                "organization-name": "testserver.com",
+                "date-range": {
+                    "start-datetime": "1970-01-01T00:16:40Z",
+                    "end-datetime": "1970-01-01T01:06:40Z"
+                },
+                "contact-info": "noreply-tlsrpt@testserver.com",
+                "report-id": "report_test",
+                "policies": [
+                    {
+                        "policy": {
+                            "policy-type": "sts",
+                            "policy-string": [
+                                "version: STSv1",
+                                "mode: enforce",
+                                "mx: *.example.com",
+                                "max_age: 86400"
+                            ],
+                            "policy-domain": "example.com",
+                            "mx-host": ["*.example.com"]
+                        },
+                        "summary": {
+                            "total-successful-session-count": 1,
+                            "total-failure-session-count": 2
+                        },
+                        "failure-details": [
+                            {
+                                "result-type": "certificate-expired",
+                                "receiving-mx-hostname": "mx1.example.com",
+                                "failed-session-count": 2
+                            }
+                        ]
+                    },
+                    {
+                        "policy": {
+                            "policy-type": "no-policy-found",
+                            "policy-domain": "example.com"
+                        },
+                        "summary": {
+                            "total-successful-session-count": 1,
+                            "total-failure-session-count": 0
+                        }
+                    }
+                ]
+            })
+        );
+
+        assert!(Report::new("a", "b", "c", vec![]).is_none());
+    }
+
+    #[test]
+    fn store() {
+        let dirpath = std::path::PathBuf::from("./tmp/tls_rpt");
+        let _ = std::fs::remove_dir_all(&dirpath);
+
+        let results = vec![
+            session(1_000, "mx1.example.com", None),
+            session(
+                2_000,
+                "mx2.example.com",
+                Some(ResultType::StartTlsNotSupported),
+            ),
+        ];
+        for result in &results {
+            result.store(&dirpath).unwrap();
+        }
+        assert!(SessionResult {
+            policy_domain: "../example.com".to_string(),
+            ..results[0].clone()
+        }
+        .store(&dirpath)
+        .is_err());
+
+        assert_eq!(
+            SessionResult::take_all(&dirpath).unwrap(),
+            vec![("example.com".to_string(), results.clone())]
+        );
+
+        // the results not reported yet are merged with the new ones.
+        let last = session(3_000, "mx1.example.com", None);
+        last.store(&dirpath).unwrap();
+        assert_eq!(
+            SessionResult::take_all(&dirpath).unwrap(),
+            vec![("example.com".to_string(), [results, vec![last]].concat())]
+        );
+
+        SessionResult::remove_taken(&dirpath, "example.com").unwrap();
+        SessionResult::remove_taken(&dirpath, "example.com").unwrap();
+        assert!(SessionResult::take_all(&dirpath).unwrap().is_empty());
+    }
+}
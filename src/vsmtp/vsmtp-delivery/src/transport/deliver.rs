@@ -15,6 +15,7 @@
  *
 */
 use super::Transport;
+use crate::{dane, mta_sts, tls_rpt};
 use trust_dns_resolver::TokioAsyncResolver;
 use vsmtp_common::{
     mail_context::MessageMetadata,
@@ -26,7 +27,10 @@ use vsmtp_common::{
     transfer::EmailTransferStatus,
     Address,
 };
-use vsmtp_config::Config;
+use vsmtp_config::{
+    field::{FieldServerDNS, TlsSecurityLevel},
+    Config,
+};
 
 enum ResultSendMail {
     /// Temporary error, increasing the `HeldBack` property to retry later.
@@ -35,19 +39,119 @@ enum ResultSendMail {
     Failed(String),
 }
 
+/// how the connections to the mail exchangers are secured.
+#[derive(Debug, Clone, Copy)]
+enum OutgoingTls<'a> {
+    /// the connection is upgraded with STARTTLS if the server supports it.
+    Opportunistic,
+    /// the connection must be encrypted.
+    Required,
+    /// the connection is authenticated with the TLSA records of the mail exchanger,
+    /// and is opportunistic if the mail exchanger does not publish any.
+    Dane {
+        port: u16,
+        resolver: &'a dane::Resolver,
+    },
+}
+
 /// the email will be forwarded to another mail exchanger via mx record resolution & smtp.
 pub struct Deliver<'r> {
     resolver: &'r TokioAsyncResolver,
+    policy_fetcher: &'r dyn mta_sts::PolicyFetcher,
+    dane_resolver: Option<dane::Resolver>,
+    null_reverse_path: bool,
+    port: u16,
 }
 
 impl<'r> Deliver<'r> {
     /// create a new deliver with a resolver to get data from the distant dns server.
     #[must_use]
     pub const fn new(resolver: &'r TokioAsyncResolver) -> Self {
-        Self { resolver }
+        Self {
+            resolver,
+            policy_fetcher: &mta_sts::HttpsPolicyFetcher,
+            dane_resolver: None,
+            null_reverse_path: false,
+            port: lettre::transport::smtp::SMTP_PORT,
+        }
+    }
+
+    /// replace the fetcher of the MTA-STS policies, fetching over HTTPS by default.
+    #[must_use]
+    pub const fn with_policy_fetcher(
+        mut self,
+        policy_fetcher: &'r dyn mta_sts::PolicyFetcher,
+    ) -> Self {
+        self.policy_fetcher = policy_fetcher;
+        self
+    }
+
+    /// replace the resolver of the DANE lookups, built from the dns configuration
+    /// of the sender's domain by default.
+    #[must_use]
+    pub fn with_dane_resolver(mut self, dane_resolver: dane::Resolver) -> Self {
+        self.dane_resolver = Some(dane_resolver);
+        self
+    }
+
+    /// send the message with the null reverse-path `<>` instead of the sender's address.
+    #[must_use]
+    pub const fn with_null_reverse_path(mut self, null_reverse_path: bool) -> Self {
+        self.null_reverse_path = null_reverse_path;
+        self
+    }
+
+    /// replace the port of the mail exchangers, 25 by default.
+    /// the port used with DANE is the one of the security level.
+    #[must_use]
+    pub const fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
     }
 }
 
+/// the mail exchanger does not accept the non-ascii addresses of the message: lettre refuses
+/// to send them when `SMTPUTF8` is missing from the EHLO extensions, see RFC 6531 section 3.2,
+/// and the server rejects them with a "553 5.6.7" reply, see RFC 6531 section 3.7.4.
+fn is_smtp_utf8_unsupported(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<lettre::transport::smtp::Error>()
+        .map_or(false, |error| {
+            let message = error.to_string();
+            (error.is_client() && message.contains("SMTPUTF8"))
+                || (error.is_permanent() && message.split_whitespace().any(|word| word == "5.6.7"))
+        })
+}
+
+/// the policy of security of the outgoing connections for the sender's domain.
+fn sender_security_level(config: &Config, from: &Address) -> TlsSecurityLevel {
+    config
+        .server
+        .r#virtual
+        .get(from.domain())
+        .and_then(|entry| entry.tls.as_ref())
+        .map_or_else(
+            || {
+                config
+                    .server
+                    .tls
+                    .as_ref()
+                    .map_or(TlsSecurityLevel::May, |tls| tls.sender_security_level)
+            },
+            |tls| tls.sender_security_level,
+        )
+}
+
+/// the dns configuration of the sender's domain.
+fn sender_dns<'a>(config: &'a Config, from: &Address) -> &'a FieldServerDNS {
+    config
+        .server
+        .r#virtual
+        .get(from.domain())
+        .and_then(|entry| entry.dns.as_ref())
+        .unwrap_or(&config.server.dns)
+}
+
 impl<'r> Deliver<'r> {
     /// fetch mx records for a specific domain and order them by priority.
     async fn get_mx_records(
@@ -65,68 +169,211 @@ impl<'r> Deliver<'r> {
         Ok(records_by_priority)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn send_email(
         &self,
         config: &Config,
         target: &str,
         envelop: &lettre::address::Envelope,
         from: &vsmtp_common::Address,
-        content: &str,
+        content: &[u8],
+        port: u16,
+        tls_required: bool,
     ) -> anyhow::Result<()> {
         lettre::AsyncTransport::send_raw(
             // TODO: transport should be cached.
-            &crate::transport::build_transport(config, self.resolver, from, target)?,
+            &crate::transport::build_transport(config, from, target, port, tls_required)?,
             envelop,
-            content.as_bytes(),
+            content,
         )
         .await?;
 
         Ok(())
     }
 
+    /// send the email to a mail exchanger of `domain`, and record the outcome for the TLS reports.
+    #[allow(clippy::too_many_arguments)]
+    async fn send_to_host(
+        &self,
+        config: &Config,
+        domain: &str,
+        host: &str,
+        envelop: &lettre::address::Envelope,
+        from: &vsmtp_common::Address,
+        content: &[u8],
+        tls: OutgoingTls<'_>,
+        sts_policy: Option<&mta_sts::Policy>,
+    ) -> anyhow::Result<()> {
+        let sts_or_none = || sts_policy.map_or_else(tls_rpt::Policy::none, tls_rpt::Policy::from);
+        let tlsa_policy = |records: &[_]| tls_rpt::Policy {
+            policy_type: tls_rpt::PolicyType::Tlsa,
+            policy_string: records.iter().map(ToString::to_string).collect(),
+            mx_host: vec![],
+        };
+
+        let (policy, tls_required, outcome) = match tls {
+            // the connection must not be downgraded if the mail exchanger publishes TLSA records.
+            OutgoingTls::Dane { port, resolver } => {
+                match dane::lookup_tlsa(resolver, host, port).await {
+                    Ok(Some(records)) => (
+                        tlsa_policy(&records),
+                        true,
+                        dane::send(records, host, port, from.domain(), envelop, content).await,
+                    ),
+                    Ok(None) => (
+                        sts_or_none(),
+                        false,
+                        self.send_email(config, host, envelop, from, content, port, false)
+                            .await,
+                    ),
+                    Err(error) => (tlsa_policy(&[]), true, Err(error)),
+                }
+            }
+            OutgoingTls::Required | OutgoingTls::Opportunistic => {
+                let tls_required = matches!(tls, OutgoingTls::Required);
+                (
+                    sts_or_none(),
+                    tls_required,
+                    self.send_email(
+                        config,
+                        host,
+                        envelop,
+                        from,
+                        content,
+                        self.port,
+                        tls_required,
+                    )
+                    .await,
+                )
+            }
+        };
+
+        tls_rpt::record(config, domain, &policy, host, &outcome, tls_required);
+        outcome
+    }
+
     // FIXME: should just return a `ResultSendMail`
+    #[allow(clippy::too_many_lines)]
     async fn deliver_one_domain(
         &self,
         config: &Config,
         metadata: &MessageMetadata,
-        content: &str,
+        content: &[u8],
         from: &Address,
         domain: &str,
         rcpt: &[Rcpt],
     ) -> anyhow::Result<(), ResultSendMail> {
-        let envelop = from
-            .full()
-            .parse()
+        // NOTE: internationalized domains are converted to A-labels, so only the addresses
+        //       with a non-ascii local part require the next hop to support SMTPUTF8.
+        let mut requires_smtp_utf8 = false;
+        let mut to_lettre_address = |address: &Address| {
+            address.with_ascii_domain().and_then(|address| {
+                requires_smtp_utf8 |= !address.is_ascii();
+                address
+                    .full()
+                    .parse::<lettre::Address>()
+                    .with_context(|| format!("address is not valid: {address}"))
+            })
+        };
+
+        let envelop = to_lettre_address(from)
             .context("envelop is invalid")
             .and_then(|from| {
                 Ok((
                     from,
                     rcpt.iter()
                         .map(|i| {
-                            i.address
-                                .full()
-                                .parse::<lettre::Address>()
+                            to_lettre_address(&i.address)
                                 .with_context(|| format!("receiver address is not valid: {i}"))
                         })
                         .collect::<anyhow::Result<Vec<_>>>()?,
                 ))
             })
             .and_then(|(from, rcpt_addresses)| {
-                lettre::address::Envelope::new(Some(from), rcpt_addresses)
-                    .context("envelop is invalid")
+                lettre::address::Envelope::new(
+                    Some(from).filter(|_| !self.null_reverse_path),
+                    rcpt_addresses,
+                )
+                .context("envelop is invalid")
             })
             .map_err(|err| ResultSendMail::Failed(err.to_string()))?;
 
-        let records = self
-            .get_mx_records(domain)
+        let security_level = sender_security_level(config, from);
+        let policy = if security_level == TlsSecurityLevel::MtaSts {
+            mta_sts::get_policy(
+                self.resolver,
+                self.policy_fetcher,
+                &config.app.dirpath.join("mta-sts"),
+                domain,
+            )
             .await
-            .with_context(|| {
-                format!(
-                    "(msg={}) failed to get mx records for '{domain}'",
+        } else {
+            None
+        };
+        let sts_policy = policy
+            .as_ref()
+            .filter(|policy| policy.mode != mta_sts::Mode::None);
+        let enforced_policy = policy
+            .as_ref()
+            .filter(|policy| policy.mode == mta_sts::Mode::Enforce);
+        let dane_resolver;
+        let dane_resolver = match (security_level, &self.dane_resolver) {
+            (TlsSecurityLevel::Dane { .. }, Some(resolver)) => Some(resolver),
+            (TlsSecurityLevel::Dane { .. }, None) => {
+                dane_resolver = dane::Resolver::from_config(sender_dns(config, from))
+                    .map_err(ResultSendMail::IncreaseHeldBack)?;
+                Some(&dane_resolver)
+            }
+            _ => None,
+        };
+
+        // the mail exchangers not matching the policy are skipped in `enforce` mode,
+        // and only reported in `testing` mode.
+        let is_allowed = |host: &str| match &policy {
+            Some(policy) if policy.mode != mta_sts::Mode::None && !policy.matches(host) => {
+                log::warn!(
+                    "(msg={}) the mail exchanger '{host}' does not match the MTA-STS policy of '{domain}'",
                     metadata.message_id
-                )
-            })
-            .map_err(ResultSendMail::IncreaseHeldBack)?;
+                );
+                policy.mode != mta_sts::Mode::Enforce
+            }
+            _ => true,
+        };
+
+        let (mx_security, records) = match dane_resolver {
+            Some(resolver) => dane::lookup_mx(resolver, domain).await,
+            None => self
+                .get_mx_records(domain)
+                .await
+                .map(|records| (dane::Security::Insecure, records)),
+        }
+        .with_context(|| {
+            format!(
+                "(msg={}) failed to get mx records for '{domain}'",
+                metadata.message_id
+            )
+        })
+        .map_err(ResultSendMail::IncreaseHeldBack)?;
+
+        let tls = match (security_level, dane_resolver) {
+            (TlsSecurityLevel::Dane { port }, Some(resolver))
+                if mx_security == dane::Security::Secure =>
+            {
+                OutgoingTls::Dane { port, resolver }
+            }
+            // DANE only applies to the mail exchangers of a validated MX RRset,
+            // see RFC 7672 section 2.2.1
+            (TlsSecurityLevel::Dane { .. }, _) => {
+                log::warn!(
+                    "(msg={}) the MX records of '{domain}' are not validated with DNSSEC, DANE does not apply",
+                    metadata.message_id
+                );
+                OutgoingTls::Opportunistic
+            }
+            (TlsSecurityLevel::Encrypt, _) => OutgoingTls::Required,
+            _ if enforced_policy.is_some() => OutgoingTls::Required,
+            _ => OutgoingTls::Opportunistic,
+        };
 
         if records.is_empty() {
             log::warn!(
@@ -134,19 +381,29 @@ impl<'r> Deliver<'r> {
                 metadata.message_id
             );
 
+            if !is_allowed(domain) {
+                return Err(ResultSendMail::IncreaseHeldBack(anyhow::anyhow!(
+                    "'{domain}' does not match its MTA-STS policy"
+                )));
+            }
+
             // using directly the AAAA record instead of an mx record.
             // see https://www.rfc-editor.org/rfc/rfc5321#section-5.1
-            self.send_email(config, domain, &envelop, from, content)
-                .await
-                .with_context(|| {
-                    format!(
-                        "(msg={}) failed to send message from '{from}' for '{domain}'",
-                        metadata.message_id
-                    )
-                })
-                .map_err(ResultSendMail::IncreaseHeldBack)?;
+            self.send_to_host(
+                config, domain, domain, &envelop, from, content, tls, sts_policy,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "(msg={}) failed to send message from '{from}' for '{domain}'",
+                    metadata.message_id
+                )
+            })
+            .map_err(ResultSendMail::IncreaseHeldBack)?;
         }
 
+        let mut smtp_utf8_unsupported = false;
+        let mut last_error = None;
         let mut records = records.iter();
         for record in records.by_ref() {
             let host = record.exchange().to_ascii();
@@ -160,25 +417,52 @@ impl<'r> Deliver<'r> {
                 );
 
                 return Err(ResultSendMail::Failed(
-                    "null record found for this domain".to_string(),
+                    "5.1.10 null record found for this domain".to_string(),
                 ));
             }
 
+            // NOTE: the root label is removed, the host name is matched with the certificate.
+            let host = host.trim_end_matches('.');
+
+            if !is_allowed(host) {
+                continue;
+            }
+
             match self
-                .send_email(config, &host, &envelop, from, content)
+                .send_to_host(
+                    config, domain, host, &envelop, from, content, tls, sts_policy,
+                )
                 .await
             {
                 Ok(_) => return Ok(()),
-                Err(err) => log::warn!(
-                    "(msg={}) failed to send message from '{from}' for '{domain}': {err}",
-                    metadata.message_id
-                ),
+                Err(err) => {
+                    smtp_utf8_unsupported |= requires_smtp_utf8 && is_smtp_utf8_unsupported(&err);
+
+                    log::warn!(
+                        "(msg={}) failed to send message from '{from}' for '{domain}': {err}",
+                        metadata.message_id
+                    );
+                    last_error = Some(err);
+                }
             }
         }
 
-        return Err(ResultSendMail::IncreaseHeldBack(anyhow::anyhow!(
-            "no valid mail exchanger found for '{domain}'",
-        )));
+        if smtp_utf8_unsupported {
+            return Err(ResultSendMail::Failed(format!(
+                "5.6.7 the message requires SMTPUTF8, which is not supported by the mail exchangers of '{domain}'"
+            )));
+        }
+
+        // the reply of the last mail exchanger tried is kept, for the delivery status notifications.
+        let message = if enforced_policy.is_some() {
+            format!("no valid mail exchanger found for '{domain}' with its MTA-STS policy")
+        } else {
+            format!("no valid mail exchanger found for '{domain}'")
+        };
+        return Err(ResultSendMail::IncreaseHeldBack(match last_error {
+            Some(err) => err.context(message),
+            None => anyhow::anyhow!(message),
+        }));
     }
 }
 
@@ -190,7 +474,7 @@ impl<'r> Transport for Deliver<'r> {
         metadata: &MessageMetadata,
         from: &vsmtp_common::Address,
         to: Vec<Rcpt>,
-        content: &str,
+        content: &[u8],
     ) -> Vec<Rcpt> {
         let mut rcpt_by_domain = std::collections::HashMap::<String, Vec<Rcpt>>::new();
         for rcpt in to {
@@ -221,7 +505,7 @@ impl<'r> Transport for Deliver<'r> {
                         error = error
                     );
                     for i in rcpt {
-                        i.email_status.held_back(error.to_string());
+                        i.email_status.held_back(format!("{error:#}"));
                     }
                 }
                 Err(ResultSendMail::Failed(reason)) => {
@@ -255,13 +539,37 @@ impl<'r> Transport for Deliver<'r> {
 #[cfg(test)]
 mod test {
 
-    use crate::transport::deliver::Deliver;
-    use trust_dns_resolver::TokioAsyncResolver;
+    use crate::transport::{
+        deliver::{is_smtp_utf8_unsupported, Deliver},
+        Transport,
+    };
+    use crate::{
+        dane, mta_sts,
+        test::{dns_serving, Validation},
+        tls_rpt,
+    };
+    use trust_dns_resolver::{
+        config::ResolverOpts,
+        proto::rr::{
+            rdata::{
+                tlsa::{CertUsage, Matching, Selector, TLSA},
+                MX, TXT,
+            },
+            Name, RData, Record,
+        },
+        TokioAsyncResolver,
+    };
     use vsmtp_common::{
         addr,
-        re::{lettre, tokio},
+        mail_context::MessageMetadata,
+        rcpt::Rcpt,
+        re::{anyhow, lettre, serde_json, tokio},
+        transfer::EmailTransferStatus,
+    };
+    use vsmtp_config::{
+        field::{FieldServerDNS, FieldServerTls, FieldTlsRpt},
+        Config,
     };
-    use vsmtp_config::{field::FieldServerDNS, Config};
 
     #[tokio::test]
     async fn test_get_mx_records() {
@@ -298,9 +606,347 @@ mod test {
                 )
                 .unwrap(),
                 &addr!("a@a.a"),
-                "content"
+                b"content",
+                lettre::transport::smtp::SMTP_PORT,
+                false
             )
             .await
             .is_err());
     }
+
+    /// a mail exchanger replying `ehlo` to the EHLO command and `mail` to the MAIL FROM command.
+    async fn serve(
+        listener: tokio::net::TcpListener,
+        ehlo: &'static str,
+        mail: &'static str,
+    ) -> anyhow::Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+        // NOTE: the pool of lettre opens an idle connection besides the one sending the message.
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(async move {
+                let mut stream = tokio::io::BufReader::new(stream);
+                stream.write_all(b"220 localhost\r\n").await?;
+                loop {
+                    let mut line = String::new();
+                    if stream.read_line(&mut line).await? == 0 {
+                        return std::io::Result::Ok(());
+                    }
+                    let reply = match line.get(..4) {
+                        Some("EHLO") => ehlo,
+                        Some("MAIL") => mail,
+                        Some("DATA") => {
+                            stream.write_all(b"354 Start mail input\r\n").await?;
+                            let mut data = String::new();
+                            while !data.ends_with("\r\n.\r\n") {
+                                if stream.read_line(&mut data).await? == 0 {
+                                    return Ok(());
+                                }
+                            }
+                            "250 Ok\r\n"
+                        }
+                        Some("QUIT") => "221 bye\r\n",
+                        _ => "250 Ok\r\n",
+                    };
+                    stream.write_all(reply.as_bytes()).await?;
+                }
+            });
+        }
+    }
+
+    async fn send_utf8_to_local_server(ehlo: &'static str, mail: &'static str) -> anyhow::Error {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(listener, ehlo, mail));
+
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap();
+        let error = Deliver::new(&resolver)
+            .send_email(
+                &Config::default(),
+                "localhost",
+                &lettre::address::Envelope::new(
+                    Some("a@a.a".parse().unwrap()),
+                    vec!["bébé@localhost".parse().unwrap()],
+                )
+                .unwrap(),
+                &addr!("a@a.a"),
+                b"content",
+                port,
+                false,
+            )
+            .await
+            .unwrap_err();
+
+        server.abort();
+        error
+    }
+
+    // NOTE: the connection pool of lettre blocks a single threaded runtime.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn smtp_utf8_unsupported() {
+        assert!(is_smtp_utf8_unsupported(
+            &send_utf8_to_local_server("250-localhost\r\n250 8BITMIME\r\n", "250 Ok\r\n").await
+        ));
+        assert!(is_smtp_utf8_unsupported(
+            &send_utf8_to_local_server(
+                "250-localhost\r\n250 SMTPUTF8\r\n",
+                "553 5.6.7 non-ascii addresses are not permitted\r\n"
+            )
+            .await
+        ));
+        assert!(!is_smtp_utf8_unsupported(
+            &send_utf8_to_local_server(
+                "250-localhost\r\n250 SMTPUTF8\r\n",
+                "550 5.1.1 user unknown\r\n"
+            )
+            .await
+        ));
+        assert!(!is_smtp_utf8_unsupported(&anyhow::anyhow!(
+            "connection refused"
+        )));
+    }
+
+    struct Stub(std::sync::Mutex<Vec<anyhow::Result<String>>>);
+
+    #[async_trait::async_trait]
+    impl mta_sts::PolicyFetcher for Stub {
+        async fn fetch(&self, _: &str) -> anyhow::Result<String> {
+            self.0.lock().unwrap().remove(0)
+        }
+    }
+
+    /// `domain` publishes a MTA-STS policy, and its single mail exchanger is `localhost`.
+    fn mta_sts_records(domain: &str) -> Vec<Record> {
+        let domain = Name::from_ascii(domain).unwrap();
+        vec![
+            Record::from_rdata(
+                Name::from_ascii("_mta-sts")
+                    .unwrap()
+                    .append_domain(&domain)
+                    .unwrap(),
+                60,
+                RData::TXT(TXT::new(vec!["v=STSv1; id=1".to_string()])),
+            ),
+            Record::from_rdata(
+                domain,
+                60,
+                RData::MX(MX::new(10, Name::from_ascii("localhost.").unwrap())),
+            ),
+        ]
+    }
+
+    // NOTE: the connection pool of lettre blocks a single threaded runtime.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mta_sts_enforce() {
+        let dirpath = std::path::PathBuf::from("./tmp/deliver_mta_sts_enforce");
+        let _ = std::fs::remove_dir_all(&dirpath);
+
+        let mut config = Config::default();
+        config.app.dirpath = dirpath.clone();
+        config.server.tls_rpt = Some(FieldTlsRpt {
+            report_interval: std::time::Duration::from_secs(60 * 60),
+            dirpath: None,
+        });
+        config.server.tls = Some(
+            serde_json::from_value::<FieldServerTls>(serde_json::json!({
+                "security_level": "May",
+                "protocol_version": "TLSv1.3",
+                "certificate": "../../../examples/config/tls/certificate.crt",
+                "private_key": "../../../examples/config/tls/private_key.key",
+                "client_ca": null,
+                "sender_security_level": "MtaSts",
+            }))
+            .unwrap(),
+        );
+
+        let resolver = crate::test::resolver_serving(
+            [mta_sts_records("skip.test."), mta_sts_records("tls.test.")].concat(),
+            ResolverOpts::default(),
+        )
+        .await;
+        let fetcher = Stub(std::sync::Mutex::new(vec![
+            Ok("version: STSv1\nmode: enforce\nmx: mx.skip.test\nmax_age: 3600\n".to_string()),
+            Ok("version: STSv1\nmode: enforce\nmx: localhost\nmax_age: 3600\n".to_string()),
+        ]));
+
+        // a mail exchanger without STARTTLS.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let metadata = MessageMetadata::default();
+        let from = addr!("john.doe@localhost");
+        let deliver = |rcpt: &str| {
+            Deliver::new(&resolver)
+                .with_policy_fetcher(&fetcher)
+                .with_port(port)
+                .deliver(
+                    &config,
+                    &metadata,
+                    &from,
+                    vec![Rcpt::new(addr!(rcpt))],
+                    b"content",
+                )
+        };
+
+        // the mail exchanger does not match the policy, it is not even contacted.
+        let rcpt = deliver("jenny.doe@skip.test").await;
+        assert!(
+            matches!(&rcpt[0].email_status, EmailTransferStatus::HeldBack { errors }
+                if format!("{errors:?}").contains("with its MTA-STS policy"))
+        );
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(100), listener.accept())
+                .await
+                .is_err()
+        );
+
+        // the mail exchanger matches the policy, but TLS is required.
+        let server = tokio::spawn(serve(
+            listener,
+            "250-localhost\r\n250 8BITMIME\r\n",
+            "250 Ok\r\n",
+        ));
+        let rcpt = deliver("jenny.doe@tls.test").await;
+        assert!(matches!(
+            &rcpt[0].email_status,
+            EmailTransferStatus::HeldBack { .. }
+        ));
+        server.abort();
+
+        let results = tls_rpt::SessionResult::take_all(&dirpath.join("tls-rpt")).unwrap();
+        assert_eq!(results.len(), 1);
+        let (policy_domain, results) = &results[0];
+        assert_eq!(policy_domain, "tls.test");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].receiving_mx_hostname, "localhost");
+        assert_eq!(results[0].policy.policy_type, tls_rpt::PolicyType::Sts);
+        assert_eq!(
+            results[0].result,
+            Some(tls_rpt::ResultType::StartTlsNotSupported)
+        );
+
+        std::fs::remove_dir_all(dirpath).unwrap();
+    }
+
+    // NOTE: the connection pool of lettre blocks a single threaded runtime.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dane_unsigned_zone() {
+        // a mail exchanger without STARTTLS.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(serve(
+            listener,
+            "250-localhost\r\n250 8BITMIME\r\n",
+            "250 Ok\r\n",
+        ));
+
+        let dirpath = std::path::PathBuf::from("./tmp/deliver_dane_unsigned_zone");
+        let _ = std::fs::remove_dir_all(&dirpath);
+
+        let mut config = Config::default();
+        config.app.dirpath = dirpath.clone();
+        config.server.tls_rpt = Some(FieldTlsRpt {
+            report_interval: std::time::Duration::from_secs(60 * 60),
+            dirpath: None,
+        });
+        config.server.tls = Some(
+            serde_json::from_value::<FieldServerTls>(serde_json::json!({
+                "security_level": "May",
+                "protocol_version": "TLSv1.3",
+                "certificate": "../../../examples/config/tls/certificate.crt",
+                "private_key": "../../../examples/config/tls/private_key.key",
+                "client_ca": null,
+                "sender_security_level": { "Dane": { "port": port } },
+            }))
+            .unwrap(),
+        );
+
+        let records = vec![
+            Record::from_rdata(
+                Name::from_ascii("dane.test.").unwrap(),
+                60,
+                RData::MX(MX::new(10, Name::from_ascii("localhost.").unwrap())),
+            ),
+            Record::from_rdata(
+                Name::from_ascii(format!("_{port}._tcp.localhost.")).unwrap(),
+                60,
+                RData::TLSA(TLSA::new(
+                    CertUsage::DomainIssued,
+                    Selector::Full,
+                    Matching::Sha256,
+                    vec![0; 32],
+                )),
+            ),
+        ];
+        let resolver = crate::test::resolver_serving(vec![], ResolverOpts::default()).await;
+
+        let metadata = MessageMetadata::default();
+        let from = addr!("john.doe@localhost");
+        let deliver = |validation| {
+            let records = records.clone();
+            let (config, metadata, from, resolver) = (&config, &metadata, &from, &resolver);
+            async move {
+                Deliver::new(resolver)
+                    .with_dane_resolver(dane::Resolver::new(
+                        &dns_serving(records, validation).await,
+                        ResolverOpts::default(),
+                    ))
+                    .with_port(port)
+                    .deliver(
+                        config,
+                        metadata,
+                        from,
+                        vec![Rcpt::new(addr!("jenny.doe@dane.test"))],
+                        b"content",
+                    )
+                    .await
+            }
+        };
+
+        // the zone is not signed, DANE does not apply and the message is sent in plain text.
+        let rcpt = deliver(Validation::Insecure).await;
+        assert!(matches!(
+            &rcpt[0].email_status,
+            EmailTransferStatus::Sent { .. }
+        ));
+        assert!(tls_rpt::SessionResult::take_all(&dirpath.join("tls-rpt"))
+            .unwrap()
+            .is_empty());
+
+        // the records are validated, the connection is never downgraded.
+        let rcpt = deliver(Validation::Secure).await;
+        assert!(
+            matches!(&rcpt[0].email_status, EmailTransferStatus::HeldBack { errors }
+                if format!("{errors:?}").contains("does not support STARTTLS"))
+        );
+
+        let results = tls_rpt::SessionResult::take_all(&dirpath.join("tls-rpt")).unwrap();
+        assert_eq!(results.len(), 1);
+        let (policy_domain, results) = &results[0];
+        assert_eq!(policy_domain, "dane.test");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].receiving_mx_hostname, "localhost");
+        assert_eq!(results[0].policy.policy_type, tls_rpt::PolicyType::Tlsa);
+        assert_eq!(results[0].policy.policy_string.len(), 1);
+        assert_eq!(
+            results[0].result,
+            Some(tls_rpt::ResultType::StartTlsNotSupported)
+        );
+        tls_rpt::SessionResult::remove_taken(&dirpath.join("tls-rpt"), policy_domain).unwrap();
+
+        // the validation failed, the mail exchanger is not used.
+        let rcpt = deliver(Validation::Bogus).await;
+        assert!(matches!(
+            &rcpt[0].email_status,
+            EmailTransferStatus::HeldBack { .. }
+        ));
+        // NOTE: the MX records could not be fetched, there is no session to report.
+        assert!(tls_rpt::SessionResult::take_all(&dirpath.join("tls-rpt"))
+            .unwrap()
+            .is_empty());
+
+        server.abort();
+        std::fs::remove_dir_all(dirpath).unwrap();
+    }
 }
@@ -15,6 +15,7 @@
  *
 */
 use super::Transport;
+use crate::tls_rpt;
 use anyhow::Context;
 use trust_dns_resolver::TokioAsyncResolver;
 use vsmtp_common::{
@@ -29,13 +30,25 @@ use vsmtp_config::Config;
 pub struct Forward<'r> {
     to: ForwardTarget,
     resolver: &'r TokioAsyncResolver,
+    null_reverse_path: bool,
 }
 
 impl<'r> Forward<'r> {
     /// create a new deliver with a resolver to get data from the distant dns server.
     #[must_use]
     pub const fn new(to: ForwardTarget, resolver: &'r TokioAsyncResolver) -> Self {
-        Self { to, resolver }
+        Self {
+            to,
+            resolver,
+            null_reverse_path: false,
+        }
+    }
+
+    /// send the message with the null reverse-path `<>` instead of the sender's address.
+    #[must_use]
+    pub const fn with_null_reverse_path(mut self, null_reverse_path: bool) -> Self {
+        self.null_reverse_path = null_reverse_path;
+        self
     }
 }
 
@@ -60,13 +73,19 @@ impl<'r> Forward<'r> {
         from: &vsmtp_common::Address,
         target: &str,
         envelop: &lettre::address::Envelope,
-        content: &str,
+        content: &[u8],
     ) -> anyhow::Result<()> {
         lettre::AsyncTransport::send_raw(
             // TODO: transport should be cached.
-            &crate::transport::build_transport(config, self.resolver, from, target)?,
+            &crate::transport::build_transport(
+                config,
+                from,
+                target,
+                lettre::transport::smtp::SMTP_PORT,
+                false,
+            )?,
             envelop,
-            content.as_bytes(),
+            content,
         )
         .await?;
 
@@ -78,7 +97,7 @@ impl<'r> Forward<'r> {
         config: &Config,
         from: &vsmtp_common::Address,
         to: &[Rcpt],
-        content: &str,
+        content: &[u8],
     ) -> anyhow::Result<()> {
         let envelop = from
             .full()
@@ -98,8 +117,11 @@ impl<'r> Forward<'r> {
                 ))
             })
             .and_then(|(from, rcpt_addresses)| {
-                lettre::address::Envelope::new(Some(from), rcpt_addresses)
-                    .context("envelop is invalid")
+                lettre::address::Envelope::new(
+                    Some(from).filter(|_| !self.null_reverse_path),
+                    rcpt_addresses,
+                )
+                .context("envelop is invalid")
             })?;
 
         // if the domain is unknown, we ask the dns to get it (tls parameters required the domain).
@@ -109,9 +131,30 @@ impl<'r> Forward<'r> {
             ForwardTarget::Socket(socket) => self.reverse_lookup(&socket.ip()).await,
         }?;
 
-        self.send_email(config, from, &target, &envelop, content)
-            .await
-            .with_context(|| format!("failed to forward email to {target}"))
+        let outcome = self
+            .send_email(config, from, &target, &envelop, content)
+            .await;
+
+        let mut domains = to
+            .iter()
+            .map(|rcpt| rcpt.address.domain())
+            .collect::<Vec<_>>();
+        domains.sort_unstable();
+        domains.dedup();
+
+        // the forwarding is opportunistic, only the failures of the tls negotiation are reported.
+        for domain in domains {
+            tls_rpt::record(
+                config,
+                domain,
+                &tls_rpt::Policy::none(),
+                &target,
+                &outcome,
+                false,
+            );
+        }
+
+        outcome.with_context(|| format!("failed to forward email to {target}"))
     }
 }
 
@@ -123,7 +166,7 @@ impl<'r> Transport for Forward<'r> {
         metadata: &MessageMetadata,
         from: &vsmtp_common::Address,
         mut to: Vec<Rcpt>,
-        content: &str,
+        content: &[u8],
     ) -> Vec<Rcpt> {
         match self.deliver_inner(config, from, &*to, content).await {
             Ok(_) => {
@@ -142,7 +185,7 @@ impl<'r> Transport for Forward<'r> {
                     error = error
                 );
                 for i in &mut to {
-                    i.email_status.held_back(error.to_string());
+                    i.email_status.held_back(format!("{error:#}"));
                 }
             }
         }
@@ -40,7 +40,7 @@ impl Transport for Maildir {
         metadata: &MessageMetadata,
         _: &vsmtp_common::Address,
         mut to: Vec<Rcpt>,
-        content: &str,
+        content: &[u8],
     ) -> Vec<Rcpt> {
         for rcpt in &mut to {
             match users::get_user_by_name(rcpt.address.local_part()).map(|user| {
@@ -120,7 +120,7 @@ fn write_to_maildir(
     user: &users::User,
     group_local: Option<&users::Group>,
     metadata: &MessageMetadata,
-    content: &str,
+    content: &[u8],
 ) -> anyhow::Result<()> {
     let maildir = create_maildir(user, group_local, metadata)?;
 
@@ -130,7 +130,7 @@ fn write_to_maildir(
         .open(&maildir)?;
 
     std::io::Write::write_all(&mut email, format!("Delivered-To: {rcpt}\n").as_bytes())?;
-    std::io::Write::write_all(&mut email, content.as_bytes())?;
+    std::io::Write::write_all(&mut email, content)?;
 
     chown(
         &maildir,
@@ -186,7 +186,7 @@ mod test {
                 message_id: message_id.to_string(),
                 ..MessageMetadata::default()
             },
-            "email content",
+            b"email content",
         )
         .expect("could not write email to maildir");
 
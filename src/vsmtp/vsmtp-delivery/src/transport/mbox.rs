@@ -47,7 +47,7 @@ impl Transport for MBox {
         metadata: &MessageMetadata,
         from: &vsmtp_common::Address,
         mut to: Vec<Rcpt>,
-        content: &str,
+        content: &[u8],
     ) -> Vec<Rcpt> {
         let timestamp = get_mbox_timestamp_format(metadata);
         let content = build_mbox_message(from, &timestamp, content);
@@ -110,12 +110,13 @@ fn get_mbox_timestamp_format(metadata: &MessageMetadata) -> String {
         .unwrap_or_else(|_| String::default())
 }
 
-fn build_mbox_message(
-    from: &vsmtp_common::Address,
-    timestamp: &str,
-    content: &str,
-) -> std::string::String {
-    format!("From {} {}\n{}\n", from, timestamp, content)
+fn build_mbox_message(from: &vsmtp_common::Address, timestamp: &str, content: &[u8]) -> Vec<u8> {
+    [
+        format!("From {} {}\n", from, timestamp).as_bytes(),
+        content,
+        b"\n",
+    ]
+    .concat()
 }
 
 fn write_content_to_mbox(
@@ -124,7 +125,7 @@ fn write_content_to_mbox(
     user: &users::User,
     group_local: Option<&users::Group>,
     metadata: &MessageMetadata,
-    content: &str,
+    content: &[u8],
 ) -> anyhow::Result<()> {
     let mut file = std::fs::OpenOptions::new()
         .create(true)
@@ -135,7 +136,7 @@ fn write_content_to_mbox(
         .with_context(|| format!("could not set owner for '{:?}' mbox", mbox))?;
 
     std::io::Write::write_all(&mut file, format!("Delivered-To: {rcpt}\n").as_bytes())?;
-    std::io::Write::write_all(&mut file, content.as_bytes())?;
+    std::io::Write::write_all(&mut file, content)?;
 
     log::debug!(
         "(msg={}) {} bytes written to {:?}",
@@ -180,7 +181,7 @@ This is a raw email."#;
             ..MessageMetadata::default()
         });
 
-        let message = build_mbox_message(&from, &timestamp, content);
+        let message = build_mbox_message(&from, &timestamp, content.as_bytes());
 
         assert_eq!(
             r#"From john@doe.com Thu Jan  1 00:00:00 1970
@@ -190,7 +191,7 @@ subject: test email
 
 This is a raw email.
 "#,
-            message
+            std::str::from_utf8(&message).unwrap()
         );
     }
 
@@ -211,7 +212,7 @@ This is a raw email.
             &user,
             None,
             &metadata,
-            content,
+            content.as_bytes(),
         )
         .expect("could not write to mbox");
 
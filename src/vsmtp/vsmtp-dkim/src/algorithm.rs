@@ -25,6 +25,9 @@ pub enum SigningAlgorithm {
     ///
     #[strum(serialize = "rsa-sha256")]
     RsaSha256,
+    /// see RFC 8463
+    #[strum(serialize = "ed25519-sha256")]
+    Ed25519Sha256,
 }
 
 impl SigningAlgorithm {
@@ -33,8 +36,14 @@ impl SigningAlgorithm {
     pub fn is_supported(&self, hash_algo: &[HashAlgorithm]) -> bool {
         hash_algo.iter().any(|a| match (a, self) {
             (HashAlgorithm::Sha1, SigningAlgorithm::RsaSha1)
-            | (HashAlgorithm::Sha256, SigningAlgorithm::RsaSha256) => true,
-            (HashAlgorithm::Sha1, SigningAlgorithm::RsaSha256)
+            | (
+                HashAlgorithm::Sha256,
+                SigningAlgorithm::RsaSha256 | SigningAlgorithm::Ed25519Sha256,
+            ) => true,
+            (
+                HashAlgorithm::Sha1,
+                SigningAlgorithm::RsaSha256 | SigningAlgorithm::Ed25519Sha256,
+            )
             | (HashAlgorithm::Sha256, SigningAlgorithm::RsaSha1) => false,
         })
     }
@@ -48,7 +57,7 @@ impl SigningAlgorithm {
                 sha1::Digest::update(&mut digest, data);
                 sha1::Digest::finalize(digest).to_vec()
             }
-            SigningAlgorithm::RsaSha256 => {
+            SigningAlgorithm::RsaSha256 | SigningAlgorithm::Ed25519Sha256 => {
                 let mut digest = <sha2::Sha256 as sha2::Digest>::new();
                 sha2::Digest::update(&mut digest, data);
                 sha2::Digest::finalize(digest).to_vec()
@@ -0,0 +1,761 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    sign::sign_hash, verify::verify_hash, Canonicalization, CanonicalizationAlgorithm, PrivateKey,
+    PublicKey, SigningAlgorithm,
+};
+use vsmtp_common::RawBody;
+
+/// The maximum number of ARC Sets in a chain, see RFC 8617 section 4.2.1
+const MAX_INSTANCE: usize = 50;
+
+/// The state of an ARC chain, tag "cv=" of the `ARC-Seal` header.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ChainValidation {
+    /// there is no ARC Set in the message
+    None,
+    /// every ARC Set of the chain has been validated
+    Pass,
+    /// the chain is invalid
+    Fail,
+}
+
+/// Possible error produced by the parsing, the validation and the sealing of an ARC chain
+#[derive(Debug, thiserror::Error)]
+pub enum ArcError {
+    /// A header is missing a required tag
+    #[error("missing required field: `{field}`")]
+    MissingRequiredField {
+        /// The name of the tag
+        field: String,
+    },
+    /// A header is malformed
+    #[error("syntax error: `{reason}`")]
+    SyntaxError {
+        /// The reason of the error
+        reason: String,
+    },
+    /// The ARC Sets do not form a valid chain
+    #[error("invalid chain: `{reason}`")]
+    InvalidChain {
+        /// The reason of the error
+        reason: String,
+    },
+    /// The algorithm of the key cannot be used for ARC
+    #[error("the algorithm `{signing_algorithm}` is not supported by ARC")]
+    UnsupportedAlgorithm {
+        /// The algorithm of the key
+        signing_algorithm: SigningAlgorithm,
+    },
+    /// The signing of a header failed
+    #[error("signing failed: `{error}`")]
+    SigningFailed {
+        /// The error produced by the signing function
+        error: rsa::errors::Error,
+    },
+}
+
+fn parse_tags(header: &str, name: &str) -> Result<Vec<(String, String)>, ArcError> {
+    if !header
+        .to_lowercase()
+        .starts_with(&format!("{}:", name.to_lowercase()))
+    {
+        return Err(ArcError::SyntaxError {
+            reason: format!("not a `{name}` header"),
+        });
+    }
+
+    header[name.len() + 1..]
+        .split(';')
+        .map(|tag| tag.split_whitespace().collect::<Vec<_>>().concat())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            tag.split_once('=')
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| ArcError::SyntaxError {
+                    reason: "tag syntax is `{tag}={value}`".to_string(),
+                })
+        })
+        .collect()
+}
+
+fn parse_instance(value: &str) -> Result<usize, ArcError> {
+    match value.parse::<usize>() {
+        Ok(instance) if (1..=MAX_INSTANCE).contains(&instance) => Ok(instance),
+        _ => Err(ArcError::SyntaxError {
+            reason: format!("`{value}` is not a valid instance"),
+        }),
+    }
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T, ArcError> {
+    value.ok_or_else(|| ArcError::MissingRequiredField {
+        field: field.to_string(),
+    })
+}
+
+fn syntax_error(field: &str, error: impl std::fmt::Display) -> ArcError {
+    ArcError::SyntaxError {
+        reason: format!("when parsing `{field}`, got: `{error}`"),
+    }
+}
+
+/// Remove the value of the tag "b=" of a header, to compute its hash.
+fn without_signature(header: &str) -> String {
+    header
+        .split(';')
+        .map(|tag| match tag.split_once('=') {
+            Some((key, _)) if key.trim() == "b" => format!("{key}="),
+            _ => tag.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Get the header fields signed, each occurrence being selected from the bottom
+/// of the header section, see RFC 6376 section 5.4.2
+fn select_headers(message: &RawBody, headers_field: &[String]) -> Vec<String> {
+    let mut headers = message.headers();
+
+    headers_field
+        .iter()
+        .filter_map(|name| {
+            let idx = headers
+                .iter()
+                .rposition(|(key, _)| key.eq_ignore_ascii_case(name))?;
+            let (key, value) = headers.remove(idx);
+            Some(format!("{key}:{value}"))
+        })
+        .collect()
+}
+
+fn hash_headers(
+    canonicalization: CanonicalizationAlgorithm,
+    signing_algorithm: SigningAlgorithm,
+    headers: &[String],
+) -> Vec<u8> {
+    let mut headers = canonicalization.canonicalize_header(headers);
+
+    // remove the final "\r\n"
+    headers.pop();
+    headers.pop();
+    signing_algorithm.hash(headers)
+}
+
+fn now() -> std::time::Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Representation of the "ARC-Authentication-Results" header
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ArcAuthenticationResults {
+    /// tag "i="
+    pub instance: usize,
+    /// the `authserv-id` and the results, as in an `Authentication-Results` header
+    pub results: String,
+    raw: String,
+}
+
+impl std::str::FromStr for ArcAuthenticationResults {
+    type Err = ArcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const NAME: &str = "ARC-Authentication-Results";
+
+        if !s.to_lowercase().starts_with("arc-authentication-results:") {
+            return Err(ArcError::SyntaxError {
+                reason: format!("not a `{NAME}` header"),
+            });
+        }
+
+        let (instance, results) =
+            s[NAME.len() + 1..]
+                .split_once(';')
+                .ok_or_else(|| ArcError::SyntaxError {
+                    reason: "the instance must be followed by the results".to_string(),
+                })?;
+
+        Ok(Self {
+            instance: match instance.trim().split_once('=') {
+                Some(("i", instance)) => parse_instance(instance.trim())?,
+                _ => {
+                    return Err(ArcError::MissingRequiredField {
+                        field: "instance".to_string(),
+                    })
+                }
+            },
+            results: results.trim().to_string(),
+            raw: s.to_string(),
+        })
+    }
+}
+
+/// Representation of the "ARC-Message-Signature" header
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ArcMessageSignature {
+    /// tag "i="
+    pub instance: usize,
+    /// tag "a="
+    pub signing_algorithm: SigningAlgorithm,
+    /// Signing Domain Identifier (SDID)
+    /// tag "d="
+    pub sdid: String,
+    /// tag "s="
+    pub selector: String,
+    /// tag "c="
+    pub canonicalization: Canonicalization,
+    /// tag "t="
+    pub signature_timestamp: Option<std::time::Duration>,
+    /// tag "h="
+    pub headers_field: Vec<String>,
+    /// tag "bh="
+    pub body_hash: String,
+    /// tag "b="
+    pub signature: String,
+    raw: String,
+}
+
+impl ArcMessageSignature {
+    /// The dns name of the public key used to verify the signature
+    #[must_use]
+    pub fn get_dns_query(&self) -> String {
+        format!("{}._domainkey.{}", self.selector, self.sdid)
+    }
+
+    fn get_body_hash(&self, message: &RawBody) -> String {
+        let body = self.canonicalization.body.canonicalize_body(
+            &message
+                .body()
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        );
+
+        base64::encode(self.signing_algorithm.hash(body))
+    }
+
+    fn get_header_hash(&self, message: &RawBody) -> Vec<u8> {
+        let mut headers = select_headers(message, &self.headers_field);
+        headers.push(without_signature(&self.raw));
+
+        hash_headers(
+            self.canonicalization.header,
+            self.signing_algorithm,
+            &headers,
+        )
+    }
+
+    /// Verify the signature of the message.
+    ///
+    /// # Errors
+    ///
+    /// * see [`crate::VerifierError`]
+    pub fn verify(&self, message: &RawBody, key: &PublicKey) -> Result<(), crate::VerifierError> {
+        if self.body_hash != self.get_body_hash(message) {
+            return Err(crate::VerifierError::BodyHashMismatch);
+        }
+
+        verify_hash(
+            self.signing_algorithm,
+            key,
+            &self.get_header_hash(message),
+            &self.signature,
+        )
+    }
+}
+
+impl std::fmt::Display for ArcMessageSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ARC-Message-Signature: i={}; a={}; c={}; d={}; s={};{}\r\n\th={};\r\n\tbh={};\r\n\tb={}",
+            self.instance,
+            self.signing_algorithm,
+            self.canonicalization,
+            self.sdid,
+            self.selector,
+            self.signature_timestamp
+                .map(|t| format!(" t={};", t.as_secs()))
+                .unwrap_or_default(),
+            self.headers_field.join(":"),
+            self.body_hash,
+            self.signature
+        )
+    }
+}
+
+impl std::str::FromStr for ArcMessageSignature {
+    type Err = ArcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut instance = None;
+        let mut signing_algorithm = None;
+        let mut sdid = None;
+        let mut selector = None;
+        let mut canonicalization = Canonicalization::default();
+        let mut signature_timestamp = None;
+        let mut headers_field = None;
+        let mut body_hash = None;
+        let mut signature = None;
+
+        for (key, value) in parse_tags(s, "ARC-Message-Signature")? {
+            match key.as_str() {
+                "i" => instance = Some(parse_instance(&value)?),
+                "a" => {
+                    signing_algorithm = Some(
+                        <SigningAlgorithm as std::str::FromStr>::from_str(&value)
+                            .map_err(|e| syntax_error("signing_algorithm", e))?,
+                    );
+                }
+                "d" => sdid = Some(value),
+                "s" => selector = Some(value),
+                "c" => {
+                    canonicalization = <Canonicalization as std::str::FromStr>::from_str(&value)
+                        .map_err(|e| syntax_error("canonicalization", e))?;
+                }
+                "t" => {
+                    signature_timestamp = Some(std::time::Duration::from_secs(
+                        value
+                            .parse::<u64>()
+                            .map_err(|e| syntax_error("signature_timestamp", e))?,
+                    ));
+                }
+                "h" => {
+                    headers_field = Some(value.split(':').map(str::to_string).collect::<Vec<_>>());
+                }
+                "bh" => body_hash = Some(value),
+                "b" => {
+                    base64::decode(&value).map_err(|e| syntax_error("signature", e))?;
+                    signature = Some(value);
+                }
+                // unknown tags are ignored
+                _ => {}
+            }
+        }
+
+        let headers_field = required(headers_field, "headers_field")?;
+        if headers_field
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case("ARC-Seal"))
+        {
+            return Err(ArcError::SyntaxError {
+                reason: "`headers_field` must not contains `ARC-Seal`".to_string(),
+            });
+        }
+
+        Ok(Self {
+            instance: required(instance, "instance")?,
+            signing_algorithm: required(signing_algorithm, "signing_algorithm")?,
+            sdid: required(sdid, "sdid")?,
+            selector: required(selector, "selector")?,
+            canonicalization,
+            signature_timestamp,
+            headers_field,
+            body_hash: required(body_hash, "body_hash")?,
+            signature: required(signature, "signature")?,
+            raw: s.to_string(),
+        })
+    }
+}
+
+/// Representation of the "ARC-Seal" header
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ArcSeal {
+    /// tag "i="
+    pub instance: usize,
+    /// tag "a="
+    pub signing_algorithm: SigningAlgorithm,
+    /// Signing Domain Identifier (SDID)
+    /// tag "d="
+    pub sdid: String,
+    /// tag "s="
+    pub selector: String,
+    /// tag "t="
+    pub signature_timestamp: Option<std::time::Duration>,
+    /// tag "cv="
+    pub chain_validation: ChainValidation,
+    /// tag "b="
+    pub signature: String,
+    raw: String,
+}
+
+impl ArcSeal {
+    /// The dns name of the public key used to verify the signature
+    #[must_use]
+    pub fn get_dns_query(&self) -> String {
+        format!("{}._domainkey.{}", self.selector, self.sdid)
+    }
+}
+
+impl std::fmt::Display for ArcSeal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ARC-Seal: i={}; a={}; cv={}; d={}; s={};{}\r\n\tb={}",
+            self.instance,
+            self.signing_algorithm,
+            self.chain_validation,
+            self.sdid,
+            self.selector,
+            self.signature_timestamp
+                .map(|t| format!(" t={};", t.as_secs()))
+                .unwrap_or_default(),
+            self.signature
+        )
+    }
+}
+
+impl std::str::FromStr for ArcSeal {
+    type Err = ArcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut instance = None;
+        let mut signing_algorithm = None;
+        let mut sdid = None;
+        let mut selector = None;
+        let mut signature_timestamp = None;
+        let mut chain_validation = None;
+        let mut signature = None;
+
+        for (key, value) in parse_tags(s, "ARC-Seal")? {
+            match key.as_str() {
+                "i" => instance = Some(parse_instance(&value)?),
+                "a" => {
+                    signing_algorithm = Some(
+                        <SigningAlgorithm as std::str::FromStr>::from_str(&value)
+                            .map_err(|e| syntax_error("signing_algorithm", e))?,
+                    );
+                }
+                "d" => sdid = Some(value),
+                "s" => selector = Some(value),
+                "t" => {
+                    signature_timestamp = Some(std::time::Duration::from_secs(
+                        value
+                            .parse::<u64>()
+                            .map_err(|e| syntax_error("signature_timestamp", e))?,
+                    ));
+                }
+                "cv" => {
+                    chain_validation = Some(
+                        <ChainValidation as std::str::FromStr>::from_str(&value)
+                            .map_err(|e| syntax_error("chain_validation", e))?,
+                    );
+                }
+                "b" => {
+                    base64::decode(&value).map_err(|e| syntax_error("signature", e))?;
+                    signature = Some(value);
+                }
+                // "h=" is not allowed in the seal
+                "h" => {
+                    return Err(ArcError::SyntaxError {
+                        reason: "`ARC-Seal` must not have a `h=` tag".to_string(),
+                    })
+                }
+                // unknown tags are ignored
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            instance: required(instance, "instance")?,
+            signing_algorithm: required(signing_algorithm, "signing_algorithm")?,
+            sdid: required(sdid, "sdid")?,
+            selector: required(selector, "selector")?,
+            signature_timestamp,
+            chain_validation: required(chain_validation, "chain_validation")?,
+            signature: required(signature, "signature")?,
+            raw: s.to_string(),
+        })
+    }
+}
+
+/// The three headers added by an ARC participant, see RFC 8617 section 4.1
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ArcSet {
+    /// the "ARC-Authentication-Results" header
+    pub authentication_results: ArcAuthenticationResults,
+    /// the "ARC-Message-Signature" header
+    pub message_signature: ArcMessageSignature,
+    /// the "ARC-Seal" header
+    pub seal: ArcSeal,
+}
+
+impl ArcSet {
+    /// The instance of the set, starting at 1.
+    #[must_use]
+    pub const fn instance(&self) -> usize {
+        self.seal.instance
+    }
+
+    /// The headers of the set, in the order they are prepended to the message.
+    #[must_use]
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        let value = |raw: &str| raw.split_once(':').map(|(_, v)| v.trim_start().to_string());
+        [
+            (
+                "ARC-Authentication-Results",
+                value(&self.authentication_results.raw).unwrap_or_default(),
+            ),
+            (
+                "ARC-Message-Signature",
+                value(&self.message_signature.raw).unwrap_or_default(),
+            ),
+            ("ARC-Seal", value(&self.seal.raw).unwrap_or_default()),
+        ]
+    }
+}
+
+/// The ARC Sets of a message, ordered by instance.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct ArcChain {
+    /// the sets of the chain, the instance `i` is at the index `i - 1`
+    pub sets: Vec<ArcSet>,
+}
+
+impl ArcChain {
+    /// Collect the ARC Sets of a message.
+    ///
+    /// # Errors
+    ///
+    /// * a header is malformed.
+    /// * an instance is missing or has more than one set of headers.
+    pub fn from_message(message: &RawBody) -> Result<Self, ArcError> {
+        type Partial = (
+            Option<ArcAuthenticationResults>,
+            Option<ArcMessageSignature>,
+            Option<ArcSeal>,
+        );
+
+        fn set_once<T>(slot: &mut Option<T>, value: T, instance: usize) -> Result<(), ArcError> {
+            if slot.replace(value).is_some() {
+                return Err(ArcError::InvalidChain {
+                    reason: format!("the instance {instance} has duplicated headers"),
+                });
+            }
+            Ok(())
+        }
+
+        let mut sets = std::collections::BTreeMap::<usize, Partial>::new();
+
+        for (key, value) in message.headers() {
+            let header = format!("{key}:{value}");
+
+            if key.eq_ignore_ascii_case("ARC-Authentication-Results") {
+                let header = <ArcAuthenticationResults as std::str::FromStr>::from_str(&header)?;
+                let instance = header.instance;
+                set_once(&mut sets.entry(instance).or_default().0, header, instance)?;
+            } else if key.eq_ignore_ascii_case("ARC-Message-Signature") {
+                let header = <ArcMessageSignature as std::str::FromStr>::from_str(&header)?;
+                let instance = header.instance;
+                set_once(&mut sets.entry(instance).or_default().1, header, instance)?;
+            } else if key.eq_ignore_ascii_case("ARC-Seal") {
+                let header = <ArcSeal as std::str::FromStr>::from_str(&header)?;
+                let instance = header.instance;
+                set_once(&mut sets.entry(instance).or_default().2, header, instance)?;
+            }
+        }
+
+        sets.into_iter()
+            .enumerate()
+            .map(|(idx, (instance, set))| match set {
+                (Some(authentication_results), Some(message_signature), Some(seal))
+                    if instance == idx + 1 =>
+                {
+                    Ok(ArcSet {
+                        authentication_results,
+                        message_signature,
+                        seal,
+                    })
+                }
+                _ => Err(ArcError::InvalidChain {
+                    reason: format!("the instance {} is incomplete or missing", idx + 1),
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|sets| Self { sets })
+    }
+
+    fn get_seal_hash(&self, seal: &ArcSeal, signing: Option<&ArcSet>) -> Vec<u8> {
+        let mut headers = vec![];
+        for set in self.sets.iter().take(seal.instance).chain(signing) {
+            headers.push(set.authentication_results.raw.clone());
+            headers.push(set.message_signature.raw.clone());
+            headers.push(if set.instance() == seal.instance {
+                without_signature(&set.seal.raw)
+            } else {
+                set.seal.raw.clone()
+            });
+        }
+
+        hash_headers(
+            CanonicalizationAlgorithm::Relaxed,
+            seal.signing_algorithm,
+            &headers,
+        )
+    }
+
+    /// Validate the chain, see RFC 8617 section 5.2
+    ///
+    /// `get_public_keys` returns the keys published at the dns name given in argument,
+    /// a signature is valid if one of the keys verifies it.
+    pub fn verify(
+        &self,
+        message: &RawBody,
+        mut get_public_keys: impl FnMut(&str) -> Vec<PublicKey>,
+    ) -> ChainValidation {
+        let last = match self.sets.last() {
+            Some(last) => last,
+            None => return ChainValidation::None,
+        };
+
+        if last.seal.chain_validation == ChainValidation::Fail {
+            return ChainValidation::Fail;
+        }
+
+        if self.sets.iter().any(|set| {
+            set.seal.chain_validation
+                != if set.instance() == 1 {
+                    ChainValidation::None
+                } else {
+                    ChainValidation::Pass
+                }
+        }) {
+            return ChainValidation::Fail;
+        }
+
+        // only the most recent message signature is required to be valid.
+        if !get_public_keys(&last.message_signature.get_dns_query())
+            .iter()
+            .any(|key| last.message_signature.verify(message, key).is_ok())
+        {
+            return ChainValidation::Fail;
+        }
+
+        for set in self.sets.iter().rev() {
+            let hash = self.get_seal_hash(&set.seal, None);
+
+            if !get_public_keys(&set.seal.get_dns_query())
+                .iter()
+                .any(|key| {
+                    verify_hash(set.seal.signing_algorithm, key, &hash, &set.seal.signature).is_ok()
+                })
+            {
+                return ChainValidation::Fail;
+            }
+        }
+
+        ChainValidation::Pass
+    }
+
+    /// Produce the next ARC Set of the chain, see RFC 8617 section 5.1
+    ///
+    /// `authentication_results` is the `authserv-id` followed by the results of
+    /// the authentication checks, and `chain_validation` the result of [`ArcChain::verify`].
+    ///
+    /// # Errors
+    ///
+    /// * the chain has already failed, or has reached the maximum number of sets.
+    /// * the signing failed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal(
+        &self,
+        message: &RawBody,
+        chain_validation: ChainValidation,
+        authentication_results: &str,
+        selector: &str,
+        sdid: &str,
+        headers_field: Vec<String>,
+        private_key: &PrivateKey,
+    ) -> Result<ArcSet, ArcError> {
+        if self.sets.last().map_or(false, |set| {
+            set.seal.chain_validation == ChainValidation::Fail
+        }) {
+            return Err(ArcError::InvalidChain {
+                reason: "the chain has already failed".to_string(),
+            });
+        }
+
+        let instance = self.sets.len() + 1;
+        if instance > MAX_INSTANCE {
+            return Err(ArcError::InvalidChain {
+                reason: format!("the chain cannot have more than {MAX_INSTANCE} sets"),
+            });
+        }
+
+        // only `rsa-sha256` is defined for ARC, see RFC 8617 section 4.1.3
+        let signing_algorithm = private_key.signing_algorithm();
+        if signing_algorithm != SigningAlgorithm::RsaSha256 {
+            return Err(ArcError::UnsupportedAlgorithm { signing_algorithm });
+        }
+        let signature_timestamp = Some(now());
+
+        let authentication_results = <ArcAuthenticationResults as std::str::FromStr>::from_str(
+            &format!("ARC-Authentication-Results: i={instance}; {authentication_results}"),
+        )?;
+
+        let mut message_signature = ArcMessageSignature {
+            instance,
+            signing_algorithm,
+            sdid: sdid.to_string(),
+            selector: selector.to_string(),
+            canonicalization: Canonicalization {
+                header: CanonicalizationAlgorithm::Relaxed,
+                body: CanonicalizationAlgorithm::Relaxed,
+            },
+            signature_timestamp,
+            // the ARC headers are never signed by the message signature
+            headers_field: headers_field
+                .into_iter()
+                .filter(|h| !h.to_lowercase().starts_with("arc-"))
+                .collect(),
+            body_hash: String::default(),
+            signature: String::default(),
+            raw: String::default(),
+        };
+        message_signature.body_hash = message_signature.get_body_hash(message);
+        message_signature.raw = message_signature.to_string();
+        message_signature.signature =
+            sign_hash(private_key, &message_signature.get_header_hash(message))
+                .map_err(|error| ArcError::SigningFailed { error })?;
+        message_signature.raw = message_signature.to_string();
+
+        let mut set = ArcSet {
+            authentication_results,
+            message_signature,
+            seal: ArcSeal {
+                instance,
+                signing_algorithm,
+                sdid: sdid.to_string(),
+                selector: selector.to_string(),
+                signature_timestamp,
+                chain_validation,
+                signature: String::default(),
+                raw: String::default(),
+            },
+        };
+        set.seal.raw = set.seal.to_string();
+        let seal_hash = self.get_seal_hash(&set.seal, Some(&set));
+        set.seal.signature = sign_hash(private_key, &seal_hash)
+            .map_err(|error| ArcError::SigningFailed { error })?;
+        set.seal.raw = set.seal.to_string();
+
+        Ok(set)
+    }
+}
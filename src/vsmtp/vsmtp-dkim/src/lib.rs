@@ -44,7 +44,9 @@
 #![allow(clippy::use_self)] // false positive with enums
 
 mod algorithm;
+mod arc;
 mod canonicalization;
+mod private_key;
 mod public_key;
 mod sign;
 mod signature;
@@ -52,11 +54,17 @@ mod verify;
 
 #[cfg(test)]
 mod tests {
+    mod arc;
     mod verify;
 }
 
 pub use algorithm::{HashAlgorithm, SigningAlgorithm};
+pub use arc::{
+    ArcAuthenticationResults, ArcChain, ArcError, ArcMessageSignature, ArcSeal, ArcSet,
+    ChainValidation,
+};
 pub use canonicalization::{Canonicalization, CanonicalizationAlgorithm};
+pub use private_key::PrivateKey;
 pub use public_key::PublicKey;
 pub use signature::Signature;
 pub use verify::VerifierError;
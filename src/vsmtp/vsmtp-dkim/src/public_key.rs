@@ -27,6 +27,7 @@ pub enum Version {
 #[strum(serialize_all = "lowercase")]
 pub enum Type {
     Rsa,
+    Ed25519,
 }
 
 impl Default for Type {
@@ -16,8 +16,7 @@
 */
 
 use crate::{
-    signature::QueryMethod, Canonicalization, CanonicalizationAlgorithm, Signature,
-    SigningAlgorithm,
+    signature::QueryMethod, Canonicalization, CanonicalizationAlgorithm, PrivateKey, Signature,
 };
 use vsmtp_common::RawBody;
 
@@ -28,11 +27,11 @@ impl Signature {
         selector: &str,
         sdid: &str,
         headers_field: Vec<String>,
-        private_key: &rsa::RsaPrivateKey,
+        private_key: &PrivateKey,
     ) -> Result<Self, rsa::errors::Error> {
         let mut signature = Signature {
             version: 1,
-            signing_algorithm: SigningAlgorithm::RsaSha256,
+            signing_algorithm: private_key.signing_algorithm(),
             sdid: String::default(),
             selector: String::default(),
             canonicalization: Canonicalization {
@@ -67,16 +66,7 @@ impl Signature {
         signature.raw = signature.to_string();
 
         let headers_hash = signature.get_header_hash(message);
-        let headers_hash = private_key.sign(
-            rsa::PaddingScheme::PKCS1v15Sign {
-                hash: Some(match signature.signing_algorithm {
-                    SigningAlgorithm::RsaSha1 => rsa::hash::Hash::SHA1,
-                    SigningAlgorithm::RsaSha256 => rsa::hash::Hash::SHA2_256,
-                }),
-            },
-            &headers_hash,
-        )?;
-        signature.signature = base64::encode(headers_hash);
+        signature.signature = sign_hash(private_key, &headers_hash)?;
 
         signature.raw.push_str(&signature.signature);
 
@@ -84,6 +74,25 @@ impl Signature {
     }
 }
 
+/// Sign the hash of the headers with the private key, the result is encoded in base64.
+pub fn sign_hash(
+    private_key: &PrivateKey,
+    headers_hash: &[u8],
+) -> Result<String, rsa::errors::Error> {
+    match private_key {
+        PrivateKey::Rsa(private_key) => private_key
+            .sign(
+                rsa::PaddingScheme::PKCS1v15Sign {
+                    hash: Some(rsa::hash::Hash::SHA2_256),
+                },
+                headers_hash,
+            )
+            .map(base64::encode),
+        // the hash is signed with PureEdDSA, see RFC 8463 section 3
+        PrivateKey::Ed25519(key_pair) => Ok(base64::encode(key_pair.sign(headers_hash))),
+    }
+}
+
 impl std::fmt::Display for Signature {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
@@ -118,8 +127,9 @@ mod tests {
 
     use crate::{
         public_key::{Type, Version},
-        HashAlgorithm, PublicKey, Signature,
+        HashAlgorithm, PrivateKey, PublicKey, Signature, SigningAlgorithm,
     };
+    use ring::signature::KeyPair;
 
     #[test]
     fn sign_and_verify() {
@@ -148,7 +158,7 @@ mod tests {
                 "Subject".to_string(),
                 "Date".to_string(),
             ],
-            &private_key,
+            &private_key.into(),
         )
         .unwrap();
 
@@ -166,4 +176,89 @@ mod tests {
 
         signature.verify(message.inner(), &key).unwrap();
     }
+
+    #[test]
+    fn sign_and_verify_ed25519() {
+        let pkcs8 =
+            ring::signature::Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                .unwrap();
+        let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref().to_vec();
+        let private_key = PrivateKey::Ed25519(std::sync::Arc::new(key_pair));
+
+        let mut message = MessageBody::try_from(concat!(
+            "From: toto@com\r\n",
+            "To: tata@com\r\n",
+            "Subject: test\r\n",
+            "\r\n",
+            "test\r\n",
+        ))
+        .unwrap();
+
+        let signature = Signature::sign(
+            message.inner(),
+            "foobar",
+            "localhost",
+            vec!["From".to_string(), "To".to_string(), "Subject".to_string()],
+            &private_key,
+        )
+        .unwrap();
+        assert_eq!(signature.signing_algorithm, SigningAlgorithm::Ed25519Sha256);
+
+        message.add_header("DKIM-Signature", &signature.raw["DKIM-Signature: ".len()..]);
+
+        let key = <PublicKey as std::str::FromStr>::from_str(&format!(
+            "v=DKIM1; k=ed25519; p={}",
+            base64::encode(&public_key)
+        ))
+        .unwrap();
+        assert_eq!(key.r#type, Type::Ed25519);
+
+        signature.verify(message.inner(), &key).unwrap();
+
+        // a rsa key is not valid for an ed25519 signature
+        assert!(signature
+            .verify(
+                message.inner(),
+                &PublicKey {
+                    r#type: Type::Rsa,
+                    ..key
+                }
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn generated_keys() {
+        for signing_algorithm in [SigningAlgorithm::RsaSha256, SigningAlgorithm::Ed25519Sha256] {
+            let pem = PrivateKey::generate_pem(signing_algorithm).unwrap();
+
+            let path = std::env::temp_dir().join(format!("generated_{signing_algorithm}.pem"));
+            std::fs::write(&path, pem).unwrap();
+
+            let private_key = PrivateKey::read_pem_file(&path).unwrap();
+            assert_eq!(private_key.signing_algorithm(), signing_algorithm);
+
+            let mut message =
+                MessageBody::try_from(concat!("From: toto@com\r\n", "\r\n", "test\r\n",)).unwrap();
+
+            let signature = Signature::sign(
+                message.inner(),
+                "foobar",
+                "localhost",
+                vec!["From".to_string()],
+                &private_key,
+            )
+            .unwrap();
+
+            message.add_header("DKIM-Signature", &signature.raw["DKIM-Signature: ".len()..]);
+
+            let key =
+                <PublicKey as std::str::FromStr>::from_str(&private_key.dns_record().unwrap())
+                    .unwrap();
+            signature.verify(message.inner(), &key).unwrap();
+        }
+
+        assert!(PrivateKey::generate_pem(SigningAlgorithm::RsaSha1).is_err());
+    }
 }
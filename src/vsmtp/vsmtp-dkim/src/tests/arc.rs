@@ -0,0 +1,164 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    public_key::{Type, Version},
+    ArcChain, ArcError, ChainValidation, HashAlgorithm, PrivateKey, PublicKey,
+};
+use rsa::pkcs8::EncodePublicKey;
+use vsmtp_common::RawBody;
+
+const MAIL: &str = concat!(
+    "From: john.doe@example.com\r\n",
+    "To: list@lists.example.org\r\n",
+    "Subject: test\r\n",
+    "Date: Mon, 1 Jan 2020 00:00:00 +0000\r\n",
+    "\r\n",
+    "test\r\n",
+);
+
+fn get_keys() -> (PrivateKey, PublicKey) {
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = rsa::RsaPublicKey::from(&private_key);
+
+    (
+        private_key.into(),
+        PublicKey {
+            version: Version::Dkim1,
+            acceptable_hash_algorithms: vec![HashAlgorithm::Sha256],
+            r#type: Type::Rsa,
+            notes: None,
+            public_key: public_key.to_public_key_der().unwrap().as_ref().to_vec(),
+            service_type: vec![],
+            flags: vec![],
+        },
+    )
+}
+
+fn headers_field() -> Vec<String> {
+    ["From", "To", "Subject", "Date"]
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+/// verify the chain of the message, and add a new set to it.
+fn seal(message: &mut RawBody, private_key: &PrivateKey, public_key: &PublicKey) {
+    let chain = ArcChain::from_message(message).unwrap();
+    let chain_validation = chain.verify(message, |_| vec![public_key.clone()]);
+
+    let set = chain
+        .seal(
+            message,
+            chain_validation,
+            &format!("mx.example.org; arc={chain_validation}"),
+            "arc",
+            "example.org",
+            headers_field(),
+            private_key,
+        )
+        .unwrap();
+
+    message.prepend_header(
+        set.headers()
+            .into_iter()
+            .map(|(name, value)| format!("{name}: {value}")),
+    );
+}
+
+#[test]
+fn no_chain() {
+    let message = RawBody::from_bytes(MAIL.as_bytes().to_vec()).unwrap();
+    let chain = ArcChain::from_message(&message).unwrap();
+
+    assert!(chain.sets.is_empty());
+    assert_eq!(chain.verify(&message, |_| vec![]), ChainValidation::None);
+}
+
+#[test]
+fn seal_and_verify() {
+    let (private_key, public_key) = get_keys();
+    let mut message = RawBody::from_bytes(MAIL.as_bytes().to_vec()).unwrap();
+
+    seal(&mut message, &private_key, &public_key);
+
+    let chain = ArcChain::from_message(&message).unwrap();
+    assert_eq!(chain.sets.len(), 1);
+    assert_eq!(chain.sets[0].seal.chain_validation, ChainValidation::None);
+    assert_eq!(
+        chain.sets[0].authentication_results.results,
+        "mx.example.org; arc=none"
+    );
+    assert_eq!(
+        chain.verify(&message, |_| vec![public_key.clone()]),
+        ChainValidation::Pass
+    );
+
+    // the keys are unknown
+    assert_eq!(chain.verify(&message, |_| vec![]), ChainValidation::Fail);
+}
+
+#[test]
+fn multiple_hops() {
+    let (private_key, public_key) = get_keys();
+    let mut message = RawBody::from_bytes(MAIL.as_bytes().to_vec()).unwrap();
+
+    seal(&mut message, &private_key, &public_key);
+
+    // a mailing list modifies the message, and seals it again.
+    message.add_header("List-Id", "<list.lists.example.org>");
+    seal(&mut message, &private_key, &public_key);
+
+    let chain = ArcChain::from_message(&message).unwrap();
+    assert_eq!(chain.sets.len(), 2);
+    assert_eq!(chain.sets[1].seal.chain_validation, ChainValidation::Pass);
+    assert_eq!(
+        chain.verify(&message, |_| vec![public_key.clone()]),
+        ChainValidation::Pass
+    );
+}
+
+#[test]
+fn modified_after_seal() {
+    let (private_key, public_key) = get_keys();
+    let mut message = RawBody::from_bytes(MAIL.as_bytes().to_vec()).unwrap();
+
+    seal(&mut message, &private_key, &public_key);
+    message.change_header("Subject", 1, Some("modified"));
+
+    let chain = ArcChain::from_message(&message).unwrap();
+    assert_eq!(
+        chain.verify(&message, |_| vec![public_key.clone()]),
+        ChainValidation::Fail
+    );
+}
+
+#[test]
+fn missing_instance() {
+    let (private_key, public_key) = get_keys();
+    let mut message = RawBody::from_bytes(MAIL.as_bytes().to_vec()).unwrap();
+
+    seal(&mut message, &private_key, &public_key);
+    seal(&mut message, &private_key, &public_key);
+
+    // remove the seal of the first instance
+    message.change_header("ARC-Seal", 2, None);
+
+    assert!(matches!(
+        ArcChain::from_message(&message),
+        Err(ArcError::InvalidChain { .. })
+    ));
+}
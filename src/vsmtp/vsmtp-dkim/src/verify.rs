@@ -15,7 +15,7 @@
  *
 */
 
-use super::{PublicKey, Signature, SigningAlgorithm};
+use super::{public_key::Type, PublicKey, Signature, SigningAlgorithm};
 use vsmtp_common::RawBody;
 
 /// Possible error produced by [`Signature::verify`]
@@ -46,6 +46,9 @@ pub enum VerifierError {
         /// The error produced by the hash function
         error: rsa::errors::Error,
     },
+    /// The hash produced of the headers does not match the Ed25519 signature
+    #[error("headers hash does not match the ed25519 signature")]
+    Ed25519Mismatch,
     /// Not a valid base64 format in the `DKIM-Signature` header
     #[error("base64 error")]
     Base64Error,
@@ -97,35 +100,57 @@ impl Signature {
 
         let headers_hash = self.get_header_hash(message);
 
-        // the type of public_key is not precised in the DNS record,
-        // so we try each format..
+        verify_hash(self.signing_algorithm, key, &headers_hash, &self.signature)
+    }
+}
 
-        let key =
-            <rsa::RsaPublicKey as rsa::pkcs1::DecodeRsaPublicKey>::from_pkcs1_der(&key.public_key)
+/// Verify the `signature` (encoded in base64) of the hash of the headers with the public key.
+pub fn verify_hash(
+    signing_algorithm: SigningAlgorithm,
+    key: &PublicKey,
+    headers_hash: &[u8],
+    signature: &str,
+) -> Result<(), VerifierError> {
+    let signature = base64::decode(signature).map_err(|_| VerifierError::Base64Error)?;
+
+    let hash = match (signing_algorithm, &key.r#type) {
+        (SigningAlgorithm::RsaSha1, Type::Rsa) => rsa::hash::Hash::SHA1,
+        (SigningAlgorithm::RsaSha256, Type::Rsa) => rsa::hash::Hash::SHA2_256,
+        // the key is the raw public key, and the hash is signed with PureEdDSA, see RFC 8463
+        (SigningAlgorithm::Ed25519Sha256, Type::Ed25519) => {
+            return ring::signature::UnparsedPublicKey::new(
+                &ring::signature::ED25519,
+                &key.public_key,
+            )
+            .verify(headers_hash, &signature)
+            .map_err(|_| VerifierError::Ed25519Mismatch);
+        }
+        _ => return Err(VerifierError::KeyFormatInvalid),
+    };
+
+    // the type of public_key is not precised in the DNS record,
+    // so we try each format..
+
+    let key =
+        <rsa::RsaPublicKey as rsa::pkcs1::DecodeRsaPublicKey>::from_pkcs1_der(&key.public_key)
+            .map(Box::new)
+            .or_else(|e| {
+                println!("invalid format: {e}");
+                <rsa::RsaPublicKey as rsa::pkcs8::DecodePublicKey>::from_public_key_der(
+                    &key.public_key,
+                )
                 .map(Box::new)
-                .or_else(|e| {
-                    println!("invalid format: {e}");
-                    <rsa::RsaPublicKey as rsa::pkcs8::DecodePublicKey>::from_public_key_der(
-                        &key.public_key,
-                    )
-                    .map(Box::new)
-                })
-                .map_err(|e| {
-                    println!("invalid format: {e}");
-                    VerifierError::KeyFormatInvalid
-                })?;
+            })
+            .map_err(|e| {
+                println!("invalid format: {e}");
+                VerifierError::KeyFormatInvalid
+            })?;
 
-        rsa::PublicKey::verify(
-            key.as_ref(),
-            rsa::PaddingScheme::PKCS1v15Sign {
-                hash: Some(match self.signing_algorithm {
-                    SigningAlgorithm::RsaSha1 => rsa::hash::Hash::SHA1,
-                    SigningAlgorithm::RsaSha256 => rsa::hash::Hash::SHA2_256,
-                }),
-            },
-            &headers_hash,
-            &base64::decode(&self.signature).map_err(|_| VerifierError::Base64Error)?,
-        )
-        .map_err(|e| VerifierError::HeaderHashMismatch { error: e })
-    }
+    rsa::PublicKey::verify(
+        key.as_ref(),
+        rsa::PaddingScheme::PKCS1v15Sign { hash: Some(hash) },
+        headers_hash,
+        &signature,
+    )
+    .map_err(|e| VerifierError::HeaderHashMismatch { error: e })
 }
@@ -0,0 +1,74 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use vsmtp_common::re::addr;
+
+/// How strictly the domain of an identifier must match the domain of the `From` header.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    strum::EnumString,
+    strum::Display,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum AlignmentMode {
+    /// the organizational domains must be the same
+    #[strum(serialize = "r")]
+    #[serde(rename = "r")]
+    Relaxed,
+    /// the domains must be the same
+    #[strum(serialize = "s")]
+    #[serde(rename = "s")]
+    Strict,
+}
+
+impl Default for AlignmentMode {
+    fn default() -> Self {
+        Self::Relaxed
+    }
+}
+
+/// Get the organizational domain of `domain`, the domain registered under a public suffix,
+/// see RFC 7489 section 3.2
+///
+/// `domain` is returned unchanged if it has no known public suffix.
+#[must_use]
+pub fn organizational_domain(domain: &str) -> String {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+
+    addr::parse_domain_name(&domain)
+        .ok()
+        .and_then(|name| name.root().map(str::to_string))
+        .unwrap_or(domain)
+}
+
+/// Is the domain of an authenticated identifier (`d=` of a DKIM signature,
+/// or the domain of the SPF identity) aligned with the domain of the `From` header.
+#[must_use]
+pub fn is_aligned(from_domain: &str, domain: &str, mode: AlignmentMode) -> bool {
+    match mode {
+        AlignmentMode::Strict => from_domain
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(domain.trim_end_matches('.')),
+        AlignmentMode::Relaxed => {
+            organizational_domain(from_domain) == organizational_domain(domain)
+        }
+    }
+}
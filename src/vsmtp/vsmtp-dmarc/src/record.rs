@@ -0,0 +1,223 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::AlignmentMode;
+
+/// The policy requested by the owner of a domain for the messages failing
+/// the DMARC evaluation.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    strum::EnumString,
+    strum::Display,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiverPolicy {
+    /// no specific action, the results are only reported
+    None,
+    /// the message should be treated as suspicious
+    Quarantine,
+    /// the transaction should be rejected
+    Reject,
+}
+
+impl ReceiverPolicy {
+    /// The policy applied to the messages not selected by the `pct` tag,
+    /// see RFC 7489 section 6.6.4
+    #[must_use]
+    pub const fn downgrade(self) -> Self {
+        match self {
+            Self::Reject => Self::Quarantine,
+            Self::Quarantine | Self::None => Self::None,
+        }
+    }
+}
+
+/// Error while parsing a DMARC record
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    /// the record does not start with `v=DMARC1`
+    #[error("the record must start with `v=DMARC1`")]
+    InvalidVersion,
+    /// a tag is invalid
+    #[error("syntax error: `{reason}`")]
+    SyntaxError {
+        /// the reason of the error
+        reason: String,
+    },
+}
+
+/// The DMARC policy published by a domain in the `_dmarc` TXT record.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Record {
+    /// tag "p="
+    pub receiver_policy: ReceiverPolicy,
+    /// tag "sp="
+    /// the policy of the subdomains, "p=" if missing
+    pub subdomain_policy: Option<ReceiverPolicy>,
+    /// tag "adkim="
+    pub dkim_alignment: AlignmentMode,
+    /// tag "aspf="
+    pub spf_alignment: AlignmentMode,
+    /// tag "pct="
+    /// the percentage of the messages to which the policy is applied
+    pub percentage: u8,
+    /// tag "rua="
+    /// the addresses to which the aggregate reports are sent
+    pub aggregate_report_uris: Vec<String>,
+    /// tag "ri="
+    /// the interval requested between the aggregate reports, in seconds
+    pub report_interval: u32,
+}
+
+impl Record {
+    /// The policy of the domain, or of its subdomains.
+    #[must_use]
+    pub fn policy(&self, is_subdomain: bool) -> ReceiverPolicy {
+        if is_subdomain {
+            self.subdomain_policy.unwrap_or(self.receiver_policy)
+        } else {
+            self.receiver_policy
+        }
+    }
+
+    /// The disposition of a message failing the evaluation, `sample` being
+    /// a random number in `0..100` selecting the message for the `pct` tag.
+    #[must_use]
+    pub fn disposition(&self, is_subdomain: bool, sample: u8) -> ReceiverPolicy {
+        let policy = self.policy(is_subdomain);
+        if sample < self.percentage {
+            policy
+        } else {
+            policy.downgrade()
+        }
+    }
+
+    /// The mail addresses of the `rua` tag, the other uri schemes and the size
+    /// limits are ignored.
+    #[must_use]
+    pub fn aggregate_report_addresses(&self) -> Vec<String> {
+        self.aggregate_report_uris
+            .iter()
+            .filter_map(|uri| uri.strip_prefix("mailto:"))
+            .map(|address| address.split('!').next().unwrap_or(address).to_string())
+            .filter(|address| address.contains('@'))
+            .collect()
+    }
+}
+
+impl std::str::FromStr for Record {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tags = s
+            .split(';')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| {
+                tag.split_once('=')
+                    .map(|(key, value)| (key.trim(), value.trim()))
+                    .ok_or_else(|| ParseError::SyntaxError {
+                        reason: format!("tag syntax is `tag=value`, got `{tag}`"),
+                    })
+            });
+
+        if tags.next() != Some(Ok(("v", "DMARC1"))) {
+            return Err(ParseError::InvalidVersion);
+        }
+
+        let mut receiver_policy = None;
+        let mut subdomain_policy = None;
+        let mut dkim_alignment = AlignmentMode::default();
+        let mut spf_alignment = AlignmentMode::default();
+        let mut percentage = 100;
+        let mut aggregate_report_uris = vec![];
+        let mut report_interval = 86400;
+
+        let syntax_error = |tag: &str, e: &dyn std::fmt::Display| ParseError::SyntaxError {
+            reason: format!("when parsing `{tag}`, got: `{e}`"),
+        };
+
+        for tag in tags {
+            match tag? {
+                ("p", value) => {
+                    receiver_policy = <ReceiverPolicy as std::str::FromStr>::from_str(value).ok();
+                }
+                ("sp", value) => {
+                    subdomain_policy = Some(
+                        <ReceiverPolicy as std::str::FromStr>::from_str(value)
+                            .map_err(|e| syntax_error("sp", &e))?,
+                    );
+                }
+                ("adkim", value) => {
+                    dkim_alignment = <AlignmentMode as std::str::FromStr>::from_str(value)
+                        .map_err(|e| syntax_error("adkim", &e))?;
+                }
+                ("aspf", value) => {
+                    spf_alignment = <AlignmentMode as std::str::FromStr>::from_str(value)
+                        .map_err(|e| syntax_error("aspf", &e))?;
+                }
+                ("pct", value) => {
+                    percentage = value
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|pct| *pct <= 100)
+                        .ok_or_else(|| syntax_error("pct", &"not a number in 0..=100"))?;
+                }
+                ("rua", value) => {
+                    aggregate_report_uris = value
+                        .split(',')
+                        .map(|uri| uri.trim().to_string())
+                        .filter(|uri| !uri.is_empty())
+                        .collect();
+                }
+                ("ri", value) => {
+                    report_interval = value.parse().map_err(|e| syntax_error("ri", &e))?;
+                }
+                // "ruf", "fo", "rf" and the unknown tags are ignored.
+                _ => {}
+            }
+        }
+
+        // NOTE: a record with an invalid or missing "p=" but with a "rua=" tag
+        // is treated as "p=none", see RFC 7489 section 6.6.3
+        let receiver_policy = match receiver_policy {
+            Some(policy) => policy,
+            None if !aggregate_report_uris.is_empty() => ReceiverPolicy::None,
+            None => {
+                return Err(ParseError::SyntaxError {
+                    reason: "missing or invalid required tag `p`".to_string(),
+                })
+            }
+        };
+
+        Ok(Self {
+            receiver_policy,
+            subdomain_policy,
+            dkim_alignment,
+            spf_alignment,
+            percentage,
+            aggregate_report_uris,
+            report_interval,
+        })
+    }
+}
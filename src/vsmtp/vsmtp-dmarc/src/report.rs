@@ -0,0 +1,349 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{ReceiverPolicy, Record};
+use vsmtp_common::re::{
+    anyhow::{self, Context},
+    serde_json,
+};
+
+/// The result of an authentication mechanism for a domain.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AuthResult {
+    /// the domain authenticated
+    pub domain: String,
+    /// the result, "pass", "fail", "none", "temperror", ...
+    pub result: String,
+}
+
+/// The evaluation of a message, recorded to produce the aggregate reports.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Evaluation {
+    /// when the message was evaluated, in seconds since the unix epoch
+    pub timestamp: u64,
+    /// the address of the client which sent the message
+    pub source_ip: std::net::IpAddr,
+    /// the domain of the `From` header
+    pub header_from: String,
+    /// the domain where the policy was found
+    pub policy_domain: String,
+    /// the policy published
+    pub record: Record,
+    /// the disposition applied to the message
+    pub disposition: ReceiverPolicy,
+    /// was a DKIM signature aligned and valid
+    pub dkim_aligned: bool,
+    /// was the SPF identity aligned and valid
+    pub spf_aligned: bool,
+    /// the results of the DKIM signatures
+    pub dkim: Vec<AuthResult>,
+    /// the result of SPF
+    pub spf: AuthResult,
+}
+
+/// the evaluations are stored in one file per policy domain.
+const EXTENSION: &str = "jsonl";
+const TAKEN: &str = "taken";
+
+impl Evaluation {
+    /// Append the evaluation to the file of its policy domain in `dirpath`.
+    ///
+    /// # Errors
+    ///
+    /// * the policy domain is not a valid file name
+    /// * the file cannot be written
+    pub fn store(&self, dirpath: &std::path::Path) -> anyhow::Result<()> {
+        if self.policy_domain.is_empty()
+            || self.policy_domain.starts_with('.')
+            || self.policy_domain.contains(['/', '\\'])
+        {
+            anyhow::bail!("invalid policy domain '{}'", self.policy_domain);
+        }
+
+        std::fs::create_dir_all(dirpath)
+            .with_context(|| format!("cannot create '{}'", dirpath.display()))?;
+
+        let path = dirpath.join(format!("{}.{EXTENSION}", self.policy_domain));
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+
+        std::io::Write::write_all(
+            &mut std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("cannot open '{}'", path.display()))?,
+            line.as_bytes(),
+        )
+        .with_context(|| format!("cannot write to '{}'", path.display()))
+    }
+
+    /// Set aside all the evaluations stored in `dirpath`, and return them grouped by policy domain.
+    ///
+    /// The evaluations are kept until [`Evaluation::remove_taken`] is called for their
+    /// policy domain, and are returned again by the next call otherwise.
+    ///
+    /// # Errors
+    ///
+    /// * the directory or a file cannot be read
+    pub fn take_all(dirpath: &std::path::Path) -> anyhow::Result<Vec<(String, Vec<Self>)>> {
+        if !dirpath.exists() {
+            return Ok(vec![]);
+        }
+
+        for entry in std::fs::read_dir(dirpath)
+            .with_context(|| format!("cannot read '{}'", dirpath.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(std::ffi::OsStr::to_str) != Some(EXTENSION) {
+                continue;
+            }
+
+            // NOTE: the file is moved first, the evaluations stored meanwhile go to a new file.
+            let taken = path.with_extension(format!("{EXTENSION}.{TAKEN}"));
+            if taken.exists() {
+                let content = std::fs::read(&path)
+                    .with_context(|| format!("cannot read '{}'", path.display()))?;
+                std::io::Write::write_all(
+                    &mut std::fs::OpenOptions::new()
+                        .append(true)
+                        .open(&taken)
+                        .with_context(|| format!("cannot open '{}'", taken.display()))?,
+                    &content,
+                )
+                .with_context(|| format!("cannot write to '{}'", taken.display()))?;
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("cannot remove '{}'", path.display()))?;
+            } else {
+                std::fs::rename(&path, &taken)
+                    .with_context(|| format!("cannot move '{}'", path.display()))?;
+            }
+        }
+
+        let mut out = vec![];
+        for entry in std::fs::read_dir(dirpath)
+            .with_context(|| format!("cannot read '{}'", dirpath.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(std::ffi::OsStr::to_str) != Some(TAKEN) {
+                continue;
+            }
+
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("cannot read '{}'", path.display()))?;
+
+            let evaluations = content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<Self>(line).ok())
+                .collect::<Vec<_>>();
+
+            if let Some(first) = evaluations.first() {
+                out.push((first.policy_domain.clone(), evaluations));
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Remove the evaluations of `policy_domain` returned by [`Evaluation::take_all`],
+    /// once they have been reported.
+    ///
+    /// # Errors
+    ///
+    /// * the file cannot be removed
+    pub fn remove_taken(dirpath: &std::path::Path, policy_domain: &str) -> anyhow::Result<()> {
+        let path = dirpath.join(format!("{policy_domain}.{EXTENSION}.{TAKEN}"));
+        match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(error).with_context(|| format!("cannot remove '{}'", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Are the evaluations reported in the same row.
+    fn same_row(&self, other: &Self) -> bool {
+        self.source_ip == other.source_ip
+            && self.header_from == other.header_from
+            && self.disposition == other.disposition
+            && self.dkim_aligned == other.dkim_aligned
+            && self.spf_aligned == other.spf_aligned
+            && self.dkim == other.dkim
+            && self.spf == other.spf
+    }
+}
+
+/// An aggregate feedback report, see RFC 7489 section 7.2
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateReport {
+    /// the name of the organization sending the report
+    pub org_name: String,
+    /// the address to contact the organization
+    pub email: String,
+    /// a unique identifier of the report
+    pub report_id: String,
+    /// the domain where the policy was found
+    pub policy_domain: String,
+    /// the last policy published
+    pub record: Record,
+    /// the timestamp of the first evaluation reported
+    pub begin: u64,
+    /// the timestamp of the last evaluation reported
+    pub end: u64,
+    /// the evaluations grouped by row, with the number of messages for each row
+    pub rows: Vec<(Evaluation, usize)>,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+impl AggregateReport {
+    /// Create the report of the evaluations of a policy domain, or `None` if there is
+    /// no evaluation.
+    #[must_use]
+    pub fn new(
+        org_name: impl Into<String>,
+        email: impl Into<String>,
+        report_id: impl Into<String>,
+        evaluations: Vec<Evaluation>,
+    ) -> Option<Self> {
+        let begin = evaluations.iter().map(|e| e.timestamp).min()?;
+        let end = evaluations.iter().map(|e| e.timestamp).max()?;
+        let last = evaluations.iter().max_by_key(|e| e.timestamp)?;
+        let (policy_domain, record) = (last.policy_domain.clone(), last.record.clone());
+
+        let mut rows = Vec::<(Evaluation, usize)>::new();
+        for evaluation in evaluations {
+            match rows.iter_mut().find(|(row, _)| row.same_row(&evaluation)) {
+                Some((_, count)) => *count += 1,
+                None => rows.push((evaluation, 1)),
+            }
+        }
+
+        Some(Self {
+            org_name: org_name.into(),
+            email: email.into(),
+            report_id: report_id.into(),
+            policy_domain,
+            record,
+            begin,
+            end,
+            rows,
+        })
+    }
+
+    /// The name of the file of the report, see RFC 7489 section 7.2.1.1
+    #[must_use]
+    pub fn filename(&self) -> String {
+        format!(
+            "{}!{}!{}!{}.xml",
+            self.org_name, self.policy_domain, self.begin, self.end
+        )
+    }
+}
+
+/// Serialize the report with the xml schema of RFC 7489 appendix C.
+impl std::fmt::Display for AggregateReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <feedback>\n\
+            \x20 <report_metadata>\n\
+            \x20   <org_name>{}</org_name>\n\
+            \x20   <email>{}</email>\n\
+            \x20   <report_id>{}</report_id>\n\
+            \x20   <date_range>\n\
+            \x20     <begin>{}</begin>\n\
+            \x20     <end>{}</end>\n\
+            \x20   </date_range>\n\
+            \x20 </report_metadata>\n\
+            \x20 <policy_published>\n\
+            \x20   <domain>{}</domain>\n\
+            \x20   <adkim>{}</adkim>\n\
+            \x20   <aspf>{}</aspf>\n\
+            \x20   <p>{}</p>\n\
+            \x20   <sp>{}</sp>\n\
+            \x20   <pct>{}</pct>\n\
+            \x20 </policy_published>\n",
+            escape(&self.org_name),
+            escape(&self.email),
+            escape(&self.report_id),
+            self.begin,
+            self.end,
+            escape(&self.policy_domain),
+            self.record.dkim_alignment,
+            self.record.spf_alignment,
+            self.record.receiver_policy,
+            self.record.policy(true),
+            self.record.percentage,
+        )?;
+
+        let pass_or_fail = |aligned| if aligned { "pass" } else { "fail" };
+
+        for (row, count) in &self.rows {
+            write!(
+                f,
+                "  <record>\n\
+                \x20   <row>\n\
+                \x20     <source_ip>{}</source_ip>\n\
+                \x20     <count>{count}</count>\n\
+                \x20     <policy_evaluated>\n\
+                \x20       <disposition>{}</disposition>\n\
+                \x20       <dkim>{}</dkim>\n\
+                \x20       <spf>{}</spf>\n\
+                \x20     </policy_evaluated>\n\
+                \x20   </row>\n\
+                \x20   <identifiers>\n\
+                \x20     <header_from>{}</header_from>\n\
+                \x20   </identifiers>\n\
+                \x20   <auth_results>\n",
+                row.source_ip,
+                row.disposition,
+                pass_or_fail(row.dkim_aligned),
+                pass_or_fail(row.spf_aligned),
+                escape(&row.header_from),
+            )?;
+
+            for (name, result) in row
+                .dkim
+                .iter()
+                .map(|dkim| ("dkim", dkim))
+                .chain(std::iter::once(("spf", &row.spf)))
+            {
+                write!(
+                    f,
+                    "      <{name}>\n\
+                    \x20       <domain>{}</domain>\n\
+                    \x20       <result>{}</result>\n\
+                    \x20     </{name}>\n",
+                    escape(&result.domain),
+                    escape(&result.result),
+                )?;
+            }
+
+            f.write_str("    </auth_results>\n  </record>\n")?;
+        }
+
+        f.write_str("</feedback>\n")
+    }
+}
@@ -0,0 +1,63 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{is_aligned, organizational_domain, AlignmentMode};
+
+#[test]
+fn organizational() {
+    assert_eq!(organizational_domain("example.com"), "example.com");
+    assert_eq!(organizational_domain("mail.example.com"), "example.com");
+    assert_eq!(organizational_domain("a.b.example.co.uk."), "example.co.uk");
+    assert_eq!(organizational_domain("MAIL.Example.COM"), "example.com");
+}
+
+#[test]
+fn relaxed() {
+    assert!(is_aligned(
+        "example.com",
+        "mail.example.com",
+        AlignmentMode::Relaxed
+    ));
+    assert!(is_aligned(
+        "news.example.com",
+        "example.com",
+        AlignmentMode::Relaxed
+    ));
+    assert!(!is_aligned(
+        "example.com",
+        "example.net",
+        AlignmentMode::Relaxed
+    ));
+    assert!(!is_aligned(
+        "example.co.uk",
+        "other.co.uk",
+        AlignmentMode::Relaxed
+    ));
+}
+
+#[test]
+fn strict() {
+    assert!(is_aligned(
+        "example.com",
+        "EXAMPLE.com.",
+        AlignmentMode::Strict
+    ));
+    assert!(!is_aligned(
+        "example.com",
+        "mail.example.com",
+        AlignmentMode::Strict
+    ));
+}
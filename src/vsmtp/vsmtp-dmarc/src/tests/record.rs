@@ -0,0 +1,107 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{AlignmentMode, ParseError, ReceiverPolicy, Record};
+
+#[test]
+fn default_values() {
+    pretty_assertions::assert_eq!(
+        "v=DMARC1; p=reject".parse::<Record>().unwrap(),
+        Record {
+            receiver_policy: ReceiverPolicy::Reject,
+            subdomain_policy: None,
+            dkim_alignment: AlignmentMode::Relaxed,
+            spf_alignment: AlignmentMode::Relaxed,
+            percentage: 100,
+            aggregate_report_uris: vec![],
+            report_interval: 86400,
+        }
+    );
+}
+
+#[test]
+fn all_tags() {
+    let record = "v=DMARC1;p=quarantine; sp=none ; adkim=s; aspf=r; pct=20; \
+        rua=mailto:dmarc@example.com!10m, https://example.com/dmarc, mailto:other@example.net; \
+        ruf=mailto:forensic@example.com; fo=1; ri=3600; unknown=tag;"
+        .parse::<Record>()
+        .unwrap();
+
+    pretty_assertions::assert_eq!(
+        record,
+        Record {
+            receiver_policy: ReceiverPolicy::Quarantine,
+            subdomain_policy: Some(ReceiverPolicy::None),
+            dkim_alignment: AlignmentMode::Strict,
+            spf_alignment: AlignmentMode::Relaxed,
+            percentage: 20,
+            aggregate_report_uris: vec![
+                "mailto:dmarc@example.com!10m".to_string(),
+                "https://example.com/dmarc".to_string(),
+                "mailto:other@example.net".to_string(),
+            ],
+            report_interval: 3600,
+        }
+    );
+    assert_eq!(
+        record.aggregate_report_addresses(),
+        vec!["dmarc@example.com", "other@example.net"]
+    );
+}
+
+#[test]
+fn invalid() {
+    assert_eq!(
+        "p=reject; v=DMARC1".parse::<Record>().unwrap_err(),
+        ParseError::InvalidVersion
+    );
+    assert_eq!(
+        "v=spf1 -all".parse::<Record>().unwrap_err(),
+        ParseError::InvalidVersion
+    );
+    assert!("v=DMARC1; sp=reject".parse::<Record>().is_err());
+    assert!("v=DMARC1; p=reject; pct=101".parse::<Record>().is_err());
+    assert!("v=DMARC1; p=reject; adkim=x".parse::<Record>().is_err());
+    assert!("v=DMARC1; p reject".parse::<Record>().is_err());
+}
+
+#[test]
+fn missing_policy_with_rua() {
+    assert_eq!(
+        "v=DMARC1; p=invalid; rua=mailto:dmarc@example.com"
+            .parse::<Record>()
+            .unwrap()
+            .receiver_policy,
+        ReceiverPolicy::None
+    );
+}
+
+#[test]
+fn disposition() {
+    let record = "v=DMARC1; p=reject; sp=quarantine; pct=50"
+        .parse::<Record>()
+        .unwrap();
+
+    assert_eq!(record.disposition(false, 0), ReceiverPolicy::Reject);
+    assert_eq!(record.disposition(false, 49), ReceiverPolicy::Reject);
+    assert_eq!(record.disposition(false, 50), ReceiverPolicy::Quarantine);
+    assert_eq!(record.disposition(true, 10), ReceiverPolicy::Quarantine);
+    assert_eq!(record.disposition(true, 99), ReceiverPolicy::None);
+
+    let record = "v=DMARC1; p=quarantine".parse::<Record>().unwrap();
+    assert_eq!(record.policy(true), ReceiverPolicy::Quarantine);
+    assert_eq!(record.disposition(true, 99), ReceiverPolicy::Quarantine);
+}
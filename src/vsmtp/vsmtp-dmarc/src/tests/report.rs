@@ -0,0 +1,185 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{AggregateReport, AuthResult, Evaluation, ReceiverPolicy, Record};
+
+fn evaluation(timestamp: u64, source_ip: &str, dkim: &str) -> Evaluation {
+    Evaluation {
+        timestamp,
+        source_ip: source_ip.parse().unwrap(),
+        header_from: "example.com".to_string(),
+        policy_domain: "example.com".to_string(),
+        record: "v=DMARC1; p=reject; rua=mailto:dmarc@example.com"
+            .parse::<Record>()
+            .unwrap(),
+        disposition: if dkim == "pass" {
+            ReceiverPolicy::None
+        } else {
+            ReceiverPolicy::Reject
+        },
+        dkim_aligned: dkim == "pass",
+        spf_aligned: false,
+        dkim: vec![AuthResult {
+            domain: "example.com".to_string(),
+            result: dkim.to_string(),
+        }],
+        spf: AuthResult {
+            domain: "bounces.example.net".to_string(),
+            result: "pass".to_string(),
+        },
+    }
+}
+
+#[test]
+fn store_and_take() {
+    let dirpath = std::env::temp_dir().join(format!("vsmtp-dmarc-{}", std::process::id()));
+
+    for i in 0..3 {
+        evaluation(i, "192.0.2.1", "pass").store(&dirpath).unwrap();
+    }
+    let mut other = evaluation(3, "192.0.2.1", "pass");
+    other.policy_domain = "example.net".to_string();
+    other.store(&dirpath).unwrap();
+
+    let mut taken = Evaluation::take_all(&dirpath).unwrap();
+    taken.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        taken
+            .iter()
+            .map(|(domain, evaluations)| (domain.as_str(), evaluations.len()))
+            .collect::<Vec<_>>(),
+        vec![("example.com", 3), ("example.net", 1)]
+    );
+
+    // the evaluations not reported yet are merged with the new ones.
+    evaluation(4, "192.0.2.1", "pass").store(&dirpath).unwrap();
+    let mut taken = Evaluation::take_all(&dirpath).unwrap();
+    taken.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        taken
+            .iter()
+            .map(|(domain, evaluations)| (domain.as_str(), evaluations.len()))
+            .collect::<Vec<_>>(),
+        vec![("example.com", 4), ("example.net", 1)]
+    );
+
+    Evaluation::remove_taken(&dirpath, "example.com").unwrap();
+    Evaluation::remove_taken(&dirpath, "example.net").unwrap();
+    Evaluation::remove_taken(&dirpath, "example.net").unwrap();
+    assert!(Evaluation::take_all(&dirpath).unwrap().is_empty());
+
+    let mut invalid = evaluation(0, "192.0.2.1", "pass");
+    invalid.policy_domain = "../passwd".to_string();
+    assert!(invalid.store(&dirpath).is_err());
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
+
+#[test]
+fn aggregate() {
+    let report = AggregateReport::new(
+        "mta.example.org",
+        "noreply-dmarc@mta.example.org",
+        "report-1",
+        vec![
+            evaluation(1_000, "192.0.2.1", "pass"),
+            evaluation(3_000, "192.0.2.2", "fail"),
+            evaluation(2_000, "192.0.2.1", "pass"),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(report.rows.len(), 2);
+    assert_eq!(
+        report.filename(),
+        "mta.example.org!example.com!1000!3000.xml"
+    );
+
+    pretty_assertions::assert_eq!(
+        report.to_string(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feedback>
+  <report_metadata>
+    <org_name>mta.example.org</org_name>
+    <email>noreply-dmarc@mta.example.org</email>
+    <report_id>report-1</report_id>
+    <date_range>
+      <begin>1000</begin>
+      <end>3000</end>
+    </date_range>
+  </report_metadata>
+  <policy_published>
+    <domain>example.com</domain>
+    <adkim>r</adkim>
+    <aspf>r</aspf>
+    <p>reject</p>
+    <sp>reject</sp>
+    <pct>100</pct>
+  </policy_published>
+  <record>
+    <row>
+      <source_ip>192.0.2.1</source_ip>
+      <count>2</count>
+      <policy_evaluated>
+        <disposition>none</disposition>
+        <dkim>pass</dkim>
+        <spf>fail</spf>
+      </policy_evaluated>
+    </row>
+    <identifiers>
+      <header_from>example.com</header_from>
+    </identifiers>
+    <auth_results>
+      <dkim>
+        <domain>example.com</domain>
+        <result>pass</result>
+      </dkim>
+      <spf>
+        <domain>bounces.example.net</domain>
+        <result>pass</result>
+      </spf>
+    </auth_results>
+  </record>
+  <record>
+    <row>
+      <source_ip>192.0.2.2</source_ip>
+      <count>1</count>
+      <policy_evaluated>
+        <disposition>reject</disposition>
+        <dkim>fail</dkim>
+        <spf>fail</spf>
+      </policy_evaluated>
+    </row>
+    <identifiers>
+      <header_from>example.com</header_from>
+    </identifiers>
+    <auth_results>
+      <dkim>
+        <domain>example.com</domain>
+        <result>fail</result>
+      </dkim>
+      <spf>
+        <domain>bounces.example.net</domain>
+        <result>pass</result>
+      </spf>
+    </auth_results>
+  </record>
+</feedback>
+"#
+    );
+
+    assert!(AggregateReport::new("a", "b", "c", vec![]).is_none());
+}
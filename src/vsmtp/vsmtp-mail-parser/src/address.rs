@@ -0,0 +1,138 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+/// Get the addresses of an address list header (`From`, `To`, `Cc` ...), the
+/// display names, the comments and the group names being removed.
+///
+/// see <https://datatracker.ietf.org/doc/html/rfc5322#section-3.4>
+#[must_use]
+pub fn get_addresses(value: &str) -> Vec<String> {
+    fn push(addresses: &mut Vec<String>, current: &mut String, angle: &mut Option<String>) {
+        let address = angle.take().unwrap_or_else(|| std::mem::take(current));
+        current.clear();
+
+        // NOTE: the route of an obsolete angle address is ignored.
+        let address = match address.strip_prefix('@') {
+            Some(route) => route.split_once(':').map_or("", |(_, address)| address),
+            None => &address,
+        };
+        if !address.is_empty() {
+            addresses.push(address.to_string());
+        }
+    }
+
+    let mut addresses = vec![];
+    let mut current = String::new();
+    let mut angle: Option<String> = None;
+    let mut in_angle = false;
+
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let buffer = if in_angle {
+                    angle.get_or_insert_with(String::new)
+                } else {
+                    &mut current
+                };
+                buffer.push(c);
+                while let Some(c) = chars.next() {
+                    buffer.push(c);
+                    match c {
+                        '\\' => buffer.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '(' => {
+                let mut depth = 1;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '(' => depth += 1,
+                        ')' if depth == 1 => break,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            '<' if !in_angle => {
+                in_angle = true;
+                angle = Some(String::new());
+            }
+            '>' if in_angle => in_angle = false,
+            // NOTE: the display name of a group.
+            ':' if !in_angle && angle.is_none() && !current.contains('@') => current.clear(),
+            ',' | ';' if !in_angle => push(&mut addresses, &mut current, &mut angle),
+            c if c.is_whitespace() => {}
+            c if in_angle => angle.get_or_insert_with(String::new).push(c),
+            c => current.push(c),
+        }
+    }
+    push(&mut addresses, &mut current, &mut angle);
+
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_addresses;
+
+    #[test]
+    fn address_list() {
+        assert_eq!(
+            get_addresses(" John Doe <john.doe@example.com>"),
+            vec!["john.doe@example.com"]
+        );
+        assert_eq!(
+            get_addresses(" john.doe@example.com (John Doe)"),
+            vec!["john.doe@example.com"]
+        );
+        assert_eq!(
+            get_addresses(" \"Doe, John @ Home\" <john.doe@example.com>"),
+            vec!["john.doe@example.com"]
+        );
+        assert_eq!(
+            get_addresses(" john@home <john.doe@example.com>"),
+            vec!["john.doe@example.com"]
+        );
+        assert_eq!(
+            get_addresses(" \"john doe\"@example.com"),
+            vec!["\"john doe\"@example.com"]
+        );
+        assert_eq!(
+            get_addresses(" <@relay.example.net:john.doe@example.com>"),
+            vec!["john.doe@example.com"]
+        );
+        assert_eq!(
+            get_addresses(" a@example.com,\r\n\t\"B\" <b@example.org>"),
+            vec!["a@example.com", "b@example.org"]
+        );
+        assert_eq!(
+            get_addresses(" friends: a@example.com, B <b@example.org>; c@example.net"),
+            vec!["a@example.com", "b@example.org", "c@example.net"]
+        );
+        assert_eq!(
+            get_addresses(" undisclosed-recipients:;"),
+            Vec::<String>::new()
+        );
+        assert_eq!(get_addresses(""), Vec::<String>::new());
+    }
+}
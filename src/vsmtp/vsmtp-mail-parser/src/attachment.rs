@@ -0,0 +1,334 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::decoder::{decode_body, decode_header_value, decode_text, to_utf8};
+use crate::helpers::get_mime_type;
+use vsmtp_common::{re::anyhow, BodyType, Mail, Mime, MimeBodyType};
+
+/// a file attached to a message, with its content decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// name of the file, from the `Content-Disposition` or `Content-Type` headers.
+    pub filename: Option<String>,
+    /// mime type of the file, like `application/pdf`.
+    pub content_type: String,
+    /// decoded octets of the file.
+    pub content: Vec<u8>,
+}
+
+impl Attachment {
+    /// size of the decoded content, in bytes.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.content.len()
+    }
+
+    /// sha256 hash of the decoded content, as a lowercase hexadecimal string.
+    #[must_use]
+    pub fn sha256(&self) -> String {
+        <sha2::Sha256 as sha2::Digest>::digest(&self.content)
+            .iter()
+            .fold(String::with_capacity(64), |mut hash, byte| {
+                // writing to a `String` cannot fail.
+                let _ = std::fmt::Write::write_fmt(&mut hash, format_args!("{byte:02x}"));
+                hash
+            })
+    }
+}
+
+/// a regular mime part of a message, with its content decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// mime type of the part, like `text/html`.
+    pub content_type: String,
+    /// name of the file, from the `Content-Disposition` or `Content-Type` headers.
+    pub filename: Option<String>,
+    /// is the part an attachment, see [`get_attachments`].
+    pub is_attachment: bool,
+    /// decoded octets of the part.
+    pub content: Vec<u8>,
+    /// decoded content converted to utf8, for the `text/*` parts.
+    pub text: Option<String>,
+}
+
+/// list the regular mime parts of a message, including those of the embedded messages,
+/// in the order of the message.
+///
+/// # Errors
+///
+/// * the content of a part cannot be decoded
+pub fn get_parts(mail: &Mail) -> anyhow::Result<Vec<Part>> {
+    let mut regular = vec![];
+    if let BodyType::Mime(mime) = &mail.body {
+        collect_regular(mime, &mut regular);
+    }
+
+    regular
+        .into_iter()
+        .map(|mime| {
+            let content_type = get_content_type(mime);
+            Ok(Part {
+                filename: get_filename(mime),
+                is_attachment: is_attachment(mime),
+                content: decode_body(mime)?,
+                text: if content_type.starts_with("text/") {
+                    Some(decode_text(mime)?)
+                } else {
+                    None
+                },
+                content_type,
+            })
+        })
+        .collect()
+}
+
+/// list the attachments of a message, including those of the embedded messages.
+///
+/// a mime part is an attachment if its disposition is `attachment` or if it has a filename.
+///
+/// # Errors
+///
+/// * the content of an attachment cannot be decoded
+pub fn get_attachments(mail: &Mail) -> anyhow::Result<Vec<Attachment>> {
+    let mut regular = vec![];
+    if let BodyType::Mime(mime) = &mail.body {
+        collect_regular(mime, &mut regular);
+    }
+
+    regular
+        .into_iter()
+        .filter(|mime| is_attachment(mime))
+        .map(|mime| {
+            Ok(Attachment {
+                filename: get_filename(mime),
+                content_type: get_content_type(mime),
+                content: decode_body(mime)?,
+            })
+        })
+        .collect()
+}
+
+fn collect_regular<'a>(mime: &'a Mime, regular: &mut Vec<&'a Mime>) {
+    match &mime.content {
+        MimeBodyType::Multipart(multipart) => {
+            for part in &multipart.parts {
+                collect_regular(part, regular);
+            }
+        }
+        MimeBodyType::Embedded(mail) => {
+            if let BodyType::Mime(mime) = &mail.body {
+                collect_regular(mime, regular);
+            }
+        }
+        MimeBodyType::Regular(_) => regular.push(mime),
+    }
+}
+
+fn get_content_type(mime: &Mime) -> String {
+    get_mime_type(&mime.headers, None).map_or_else(
+        |_| "application/octet-stream".to_string(),
+        |(t, s)| format!("{t}/{s}"),
+    )
+}
+
+/// the disposition type is case-insensitive, and can be followed by parameters.
+///
+/// see <https://datatracker.ietf.org/doc/html/rfc2183#section-2>
+fn is_attachment(mime: &Mime) -> bool {
+    let disposition = mime
+        .headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("content-disposition"))
+        .and_then(|h| h.value.split(';').next())
+        .map(str::trim);
+
+    disposition.map_or(false, |d| d.eq_ignore_ascii_case("attachment"))
+        || get_filename(mime).is_some()
+}
+
+/// get the filename of a mime part, either from the `filename` parameter
+/// of `Content-Disposition` or the `name` parameter of `Content-Type`.
+fn get_filename(mime: &Mime) -> Option<String> {
+    let get_arg = |header: &str, arg: &str| {
+        mime.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(header))
+            .and_then(|h| h.args.get(arg))
+    };
+
+    get_arg("content-disposition", "filename*")
+        .or_else(|| get_arg("content-type", "name*"))
+        .and_then(|value| decode_extended_value(value))
+        .or_else(|| {
+            get_arg("content-disposition", "filename")
+                .or_else(|| get_arg("content-type", "name"))
+                .map(|value| decode_header_value(value))
+        })
+}
+
+/// decode a parameter value like `utf-8'en'%C3%A9t%C3%A9.pdf`.
+///
+/// see <https://datatracker.ietf.org/doc/html/rfc2231#section-4>
+fn decode_extended_value(value: &str) -> Option<String> {
+    let mut split = value.splitn(3, '\'');
+    let (charset, _language, encoded) = (split.next()?, split.next()?, split.next()?);
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.bytes();
+    while let Some(c) = iter.next() {
+        if c == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(c);
+        }
+    }
+
+    Some(to_utf8(&bytes, charset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MailMimeParser;
+    use vsmtp_common::{MailParser, MimeHeader};
+
+    #[test]
+    fn list_attachments() {
+        let mail = MailMimeParser::default()
+            .parse_lines(&[
+                "From: a@example.com",
+                "Date: Tue, 30 Nov 2021 20:54:27 +0100",
+                "MIME-Version: 1.0",
+                "Content-Type: multipart/mixed; boundary=\"frontier\"",
+                "",
+                "--frontier",
+                "Content-Type: text/plain; charset=us-ascii",
+                "",
+                "see attached",
+                "--frontier",
+                "Content-Type: application/x-msdownload",
+                "Content-Disposition: attachment; filename=\"invoice.exe\"",
+                "Content-Transfer-Encoding: base64",
+                "",
+                "TVqQAA==",
+                "--frontier",
+                "Content-Type: text/plain; name=\"=?utf-8?B?w6l0w6kudHh0?=\"",
+                "",
+                "summer",
+                "--frontier--",
+            ])
+            .unwrap()
+            .unwrap_right();
+
+        let attachments = get_attachments(&mail).unwrap();
+
+        assert_eq!(
+            attachments,
+            vec![
+                Attachment {
+                    filename: Some("invoice.exe".to_string()),
+                    content_type: "application/x-msdownload".to_string(),
+                    content: b"MZ\x90\x00".to_vec(),
+                },
+                Attachment {
+                    filename: Some("été.txt".to_string()),
+                    content_type: "text/plain".to_string(),
+                    content: b"summer".to_vec(),
+                },
+            ]
+        );
+        assert_eq!(attachments[0].size(), 4);
+        assert_eq!(
+            attachments[1].sha256(),
+            "e83664255c6963e962bb20f9fcfaad1b570ddf5da69f5444ed37e5260f3ef689"
+        );
+    }
+
+    #[test]
+    fn disposition_case_and_parameters() {
+        let mime = |value: &str| Mime {
+            headers: vec![MimeHeader {
+                name: "Content-Disposition".to_string(),
+                value: value.to_string(),
+                args: std::collections::HashMap::new(),
+            }],
+            content: MimeBodyType::Regular(vec!["foo".to_string()]),
+        };
+
+        assert!(is_attachment(&mime("attachment")));
+        assert!(is_attachment(&mime("ATTACHMENT")));
+        assert!(is_attachment(&mime("Attachment ; size=3")));
+        assert!(!is_attachment(&mime("inline")));
+        assert!(!is_attachment(&mime("attachments")));
+    }
+
+    #[test]
+    fn list_parts() {
+        let mail = MailMimeParser::default()
+            .parse_lines(&[
+                "From: a@example.com",
+                "Date: Tue, 30 Nov 2021 20:54:27 +0100",
+                "MIME-Version: 1.0",
+                "Content-Type: multipart/mixed; boundary=\"frontier\"",
+                "",
+                "--frontier",
+                "Content-Type: text/plain; charset=iso-8859-1",
+                "Content-Transfer-Encoding: quoted-printable",
+                "",
+                "=E9t=E9",
+                "--frontier",
+                "Content-Type: application/octet-stream",
+                "Content-Disposition: ATTACHMENT; size=4",
+                "Content-Transfer-Encoding: base64",
+                "",
+                "TVqQAA==",
+                "--frontier--",
+            ])
+            .unwrap()
+            .unwrap_right();
+
+        assert_eq!(
+            get_parts(&mail).unwrap(),
+            vec![
+                Part {
+                    content_type: "text/plain".to_string(),
+                    filename: None,
+                    is_attachment: false,
+                    content: b"\xE9t\xE9".to_vec(),
+                    text: Some("été".to_string()),
+                },
+                Part {
+                    content_type: "application/octet-stream".to_string(),
+                    filename: None,
+                    is_attachment: true,
+                    content: b"MZ\x90\x00".to_vec(),
+                    text: None,
+                },
+            ]
+        );
+        assert_eq!(get_attachments(&mail).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn extended_filename() {
+        assert_eq!(
+            decode_extended_value("utf-8''%C3%A9t%C3%A9.pdf"),
+            Some("été.pdf".to_string())
+        );
+        assert_eq!(decode_extended_value("no-quotes"), None);
+    }
+}
@@ -0,0 +1,271 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use vsmtp_common::{
+    re::{anyhow, base64},
+    Mime, MimeBodyType, MimeHeader,
+};
+
+/// get the value of a mime header, the name must be lowercase.
+fn find_header<'a>(headers: &'a [MimeHeader], name: &str) -> Option<&'a MimeHeader> {
+    headers.iter().find(|h| h.name == name)
+}
+
+/// return the octets of a regular mime part, decoded following its
+/// `Content-Transfer-Encoding` header.
+///
+/// see <https://datatracker.ietf.org/doc/html/rfc2045#section-6>
+///
+/// # Errors
+///
+/// * the part is not a regular section (multipart or embedded message)
+/// * the base64 content is invalid
+pub fn decode_body(mime: &Mime) -> anyhow::Result<Vec<u8>> {
+    let lines = match &mime.content {
+        MimeBodyType::Regular(lines) => lines,
+        MimeBodyType::Multipart(_) | MimeBodyType::Embedded(_) => {
+            anyhow::bail!("only regular mime sections can be decoded")
+        }
+    };
+
+    match find_header(&mime.headers, "content-transfer-encoding").map(|h| h.value.as_str()) {
+        Some("base64") => Ok(base64::decode(
+            lines
+                .iter()
+                .flat_map(|line| line.bytes())
+                .filter(|c| !c.is_ascii_whitespace())
+                .collect::<Vec<_>>(),
+        )?),
+        Some("quoted-printable") => Ok(decode_quoted_printable(lines)),
+        // 7bit, 8bit, binary or unknown encodings are kept as is.
+        _ => Ok(lines.join("\r\n").into_bytes()),
+    }
+}
+
+/// return the content of a regular mime part decoded and converted to utf8,
+/// using the `charset` parameter of its `Content-Type` header (us-ascii by default).
+///
+/// # Errors
+///
+/// * see [`decode_body`]
+pub fn decode_text(mime: &Mime) -> anyhow::Result<String> {
+    let charset = find_header(&mime.headers, "content-type")
+        .and_then(|h| h.args.get("charset"))
+        .map_or("us-ascii", String::as_str);
+
+    Ok(to_utf8(&decode_body(mime)?, charset))
+}
+
+/// convert octets encoded with `charset` to utf8, invalid sequences and
+/// unknown charsets are replaced lossily.
+#[must_use]
+pub fn to_utf8(bytes: &[u8], charset: &str) -> String {
+    encoding_rs::Encoding::for_label(charset.trim().as_bytes())
+        .unwrap_or(encoding_rs::UTF_8)
+        .decode(bytes)
+        .0
+        .into_owned()
+}
+
+/// decode the "encoded-word" of a header value, like `=?utf-8?B?w6l0w6k=?=`.
+/// the whitespaces between two adjacent encoded-words are removed.
+///
+/// see <https://datatracker.ietf.org/doc/html/rfc2047>
+#[must_use]
+pub fn decode_header_value(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut previous_is_encoded = false;
+
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+
+        if let Some((decoded, consumed)) = decode_encoded_word(candidate) {
+            if !(previous_is_encoded && before.trim().is_empty()) {
+                output.push_str(before);
+            }
+            output.push_str(&decoded);
+            rest = &candidate[consumed..];
+            previous_is_encoded = true;
+        } else {
+            output.push_str(before);
+            output.push_str("=?");
+            rest = &candidate[2..];
+            previous_is_encoded = false;
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// decode one encoded-word at the start of `input` (`=?charset?encoding?text?=`),
+/// and return the decoded text and the length of the encoded-word.
+fn decode_encoded_word(input: &str) -> Option<(String, usize)> {
+    let mut parts = input.get(2..)?.splitn(3, '?');
+    let (charset, encoding, rest) = (parts.next()?, parts.next()?, parts.next()?);
+    let text = &rest[..rest.find("?=")?];
+
+    if charset.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let bytes = match encoding {
+        "B" | "b" => base64::decode(text).ok()?,
+        "Q" | "q" => decode_hex_escapes(text.as_bytes(), true),
+        _ => return None,
+    };
+
+    Some((
+        // the charset can be followed by a language, see rfc2231 section 5.
+        to_utf8(&bytes, charset.split('*').next()?),
+        2 + charset.len() + 1 + encoding.len() + 1 + text.len() + 2,
+    ))
+}
+
+/// see <https://datatracker.ietf.org/doc/html/rfc2045#section-6.7>
+fn decode_quoted_printable(lines: &[String]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(lines.iter().map(String::len).sum());
+
+    for (idx, line) in lines.iter().enumerate() {
+        // trailing whitespaces are added by transport and must be removed.
+        let line = line.trim_end_matches([' ', '\t']);
+
+        if let Some(line) = line.strip_suffix('=') {
+            // soft line break
+            output.extend(decode_hex_escapes(line.as_bytes(), false));
+        } else {
+            output.extend(decode_hex_escapes(line.as_bytes(), false));
+            if idx != lines.len() - 1 {
+                output.extend_from_slice(b"\r\n");
+            }
+        }
+    }
+
+    output
+}
+
+/// replace the `=XX` sequences by their octets, and `_` by a space
+/// if `underscore_is_space` (the "Q" encoding of rfc2047).
+/// invalid sequences are kept as is.
+fn decode_hex_escapes(input: &[u8], underscore_is_space: bool) -> Vec<u8> {
+    fn hex_value(c: u8) -> Option<u8> {
+        char::from(c)
+            .to_digit(16)
+            .and_then(|d| u8::try_from(d).ok())
+    }
+
+    let mut output = Vec::with_capacity(input.len());
+    let mut idx = 0;
+
+    while idx < input.len() {
+        match input[idx] {
+            b'=' => {
+                match (
+                    input.get(idx + 1).copied().and_then(hex_value),
+                    input.get(idx + 2).copied().and_then(hex_value),
+                ) {
+                    (Some(high), Some(low)) => {
+                        output.push(high << 4 | low);
+                        idx += 3;
+                        continue;
+                    }
+                    _ => output.push(b'='),
+                }
+            }
+            b'_' if underscore_is_space => output.push(b' '),
+            c => output.push(c),
+        }
+        idx += 1;
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_mime_header;
+
+    fn mime(headers: &[(&str, &str)], lines: &[&str]) -> Mime {
+        Mime {
+            headers: headers
+                .iter()
+                .map(|(name, value)| get_mime_header(name, value))
+                .collect(),
+            content: MimeBodyType::Regular(lines.iter().map(ToString::to_string).collect()),
+        }
+    }
+
+    #[test]
+    fn base64() {
+        let part = mime(
+            &[
+                ("content-type", "text/plain; charset=utf-8"),
+                ("content-transfer-encoding", "base64"),
+            ],
+            &["w6l0w6kgw6AgbGEg", "cGxhZ2U="],
+        );
+
+        assert_eq!(decode_text(&part).unwrap(), "été à la plage");
+    }
+
+    #[test]
+    fn quoted_printable() {
+        let part = mime(
+            &[
+                ("content-type", "text/plain; charset=iso-8859-1"),
+                ("content-transfer-encoding", "quoted-printable"),
+            ],
+            &["caf=E9 au lait, tr=", "=E8s chaud  ", "=3D fin"],
+        );
+
+        assert_eq!(
+            decode_body(&part).unwrap(),
+            b"caf\xe9 au lait, tr\xe8s chaud\r\n= fin"
+        );
+        assert_eq!(
+            decode_text(&part).unwrap(),
+            "café au lait, très chaud\r\n= fin"
+        );
+    }
+
+    #[test]
+    fn no_encoding() {
+        let part = mime(&[], &["hello", "world"]);
+        assert_eq!(decode_text(&part).unwrap(), "hello\r\nworld");
+    }
+
+    #[test]
+    fn invalid_base64() {
+        let part = mime(&[("content-transfer-encoding", "base64")], &["!!!"]);
+        assert!(decode_body(&part).is_err());
+    }
+
+    #[test]
+    fn encoded_words() {
+        assert_eq!(
+            decode_header_value("=?utf-8?B?w6l0w6k=?= =?ISO-8859-1?Q?=E0_la_plage?="),
+            "étéà la plage"
+        );
+        assert_eq!(
+            decode_header_value("Re: =?utf-8?q?caf=C3=A9?= du matin"),
+            "Re: café du matin"
+        );
+        assert_eq!(decode_header_value("=?not encoded?="), "=?not encoded?=");
+        assert_eq!(decode_header_value("plain text"), "plain text");
+    }
+}
@@ -25,10 +25,16 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
+mod address;
+mod attachment;
+mod decoder;
 mod error;
 mod helpers;
 mod parser;
 
+pub use address::get_addresses;
+pub use attachment::{get_attachments, get_parts, Attachment, Part};
+pub use decoder::{decode_body, decode_header_value, decode_text, to_utf8};
 pub use parser::get_mime_header;
 pub use parser::MailMimeParser;
 
@@ -0,0 +1,199 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    dsl::service::{Service, ServiceAddress},
+    modules::EngineResult,
+};
+use rhai::EvalAltResult;
+use vsmtp_common::re::{anyhow, log};
+
+/// size of the chunks sent to clamd with the `INSTREAM` command.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub fn parse_clamav_service(
+    context: &mut rhai::EvalContext,
+    input: &[rhai::Expression],
+    service_name: &str,
+) -> EngineResult<Service> {
+    let options: rhai::Map = context
+        .eval_expression_tree(&input[3])?
+        .try_cast()
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            "clamav service options must be a map".into()
+        })?;
+
+    let address = options
+        .get("address")
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            format!("clamav service {service_name} is missing the 'address' option.").into()
+        })?
+        .to_string()
+        .parse::<ServiceAddress>()
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+
+    let timeout: std::time::Duration = options
+        .get("timeout")
+        .map_or_else(|| "30s".to_string(), ToString::to_string)
+        .parse::<vsmtp_config::re::humantime::Duration>()
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?
+        .into();
+
+    Ok(Service::ClamAV { address, timeout })
+}
+
+/// Verdict of clamd for a scanned content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    /// no virus found.
+    Clean,
+    /// a virus was found, with its signature name.
+    Infected(String),
+}
+
+/// scan `content` with the `INSTREAM` command of clamd.
+///
+/// # Errors
+///
+/// * the connection to clamd failed or timed out.
+/// * clamd replied with an error (for example if `StreamMaxLength` is exceeded).
+pub fn scan(
+    address: &ServiceAddress,
+    timeout: &std::time::Duration,
+    content: &[u8],
+) -> anyhow::Result<ScanResult> {
+    log::trace!("scanning {} bytes with clamd at '{address}'", content.len());
+
+    parse_response(&instream(address.connect(timeout)?, content)?)
+}
+
+/// send the content in chunks, each prefixed by its length as a 4 bytes
+/// big endian integer, and terminated by a chunk of length 0.
+fn instream<S: std::io::Read + std::io::Write>(
+    mut stream: S,
+    content: &[u8],
+) -> anyhow::Result<String> {
+    stream.write_all(b"zINSTREAM\0")?;
+    for chunk in content.chunks(CHUNK_SIZE) {
+        stream.write_all(&u32::try_from(chunk.len())?.to_be_bytes())?;
+        stream.write_all(chunk)?;
+    }
+    stream.write_all(&0_u32.to_be_bytes())?;
+    stream.flush()?;
+
+    // the response is terminated by a null character with the `z` prefix.
+    let mut response = vec![];
+    std::io::BufRead::read_until(&mut std::io::BufReader::new(stream), b'\0', &mut response)?;
+
+    Ok(String::from_utf8_lossy(&response)
+        .trim_end_matches(['\0', '\n'])
+        .to_string())
+}
+
+/// parse a response like `stream: OK` or `stream: Eicar-Signature FOUND`.
+fn parse_response(response: &str) -> anyhow::Result<ScanResult> {
+    let verdict = response
+        .strip_prefix("stream:")
+        .map_or(response, str::trim_start);
+
+    if verdict == "OK" {
+        Ok(ScanResult::Clean)
+    } else if let Some(virus) = verdict.strip_suffix(" FOUND") {
+        Ok(ScanResult::Infected(virus.to_string()))
+    } else {
+        anyhow::bail!("clamd replied with an error: '{response}'")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a minimal clamd answering to one `INSTREAM` command, finding a virus
+    /// if the content contains "EICAR".
+    fn fake_clamd() -> std::net::SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut command = [0; 10];
+            std::io::Read::read_exact(&mut stream, &mut command).unwrap();
+            assert_eq!(&command, b"zINSTREAM\0");
+
+            let mut content = vec![];
+            loop {
+                let mut size = [0; 4];
+                std::io::Read::read_exact(&mut stream, &mut size).unwrap();
+                let size = u32::from_be_bytes(size) as usize;
+                if size == 0 {
+                    break;
+                }
+                let mut chunk = vec![0; size];
+                std::io::Read::read_exact(&mut stream, &mut chunk).unwrap();
+                content.extend(chunk);
+            }
+
+            let response: &[u8] = if content.windows(5).any(|w| w == b"EICAR") {
+                b"stream: Eicar-Test-Signature FOUND\0"
+            } else {
+                b"stream: OK\0"
+            };
+            std::io::Write::write_all(&mut stream, response).unwrap();
+        });
+
+        address
+    }
+
+    #[test]
+    fn clean() {
+        let address = ServiceAddress::Tcp(fake_clamd().to_string());
+        assert_eq!(
+            scan(
+                &address,
+                &std::time::Duration::from_secs(5),
+                &[b'a'; 100_000]
+            )
+            .unwrap(),
+            ScanResult::Clean
+        );
+    }
+
+    #[test]
+    fn infected() {
+        let address = ServiceAddress::Tcp(fake_clamd().to_string());
+        assert_eq!(
+            scan(
+                &address,
+                &std::time::Duration::from_secs(5),
+                b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*"
+            )
+            .unwrap(),
+            ScanResult::Infected("Eicar-Test-Signature".to_string())
+        );
+    }
+
+    #[test]
+    fn error() {
+        assert!(parse_response("INSTREAM size limit exceeded. ERROR").is_err());
+        assert!(scan(
+            &"unix:/does/not/exist.sock".parse().unwrap(),
+            &std::time::Duration::from_secs(1),
+            b""
+        )
+        .is_err());
+    }
+}
@@ -15,96 +15,393 @@
  *
 */
 
-use std::{io::Write, str::FromStr};
+use std::{
+    io::{Read, Write},
+    str::FromStr,
+};
 
-use vsmtp_common::re::anyhow::{self, Context};
+use vsmtp_common::{
+    libc_abstraction::{inotify_add_watch, inotify_init, inotify_rm_watch},
+    re::{
+        anyhow::{self, Context},
+        libc, log,
+    },
+};
 
 use crate::{dsl::service::Service, modules::EngineResult};
 
 use super::{AccessMode, Refresh};
 
-/// query a record matching the first element.
-pub fn query_key(
-    path: &std::path::PathBuf,
-    delimiter: u8,
-    _: &Refresh,
-    fd: &std::fs::File,
-    key: &str,
-) -> anyhow::Result<Option<csv::StringRecord>> {
-    let mut reader = csv::ReaderBuilder::new()
+/// the modification time and the size of a file, to detect a change.
+type Version = (std::time::SystemTime, u64);
+
+fn version(path: &std::path::Path) -> anyhow::Result<Version> {
+    let metadata =
+        std::fs::metadata(path).with_context(|| format!("failed to stat csv database {path:?}"))?;
+
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+fn reader<R: Read>(delimiter: u8, input: R) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
         .has_headers(false)
         .trim(csv::Trim::All)
         .delimiter(delimiter)
-        .from_reader(fd);
+        // records can have a different number of fields, like a list of aliases.
+        .flexible(true)
+        .from_reader(input)
+}
+
+/// the records of a csv file, by their first field.
+#[derive(Default)]
+struct Index {
+    records: std::collections::HashMap<String, csv::StringRecord>,
+    /// version of the file when it was read, none if it was never read.
+    version: Option<Version>,
+}
+
+/// the changes of a file notified by the system.
+///
+/// the parent directory is watched, to see the file replaced by a rename.
+struct Watcher {
+    inotify: std::sync::Arc<std::fs::File>,
+    watch: i32,
+    changed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Watcher {
+    // struct inotify_event { int wd; uint32_t mask; uint32_t cookie; uint32_t len; char name[]; }
+    const EVENT_SIZE: usize = 16;
+
+    fn new(path: &std::path::Path) -> anyhow::Result<Self> {
+        let directory = match path.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => std::path::Path::new("."),
+            Some(parent) => parent,
+            None => anyhow::bail!("csv database {path:?} is not a file"),
+        };
+        let filename = path
+            .file_name()
+            .map(|filename| std::os::unix::ffi::OsStrExt::as_bytes(filename).to_vec())
+            .ok_or_else(|| anyhow::anyhow!("csv database {path:?} is not a file"))?;
+
+        let inotify = std::sync::Arc::new(inotify_init()?);
+        let watch = inotify_add_watch(
+            &inotify,
+            directory,
+            libc::IN_MODIFY
+                | libc::IN_CLOSE_WRITE
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_TO,
+        )
+        .with_context(|| format!("failed to watch csv database {path:?}"))?;
+        let changed = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let (events, flag) = (inotify.clone(), changed.clone());
+        std::thread::Builder::new()
+            .name("csv-watcher".to_string())
+            .spawn(move || Self::run(&events, &filename, &flag))?;
+
+        Ok(Self {
+            inotify,
+            watch,
+            changed,
+        })
+    }
 
-    for record in reader.records() {
-        match record {
-            Ok(record) => {
-                if record.get(0).filter(|fst| *fst == key).is_some() {
-                    return Ok(Some(record));
+    fn run(inotify: &std::fs::File, filename: &[u8], changed: &std::sync::atomic::AtomicBool) {
+        let mut buffer = [0; 4096];
+
+        loop {
+            let length = match (&mut &*inotify).read(&mut buffer) {
+                Ok(length) => length,
+                Err(error) => {
+                    log::warn!("csv database watcher stopped: {error}");
+                    return;
                 }
+            };
+
+            let mut events = &buffer[..length];
+            while events.len() >= Self::EVENT_SIZE {
+                let field = |offset: usize| {
+                    u32::from_ne_bytes([
+                        events[offset],
+                        events[offset + 1],
+                        events[offset + 2],
+                        events[offset + 3],
+                    ])
+                };
+                let (mask, name_length) = (field(4), field(12) as usize);
+
+                // the watch has been removed, the database was dropped.
+                if mask & libc::IN_IGNORED != 0 {
+                    return;
+                }
+
+                // the name is padded with null bytes.
+                let name = &events[Self::EVENT_SIZE..Self::EVENT_SIZE + name_length];
+                if name.split(|byte| *byte == 0).next() == Some(filename) {
+                    changed.store(true, std::sync::atomic::Ordering::Release);
+                }
+
+                events = &events[Self::EVENT_SIZE + name_length..];
             }
-            Err(err) => anyhow::bail!(
-                "tried to read from csv database {path:?}, but an error occurred: {err}"
-            ),
-        };
+        }
     }
 
-    Ok(None)
+    fn take(&self) -> bool {
+        self.changed
+            .swap(false, std::sync::atomic::Ordering::AcqRel)
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // wakes up the thread reading the events with `IN_IGNORED`.
+        if let Err(error) = inotify_rm_watch(&self.inotify, self.watch) {
+            log::warn!("failed to stop watching a csv database: {error}");
+        }
+    }
 }
 
-/// add a record to the csv database.
-pub fn add_record(
-    path: &std::path::PathBuf,
+/// a csv file, indexed in memory by the first field of its records.
+pub struct Database {
+    path: std::path::PathBuf,
     delimiter: u8,
-    fd: &std::fs::File,
-    record: &[String],
-) -> anyhow::Result<()> {
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(false)
-        .delimiter(delimiter)
-        .from_writer(fd);
+    access: AccessMode,
+    refresh: Refresh,
+    index: std::sync::RwLock<Index>,
+    /// last time the version of the file was checked, for a periodic refresh.
+    checked: std::sync::Mutex<std::time::Instant>,
+    watcher: Option<Watcher>,
+}
 
-    writer
-        .write_record(record)
-        .context(format!("failed to write to csv database at {path:?}"))?;
+impl std::fmt::Debug for Database {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Database")
+            .field("path", &self.path)
+            .field("access", &self.access)
+            .field("refresh", &self.refresh)
+            .finish_non_exhaustive()
+    }
+}
 
-    writer
-        .flush()
-        .context(format!("failed to write to csv database at {path:?}"))?;
+impl Database {
+    /// open a csv file and read its records if it is readable.
+    ///
+    /// # Errors
+    ///
+    /// * the file cannot be opened with the access mode.
+    /// * the file is not a valid csv file.
+    /// * the file cannot be watched, for a `notify` refresh.
+    pub fn open(
+        path: std::path::PathBuf,
+        delimiter: u8,
+        access: AccessMode,
+        refresh: Refresh,
+    ) -> anyhow::Result<Self> {
+        std::fs::OpenOptions::new()
+            .read(access.is_readable())
+            .write(access.is_writable())
+            .open(&path)
+            .with_context(|| format!("could not load database at {path:?}"))?;
 
-    Ok(())
-}
+        let watcher = match refresh {
+            Refresh::Notify => Some(Watcher::new(&path)?),
+            _ => None,
+        };
 
-/// remove a record from a csv database.
-pub fn remove_record(path: &std::path::PathBuf, key: &str) -> anyhow::Result<()> {
-    let content = std::fs::read_to_string(path)
-        .context(format!("failed to read a csv database at {path:?}"))?;
+        let database = Self {
+            path,
+            delimiter,
+            access,
+            refresh,
+            index: std::sync::RwLock::new(Index::default()),
+            checked: std::sync::Mutex::new(std::time::Instant::now()),
+            watcher,
+        };
 
-    let mut writer = std::io::BufWriter::new(
-        std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
+        if database.access.is_readable() {
+            *database.index.write().unwrap() = database.load()?;
+        }
+
+        Ok(database)
+    }
+
+    fn load(&self) -> anyhow::Result<Index> {
+        let path = &self.path;
+        // taken before reading, a change while reading is seen on the next query.
+        let version = version(path)?;
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open csv database {path:?}"))?;
+
+        let mut records = std::collections::HashMap::new();
+        for record in reader(self.delimiter, std::io::BufReader::new(file)).into_records() {
+            let record = record.with_context(|| {
+                format!("tried to read from csv database {path:?}, but an error occurred")
+            })?;
+
+            // like a scan of the file, the first record of a key is used.
+            if let Some(key) = record.get(0) {
+                records.entry(key.to_string()).or_insert(record);
+            }
+        }
+
+        Ok(Index {
+            records,
+            version: Some(version),
+        })
+    }
+
+    /// true if the file has been modified since it was read, and must be read again.
+    fn is_stale(&self) -> anyhow::Result<bool> {
+        let check = match self.refresh {
+            Refresh::No => false,
+            Refresh::Always => true,
+            Refresh::Every(period) => {
+                let mut checked = self.checked.lock().unwrap();
+                if checked.elapsed() >= period {
+                    *checked = std::time::Instant::now();
+                    true
+                } else {
+                    false
+                }
+            }
+            Refresh::Notify => self.watcher.as_ref().map_or(false, Watcher::take),
+        };
+
+        // the changes made by `add` and `remove` are already in the index.
+        Ok(check && self.index.read().unwrap().version != Some(version(&self.path)?))
+    }
+
+    /// query the record matching the first field.
+    ///
+    /// # Errors
+    ///
+    /// * the database is opened in write only mode.
+    /// * the file has been modified, and cannot be read again.
+    pub fn query(&self, key: &str) -> anyhow::Result<Option<csv::StringRecord>> {
+        if !self.access.is_readable() {
+            anyhow::bail!("csv database {:?} is opened in write only mode", self.path);
+        }
+
+        if self.is_stale()? {
+            let index = self.load()?;
+            *self.index.write().unwrap() = index;
+            log::debug!("csv database {:?} has been read again", self.path);
+        }
+
+        Ok(self.index.read().unwrap().records.get(key).cloned())
+    }
+
+    /// add a record at the end of the file.
+    ///
+    /// # Errors
+    ///
+    /// * the database is opened in read only mode.
+    /// * the file cannot be written.
+    pub fn add(&self, record: &[String]) -> anyhow::Result<()> {
+        let path = &self.path;
+        if !self.access.is_writable() {
+            anyhow::bail!("csv database {path:?} is opened in read only mode");
+        }
+
+        let mut index = self.index.write().unwrap();
+        let up_to_date = index.version == Some(version(path)?);
+
+        let file = std::fs::OpenOptions::new()
+            .append(true)
             .open(path)
-            .context(format!("failed to open a csv database at {path:?}"))?,
-    );
-
-    for line in content.lines() {
-        if !line.starts_with(key) {
-            writer
-                .write_vectored(&[
-                    std::io::IoSlice::new(line.as_bytes()),
-                    std::io::IoSlice::new(b"\n"),
-                ])
-                .context(format!("failed to update a csv database at {path:?}"))?;
+            .with_context(|| format!("failed to open csv database {path:?}"))?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .delimiter(self.delimiter)
+            .flexible(true)
+            .from_writer(file);
+
+        writer
+            .write_record(record)
+            .with_context(|| format!("failed to write to csv database at {path:?}"))?;
+        writer
+            .flush()
+            .with_context(|| format!("failed to write to csv database at {path:?}"))?;
+
+        // the index is updated whatever the refresh mode, the version only if no other
+        // change has to be loaded.
+        let mut record = csv::StringRecord::from(record);
+        record.trim();
+        if let Some(key) = record.get(0) {
+            index.records.entry(key.to_string()).or_insert(record);
+        }
+        if up_to_date {
+            index.version = Some(version(path)?);
         }
+        drop(index);
+
+        Ok(())
     }
 
-    writer
-        .flush()
-        .context(format!("failed to update a csv database at {path:?}"))?;
+    /// remove the records matching exactly the first field, the file is written
+    /// to a temporary file renamed over the database.
+    ///
+    /// # Errors
+    ///
+    /// * the database is opened in read only mode.
+    /// * the file is not a valid csv file.
+    /// * the temporary file cannot be written in the directory of the database.
+    pub fn remove(&self, key: &str) -> anyhow::Result<()> {
+        let path = &self.path;
+        if !self.access.is_writable() {
+            anyhow::bail!("csv database {path:?} is opened in read only mode");
+        }
+
+        let mut index = self.index.write().unwrap();
+        let up_to_date = index.version == Some(version(path)?);
+
+        let content = std::fs::read(path)
+            .with_context(|| format!("failed to read a csv database at {path:?}"))?;
+
+        // the records kept are copied as they are in the file.
+        let mut kept = Vec::with_capacity(content.len());
+        let mut reader = reader(self.delimiter, content.as_slice());
+        let mut record = csv::ByteRecord::new();
+        loop {
+            let start = usize::try_from(reader.position().byte())?;
+            if !reader
+                .read_byte_record(&mut record)
+                .with_context(|| format!("failed to read a csv database at {path:?}"))?
+            {
+                break;
+            }
+            let end = usize::try_from(reader.position().byte())?;
+
+            if record.get(0) != Some(key.as_bytes()) {
+                kept.extend_from_slice(&content[start..end]);
+            }
+        }
+
+        let temporary = path.with_file_name(format!(
+            ".{}.tmp",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        let mut file = std::fs::File::create(&temporary)
+            .with_context(|| format!("failed to create {temporary:?} to update {path:?}"))?;
+        file.write_all(&kept)
+            .and_then(|()| file.sync_all())
+            .and_then(|()| {
+                std::fs::set_permissions(&temporary, std::fs::metadata(path)?.permissions())
+            })
+            .and_then(|()| std::fs::rename(&temporary, path))
+            .with_context(|| format!("failed to update a csv database at {path:?}"))?;
+
+        index.records.remove(key);
+        if up_to_date {
+            index.version = Some(version(path)?);
+        }
+        drop(index);
 
-    Ok(())
+        Ok(())
+    }
 }
 
 pub fn parse_csv_database(db_name: &str, options: &rhai::Map) -> EngineResult<Service> {
@@ -135,26 +432,191 @@ pub fn parse_csv_database(db_name: &str, options: &rhai::Map) -> EngineResult<Se
         format!("{} is not a correct database access mode", access).into()
     })?;
 
-    let fd = std::fs::OpenOptions::new()
-        .append(true)
-        .read(match access {
-            AccessMode::ReadWrite | AccessMode::Read => true,
-            AccessMode::Write => false,
-        })
-        .write(match access {
-            AccessMode::ReadWrite | AccessMode::Write => true,
-            AccessMode::Read => false,
-        })
-        .open(&connector)
-        .map_err::<Box<rhai::EvalAltResult>, _>(|err| {
-            format!("could not load database at {connector:?}: {err}").into()
-        })?;
-
     Ok(Service::CSVDatabase {
-        path: connector,
-        delimiter,
-        access,
-        refresh,
-        fd,
+        database: Database::open(connector, delimiter, access, refresh)
+            .map_err::<Box<rhai::EvalAltResult>, _>(|err| format!("{err:#}").into())?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "\
+john, john.doe@example.com
+john.doe, john.doe@example.com,\"Doe,\nJohn\"
+staff, john.doe@example.com, jenny.doe@example.com
+john, duplicate@example.com
+";
+
+    fn database(name: &str, access: AccessMode, refresh: Refresh) -> Database {
+        let path = std::path::PathBuf::from(format!("./tmp/csv/{name}.csv"));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, CONTENT).unwrap();
+
+        Database::open(path, b',', access, refresh).unwrap()
+    }
+
+    fn modify(database: &Database, line: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&database.path)
+            .unwrap();
+        writeln!(file, "{line}").unwrap();
+    }
+
+    fn get(database: &Database, key: &str) -> Option<Vec<String>> {
+        database
+            .query(key)
+            .unwrap()
+            .map(|record| record.iter().map(str::to_string).collect())
+    }
+
+    #[test]
+    fn query() {
+        let database = database("query", AccessMode::Read, Refresh::No);
+
+        assert_eq!(
+            get(&database, "john"),
+            Some(vec!["john".to_string(), "john.doe@example.com".to_string()])
+        );
+        assert_eq!(get(&database, "john.doe").unwrap()[2], "Doe,\nJohn");
+        assert_eq!(get(&database, "staff").unwrap().len(), 3);
+        assert_eq!(get(&database, "jenny"), None);
+
+        assert!(database.add(&["jenny".to_string()]).is_err());
+        assert!(database.remove("john").is_err());
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn write_only() {
+        let database = database("write_only", AccessMode::Write, Refresh::No);
+
+        assert!(database.query("john").is_err());
+        database.add(&["jenny".to_string()]).unwrap();
+        database.remove("john").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&database.path).unwrap(),
+            "john.doe, john.doe@example.com,\"Doe,\nJohn\"\n\
+            staff, john.doe@example.com, jenny.doe@example.com\n\
+            jenny\n"
+        );
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let database = database("add_and_remove", AccessMode::ReadWrite, Refresh::No);
+
+        database
+            .add(&["jenny".to_string(), "jenny.doe@example.com".to_string()])
+            .unwrap();
+        assert_eq!(get(&database, "jenny").unwrap()[1], "jenny.doe@example.com");
+
+        // "john" is a prefix of "john.doe", which is kept as it is.
+        database.remove("john").unwrap();
+        assert_eq!(get(&database, "john"), None);
+        assert_eq!(get(&database, "john.doe").unwrap().len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(&database.path).unwrap(),
+            "john.doe, john.doe@example.com,\"Doe,\nJohn\"\n\
+            staff, john.doe@example.com, jenny.doe@example.com\n\
+            jenny,jenny.doe@example.com\n"
+        );
+
+        database.remove("unknown").unwrap();
+        assert_eq!(get(&database, "staff").unwrap().len(), 3);
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn refresh_no() {
+        let database = database("refresh_no", AccessMode::Read, Refresh::No);
+
+        modify(&database, "jenny, jenny.doe@example.com");
+        assert_eq!(get(&database, "jenny"), None);
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn refresh_no_write() {
+        let database = database("refresh_no_write", AccessMode::ReadWrite, Refresh::No);
+
+        // the index does not follow the file, but follows the changes of the database.
+        modify(&database, "jenny, jenny.doe@example.com");
+        database.remove("john").unwrap();
+        assert_eq!(get(&database, "john"), None);
+        assert_eq!(get(&database, "jenny"), None);
+
+        database.add(&["james".to_string()]).unwrap();
+        assert_eq!(get(&database, "james"), Some(vec!["james".to_string()]));
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn refresh_always() {
+        let database = database("refresh_always", AccessMode::Read, Refresh::Always);
+
+        modify(&database, "jenny, jenny.doe@example.com");
+        assert_eq!(get(&database, "jenny").unwrap()[1], "jenny.doe@example.com");
+
+        std::fs::write(&database.path, "jenny").unwrap();
+        assert_eq!(get(&database, "jenny").unwrap().len(), 1);
+        assert_eq!(get(&database, "john"), None);
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn refresh_every() {
+        let database = database(
+            "refresh_every",
+            AccessMode::Read,
+            Refresh::Every(std::time::Duration::from_millis(200)),
+        );
+
+        modify(&database, "jenny, jenny.doe@example.com");
+        assert_eq!(get(&database, "jenny"), None);
+
+        std::thread::sleep(std::time::Duration::from_millis(250));
+        assert_eq!(get(&database, "jenny").unwrap()[1], "jenny.doe@example.com");
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+
+    #[test]
+    fn refresh_notify() {
+        let database = database("refresh_notify", AccessMode::ReadWrite, Refresh::Notify);
+
+        // the changes of the database itself do not need to read the file again.
+        database.remove("staff").unwrap();
+        assert_eq!(get(&database, "staff"), None);
+
+        modify(&database, "jenny, jenny.doe@example.com");
+        let notified = (0..50).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            get(&database, "jenny").is_some()
+        });
+        assert!(notified);
+
+        // replaced by a rename, like an editor does.
+        let replacement = database.path.with_file_name("refresh_notify.csv.new");
+        std::fs::write(&replacement, "jenny, jenny@example.com\n").unwrap();
+        std::fs::rename(&replacement, &database.path).unwrap();
+        let notified = (0..50).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            get(&database, "john").is_none()
+        });
+        assert!(notified);
+        assert_eq!(get(&database, "jenny").unwrap()[1], "jenny@example.com");
+
+        std::fs::remove_file(database.path).unwrap();
+    }
+}
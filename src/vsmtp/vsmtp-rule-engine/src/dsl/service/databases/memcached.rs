@@ -0,0 +1,348 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::{read_data, read_line, Connections, KeyValue, Refused, Stream};
+use crate::dsl::service::ServiceAddress;
+use vsmtp_common::re::anyhow;
+
+/// an expiration time longer than 30 days is read by memcached as a unix timestamp.
+const MAX_RELATIVE_EXPIRATION: u64 = 60 * 60 * 24 * 30;
+
+/// memcached keys are limited to 250 bytes, without whitespaces nor control characters.
+fn check_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() || key.len() > 250 || key.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        anyhow::bail!("'{key}' is not a valid memcached key");
+    }
+    Ok(())
+}
+
+/// convert a time to live to the expiration time of memcached.
+fn expiration(ttl: Option<&std::time::Duration>) -> anyhow::Result<u64> {
+    let seconds = match ttl {
+        Some(ttl) => ttl.as_secs() + u64::from(ttl.subsec_nanos() != 0),
+        None => return Ok(0),
+    };
+
+    if seconds <= MAX_RELATIVE_EXPIRATION {
+        Ok(seconds)
+    } else {
+        Ok(std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs()
+            + seconds)
+    }
+}
+
+/// send a command and read the first line of its reply.
+fn command(stream: &mut Stream, command: &[u8]) -> anyhow::Result<String> {
+    std::io::Write::write_all(stream.get_mut(), command)?;
+    let line = read_line(stream)?;
+
+    if line == "ERROR" || line.starts_with("CLIENT_ERROR") || line.starts_with("SERVER_ERROR") {
+        return Err(Refused(line).into());
+    }
+    Ok(line)
+}
+
+fn storage(verb: &str, key: &str, value: &str, exptime: u64) -> Vec<u8> {
+    format!("{verb} {key} 0 {exptime} {}\r\n{value}\r\n", value.len()).into_bytes()
+}
+
+/// a client of a memcached server, using the text protocol.
+#[derive(Debug)]
+pub struct Client {
+    /// Address of the server, a unix or tcp socket.
+    pub address: ServiceAddress,
+    /// A duration after which a read or write to the server is aborted.
+    pub timeout: std::time::Duration,
+    connections: Connections,
+}
+
+impl Client {
+    /// create a client, keeping at most `connections` idle connections to the server.
+    #[must_use]
+    pub fn new(address: ServiceAddress, timeout: std::time::Duration, connections: usize) -> Self {
+        Self {
+            address,
+            timeout,
+            connections: Connections::new(connections),
+        }
+    }
+
+    fn run<T>(&self, operation: impl Fn(&mut Stream) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        self.connections
+            .run(&self.address, &self.timeout, |_| Ok(()), operation)
+    }
+}
+
+impl KeyValue for Client {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        check_key(key)?;
+
+        self.run(|stream| {
+            let line = command(stream, format!("get {key}\r\n").as_bytes())?;
+            if line == "END" {
+                return Ok(None);
+            }
+
+            let length = line
+                .strip_prefix("VALUE ")
+                .and_then(|line| line.split(' ').nth(2))
+                .and_then(|length| length.parse::<usize>().ok())
+                .ok_or_else(|| anyhow::anyhow!("unexpected reply to get: '{line}'"))?;
+            let value = read_data(stream, length)?;
+
+            match read_line(stream)?.as_str() {
+                "END" => Ok(Some(String::from_utf8_lossy(&value).into_owned())),
+                line => anyhow::bail!("unexpected reply to get: '{line}'"),
+            }
+        })
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<&std::time::Duration>) -> anyhow::Result<()> {
+        check_key(key)?;
+        let exptime = expiration(ttl)?;
+
+        self.run(
+            |stream| match command(stream, &storage("set", key, value, exptime))?.as_str() {
+                "STORED" => Ok(()),
+                line => Err(Refused(line.to_string()).into()),
+            },
+        )
+    }
+
+    // memcached values are unsigned, a decrement stops at `0`.
+    fn incr(&self, key: &str, amount: i64) -> anyhow::Result<i64> {
+        check_key(key)?;
+        let verb = if amount < 0 { "decr" } else { "incr" };
+        let change = format!("{verb} {key} {}\r\n", amount.unsigned_abs());
+        let initial = amount.max(0).to_string();
+
+        self.run(|stream| {
+            // the key can be added by another client between `incr` and `add`.
+            for _ in 0..2 {
+                match command(stream, change.as_bytes())?.as_str() {
+                    "NOT_FOUND" => {}
+                    value => {
+                        return value
+                            .parse()
+                            .map_err(|_| anyhow::anyhow!("unexpected reply to {verb}: '{value}'"))
+                    }
+                }
+
+                match command(stream, &storage("add", key, &initial, 0))?.as_str() {
+                    "STORED" => return Ok(amount.max(0)),
+                    "NOT_STORED" => {}
+                    line => anyhow::bail!("unexpected reply to add: '{line}'"),
+                }
+            }
+            Err(Refused(format!("{key} is modified concurrently")).into())
+        })
+    }
+
+    fn expire(&self, key: &str, ttl: &std::time::Duration) -> anyhow::Result<bool> {
+        check_key(key)?;
+        let exptime = expiration(Some(ttl))?;
+
+        self.run(|stream| {
+            match command(stream, format!("touch {key} {exptime}\r\n").as_bytes())?.as_str() {
+                "TOUCHED" => Ok(true),
+                "NOT_FOUND" => Ok(false),
+                line => anyhow::bail!("unexpected reply to touch: '{line}'"),
+            }
+        })
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        check_key(key)?;
+
+        self.run(
+            |stream| match command(stream, format!("delete {key}\r\n").as_bytes())?.as_str() {
+                "DELETED" | "NOT_FOUND" => Ok(()),
+                line => anyhow::bail!("unexpected reply to delete: '{line}'"),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod stand_in {
+    //! a memcached server in memory, implementing the commands used by the client.
+
+    use super::{read_data, read_line};
+
+    /// the values stored in the server, with their expiration time.
+    pub type Store =
+        std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, (String, u64)>>>;
+
+    fn handle(
+        line: &str,
+        reader: &mut std::io::BufReader<std::net::TcpStream>,
+        store: &Store,
+    ) -> String {
+        let mut store = store.lock().unwrap();
+
+        match line.split(' ').collect::<Vec<_>>().as_slice() {
+            ["get", key] => store.get(*key).map_or_else(
+                || "END\r\n".to_string(),
+                |(value, _)| format!("VALUE {key} 0 {}\r\n{value}\r\nEND\r\n", value.len()),
+            ),
+            [verb @ ("set" | "add"), key, _, exptime, length] => {
+                let value = read_data(reader, length.parse().unwrap()).unwrap();
+                if *verb == "add" && store.contains_key(*key) {
+                    return "NOT_STORED\r\n".to_string();
+                }
+                store.insert(
+                    (*key).to_string(),
+                    (String::from_utf8(value).unwrap(), exptime.parse().unwrap()),
+                );
+                "STORED\r\n".to_string()
+            }
+            [verb @ ("incr" | "decr"), key, amount] => match store.get_mut(*key) {
+                Some((value, _)) => match value.parse::<u64>() {
+                    Ok(current) => {
+                        let amount = amount.parse::<u64>().unwrap();
+                        *value = if *verb == "incr" {
+                            current + amount
+                        } else {
+                            current.saturating_sub(amount)
+                        }
+                        .to_string();
+                        format!("{value}\r\n")
+                    }
+                    Err(_) => "CLIENT_ERROR cannot increment or decrement non-numeric value\r\n"
+                        .to_string(),
+                },
+                None => "NOT_FOUND\r\n".to_string(),
+            },
+            ["touch", key, exptime] => match store.get_mut(*key) {
+                Some((_, expire)) => {
+                    *expire = exptime.parse().unwrap();
+                    "TOUCHED\r\n".to_string()
+                }
+                None => "NOT_FOUND\r\n".to_string(),
+            },
+            ["delete", key] => match store.remove(*key) {
+                Some(_) => "DELETED\r\n".to_string(),
+                None => "NOT_FOUND\r\n".to_string(),
+            },
+            _ => "ERROR\r\n".to_string(),
+        }
+    }
+
+    /// listen on a random port, return its address and the store of the server.
+    pub fn serve() -> (String, Store) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let store = Store::default();
+
+        let server_store = store.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let store = server_store.clone();
+
+                std::thread::spawn(move || {
+                    let mut writer = stream.try_clone().unwrap();
+                    let mut reader = std::io::BufReader::new(stream);
+
+                    while let Ok(line) = read_line(&mut reader) {
+                        let reply = handle(&line, &mut reader, &store);
+                        std::io::Write::write_all(&mut writer, reply.as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+
+        (address, store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(address: &str) -> Client {
+        Client::new(
+            ServiceAddress::Tcp(address.to_string()),
+            std::time::Duration::from_secs(5),
+            2,
+        )
+    }
+
+    #[test]
+    fn keys() {
+        assert!(check_key("greylist:john.doe@example.com").is_ok());
+        assert!(check_key("").is_err());
+        assert!(check_key("with space").is_err());
+        assert!(check_key("with\r\nnewline").is_err());
+        assert!(check_key(&"a".repeat(251)).is_err());
+    }
+
+    #[test]
+    fn expirations() {
+        assert_eq!(expiration(None).unwrap(), 0);
+        assert_eq!(
+            expiration(Some(&std::time::Duration::from_millis(1500))).unwrap(),
+            2
+        );
+        assert!(
+            expiration(Some(&std::time::Duration::from_secs(
+                MAX_RELATIVE_EXPIRATION + 1
+            )))
+            .unwrap()
+                > MAX_RELATIVE_EXPIRATION * 12
+        );
+    }
+
+    #[test]
+    fn operations() {
+        let (address, store) = stand_in::serve();
+        let client = client(&address);
+
+        assert_eq!(client.get("greylist").unwrap(), None);
+        client
+            .set("greylist", "john.doe@example.com", None)
+            .unwrap();
+        assert_eq!(
+            client.get("greylist").unwrap().as_deref(),
+            Some("john.doe@example.com")
+        );
+
+        client
+            .set("session", "ok", Some(&std::time::Duration::from_secs(10)))
+            .unwrap();
+        assert_eq!(store.lock().unwrap()["session"].1, 10);
+
+        assert_eq!(client.incr("counter", 1).unwrap(), 1);
+        assert_eq!(client.incr("counter", 5).unwrap(), 6);
+        assert_eq!(client.incr("counter", -10).unwrap(), 0);
+        assert!(client.incr("greylist", 1).is_err());
+
+        assert!(client
+            .expire("counter", &std::time::Duration::from_secs(60))
+            .unwrap());
+        assert_eq!(store.lock().unwrap()["counter"].1, 60);
+        assert!(!client
+            .expire("unknown", &std::time::Duration::from_secs(60))
+            .unwrap());
+
+        client.remove("counter").unwrap();
+        assert_eq!(client.get("counter").unwrap(), None);
+        assert!(client.get("invalid key").is_err());
+    }
+}
@@ -1,4 +1,11 @@
 pub mod csv;
+pub mod memcached;
+pub mod redis;
+pub mod sql;
+
+use super::{Service, ServiceAddress, ServiceStream};
+use crate::modules::EngineResult;
+use vsmtp_common::re::{anyhow, log};
 
 /// the access mode to the database.
 #[derive(Debug)]
@@ -22,6 +29,20 @@ impl std::fmt::Display for AccessMode {
     }
 }
 
+impl AccessMode {
+    /// true if the records can be read.
+    #[must_use]
+    pub const fn is_readable(&self) -> bool {
+        matches!(self, Self::Read | Self::ReadWrite)
+    }
+
+    /// true if records can be added or removed.
+    #[must_use]
+    pub const fn is_writable(&self) -> bool {
+        matches!(self, Self::Write | Self::ReadWrite)
+    }
+}
+
 impl std::str::FromStr for AccessMode {
     type Err = ();
 
@@ -36,10 +57,17 @@ impl std::str::FromStr for AccessMode {
 }
 
 /// refresh rate of the database.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Refresh {
+    /// the file is read again on a query when it has been modified.
     Always,
+    /// the file is read once.
     No,
+    /// the file is read again on a query when it has been modified,
+    /// at most once per period.
+    Every(std::time::Duration),
+    /// the file is read again on a query when a change has been notified by the system.
+    Notify,
 }
 
 impl std::str::FromStr for Refresh {
@@ -49,7 +77,379 @@ impl std::str::FromStr for Refresh {
         match s {
             "always" => Ok(Self::Always),
             "no" => Ok(Self::No),
+            "notify" => Ok(Self::Notify),
+            period => period
+                .parse::<vsmtp_config::re::humantime::Duration>()
+                .ok()
+                .map(Into::into)
+                .filter(|period: &std::time::Duration| !period.is_zero())
+                .map(Self::Every)
+                .ok_or(()),
+        }
+    }
+}
+
+/// behavior of a key-value database service when the database cannot be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// the operation is ignored, as if the key did not exist.
+    Open,
+    /// the operation produces an error in the rules.
+    Closed,
+}
+
+impl std::str::FromStr for Failure {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "closed" => Ok(Self::Closed),
             _ => Err(()),
         }
     }
 }
+
+impl Failure {
+    /// apply the failure behavior to the result of an operation on `service`,
+    /// `fallback` is used when the failure is open.
+    ///
+    /// # Errors
+    ///
+    /// * the operation failed, and the failure is closed.
+    pub fn apply<T>(
+        self,
+        service: &str,
+        result: anyhow::Result<T>,
+        fallback: T,
+    ) -> anyhow::Result<T> {
+        match (result, self) {
+            (Ok(value), _) => Ok(value),
+            (Err(error), Self::Open) => {
+                log::warn!(
+                    "{service} database is unavailable, the operation is ignored: {error:#}"
+                );
+                Ok(fallback)
+            }
+            (Err(error), Self::Closed) => Err(error),
+        }
+    }
+}
+
+/// an error replied by a key-value database, the connection is still usable.
+#[derive(Debug, thiserror::Error)]
+#[error("the database replied with an error: {0}")]
+pub struct Refused(pub String);
+
+/// operations of a key-value database.
+pub trait KeyValue {
+    /// get the value of `key`.
+    ///
+    /// # Errors
+    ///
+    /// * the database could not be reached.
+    /// * the database replied with an error.
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+
+    /// set the value of `key`, expiring after `ttl` if any.
+    ///
+    /// # Errors
+    ///
+    /// * the database could not be reached.
+    /// * the database replied with an error.
+    fn set(&self, key: &str, value: &str, ttl: Option<&std::time::Duration>) -> anyhow::Result<()>;
+
+    /// increment the value of `key` by `amount`, a missing key is set to `0` before.
+    ///
+    /// # Errors
+    ///
+    /// * the database could not be reached.
+    /// * the value of the key is not an integer.
+    fn incr(&self, key: &str, amount: i64) -> anyhow::Result<i64>;
+
+    /// expire `key` after `ttl`, return `false` if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// * the database could not be reached.
+    /// * the database replied with an error.
+    fn expire(&self, key: &str, ttl: &std::time::Duration) -> anyhow::Result<bool>;
+
+    /// remove `key`.
+    ///
+    /// # Errors
+    ///
+    /// * the database could not be reached.
+    /// * the database replied with an error.
+    fn remove(&self, key: &str) -> anyhow::Result<()>;
+}
+
+/// a connection to a key-value database.
+pub type Stream = std::io::BufReader<ServiceStream>;
+
+/// idle connections to a key-value database, reused by the next operations.
+#[derive(Debug)]
+pub struct Connections {
+    max: usize,
+    idle: std::sync::Mutex<Vec<Stream>>,
+}
+
+impl Connections {
+    /// keep at most `max` idle connections.
+    #[must_use]
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            idle: std::sync::Mutex::new(Vec::with_capacity(max)),
+        }
+    }
+
+    /// run `operation` on an idle connection, or on a new one opened with `connect`.
+    ///
+    /// the idle connections closed by the database in the meantime are dropped before
+    /// anything is written on them. The operation is never retried once sent, a command
+    /// like `INCRBY` could be applied twice. The connection is dropped if the operation
+    /// fails, unless the database replied with an error ([`Refused`]).
+    ///
+    /// # Errors
+    ///
+    /// * the connection failed.
+    /// * the operation failed.
+    pub fn run<T>(
+        &self,
+        address: &ServiceAddress,
+        timeout: &std::time::Duration,
+        connect: impl Fn(&mut Stream) -> anyhow::Result<()>,
+        operation: impl Fn(&mut Stream) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let idle = {
+            let mut idle = self
+                .idle
+                .lock()
+                .map_err(|_| anyhow::anyhow!("the connections of the database are poisoned"))?;
+
+            let mut open = None;
+            while let Some(mut stream) = idle.pop() {
+                if is_open(&mut stream) {
+                    open = Some(stream);
+                    break;
+                }
+                log::debug!("idle connection to '{address}' closed by the database");
+            }
+            open
+        };
+
+        let mut stream = if let Some(stream) = idle {
+            stream
+        } else {
+            let mut stream = std::io::BufReader::new(address.connect(timeout)?);
+            connect(&mut stream)?;
+            stream
+        };
+
+        let result = operation(&mut stream);
+        if result
+            .as_ref()
+            .map_or_else(|error| error.downcast_ref::<Refused>().is_some(), |_| true)
+        {
+            self.release(stream);
+        }
+
+        result
+    }
+
+    fn release(&self, stream: Stream) {
+        if let Ok(mut idle) = self.idle.lock() {
+            if idle.len() < self.max {
+                idle.push(stream);
+            }
+        }
+    }
+}
+
+/// check without blocking that the database did not close an idle connection,
+/// nothing is expected to be read on it.
+fn is_open(stream: &mut Stream) -> bool {
+    if !stream.buffer().is_empty() || stream.get_ref().set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let pending = std::io::Read::read(stream.get_mut(), &mut [0; 1]);
+    stream.get_ref().set_nonblocking(false).is_ok()
+        && matches!(pending, Err(error) if error.kind() == std::io::ErrorKind::WouldBlock)
+}
+
+/// read a line terminated by `\r\n`, without the terminator.
+///
+/// # Errors
+///
+/// * the connection was closed or timed out.
+/// * the line is longer than 1024 bytes.
+fn read_line(stream: &mut impl std::io::BufRead) -> anyhow::Result<String> {
+    let mut line = Vec::new();
+    std::io::BufRead::read_until(
+        &mut std::io::Read::take(&mut *stream, 1024),
+        b'\n',
+        &mut line,
+    )?;
+
+    match line.strip_suffix(b"\r\n") {
+        Some(line) => Ok(String::from_utf8_lossy(line).into_owned()),
+        None if line.is_empty() => anyhow::bail!("connection closed by the database"),
+        None => anyhow::bail!("invalid line received from the database"),
+    }
+}
+
+/// read `length` bytes of data followed by `\r\n`.
+///
+/// # Errors
+///
+/// * the connection was closed or timed out.
+/// * the data is not followed by `\r\n`.
+fn read_data(stream: &mut impl std::io::BufRead, length: usize) -> anyhow::Result<Vec<u8>> {
+    let mut data = vec![0; length + 2];
+    std::io::Read::read_exact(stream, &mut data)?;
+
+    if data.split_off(length) != b"\r\n" {
+        anyhow::bail!("invalid data received from the database");
+    }
+
+    Ok(data)
+}
+
+/// parse a time to live, a number of seconds or a duration like `"10m"`.
+///
+/// # Errors
+///
+/// * the value is not a valid duration.
+/// * the duration is zero or negative.
+pub fn parse_ttl(ttl: &rhai::Dynamic) -> anyhow::Result<std::time::Duration> {
+    let ttl = match ttl.as_int() {
+        Ok(seconds) => std::time::Duration::from_secs(u64::try_from(seconds).unwrap_or_default()),
+        Err(_) => ttl
+            .to_string()
+            .parse::<vsmtp_config::re::humantime::Duration>()?
+            .into(),
+    };
+
+    if ttl.is_zero() {
+        anyhow::bail!("a time to live must be positive");
+    }
+
+    Ok(ttl)
+}
+
+/// parse the options of a `redis` or `memcached` database.
+pub fn parse_key_value_database(
+    db_name: &str,
+    db_type: &str,
+    options: &rhai::Map,
+) -> EngineResult<Service> {
+    let address = options
+        .get("address")
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            format!("database {db_name} is missing the 'address' option.").into()
+        })?
+        .to_string()
+        .parse::<ServiceAddress>()
+        .map_err::<Box<rhai::EvalAltResult>, _>(|err| err.to_string().into())?;
+
+    let timeout: std::time::Duration = options
+        .get("timeout")
+        .map_or_else(|| "5s".to_string(), ToString::to_string)
+        .parse::<vsmtp_config::re::humantime::Duration>()
+        .map_err::<Box<rhai::EvalAltResult>, _>(|err| err.to_string().into())?
+        .into();
+
+    let connections = options.get("connections").map_or(Ok(4), |connections| {
+        connections
+            .as_int()
+            .ok()
+            .and_then(|connections| usize::try_from(connections).ok())
+            .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+                format!("the 'connections' option of database {db_name} must be a number").into()
+            })
+    })?;
+
+    let failure = options
+        .get("failure")
+        .map_or_else(|| "closed".to_string(), ToString::to_string);
+    let failure = failure
+        .parse::<Failure>()
+        .map_err::<Box<rhai::EvalAltResult>, _>(|()| {
+            format!(
+                "{failure} is not a correct database failure behavior, expected 'open' or 'closed'"
+            )
+            .into()
+        })?;
+
+    Ok(match db_type {
+        "redis" => Service::RedisDatabase {
+            failure,
+            client: redis::Client::new(
+                address,
+                timeout,
+                options.get("password").map(ToString::to_string),
+                options
+                    .get("database")
+                    .map_or(Ok(0), rhai::Dynamic::as_int)
+                    .map_err::<Box<rhai::EvalAltResult>, _>(|_| {
+                        format!("the 'database' option of database {db_name} must be a number")
+                            .into()
+                    })?,
+                connections,
+            ),
+        },
+        _ => Service::MemcachedDatabase {
+            failure,
+            client: memcached::Client::new(address, timeout, connections),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh() {
+        assert_eq!("always".parse::<Refresh>(), Ok(Refresh::Always));
+        assert_eq!("no".parse::<Refresh>(), Ok(Refresh::No));
+        assert_eq!("notify".parse::<Refresh>(), Ok(Refresh::Notify));
+        assert_eq!(
+            "10m".parse::<Refresh>(),
+            Ok(Refresh::Every(std::time::Duration::from_secs(600)))
+        );
+        assert!("0s".parse::<Refresh>().is_err());
+        assert!("sometimes".parse::<Refresh>().is_err());
+    }
+
+    #[test]
+    fn failure() {
+        assert_eq!(
+            Failure::Open
+                .apply("redis", Err(anyhow::anyhow!("unreachable")), 0)
+                .unwrap(),
+            0
+        );
+        assert!(Failure::Closed
+            .apply("redis", Err(anyhow::anyhow!("unreachable")), 0)
+            .is_err());
+        assert_eq!(Failure::Closed.apply("redis", Ok(1), 0).unwrap(), 1);
+    }
+
+    #[test]
+    fn ttl() {
+        assert_eq!(
+            parse_ttl(&rhai::Dynamic::from(60_i64)).unwrap(),
+            std::time::Duration::from_secs(60)
+        );
+        assert_eq!(
+            parse_ttl(&rhai::Dynamic::from("10m".to_string())).unwrap(),
+            std::time::Duration::from_secs(600)
+        );
+        assert!(parse_ttl(&rhai::Dynamic::from(-1_i64)).is_err());
+        assert!(parse_ttl(&rhai::Dynamic::from("0s".to_string())).is_err());
+        assert!(parse_ttl(&rhai::Dynamic::from("soon".to_string())).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::{read_data, read_line, Connections, KeyValue, Refused, Stream};
+use crate::dsl::service::ServiceAddress;
+use vsmtp_common::re::anyhow;
+
+/// maximum depth of the nested arrays of a reply.
+const MAX_DEPTH: usize = 4;
+
+/// a reply of redis, using the RESP protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// a simple string, like `OK`.
+    Status(String),
+    /// an integer.
+    Integer(i64),
+    /// a binary safe string, `None` if the key does not exist.
+    Bulk(Option<Vec<u8>>),
+    /// an array of replies.
+    Array(Option<Vec<Reply>>),
+}
+
+/// encode a command as an array of bulk strings.
+fn encode(args: &[&[u8]]) -> Vec<u8> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        command.extend_from_slice(arg);
+        command.extend_from_slice(b"\r\n");
+    }
+    command
+}
+
+fn read_reply(stream: &mut impl std::io::BufRead, depth: usize) -> anyhow::Result<Reply> {
+    let line = read_line(stream)?;
+    let (kind, value) = line.split_at(line.len().min(1));
+
+    let length = || {
+        value
+            .parse::<i64>()
+            .map(|length| usize::try_from(length).ok())
+            .map_err(|_| anyhow::anyhow!("invalid length received from redis: '{value}'"))
+    };
+
+    match kind {
+        "+" => Ok(Reply::Status(value.to_string())),
+        "-" => Err(Refused(value.to_string()).into()),
+        ":" => Ok(Reply::Integer(value.parse().map_err(|_| {
+            anyhow::anyhow!("invalid integer received from redis: '{value}'")
+        })?)),
+        "$" => Ok(Reply::Bulk(match length()? {
+            Some(length) => Some(read_data(stream, length)?),
+            None => None,
+        })),
+        "*" if depth < MAX_DEPTH => Ok(Reply::Array(match length()? {
+            Some(length) => Some(
+                (0..length)
+                    .map(|_| read_reply(stream, depth + 1))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            None => None,
+        })),
+        _ => anyhow::bail!("invalid reply received from redis: '{line}'"),
+    }
+}
+
+/// send a command and read its reply.
+fn command(stream: &mut Stream, args: &[&[u8]]) -> anyhow::Result<Reply> {
+    std::io::Write::write_all(stream.get_mut(), &encode(args))?;
+    read_reply(stream, 0)
+}
+
+fn millis(duration: &std::time::Duration) -> Vec<u8> {
+    duration.as_millis().max(1).to_string().into_bytes()
+}
+
+/// a client of a redis server.
+pub struct Client {
+    /// Address of the server, a unix or tcp socket.
+    pub address: ServiceAddress,
+    /// A duration after which a read or write to the server is aborted.
+    pub timeout: std::time::Duration,
+    password: Option<String>,
+    database: i64,
+    connections: Connections,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("address", &self.address)
+            .field("timeout", &self.timeout)
+            .field("database", &self.database)
+            .field("connections", &self.connections)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// create a client, keeping at most `connections` idle connections to the server.
+    #[must_use]
+    pub fn new(
+        address: ServiceAddress,
+        timeout: std::time::Duration,
+        password: Option<String>,
+        database: i64,
+        connections: usize,
+    ) -> Self {
+        Self {
+            address,
+            timeout,
+            password,
+            database,
+            connections: Connections::new(connections),
+        }
+    }
+
+    fn run<T>(&self, operation: impl Fn(&mut Stream) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        self.connections.run(
+            &self.address,
+            &self.timeout,
+            |stream| {
+                if let Some(password) = &self.password {
+                    command(stream, &[b"AUTH", password.as_bytes()])?;
+                }
+                if self.database != 0 {
+                    command(stream, &[b"SELECT", self.database.to_string().as_bytes()])?;
+                }
+                Ok(())
+            },
+            operation,
+        )
+    }
+}
+
+impl KeyValue for Client {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        match self.run(|stream| command(stream, &[b"GET", key.as_bytes()]))? {
+            Reply::Bulk(value) => {
+                Ok(value.map(|value| String::from_utf8_lossy(&value).into_owned()))
+            }
+            reply => anyhow::bail!("unexpected reply to GET: {reply:?}"),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<&std::time::Duration>) -> anyhow::Result<()> {
+        self.run(|stream| match ttl {
+            Some(ttl) => command(
+                stream,
+                &[
+                    b"SET",
+                    key.as_bytes(),
+                    value.as_bytes(),
+                    b"PX",
+                    &millis(ttl),
+                ],
+            ),
+            None => command(stream, &[b"SET", key.as_bytes(), value.as_bytes()]),
+        })
+        .map(|_| ())
+    }
+
+    fn incr(&self, key: &str, amount: i64) -> anyhow::Result<i64> {
+        match self.run(|stream| {
+            command(
+                stream,
+                &[b"INCRBY", key.as_bytes(), amount.to_string().as_bytes()],
+            )
+        })? {
+            Reply::Integer(value) => Ok(value),
+            reply => anyhow::bail!("unexpected reply to INCRBY: {reply:?}"),
+        }
+    }
+
+    fn expire(&self, key: &str, ttl: &std::time::Duration) -> anyhow::Result<bool> {
+        match self.run(|stream| command(stream, &[b"PEXPIRE", key.as_bytes(), &millis(ttl)]))? {
+            Reply::Integer(value) => Ok(value == 1),
+            reply => anyhow::bail!("unexpected reply to PEXPIRE: {reply:?}"),
+        }
+    }
+
+    fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.run(|stream| command(stream, &[b"DEL", key.as_bytes()]))
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+pub mod stand_in {
+    //! a redis server in memory, implementing the commands used by the client.
+
+    use super::{read_reply, Reply};
+
+    /// the values stored in the server, with their time to live in milliseconds.
+    pub type Store =
+        std::sync::Arc<std::sync::Mutex<std::collections::HashMap<String, (String, Option<u128>)>>>;
+
+    fn bulk(value: &[u8]) -> String {
+        String::from_utf8_lossy(value).into_owned()
+    }
+
+    fn handle(args: &[Vec<u8>], store: &Store, password: Option<&str>) -> Vec<u8> {
+        let mut store = store.lock().unwrap();
+        let args = args.iter().map(|arg| bulk(arg)).collect::<Vec<_>>();
+
+        match args
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            ["AUTH", given] if Some(*given) == password => b"+OK\r\n".to_vec(),
+            ["AUTH", _] => b"-WRONGPASS invalid password\r\n".to_vec(),
+            ["SELECT", _] => b"+OK\r\n".to_vec(),
+            ["GET", key] => store.get(*key).map_or_else(
+                || b"$-1\r\n".to_vec(),
+                |(value, _)| format!("${}\r\n{value}\r\n", value.len()).into_bytes(),
+            ),
+            ["SET", key, value] => {
+                store.insert((*key).to_string(), ((*value).to_string(), None));
+                b"+OK\r\n".to_vec()
+            }
+            ["SET", key, value, "PX", ttl] => {
+                store.insert(
+                    (*key).to_string(),
+                    ((*value).to_string(), Some(ttl.parse().unwrap())),
+                );
+                b"+OK\r\n".to_vec()
+            }
+            ["INCRBY", key, amount] => {
+                let (value, _) = store
+                    .entry((*key).to_string())
+                    .or_insert_with(|| ("0".to_string(), None));
+                match value.parse::<i64>() {
+                    Ok(current) => {
+                        *value = (current + amount.parse::<i64>().unwrap()).to_string();
+                        format!(":{value}\r\n").into_bytes()
+                    }
+                    Err(_) => b"-ERR value is not an integer or out of range\r\n".to_vec(),
+                }
+            }
+            ["PEXPIRE", key, ttl] => match store.get_mut(*key) {
+                Some((_, expire)) => {
+                    *expire = Some(ttl.parse().unwrap());
+                    b":1\r\n".to_vec()
+                }
+                None => b":0\r\n".to_vec(),
+            },
+            ["DEL", key] => {
+                format!(":{}\r\n", i32::from(store.remove(*key).is_some())).into_bytes()
+            }
+            _ => b"-ERR unknown command\r\n".to_vec(),
+        }
+    }
+
+    /// listen on a random port, return its address, the store of the server
+    /// and the number of connections accepted.
+    pub fn serve(
+        password: Option<&'static str>,
+    ) -> (
+        String,
+        Store,
+        std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    ) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let store = Store::default();
+        let accepted = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let (server_store, server_accepted) = (store.clone(), accepted.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let store = server_store.clone();
+                server_accepted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+                std::thread::spawn(move || {
+                    let mut writer = stream.try_clone().unwrap();
+                    let mut reader = std::io::BufReader::new(stream);
+
+                    while let Ok(Reply::Array(Some(args))) = read_reply(&mut reader, 0) {
+                        let args = args
+                            .into_iter()
+                            .map(|arg| match arg {
+                                Reply::Bulk(Some(arg)) => arg,
+                                _ => panic!("commands are arrays of bulk strings"),
+                            })
+                            .collect::<Vec<_>>();
+
+                        std::io::Write::write_all(&mut writer, &handle(&args, &store, password))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        (address, store, accepted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(address: &str, password: Option<&str>) -> Client {
+        Client::new(
+            ServiceAddress::Tcp(address.to_string()),
+            std::time::Duration::from_secs(5),
+            password.map(str::to_string),
+            1,
+            2,
+        )
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(
+            encode(&[b"SET", b"key", b"value"]),
+            b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n"
+        );
+    }
+
+    #[test]
+    fn replies() {
+        let read = |mut input: &[u8]| read_reply(&mut input, 0);
+
+        assert_eq!(read(b"+OK\r\n").unwrap(), Reply::Status("OK".to_string()));
+        assert_eq!(read(b":42\r\n").unwrap(), Reply::Integer(42));
+        assert_eq!(
+            read(b"$5\r\nhello\r\n").unwrap(),
+            Reply::Bulk(Some(b"hello".to_vec()))
+        );
+        assert_eq!(read(b"$-1\r\n").unwrap(), Reply::Bulk(None));
+        assert_eq!(
+            read(b"*2\r\n:1\r\n$-1\r\n").unwrap(),
+            Reply::Array(Some(vec![Reply::Integer(1), Reply::Bulk(None)]))
+        );
+        assert!(read(b"-ERR unknown command\r\n").is_err());
+        assert!(read(b"$5\r\nhell\r\n").is_err());
+        assert!(read(b"*1\r\n*1\r\n*1\r\n*1\r\n*1\r\n:1\r\n").is_err());
+        assert!(read(b"").is_err());
+    }
+
+    #[test]
+    fn operations() {
+        let (address, store, accepted) = stand_in::serve(Some("secret"));
+        let client = client(&address, Some("secret"));
+
+        assert_eq!(client.get("greylist").unwrap(), None);
+        client
+            .set("greylist", "john.doe@example.com", None)
+            .unwrap();
+        assert_eq!(
+            client.get("greylist").unwrap().as_deref(),
+            Some("john.doe@example.com")
+        );
+
+        client
+            .set("session", "ok", Some(&std::time::Duration::from_secs(10)))
+            .unwrap();
+        assert_eq!(store.lock().unwrap()["session"].1, Some(10_000));
+
+        assert_eq!(client.incr("counter", 1).unwrap(), 1);
+        assert_eq!(client.incr("counter", 5).unwrap(), 6);
+        assert!(client.incr("greylist", 1).is_err());
+
+        assert!(client
+            .expire("counter", &std::time::Duration::from_secs(60))
+            .unwrap());
+        assert_eq!(store.lock().unwrap()["counter"].1, Some(60_000));
+        assert!(!client
+            .expire("unknown", &std::time::Duration::from_secs(60))
+            .unwrap());
+
+        client.remove("counter").unwrap();
+        assert_eq!(client.get("counter").unwrap(), None);
+
+        // the connection is reused, even after an error replied by the server.
+        assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn closed_connections() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let received = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (closed, wait_closed) = std::sync::mpsc::channel();
+
+        // each connection answers a command, and may receive another one before being closed.
+        let server_received = received.clone();
+        let server = std::thread::spawn(move || {
+            for unanswered in [true, false, false] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = std::io::BufReader::new(stream);
+
+                read_reply(&mut reader, 0).unwrap();
+                server_received.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                std::io::Write::write_all(&mut writer, b":1\r\n").unwrap();
+
+                if unanswered {
+                    read_reply(&mut reader, 0).unwrap();
+                    server_received.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+
+                drop(reader);
+                drop(writer);
+                closed.send(()).unwrap();
+            }
+        });
+
+        let client = Client::new(
+            ServiceAddress::Tcp(address),
+            std::time::Duration::from_secs(5),
+            None,
+            0,
+            2,
+        );
+        assert_eq!(client.incr("counter", 1).unwrap(), 1);
+        // the command is received but not answered, it must not be sent again.
+        assert!(client.incr("counter", 1).is_err());
+        wait_closed.recv().unwrap();
+        assert_eq!(received.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // the database closes the idle connection, a new one is opened.
+        assert_eq!(client.incr("counter", 1).unwrap(), 1);
+        wait_closed.recv().unwrap();
+        assert_eq!(client.incr("counter", 1).unwrap(), 1);
+        assert_eq!(received.load(std::sync::atomic::Ordering::SeqCst), 4);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn authentication() {
+        let (address, _, _) = stand_in::serve(Some("secret"));
+        assert!(client(&address, Some("wrong")).get("key").is_err());
+    }
+
+    #[test]
+    fn unreachable() {
+        let address = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        assert!(client(&address, None).get("key").is_err());
+    }
+}
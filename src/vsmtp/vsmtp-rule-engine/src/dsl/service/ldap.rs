@@ -0,0 +1,648 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{
+    dsl::service::{runtime::Runtime, Service},
+    modules::EngineResult,
+};
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+use rhai::EvalAltResult;
+use vsmtp_common::re::{anyhow, log, tokio};
+
+/// the `invalidCredentials` result code of a bind.
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// replace `%u` by the local part of `key` and `%d` by its domain in `template`,
+/// the substitutions are escaped with `escape`. A key without domain is substituted
+/// entirely to `%u`.
+fn substitute(template: &str, key: &str, escape: fn(&str) -> String) -> String {
+    let (user, domain) = key.rsplit_once('@').unwrap_or((key, ""));
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('u') => output.push_str(&escape(user)),
+            Some('d') => output.push_str(&escape(domain)),
+            Some(other) if other != '%' => {
+                output.push('%');
+                output.push(other);
+            }
+            Some(_) | None => output.push('%'),
+        }
+    }
+
+    output
+}
+
+fn escape_filter(value: &str) -> String {
+    ldap3::ldap_escape(value).into_owned()
+}
+
+fn escape_dn(value: &str) -> String {
+    ldap3::dn_escape(value).into_owned()
+}
+
+/// convert an entry to a map of its attributes, with its dn in the `dn` field.
+fn to_map(entry: SearchEntry) -> rhai::Map {
+    std::iter::once(("dn".into(), entry.dn.into()))
+        .chain(entry.attrs.into_iter().map(|(attribute, values)| {
+            (
+                attribute.into(),
+                values
+                    .into_iter()
+                    .map(rhai::Dynamic::from)
+                    .collect::<rhai::Array>()
+                    .into(),
+            )
+        }))
+        .collect()
+}
+
+/// the search used to find the entries of a key.
+#[derive(Debug, Clone)]
+pub struct Search {
+    /// The dn where the search starts.
+    pub base: String,
+    /// The depth of the search.
+    pub scope: Scope,
+    /// The filter of the search, with `%u` and `%d` substitutions.
+    pub filter: String,
+    /// The attributes returned for each entry, all of them if empty.
+    pub attributes: Vec<String>,
+}
+
+/// the parameters of a connection to the directory.
+#[derive(Clone)]
+struct Connector {
+    url: String,
+    starttls: bool,
+    timeout: std::time::Duration,
+    bind: Option<(String, String)>,
+}
+
+impl Connector {
+    async fn connect(&self, bind: Option<(&str, &str)>) -> anyhow::Result<Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(self.timeout)
+            .set_starttls(self.starttls);
+        let (connection, mut ldap) = LdapConnAsync::with_settings(settings, &self.url).await?;
+
+        tokio::spawn(async move {
+            if let Err(error) = connection.drive().await {
+                log::warn!("ldap connection error: {error}");
+            }
+        });
+
+        ldap.with_timeout(self.timeout);
+        if let Some((dn, password)) = bind {
+            ldap.simple_bind(dn, password).await?.success()?;
+        }
+
+        Ok(ldap)
+    }
+}
+
+/// a client of a ldap directory.
+pub struct Client {
+    connector: Connector,
+    search: Search,
+    user_dn: Option<String>,
+    runtime: Runtime,
+    /// the connection used for the searches, bound with the credentials of the service.
+    connection: std::sync::Arc<tokio::sync::Mutex<Option<Ldap>>>,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("url", &self.connector.url)
+            .field("starttls", &self.connector.starttls)
+            .field("timeout", &self.connector.timeout)
+            .field(
+                "bind_dn",
+                &self.connector.bind.as_ref().map(|(dn, _)| dn.as_str()),
+            )
+            .field("search", &self.search)
+            .field("user_dn", &self.user_dn)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// search the entries of `key`, with the search of the service.
+    ///
+    /// # Errors
+    ///
+    /// * the directory could not be reached.
+    /// * the bind of the service failed.
+    /// * the directory replied with an error.
+    pub fn search(&self, key: &str) -> anyhow::Result<Vec<rhai::Map>> {
+        Ok(self
+            .search_entries(substitute(&self.search.filter, key, escape_filter))?
+            .into_iter()
+            .map(to_map)
+            .collect())
+    }
+
+    fn search_entries(&self, filter: String) -> anyhow::Result<Vec<SearchEntry>> {
+        let connector = self.connector.clone();
+        let search = self.search.clone();
+        let connection = self.connection.clone();
+
+        self.runtime.block_on(self.connector.timeout, async move {
+            // the connection may have been closed by the directory since the last search,
+            // it is opened again once.
+            for retry in [true, false] {
+                let cached = connection.lock().await.clone();
+                let mut ldap = match cached {
+                    Some(ldap) if !ldap.clone().is_closed() => ldap,
+                    _ => {
+                        let ldap = connector
+                            .connect(
+                                connector
+                                    .bind
+                                    .as_ref()
+                                    .map(|(dn, password)| (dn.as_str(), password.as_str())),
+                            )
+                            .await?;
+                        *connection.lock().await = Some(ldap.clone());
+                        ldap
+                    }
+                };
+
+                match ldap
+                    .search(&search.base, search.scope, &filter, &search.attributes)
+                    .await
+                    .and_then(ldap3::SearchResult::success)
+                {
+                    Ok((entries, _)) => {
+                        return Ok(entries.into_iter().map(SearchEntry::construct).collect())
+                    }
+                    Err(error @ LdapError::LdapResult { .. }) => return Err(error.into()),
+                    Err(error) if retry => {
+                        log::debug!(
+                            "ldap connection to '{}' failed, reconnecting: {error}",
+                            connector.url
+                        );
+                        *connection.lock().await = None;
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+
+            unreachable!("the last try always returns")
+        })
+    }
+
+    /// check the password of `user` with a simple bind, the dn of the user is built
+    /// with `user_dn`, or searched with the search of the service.
+    ///
+    /// # Errors
+    ///
+    /// * the directory could not be reached.
+    /// * the directory replied with an error other than `invalidCredentials`.
+    pub fn bind(&self, user: &str, password: &str) -> anyhow::Result<bool> {
+        // an empty password is an unauthenticated bind, which always succeeds.
+        if password.is_empty() {
+            return Ok(false);
+        }
+
+        let dn = match &self.user_dn {
+            Some(template) => substitute(template, user, escape_dn),
+            None => match self
+                .search_entries(substitute(&self.search.filter, user, escape_filter))?
+                .as_slice()
+            {
+                [entry] => entry.dn.clone(),
+                // unknown or ambiguous user.
+                _ => return Ok(false),
+            },
+        };
+
+        let connector = self.connector.clone();
+        let password = password.to_string();
+
+        self.runtime.block_on(self.connector.timeout, async move {
+            let mut ldap = connector.connect(None).await?;
+            let result = ldap.simple_bind(&dn, &password).await?;
+            let _ = ldap.unbind().await;
+
+            match result.rc {
+                0 => Ok(true),
+                INVALID_CREDENTIALS => Ok(false),
+                _ => Err(LdapError::from(result).into()),
+            }
+        })
+    }
+}
+
+fn get_string(options: &rhai::Map, key: &str, service_name: &str) -> EngineResult<String> {
+    options
+        .get(key)
+        .ok_or_else::<Box<EvalAltResult>, _>(|| {
+            format!("ldap service {service_name} is missing the '{key}' option.").into()
+        })
+        .map(ToString::to_string)
+}
+
+fn get_map(options: &rhai::Map, key: &str, service_name: &str) -> EngineResult<rhai::Map> {
+    options
+        .get(key)
+        .ok_or_else::<Box<EvalAltResult>, _>(|| {
+            format!("ldap service {service_name} is missing the '{key}' option.").into()
+        })?
+        .clone()
+        .try_cast()
+        .ok_or_else::<Box<EvalAltResult>, _>(|| {
+            format!("the '{key}' option of ldap service {service_name} must be a map").into()
+        })
+}
+
+pub fn parse_ldap_service(
+    context: &mut rhai::EvalContext,
+    input: &[rhai::Expression],
+    service_name: &str,
+) -> EngineResult<Service> {
+    let options: rhai::Map = context
+        .eval_expression_tree(&input[3])?
+        .try_cast()
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            "ldap service options must be a map".into()
+        })?;
+
+    let url = get_string(&options, "url", service_name)?;
+
+    let starttls = options
+        .get("starttls")
+        .map_or(Ok(false), rhai::Dynamic::as_bool)
+        .map_err::<Box<EvalAltResult>, _>(|_| {
+            format!("the 'starttls' option of ldap service {service_name} must be a boolean").into()
+        })?;
+
+    let timeout: std::time::Duration = options
+        .get("timeout")
+        .map_or_else(|| "5s".to_string(), ToString::to_string)
+        .parse::<vsmtp_config::re::humantime::Duration>()
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?
+        .into();
+
+    let bind = if options.contains_key("bind") {
+        let bind = get_map(&options, "bind", service_name)?;
+        Some((
+            get_string(&bind, "dn", service_name)?,
+            get_string(&bind, "password", service_name)?,
+        ))
+    } else {
+        None
+    };
+
+    let search = get_map(&options, "search", service_name)?;
+    let scope = search
+        .get("scope")
+        .map_or_else(|| "sub".to_string(), ToString::to_string);
+    let search = Search {
+        base: get_string(&search, "base", service_name)?,
+        scope: match scope.as_str() {
+            "base" => Scope::Base,
+            "one" => Scope::OneLevel,
+            "sub" => Scope::Subtree,
+            _ => {
+                return Err(format!(
+                    "{scope} is not a correct search scope, expected 'base', 'one' or 'sub'"
+                )
+                .into())
+            }
+        },
+        filter: get_string(&search, "filter", service_name)?,
+        attributes: search
+            .get("attributes")
+            .map_or_else(
+                || Some(vec![]),
+                |attributes| {
+                    attributes
+                        .clone()
+                        .try_cast::<rhai::Array>()?
+                        .into_iter()
+                        .map(rhai::Dynamic::try_cast)
+                        .collect::<Option<Vec<String>>>()
+                },
+            )
+            .ok_or_else::<Box<EvalAltResult>, _>(|| {
+                "the attributes of a ldap search must be an array of strings".into()
+            })?,
+    };
+
+    let runtime = Runtime::new(service_name)
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+
+    Ok(Service::Ldap {
+        client: Client {
+            connector: Connector {
+                url,
+                starttls,
+                timeout,
+                bind,
+            },
+            search,
+            user_dn: options.get("user_dn").map(ToString::to_string),
+            runtime,
+            connection: std::sync::Arc::new(tokio::sync::Mutex::new(None)),
+        },
+    })
+}
+
+#[cfg(test)]
+pub mod stand_in {
+    //! a ldap directory in memory, implementing the bind and the equality searches.
+
+    use std::io::{Read, Write};
+
+    /// the dn and the password of the service account.
+    pub const SERVICE: (&str, &str) = ("cn=vsmtp,dc=example,dc=com", "service");
+    /// the dn and the password of the user in the directory.
+    pub const USER: (&str, &str) = ("uid=john.doe,ou=people,dc=example,dc=com", "secret");
+
+    fn attributes() -> Vec<(&'static str, Vec<&'static str>)> {
+        vec![
+            ("objectClass", vec!["inetOrgPerson"]),
+            ("uid", vec!["john.doe"]),
+            ("mail", vec!["john.doe@example.com"]),
+            ("mailAlias", vec!["john@example.com", "jdoe@example.com"]),
+        ]
+    }
+
+    fn read_tlv(stream: &mut impl Read) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0; 2];
+        stream.read_exact(&mut header).ok()?;
+
+        let length = if header[1] & 0x80 == 0 {
+            usize::from(header[1])
+        } else {
+            let mut bytes = vec![0; usize::from(header[1] & 0x7f)];
+            stream.read_exact(&mut bytes).ok()?;
+            bytes
+                .into_iter()
+                .fold(0, |length, byte| (length << 8) | usize::from(byte))
+        };
+
+        let mut content = vec![0; length];
+        stream.read_exact(&mut content).ok()?;
+        Some((header[0], content))
+    }
+
+    fn split(mut content: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        std::iter::from_fn(|| read_tlv(&mut content)).collect()
+    }
+
+    fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut output = vec![tag];
+        match u8::try_from(content.len()) {
+            Ok(length) if length < 0x80 => output.push(length),
+            _ => {
+                let length = content.len().to_be_bytes();
+                let length = &length[length.iter().position(|b| *b != 0).unwrap()..];
+                output.push(0x80 | u8::try_from(length.len()).unwrap());
+                output.extend_from_slice(length);
+            }
+        }
+        output.extend_from_slice(content);
+        output
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        encode(0x04, value.as_bytes())
+    }
+
+    fn result(tag: u8, code: u8) -> Vec<u8> {
+        encode(
+            tag,
+            &[encode(0x0a, &[code]), string(""), string("")].concat(),
+        )
+    }
+
+    /// collect the equality matches of a filter, through the `and` filters.
+    fn equalities(tag: u8, content: &[u8], output: &mut Vec<(String, String)>) {
+        match tag {
+            0xa3 => {
+                let fields = split(content);
+                output.push((
+                    String::from_utf8_lossy(&fields[0].1).to_lowercase(),
+                    String::from_utf8_lossy(&fields[1].1).into_owned(),
+                ));
+            }
+            0xa0 => {
+                for (tag, content) in split(content) {
+                    equalities(tag, &content, output);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn search(fields: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut filter = vec![];
+        equalities(fields[6].0, &fields[6].1, &mut filter);
+        let requested = split(&fields[7].1)
+            .into_iter()
+            .map(|(_, attribute)| String::from_utf8_lossy(&attribute).to_lowercase())
+            .collect::<Vec<_>>();
+
+        let matches = filter.iter().all(|(attribute, value)| {
+            attributes().into_iter().any(|(name, values)| {
+                name.to_lowercase() == *attribute && values.contains(&value.as_str())
+            })
+        });
+
+        if !matches || filter.is_empty() {
+            return vec![];
+        }
+
+        let attributes = attributes()
+            .into_iter()
+            .filter(|(name, _)| requested.is_empty() || requested.contains(&name.to_lowercase()))
+            .map(|(name, values)| {
+                let values = values.into_iter().flat_map(string).collect::<Vec<_>>();
+                encode(0x30, &[string(name), encode(0x31, &values)].concat())
+            })
+            .collect::<Vec<_>>()
+            .concat();
+
+        encode(0x64, &[string(USER.0), encode(0x30, &attributes)].concat())
+    }
+
+    fn handle(mut stream: std::net::TcpStream) {
+        let mut bound = false;
+
+        while let Some((0x30, message)) = read_tlv(&mut stream) {
+            let message = split(&message);
+            let id = encode(0x02, &message[0].1);
+            let (operation, content) = &message[1];
+            let fields = split(content);
+
+            let replies = match operation {
+                0x60 => {
+                    let credentials = (
+                        String::from_utf8_lossy(&fields[1].1).into_owned(),
+                        String::from_utf8_lossy(&fields[2].1).into_owned(),
+                    );
+                    bound = [SERVICE, USER].iter().any(|(dn, password)| {
+                        credentials == ((*dn).to_string(), (*password).to_string())
+                    });
+                    vec![result(0x61, if bound { 0 } else { 49 })]
+                }
+                // insufficientAccessRights for an anonymous search.
+                0x63 if !bound => vec![result(0x65, 50)],
+                0x63 => {
+                    let entry = search(&fields);
+                    if entry.is_empty() {
+                        vec![result(0x65, 0)]
+                    } else {
+                        vec![entry, result(0x65, 0)]
+                    }
+                }
+                _ => return,
+            };
+
+            for reply in replies {
+                if stream
+                    .write_all(&encode(0x30, &[id.clone(), reply].concat()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// listen on a random port, return the url of the directory.
+    pub fn serve() -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                std::thread::spawn(move || handle(stream));
+            }
+        });
+
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(url: String, service_password: &str, user_dn: Option<&str>) -> Client {
+        Client {
+            connector: Connector {
+                url,
+                starttls: false,
+                timeout: std::time::Duration::from_secs(5),
+                bind: Some((
+                    stand_in::SERVICE.0.to_string(),
+                    service_password.to_string(),
+                )),
+            },
+            search: Search {
+                base: "ou=people,dc=example,dc=com".to_string(),
+                scope: Scope::Subtree,
+                filter: "(&(objectClass=inetOrgPerson)(mail=%u@%d))".to_string(),
+                attributes: vec!["mail".to_string(), "mailAlias".to_string()],
+            },
+            user_dn: user_dn.map(str::to_string),
+            runtime: Runtime::new("test").unwrap(),
+            connection: std::sync::Arc::new(tokio::sync::Mutex::new(None)),
+        }
+    }
+
+    #[test]
+    fn substitution() {
+        assert_eq!(
+            substitute("(mail=%u@%d)", "john.doe@example.com", escape_filter),
+            "(mail=john.doe@example.com)"
+        );
+        assert_eq!(
+            substitute("(uid=%u)(domain=%d)(%%)", "john.doe", escape_filter),
+            "(uid=john.doe)(domain=)(%)"
+        );
+        assert_eq!(
+            substitute("(uid=%u)", "*)(uid=*", escape_filter),
+            "(uid=\\2a\\29\\28uid=\\2a)"
+        );
+        assert_eq!(
+            substitute("uid=%u,dc=example", "doe,john", escape_dn),
+            "uid=doe\\2cjohn,dc=example"
+        );
+        assert_eq!(substitute("%x%", "key", escape_filter), "%x%");
+    }
+
+    #[test]
+    fn search() {
+        let client = client(stand_in::serve(), stand_in::SERVICE.1, None);
+
+        let entries = client.search("john.doe@example.com").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get("dn").unwrap().to_string(), stand_in::USER.0);
+        assert_eq!(
+            entries[0]
+                .get("mailAlias")
+                .unwrap()
+                .clone()
+                .into_typed_array::<String>()
+                .unwrap(),
+            vec!["john@example.com", "jdoe@example.com"]
+        );
+        assert!(entries[0].get("uid").is_none());
+
+        assert!(client.search("jenny.doe@example.com").unwrap().is_empty());
+    }
+
+    #[test]
+    fn service_bind() {
+        let client = client(stand_in::serve(), "wrong", None);
+        assert!(client.search("john.doe@example.com").is_err());
+    }
+
+    #[test]
+    fn bind() {
+        let client = client(stand_in::serve(), stand_in::SERVICE.1, None);
+
+        assert!(client.bind("john.doe@example.com", "secret").unwrap());
+        assert!(!client.bind("john.doe@example.com", "wrong").unwrap());
+        assert!(!client.bind("john.doe@example.com", "").unwrap());
+        assert!(!client.bind("jenny.doe@example.com", "secret").unwrap());
+    }
+
+    #[test]
+    fn bind_with_user_dn() {
+        let client = client(
+            stand_in::serve(),
+            stand_in::SERVICE.1,
+            Some("uid=%u,ou=people,dc=example,dc=com"),
+        );
+
+        assert!(client.bind("john.doe", "secret").unwrap());
+        assert!(!client.bind("john.doe", "wrong").unwrap());
+    }
+}
@@ -0,0 +1,1094 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+//! client of the sendmail milter protocol (version 6).
+//!
+//! the events of a session are sent to the filter each time the service is called,
+//! catching up with the state of the transaction: a milter can be called in every
+//! stage, or only once in `preq` to send all the events of the transaction at once.
+
+use super::{Service, ServiceAddress, ServiceStream};
+use crate::modules::EngineResult;
+use rhai::EvalAltResult;
+use vsmtp_common::{
+    mail_context::MailContext,
+    rcpt::Rcpt,
+    re::{anyhow, log},
+    status::Status,
+    transfer::Transfer,
+    Address, CodeID, MessageBody, RawBody, Reply, ReplyCode, ReplyOrCodeID,
+};
+
+/// version of the milter protocol implemented.
+const VERSION: u32 = 6;
+
+/// size of the chunks of the body sent to the filter.
+const CHUNK_SIZE: usize = 65535;
+
+/// maximum size of a packet sent by the filter.
+const PACKET_MAX_SIZE: usize = 1024 * 1024;
+
+/// sessions unused for this duration are closed.
+const SESSION_IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// period of the search for the idle sessions.
+const SESSION_SWEEP_PERIOD: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// commands sent to the filter, see `SMFIC_*`.
+mod command {
+    pub const ABORT: u8 = b'A';
+    pub const BODY: u8 = b'B';
+    pub const CONNECT: u8 = b'C';
+    pub const MACRO: u8 = b'D';
+    pub const BODYEOB: u8 = b'E';
+    pub const HELO: u8 = b'H';
+    pub const HEADER: u8 = b'L';
+    pub const MAIL: u8 = b'M';
+    pub const EOH: u8 = b'N';
+    pub const OPTNEG: u8 = b'O';
+    pub const QUIT: u8 = b'Q';
+    pub const RCPT: u8 = b'R';
+    pub const DATA: u8 = b'T';
+}
+
+/// responses of the filter, see `SMFIR_*`.
+mod response {
+    pub const ADDRCPT: u8 = b'+';
+    pub const DELRCPT: u8 = b'-';
+    pub const ADDRCPT_PAR: u8 = b'2';
+    pub const ACCEPT: u8 = b'a';
+    pub const CONTINUE: u8 = b'c';
+    pub const DISCARD: u8 = b'd';
+    pub const CHGFROM: u8 = b'e';
+    pub const ADDHEADER: u8 = b'h';
+    pub const INSHEADER: u8 = b'i';
+    pub const CHGHEADER: u8 = b'm';
+    pub const PROGRESS: u8 = b'p';
+    pub const QUARANTINE: u8 = b'q';
+    pub const REJECT: u8 = b'r';
+    pub const SKIP: u8 = b's';
+    pub const TEMPFAIL: u8 = b't';
+    pub const REPLYCODE: u8 = b'y';
+}
+
+/// actions the filter is allowed to do, see `SMFIF_*`.
+/// changing the body is not supported.
+const ACTIONS: u32 = 0x01 // add headers
+    | 0x04 // add recipients
+    | 0x08 // delete recipients
+    | 0x10 // change or delete headers
+    | 0x20 // quarantine
+    | 0x40 // change envelope sender
+    | 0x80; // add recipients with esmtp parameters
+
+/// steps of the protocol the filter can disable, see `SMFIP_*`.
+mod protocol {
+    pub const NOCONNECT: u32 = 0x01;
+    pub const NOHELO: u32 = 0x02;
+    pub const NOMAIL: u32 = 0x04;
+    pub const NORCPT: u32 = 0x08;
+    pub const NOBODY: u32 = 0x10;
+    pub const NOHDRS: u32 = 0x20;
+    pub const NOEOH: u32 = 0x40;
+    pub const NR_HDR: u32 = 0x80;
+    pub const NODATA: u32 = 0x200;
+    pub const SKIP: u32 = 0x400;
+    pub const NR_CONN: u32 = 0x1000;
+    pub const NR_HELO: u32 = 0x2000;
+    pub const NR_MAIL: u32 = 0x4000;
+    pub const NR_RCPT: u32 = 0x8000;
+    pub const NR_DATA: u32 = 0x10000;
+    pub const NR_EOH: u32 = 0x40000;
+    pub const NR_BODY: u32 = 0x80000;
+
+    /// all the steps supported by this client.
+    pub const SUPPORTED: u32 = NOCONNECT
+        | NOHELO
+        | NOMAIL
+        | NORCPT
+        | NOBODY
+        | NOHDRS
+        | NOEOH
+        | NR_HDR
+        | NODATA
+        | SKIP
+        | NR_CONN
+        | NR_HELO
+        | NR_MAIL
+        | NR_RCPT
+        | NR_DATA
+        | NR_EOH
+        | NR_BODY;
+}
+
+/// stop sending events if the filter returns anything else than `continue`.
+macro_rules! step {
+    ($e:expr) => {
+        match $e? {
+            Verdict::Continue => {}
+            other => return Ok((other, vec![])),
+        }
+    };
+}
+
+/// identify a smtp session: the address of the client and the time of the connection.
+pub type SessionKey = (std::net::SocketAddr, std::time::SystemTime);
+
+/// the sessions opened with a filter.
+pub type Sessions = std::sync::Mutex<std::collections::HashMap<SessionKey, Session>>;
+
+/// the response of the filter to an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Verdict {
+    Continue,
+    Accept,
+    Reject(Option<Reply>),
+    Tempfail(Option<Reply>),
+    Discard,
+    Skip,
+}
+
+/// a modification of the message requested by the filter at the end of the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Modification {
+    /// append a header.
+    AddHeader(String, String),
+    /// insert a header at an index.
+    InsertHeader(usize, String, String),
+    /// change the nth occurrence of a header, or remove it if the value is empty.
+    ChangeHeader(usize, String, String),
+    /// add a recipient.
+    AddRcpt(String),
+    /// remove a recipient.
+    DeleteRcpt(String),
+    /// change the sender of the envelop.
+    ChangeFrom(String),
+    /// put the message in quarantine, with a reason.
+    Quarantine(String),
+}
+
+/// the transaction being sent to the filter.
+#[derive(Debug)]
+struct Transaction {
+    message_id: String,
+    rcpt_count: usize,
+    /// the filter does not want more events for this transaction.
+    done: bool,
+}
+
+/// a connection with a filter for one smtp session.
+#[derive(Debug)]
+pub struct Session {
+    stream: ServiceStream,
+    /// the steps of the protocol negotiated with the filter.
+    protocol: u32,
+    helo: Option<String>,
+    transaction: Option<Transaction>,
+    /// the filter does not want more events for this session.
+    done: bool,
+    last_used: std::time::Instant,
+}
+
+pub fn parse_milter_service(
+    context: &mut rhai::EvalContext,
+    input: &[rhai::Expression],
+    service_name: &str,
+) -> EngineResult<Service> {
+    let options: rhai::Map = context
+        .eval_expression_tree(&input[3])?
+        .try_cast()
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            "milter service options must be a map".into()
+        })?;
+
+    let address = options
+        .get("address")
+        .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+            format!("milter service {service_name} is missing the 'address' option.").into()
+        })?
+        .to_string()
+        .parse::<ServiceAddress>()
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+
+    let timeout: std::time::Duration = options
+        .get("timeout")
+        .map_or_else(|| "30s".to_string(), ToString::to_string)
+        .parse::<vsmtp_config::re::humantime::Duration>()
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?
+        .into();
+
+    let quarantine = options
+        .get("quarantine")
+        .map_or_else(|| "milter".to_string(), ToString::to_string);
+
+    let sessions = std::sync::Arc::new(Sessions::default());
+    sweep_idle_sessions(std::sync::Arc::downgrade(&sessions));
+
+    Ok(Service::Milter {
+        address,
+        timeout,
+        quarantine,
+        sessions,
+    })
+}
+
+/// close the sessions unused for [`SESSION_IDLE_TIMEOUT`].
+///
+/// # Errors
+///
+/// * the mutex is poisoned
+fn close_idle_sessions(sessions: &Sessions) -> anyhow::Result<()> {
+    let idle = {
+        let mut sessions = sessions.lock().map_err(poisoned)?;
+        let (idle, used): (
+            std::collections::HashMap<_, _>,
+            std::collections::HashMap<_, _>,
+        ) = std::mem::take(&mut *sessions)
+            .into_iter()
+            .partition(|(_, session)| session.last_used.elapsed() >= SESSION_IDLE_TIMEOUT);
+        *sessions = used;
+        idle
+    };
+
+    // the sessions are closed without holding the lock.
+    for (_, session) in idle {
+        session.quit();
+    }
+    Ok(())
+}
+
+/// close the idle sessions periodically, the sessions which are not closed at the end
+/// of their transaction are not kept open by an idle server.
+/// the thread stops once the service is dropped.
+fn sweep_idle_sessions(sessions: std::sync::Weak<Sessions>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SESSION_SWEEP_PERIOD);
+
+        match sessions.upgrade() {
+            Some(sessions) => {
+                if let Err(error) = close_idle_sessions(&sessions) {
+                    log::warn!("failed to close the idle milter sessions: {error}");
+                }
+            }
+            None => return,
+        }
+    });
+}
+
+fn write_packet(stream: &mut ServiceStream, command: u8, data: &[u8]) -> anyhow::Result<()> {
+    let mut packet = Vec::with_capacity(data.len() + 5);
+    packet.extend_from_slice(&u32::try_from(data.len() + 1)?.to_be_bytes());
+    packet.push(command);
+    packet.extend_from_slice(data);
+    std::io::Write::write_all(stream, &packet)?;
+    Ok(())
+}
+
+fn read_packet(stream: &mut ServiceStream) -> anyhow::Result<(u8, Vec<u8>)> {
+    let mut length = [0; 4];
+    std::io::Read::read_exact(stream, &mut length)?;
+    let length = usize::try_from(u32::from_be_bytes(length))?;
+
+    if length == 0 || length > PACKET_MAX_SIZE {
+        anyhow::bail!("invalid milter packet length: {length}");
+    }
+
+    let mut packet = vec![0; length];
+    std::io::Read::read_exact(stream, &mut packet)?;
+    let data = packet.split_off(1);
+    Ok((packet[0], data))
+}
+
+/// concatenate strings, each terminated by a null character.
+fn null_terminated<'a>(strings: impl IntoIterator<Item = &'a str>) -> Vec<u8> {
+    strings.into_iter().fold(vec![], |mut out, s| {
+        out.extend_from_slice(s.as_bytes());
+        out.push(b'\0');
+        out
+    })
+}
+
+/// split the data of a packet into null terminated strings.
+fn split_strings(data: &[u8]) -> Vec<String> {
+    data.strip_suffix(b"\0")
+        .unwrap_or(data)
+        .split(|c| *c == b'\0')
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect()
+}
+
+/// remove the `<>` around an address sent by the filter.
+fn strip_brackets(address: &str) -> &str {
+    address
+        .trim()
+        .strip_prefix('<')
+        .and_then(|a| a.strip_suffix('>'))
+        .unwrap_or_else(|| address.trim())
+}
+
+/// parse the data of a `SMFIR_INSHEADER` or `SMFIR_CHGHEADER` response.
+fn parse_indexed_header(data: &[u8]) -> anyhow::Result<(usize, String, String)> {
+    let index = data
+        .get(..4)
+        .ok_or_else(|| anyhow::anyhow!("missing header index in milter response"))?;
+    let index = usize::try_from(u32::from_be_bytes(index.try_into()?))?;
+
+    let mut strings = split_strings(&data[4..]).into_iter();
+    Ok((
+        index,
+        strings.next().unwrap_or_default(),
+        strings.next().unwrap_or_default(),
+    ))
+}
+
+fn parse_reply(data: &[u8]) -> anyhow::Result<Verdict> {
+    let reply = Reply::parse_str(split_strings(data).first().map_or("", String::as_str))?;
+    Ok(match reply.code() {
+        ReplyCode::Code { code } | ReplyCode::Enhanced { code, .. }
+            if (400..500).contains(code) =>
+        {
+            Verdict::Tempfail(Some(reply))
+        }
+        _ => Verdict::Reject(Some(reply)),
+    })
+}
+
+impl Session {
+    /// open a connection and negotiate the protocol with the filter.
+    fn open(address: &ServiceAddress, timeout: &std::time::Duration) -> anyhow::Result<Self> {
+        let mut stream = address.connect(timeout)?;
+
+        write_packet(
+            &mut stream,
+            command::OPTNEG,
+            &[VERSION, ACTIONS, protocol::SUPPORTED]
+                .iter()
+                .flat_map(|i| i.to_be_bytes())
+                .collect::<Vec<_>>(),
+        )?;
+
+        let (code, data) = read_packet(&mut stream)?;
+        if code != command::OPTNEG || data.len() < 12 {
+            anyhow::bail!("invalid option negotiation from milter at '{address}'");
+        }
+
+        let version = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let protocol = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+        if version < 2 || protocol & !protocol::SUPPORTED != 0 {
+            anyhow::bail!(
+                "milter at '{address}' requested an unsupported protocol (version {version}, steps {protocol:#x})"
+            );
+        }
+
+        Ok(Self {
+            stream,
+            protocol,
+            helo: None,
+            transaction: None,
+            done: false,
+            last_used: std::time::Instant::now(),
+        })
+    }
+
+    const fn has(&self, flag: u32) -> bool {
+        self.protocol & flag != 0
+    }
+
+    fn macros(&mut self, command: u8, macros: &[(&str, &str)]) -> anyhow::Result<()> {
+        let mut data = vec![command];
+        data.extend(null_terminated(
+            macros.iter().flat_map(|(name, value)| [*name, *value]),
+        ));
+        write_packet(&mut self.stream, command::MACRO, &data)
+    }
+
+    /// send a command, and read the verdict of the filter if `no_reply` is not negotiated.
+    fn send(&mut self, command: u8, data: &[u8], no_reply: u32) -> anyhow::Result<Verdict> {
+        write_packet(&mut self.stream, command, data)?;
+        if self.has(no_reply) {
+            return Ok(Verdict::Continue);
+        }
+
+        loop {
+            let (code, data) = read_packet(&mut self.stream)?;
+            return Ok(match code {
+                response::PROGRESS => continue,
+                response::CONTINUE => Verdict::Continue,
+                response::ACCEPT => Verdict::Accept,
+                response::REJECT => Verdict::Reject(None),
+                response::TEMPFAIL => Verdict::Tempfail(None),
+                response::DISCARD => Verdict::Discard,
+                response::SKIP => Verdict::Skip,
+                response::REPLYCODE => parse_reply(&data)?,
+                unknown => anyhow::bail!("unexpected milter response '{}'", unknown as char),
+            });
+        }
+    }
+
+    /// send the end of the message, and read the modifications requested by the filter.
+    fn end_of_message(&mut self) -> anyhow::Result<(Verdict, Vec<Modification>)> {
+        write_packet(&mut self.stream, command::BODYEOB, &[])?;
+
+        let mut modifications = vec![];
+        loop {
+            let (code, data) = read_packet(&mut self.stream)?;
+            let mut strings = split_strings(&data).into_iter();
+            let mut next = || strings.next().unwrap_or_default();
+
+            match code {
+                response::PROGRESS => {}
+                response::ADDHEADER => {
+                    modifications.push(Modification::AddHeader(next(), next()));
+                }
+                response::INSHEADER => {
+                    let (index, name, value) = parse_indexed_header(&data)?;
+                    modifications.push(Modification::InsertHeader(index, name, value));
+                }
+                response::CHGHEADER => {
+                    let (index, name, value) = parse_indexed_header(&data)?;
+                    modifications.push(Modification::ChangeHeader(index, name, value));
+                }
+                response::ADDRCPT | response::ADDRCPT_PAR => {
+                    modifications.push(Modification::AddRcpt(next()));
+                }
+                response::DELRCPT => modifications.push(Modification::DeleteRcpt(next())),
+                response::CHGFROM => modifications.push(Modification::ChangeFrom(next())),
+                response::QUARANTINE => modifications.push(Modification::Quarantine(next())),
+                response::CONTINUE => return Ok((Verdict::Continue, modifications)),
+                response::ACCEPT => return Ok((Verdict::Accept, modifications)),
+                response::REJECT => return Ok((Verdict::Reject(None), modifications)),
+                response::TEMPFAIL => return Ok((Verdict::Tempfail(None), modifications)),
+                response::DISCARD => return Ok((Verdict::Discard, modifications)),
+                response::REPLYCODE => return Ok((parse_reply(&data)?, modifications)),
+                unknown => anyhow::bail!("unexpected milter response '{}'", unknown as char),
+            }
+        }
+    }
+
+    fn quit(mut self) {
+        if let Err(error) = write_packet(&mut self.stream, command::QUIT, &[]) {
+            log::debug!("failed to close milter session: {error}");
+        }
+    }
+
+    /// send the events of the session not yet sent to the filter.
+    ///
+    /// return the first verdict which is not `continue`, and the modifications
+    /// requested at the end of the message.
+    fn catch_up(
+        &mut self,
+        ctx: &MailContext,
+        message: &MessageBody,
+    ) -> anyhow::Result<(Verdict, Vec<Modification>)> {
+        if self.done {
+            return Ok((Verdict::Continue, vec![]));
+        }
+
+        if self.helo.is_none() {
+            self.macros(
+                command::CONNECT,
+                &[
+                    ("j", &ctx.connection.server_name),
+                    ("{daemon_name}", "vsmtp"),
+                ],
+            )?;
+
+            if !self.has(protocol::NOCONNECT) {
+                let ip = ctx.client_addr.ip();
+                let mut data = null_terminated([format!("[{ip}]").as_str()]);
+                data.push(if ip.is_ipv4() { b'4' } else { b'6' });
+                data.extend_from_slice(&ctx.client_addr.port().to_be_bytes());
+                data.extend(null_terminated([ip.to_string().as_str()]));
+                step!(self.send(command::CONNECT, &data, protocol::NR_CONN));
+            }
+            self.helo = Some(String::new());
+        }
+
+        if !ctx.envelop.helo.is_empty() && self.helo.as_deref() != Some(&ctx.envelop.helo) {
+            if !self.has(protocol::NOHELO) {
+                step!(self.send(
+                    command::HELO,
+                    &null_terminated([ctx.envelop.helo.as_str()]),
+                    protocol::NR_HELO
+                ));
+            }
+            self.helo = Some(ctx.envelop.helo.clone());
+        }
+
+        let message_id = match &ctx.metadata {
+            Some(metadata) => metadata.message_id.clone(),
+            None => return Ok((Verdict::Continue, vec![])),
+        };
+
+        match &self.transaction {
+            Some(transaction) if transaction.message_id == message_id => {}
+            previous => {
+                if previous
+                    .as_ref()
+                    .map_or(false, |transaction| !transaction.done)
+                {
+                    write_packet(&mut self.stream, command::ABORT, &[])?;
+                }
+                self.transaction = Some(Transaction {
+                    message_id: message_id.clone(),
+                    rcpt_count: 0,
+                    done: false,
+                });
+
+                self.macros(command::MAIL, &[("i", &message_id)])?;
+                if !self.has(protocol::NOMAIL) {
+                    step!(self.send(
+                        command::MAIL,
+                        &null_terminated([format!("<{}>", ctx.envelop.mail_from.full()).as_str()]),
+                        protocol::NR_MAIL
+                    ));
+                }
+            }
+        }
+
+        if self.transaction.as_ref().map_or(true, |t| t.done) {
+            return Ok((Verdict::Continue, vec![]));
+        }
+
+        while let Some(rcpt) = self
+            .transaction
+            .as_ref()
+            .and_then(|t| ctx.envelop.rcpt.get(t.rcpt_count))
+        {
+            if let Some(transaction) = &mut self.transaction {
+                transaction.rcpt_count += 1;
+            }
+            if !self.has(protocol::NORCPT) {
+                step!(self.send(
+                    command::RCPT,
+                    &null_terminated([format!("<{}>", rcpt.address.full()).as_str()]),
+                    protocol::NR_RCPT
+                ));
+            }
+        }
+
+        // the message is available from the `preq` stage.
+        let raw = message.inner();
+        if raw.headers_lines().next().is_none() && raw.body_bytes().is_none() {
+            return Ok((Verdict::Continue, vec![]));
+        }
+
+        if let Some(transaction) = &mut self.transaction {
+            transaction.done = true;
+        }
+        self.send_message(raw, &message_id)
+    }
+
+    /// send the content of the message, and read the modifications requested by the filter.
+    fn send_message(
+        &mut self,
+        raw: &RawBody,
+        message_id: &str,
+    ) -> anyhow::Result<(Verdict, Vec<Modification>)> {
+        if !self.has(protocol::NODATA) {
+            step!(self.send(command::DATA, &[], protocol::NR_DATA));
+        }
+
+        if !self.has(protocol::NOHDRS) {
+            for (name, value) in raw.headers() {
+                step!(self.send(
+                    command::HEADER,
+                    &null_terminated([name.as_str(), value.strip_prefix(' ').unwrap_or(&value)]),
+                    protocol::NR_HDR
+                ));
+            }
+        }
+
+        if !self.has(protocol::NOEOH) {
+            step!(self.send(command::EOH, &[], protocol::NR_EOH));
+        }
+
+        if !self.has(protocol::NOBODY) {
+            for chunk in raw.body_bytes().unwrap_or_default().chunks(CHUNK_SIZE) {
+                match self.send(command::BODY, chunk, protocol::NR_BODY)? {
+                    Verdict::Continue => {}
+                    Verdict::Skip => break,
+                    other => return Ok((other, vec![])),
+                }
+            }
+        }
+
+        self.macros(command::BODYEOB, &[("i", message_id)])?;
+        self.end_of_message()
+    }
+}
+
+/// apply the modifications requested by the filter to the message and its envelop.
+fn apply(
+    modifications: Vec<Modification>,
+    ctx: &mut MailContext,
+    message: &mut MessageBody,
+    quarantine: &str,
+) -> Status {
+    let mut status = Status::Next;
+
+    for modification in modifications {
+        log::debug!("applying milter modification: {modification:?}");
+        match modification {
+            Modification::AddHeader(name, value) => message.append_header(&name, &value),
+            Modification::InsertHeader(index, name, value) => {
+                message.insert_header(index, &name, &value);
+            }
+            Modification::ChangeHeader(index, name, value) => {
+                message.change_header(&name, index, (!value.is_empty()).then(|| value.as_str()));
+            }
+            Modification::AddRcpt(rcpt) => match Address::try_from(strip_brackets(&rcpt)) {
+                Ok(address) => ctx.envelop.rcpt.push(Rcpt::new(address)),
+                Err(error) => log::warn!("milter added an invalid recipient '{rcpt}': {error}"),
+            },
+            Modification::DeleteRcpt(rcpt) => {
+                let rcpt = strip_brackets(&rcpt);
+                ctx.envelop.rcpt.retain(|r| r.address.full() != rcpt);
+            }
+            Modification::ChangeFrom(from) => match Address::try_from(strip_brackets(&from)) {
+                Ok(address) => ctx.envelop.mail_from = address,
+                Err(error) => {
+                    log::warn!("milter changed the sender to an invalid address '{from}': {error}");
+                }
+            },
+            Modification::Quarantine(reason) => {
+                log::warn!("milter put the message in quarantine: '{reason}'");
+                status = Status::Quarantine(quarantine.to_string());
+            }
+        }
+    }
+
+    status
+}
+
+fn poisoned<T>(_: T) -> anyhow::Error {
+    anyhow::anyhow!("mutex poisoned")
+}
+
+/// send the events of the current session to the filter, and apply its response.
+///
+/// # Errors
+///
+/// * the connection with the filter failed
+/// * the filter does not respect the protocol
+/// * a mutex is poisoned
+pub fn run(
+    address: &ServiceAddress,
+    timeout: &std::time::Duration,
+    quarantine: &str,
+    sessions: &Sessions,
+    ctx: &std::sync::RwLock<MailContext>,
+    message: &std::sync::RwLock<MessageBody>,
+) -> anyhow::Result<Status> {
+    let key = {
+        let ctx = ctx.read().map_err(poisoned)?;
+        (ctx.client_addr, ctx.connection.timestamp)
+    };
+
+    let session = sessions.lock().map_err(poisoned)?.remove(&key);
+
+    let mut session = match session {
+        Some(session) => session,
+        None => Session::open(address, timeout)?,
+    };
+
+    let (verdict, modifications) = {
+        let ctx = ctx.read().map_err(poisoned)?;
+        let message = message.read().map_err(poisoned)?;
+        session.catch_up(&ctx, &message)?
+    };
+    session.last_used = std::time::Instant::now();
+
+    let status = match verdict {
+        Verdict::Continue | Verdict::Skip => {
+            let mut ctx = ctx.write().map_err(poisoned)?;
+            let mut message = message.write().map_err(poisoned)?;
+            apply(modifications, &mut ctx, &mut message, quarantine)
+        }
+        Verdict::Accept => {
+            // no more events for the transaction, or for the session if not started.
+            match &mut session.transaction {
+                Some(transaction) => transaction.done = true,
+                None => session.done = true,
+            }
+            let mut ctx = ctx.write().map_err(poisoned)?;
+            let mut message = message.write().map_err(poisoned)?;
+            apply(modifications, &mut ctx, &mut message, quarantine)
+        }
+        Verdict::Reject(reply) => {
+            Status::Deny(reply.map_or(ReplyOrCodeID::Left(CodeID::Denied), ReplyOrCodeID::Right))
+        }
+        Verdict::Tempfail(reply) => {
+            Status::Deny(ReplyOrCodeID::Right(reply.unwrap_or_else(|| {
+                Reply::parse_str("451 4.7.1 Service unavailable - try again later")
+                    .expect("valid reply")
+            })))
+        }
+        // NOTE: the message is kept in quarantine instead of being dropped.
+        Verdict::Discard => Status::Quarantine(quarantine.to_string()),
+    };
+
+    if matches!(status, Status::Quarantine(_)) {
+        ctx.write()
+            .map_err(poisoned)?
+            .envelop
+            .rcpt
+            .iter_mut()
+            .for_each(|rcpt| rcpt.transfer_method = Transfer::None);
+    }
+
+    if matches!(status, Status::Deny(_) | Status::Quarantine(_)) {
+        session.quit();
+    } else {
+        sessions.lock().map_err(poisoned)?.insert(key, session);
+    }
+
+    Ok(status)
+}
+
+/// close the session opened with the filter for the smtp session `key`,
+/// once its transaction or its connection is over.
+///
+/// # Errors
+///
+/// * the mutex is poisoned
+pub fn close(sessions: &Sessions, key: &SessionKey) -> anyhow::Result<()> {
+    let session = sessions.lock().map_err(poisoned)?.remove(key);
+    if let Some(session) = session {
+        session.quit();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::actions::test::get_default_context;
+
+    type Script = fn(u8, &[u8]) -> Vec<(u8, Vec<u8>)>;
+
+    /// a filter answering to each command with `script`, sending the commands
+    /// received through the returned channel.
+    fn fake_milter(script: Script) -> (ServiceAddress, std::sync::mpsc::Receiver<u8>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = ServiceAddress::Tcp(listener.local_addr().unwrap().to_string());
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            loop {
+                let mut length = [0; 4];
+                if std::io::Read::read_exact(&mut stream, &mut length).is_err() {
+                    return;
+                }
+                let mut packet = vec![0; u32::from_be_bytes(length) as usize];
+                std::io::Read::read_exact(&mut stream, &mut packet).unwrap();
+                sender.send(packet[0]).unwrap();
+
+                let responses = if packet[0] == command::OPTNEG {
+                    vec![(
+                        command::OPTNEG,
+                        [6u32, ACTIONS, 0]
+                            .iter()
+                            .flat_map(|i| i.to_be_bytes())
+                            .collect(),
+                    )]
+                } else {
+                    script(packet[0], &packet[1..])
+                };
+                for (code, data) in responses {
+                    let mut response = u32::try_from(data.len() + 1)
+                        .unwrap()
+                        .to_be_bytes()
+                        .to_vec();
+                    response.push(code);
+                    response.extend(data);
+                    std::io::Write::write_all(&mut stream, &response).unwrap();
+                }
+            }
+        });
+
+        (address, receiver)
+    }
+
+    fn context() -> std::sync::RwLock<MailContext> {
+        let mut ctx = get_default_context();
+        ctx.envelop.helo = "client.com".to_string();
+        ctx.envelop.mail_from = vsmtp_common::addr!("john@client.com");
+        ctx.envelop.rcpt = vec![
+            Rcpt::new(vsmtp_common::addr!("green@example.com")),
+            Rcpt::new(vsmtp_common::addr!("blue@example.com")),
+        ];
+        ctx.metadata.as_mut().unwrap().message_id = "message-id".to_string();
+        std::sync::RwLock::new(ctx)
+    }
+
+    fn message() -> std::sync::RwLock<MessageBody> {
+        std::sync::RwLock::new(MessageBody::new(
+            vec![
+                "From: john@client.com".to_string(),
+                "Subject: hello".to_string(),
+            ],
+            "hello world\r\n".to_string(),
+        ))
+    }
+
+    fn run_with(
+        address: &ServiceAddress,
+        sessions: &Sessions,
+        ctx: &std::sync::RwLock<MailContext>,
+        message: &std::sync::RwLock<MessageBody>,
+    ) -> anyhow::Result<Status> {
+        run(
+            address,
+            &std::time::Duration::from_secs(5),
+            "milter",
+            sessions,
+            ctx,
+            message,
+        )
+    }
+
+    #[test]
+    fn modifications() {
+        let (address, commands) = fake_milter(|command, _| match command {
+            command::MACRO => vec![],
+            command::BODYEOB => vec![
+                (
+                    response::ADDHEADER,
+                    null_terminated(["X-Milter", "checked"]),
+                ),
+                (response::CHGHEADER, {
+                    let mut data = 1u32.to_be_bytes().to_vec();
+                    data.extend(null_terminated(["Subject", "[spam] hello"]));
+                    data
+                }),
+                (response::DELRCPT, null_terminated(["<blue@example.com>"])),
+                (response::ADDRCPT, null_terminated(["<red@example.com>"])),
+                (response::CHGFROM, null_terminated(["<bounce@client.com>"])),
+                (response::CONTINUE, vec![]),
+            ],
+            _ => vec![(response::CONTINUE, vec![])],
+        });
+
+        let (ctx, message) = (context(), message());
+        let sessions = Sessions::default();
+        assert_eq!(
+            run_with(&address, &sessions, &ctx, &message).unwrap(),
+            Status::Next
+        );
+
+        assert_eq!(
+            commands.try_iter().collect::<Vec<_>>(),
+            vec![
+                command::OPTNEG,
+                command::MACRO,
+                command::CONNECT,
+                command::HELO,
+                command::MACRO,
+                command::MAIL,
+                command::RCPT,
+                command::RCPT,
+                command::DATA,
+                command::HEADER,
+                command::HEADER,
+                command::EOH,
+                command::BODY,
+                command::MACRO,
+                command::BODYEOB,
+            ]
+        );
+
+        let ctx = ctx.read().unwrap();
+        assert_eq!(ctx.envelop.mail_from.full(), "bounce@client.com");
+        assert_eq!(
+            ctx.envelop
+                .rcpt
+                .iter()
+                .map(|rcpt| rcpt.address.full())
+                .collect::<Vec<_>>(),
+            vec!["green@example.com", "red@example.com"]
+        );
+
+        let message = message.read().unwrap();
+        assert_eq!(message.get_header("X-Milter"), Some("checked".to_string()));
+        assert_eq!(
+            message.get_header("Subject"),
+            Some("[spam] hello".to_string())
+        );
+        assert_eq!(sessions.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reject_recipient() {
+        let (address, commands) = fake_milter(|command, data| match command {
+            command::MACRO => vec![],
+            command::RCPT if data.starts_with(b"<blue") => vec![
+                (response::PROGRESS, vec![]),
+                (
+                    response::REPLYCODE,
+                    null_terminated(["550 5.7.1 blue is not welcome"]),
+                ),
+            ],
+            _ => vec![(response::CONTINUE, vec![])],
+        });
+
+        let sessions = Sessions::default();
+        assert_eq!(
+            run_with(&address, &sessions, &context(), &message()).unwrap(),
+            Status::Deny(ReplyOrCodeID::Right(
+                Reply::parse_str("550 5.7.1 blue is not welcome").unwrap()
+            ))
+        );
+        assert!(commands.iter().any(|command| command == command::QUIT));
+        assert!(sessions.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn catch_up_between_stages() {
+        let (address, commands) = fake_milter(|command, _| match command {
+            command::MACRO | command::ABORT => vec![],
+            _ => vec![(response::CONTINUE, vec![])],
+        });
+        let sessions = Sessions::default();
+
+        // connect stage: no helo nor transaction yet.
+        let ctx = context();
+        let metadata = ctx.write().unwrap().metadata.take();
+        let helo = std::mem::take(&mut ctx.write().unwrap().envelop.helo);
+        let message = std::sync::RwLock::new(MessageBody::default());
+
+        run_with(&address, &sessions, &ctx, &message).unwrap();
+        assert_eq!(commands.recv().unwrap(), command::OPTNEG,);
+        assert_eq!(commands.recv().unwrap(), command::MACRO);
+        assert_eq!(commands.recv().unwrap(), command::CONNECT);
+
+        // rcpt stage: the same session is used.
+        ctx.write().unwrap().metadata = metadata;
+        ctx.write().unwrap().envelop.helo = helo;
+        run_with(&address, &sessions, &ctx, &message).unwrap();
+        assert_eq!(
+            (0..5).map(|_| commands.recv().unwrap()).collect::<Vec<_>>(),
+            vec![
+                command::HELO,
+                command::MACRO,
+                command::MAIL,
+                command::RCPT,
+                command::RCPT
+            ]
+        );
+
+        // a new transaction aborts the previous one.
+        ctx.write().unwrap().metadata.as_mut().unwrap().message_id = "other".to_string();
+        ctx.write().unwrap().envelop.rcpt.truncate(1);
+        run_with(&address, &sessions, &ctx, &message).unwrap();
+        assert_eq!(
+            (0..4).map(|_| commands.recv().unwrap()).collect::<Vec<_>>(),
+            vec![command::ABORT, command::MACRO, command::MAIL, command::RCPT]
+        );
+    }
+
+    #[test]
+    fn close_session() {
+        let (address, commands) = fake_milter(|command, _| match command {
+            command::MACRO => vec![],
+            _ => vec![(response::CONTINUE, vec![])],
+        });
+        let sessions = Sessions::default();
+
+        let ctx = context();
+        let key = {
+            let ctx = ctx.read().unwrap();
+            (ctx.client_addr, ctx.connection.timestamp)
+        };
+        let message = std::sync::RwLock::new(MessageBody::default());
+
+        run_with(&address, &sessions, &ctx, &message).unwrap();
+        assert_eq!(sessions.lock().unwrap().len(), 1);
+
+        close(&sessions, &key).unwrap();
+        assert!(sessions.lock().unwrap().is_empty());
+        assert_eq!(commands.iter().last(), Some(command::QUIT));
+
+        // closing a session which does not exist is a no-op.
+        close(&sessions, &key).unwrap();
+    }
+
+    #[test]
+    fn close_idle() {
+        let (address, commands) = fake_milter(|command, _| match command {
+            command::MACRO => vec![],
+            _ => vec![(response::CONTINUE, vec![])],
+        });
+        let sessions = Sessions::default();
+
+        run_with(
+            &address,
+            &sessions,
+            &context(),
+            &std::sync::RwLock::new(MessageBody::default()),
+        )
+        .unwrap();
+
+        // the session is still used.
+        close_idle_sessions(&sessions).unwrap();
+        assert_eq!(sessions.lock().unwrap().len(), 1);
+
+        for session in sessions.lock().unwrap().values_mut() {
+            session.last_used = std::time::Instant::now()
+                .checked_sub(SESSION_IDLE_TIMEOUT)
+                .unwrap();
+        }
+        close_idle_sessions(&sessions).unwrap();
+        assert!(sessions.lock().unwrap().is_empty());
+        assert_eq!(commands.iter().last(), Some(command::QUIT));
+    }
+
+    #[test]
+    fn discard() {
+        let (address, _commands) = fake_milter(|command, _| match command {
+            command::MACRO => vec![],
+            command::BODYEOB => vec![(response::DISCARD, vec![])],
+            _ => vec![(response::CONTINUE, vec![])],
+        });
+
+        let ctx = context();
+        assert_eq!(
+            run_with(&address, &Sessions::default(), &ctx, &message()).unwrap(),
+            Status::Quarantine("milter".to_string())
+        );
+        assert!(ctx
+            .read()
+            .unwrap()
+            .envelop
+            .rcpt
+            .iter()
+            .all(|rcpt| rcpt.transfer_method == Transfer::None));
+    }
+
+    #[test]
+    fn error() {
+        assert!(run_with(
+            &"unix:/does/not/exist.sock".parse().unwrap(),
+            &Sessions::default(),
+            &context(),
+            &message()
+        )
+        .is_err());
+        assert!(parse_indexed_header(b"ab").is_err());
+        assert_eq!(strip_brackets(" <a@b.c> "), "a@b.c");
+    }
+}
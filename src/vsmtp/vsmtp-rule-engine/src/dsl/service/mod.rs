@@ -15,13 +15,129 @@
  *
 */
 
-use vsmtp_common::transfer::SmtpConnection;
+use vsmtp_common::{re::anyhow, transfer::SmtpConnection};
 
+pub mod clamav;
 pub mod cmd;
 pub mod databases;
+pub mod ldap;
+pub mod milter;
 pub mod parsing;
+pub mod runtime;
 pub mod smtp;
 
+/// address of a daemon used by a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceAddress {
+    /// a unix socket, declared with `unix:/path/to/daemon.sock`.
+    Unix(std::path::PathBuf),
+    /// a tcp socket, declared with `host:port`.
+    Tcp(String),
+}
+
+impl std::str::FromStr for ServiceAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Self::Unix(path.into())),
+            Some(_) => anyhow::bail!("the path of the unix socket is empty"),
+            None if s.rsplit_once(':').is_some() => Ok(Self::Tcp(s.to_string())),
+            None => anyhow::bail!(
+                "'{s}' is not a valid address, expected 'unix:<path>' or '<host>:<port>'"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for ServiceAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Tcp(address) => f.write_str(address),
+        }
+    }
+}
+
+impl ServiceAddress {
+    /// open a connection to the daemon, `timeout` is applied to the connection,
+    /// and to each read and write on the stream.
+    ///
+    /// # Errors
+    ///
+    /// * the address cannot be resolved
+    /// * the connection failed
+    pub fn connect(&self, timeout: &std::time::Duration) -> anyhow::Result<ServiceStream> {
+        match self {
+            Self::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(*timeout))?;
+                stream.set_write_timeout(Some(*timeout))?;
+                Ok(ServiceStream::Unix(stream))
+            }
+            Self::Tcp(address) => {
+                let address = std::net::ToSocketAddrs::to_socket_addrs(address)?
+                    .next()
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("'{address}' does not resolve to any address")
+                    })?;
+                let stream = std::net::TcpStream::connect_timeout(&address, *timeout)?;
+                stream.set_read_timeout(Some(*timeout))?;
+                stream.set_write_timeout(Some(*timeout))?;
+                Ok(ServiceStream::Tcp(stream))
+            }
+        }
+    }
+}
+
+/// a connection to a daemon used by a service.
+#[derive(Debug)]
+pub enum ServiceStream {
+    /// a unix socket.
+    Unix(std::os::unix::net::UnixStream),
+    /// a tcp socket.
+    Tcp(std::net::TcpStream),
+}
+
+impl ServiceStream {
+    /// move the socket in or out of the nonblocking mode.
+    ///
+    /// # Errors
+    ///
+    /// * the mode of the socket could not be changed
+    pub fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Self::Unix(stream) => stream.set_nonblocking(nonblocking),
+            Self::Tcp(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl std::io::Read for ServiceStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.read(buf),
+            Self::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl std::io::Write for ServiceStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.write(buf),
+            Self::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Unix(stream) => stream.flush(),
+            Self::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
 /// service that enable the user to integrate third party software
 /// into his rules.
 #[derive(Debug)]
@@ -42,16 +158,46 @@ pub enum Service {
 
     /// A database connector based on the csv file format.
     CSVDatabase {
-        /// A path to the file to open.
-        path: std::path::PathBuf,
-        /// Access mode to the database.
-        access: databases::AccessMode,
-        /// Delimiter character to separate fields in records.
-        delimiter: u8,
-        /// Database refresh mode.
-        refresh: databases::Refresh,
-        /// Raw content of the database.
-        fd: std::fs::File,
+        /// The file, indexed by the first field of its records.
+        database: databases::csv::Database,
+    },
+
+    /// A database connector for mysql, postgres or sqlite.
+    SQLDatabase {
+        /// Url of the database, without its password.
+        connector: String,
+        /// The statement used to fetch the records of a key.
+        query: String,
+        /// Optional: the statement used to add a record.
+        add: Option<String>,
+        /// Optional: the statement used to remove the records of a key.
+        remove: Option<String>,
+        /// A duration after which a statement is aborted.
+        timeout: std::time::Duration,
+        /// The connections to the database.
+        pool: databases::sql::Pool,
+    },
+
+    /// A key-value database connector for redis.
+    RedisDatabase {
+        /// Behavior of the service when the server cannot be reached.
+        failure: databases::Failure,
+        /// The client of the server.
+        client: databases::redis::Client,
+    },
+
+    /// A key-value database connector for memcached.
+    MemcachedDatabase {
+        /// Behavior of the service when the server cannot be reached.
+        failure: databases::Failure,
+        /// The client of the server.
+        client: databases::memcached::Client,
+    },
+
+    /// A client of a ldap directory.
+    Ldap {
+        /// The client of the directory.
+        client: ldap::Client,
     },
 
     /// A service that handles smtp transactions.
@@ -61,6 +207,26 @@ pub enum Service {
         /// Delegation results address.
         receiver: std::net::SocketAddr,
     },
+
+    /// A service scanning messages with a clamav daemon.
+    ClamAV {
+        /// Address of the daemon, a unix or tcp socket.
+        address: ServiceAddress,
+        /// A duration after which the scan is aborted.
+        timeout: std::time::Duration,
+    },
+
+    /// A client of a filter implementing the milter protocol.
+    Milter {
+        /// Address of the filter, a unix or tcp socket.
+        address: ServiceAddress,
+        /// A duration after which a read or write to the filter is aborted.
+        timeout: std::time::Duration,
+        /// The quarantine queue used when the filter discards or quarantines a message.
+        quarantine: String,
+        /// The sessions opened with the filter, one per smtp connection.
+        sessions: std::sync::Arc<milter::Sessions>,
+    },
 }
 
 impl std::fmt::Display for Service {
@@ -71,8 +237,33 @@ impl std::fmt::Display for Service {
             match self {
                 Service::Cmd { .. } => "cmd",
                 Self::CSVDatabase { .. } => "csv-database",
+                Self::SQLDatabase { .. } => "sql-database",
+                Self::RedisDatabase { .. } => "redis-database",
+                Self::MemcachedDatabase { .. } => "memcached-database",
+                Self::Ldap { .. } => "ldap",
                 Self::Smtp { .. } => "smtp",
+                Self::ClamAV { .. } => "clamav",
+                Self::Milter { .. } => "milter",
             }
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address() {
+        assert_eq!(
+            "unix:/run/clamd.sock".parse::<ServiceAddress>().unwrap(),
+            ServiceAddress::Unix("/run/clamd.sock".into())
+        );
+        assert_eq!(
+            "127.0.0.1:3310".parse::<ServiceAddress>().unwrap(),
+            ServiceAddress::Tcp("127.0.0.1:3310".to_string())
+        );
+        assert!("unix:".parse::<ServiceAddress>().is_err());
+        assert!("localhost".parse::<ServiceAddress>().is_err());
+    }
+}
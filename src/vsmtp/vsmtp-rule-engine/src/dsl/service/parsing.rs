@@ -14,7 +14,10 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use super::{cmd::parse_cmd_service, smtp::parse_smtp_service, Service};
+use super::{
+    clamav::parse_clamav_service, cmd::parse_cmd_service, ldap::parse_ldap_service,
+    milter::parse_milter_service, smtp::parse_smtp_service, Service,
+};
 use crate::modules::EngineResult;
 
 /// parse a service using rhai's parser.
@@ -28,7 +31,7 @@ pub fn parse_service(
         // type of the service.
         3 => match symbols[2].as_str() {
             // for a regular service, next is the '=' token or ':' token in case of the db type.
-            "cmd" | "smtp" | "db" => Ok(Some("$symbol$".into())),
+            "cmd" | "smtp" | "clamav" | "milter" | "ldap" | "db" => Ok(Some("$symbol$".into())),
             entry => Err(rhai::ParseError(
                 Box::new(rhai::ParseErrorType::BadInput(
                     rhai::LexError::ImproperSymbol(
@@ -56,7 +59,7 @@ pub fn parse_service(
         },
         5 => match symbols[4].as_str() {
             // database formats
-            "csv" => Ok(Some("=".into())),
+            "csv" | "sql" | "redis" | "memcached" => Ok(Some("=".into())),
             // an expression, in the case of a regular service, whe are done parsing.
             _ => Ok(None),
         },
@@ -91,6 +94,9 @@ pub fn create_service(
         "db" => open_database(context, input, &service_name),
         "cmd" => parse_cmd_service(context, input, &service_name),
         "smtp" => parse_smtp_service(context, input, &service_name),
+        "clamav" => parse_clamav_service(context, input, &service_name),
+        "milter" => parse_milter_service(context, input, &service_name),
+        "ldap" => parse_ldap_service(context, input, &service_name),
         unknown => Err(format!("{unknown} serice does not exist").into()),
     }?;
 
@@ -107,7 +113,7 @@ pub fn create_service(
     Ok(rhai::Dynamic::from(ptr))
 }
 
-/// open a file database using the csv crate.
+/// open a database, a csv file, a sql connector or a key-value store.
 fn open_database(
     context: &mut rhai::EvalContext,
     input: &[rhai::Expression],
@@ -127,6 +133,10 @@ fn open_database(
 
         let service = match database_type {
             "csv" => super::databases::csv::parse_csv_database(service_name, &options)?,
+            "sql" => super::databases::sql::parse_sql_database(service_name, &options)?,
+            "redis" | "memcached" => {
+                super::databases::parse_key_value_database(service_name, database_type, &options)?
+            }
             _ => todo!(),
         };
 
@@ -0,0 +1,126 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use vsmtp_common::re::{
+    anyhow::{self, Context},
+    tokio,
+};
+
+/// a runtime driving the async clients of a service, because the rule engine
+/// runs synchronously.
+#[derive(Debug)]
+pub struct Runtime {
+    inner: Option<tokio::runtime::Runtime>,
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // NOTE: the rule engine can be dropped in an async context,
+        //       where blocking on the runtime's threads would panic.
+        if let Some(runtime) = self.inner.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Runtime {
+    /// create a runtime with a single worker thread, named after the service.
+    ///
+    /// # Errors
+    ///
+    /// * the thread of the runtime could not be created.
+    pub fn new(name: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: Some(
+                tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(1)
+                    .thread_name(format!("vsl-{name}"))
+                    .enable_all()
+                    .build()
+                    .with_context(|| format!("failed to create the runtime of {name}"))?,
+            ),
+        })
+    }
+
+    fn handle(&self) -> &tokio::runtime::Runtime {
+        self.inner
+            .as_ref()
+            .expect("the runtime is only taken on drop")
+    }
+
+    /// enter the context of the runtime, to create objects spawning tasks.
+    pub fn enter(&self) -> tokio::runtime::EnterGuard<'_> {
+        self.handle().enter()
+    }
+
+    /// run `future` on the runtime and wait for its result, at most for `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// * `future` failed.
+    /// * `timeout` elapsed.
+    /// * the runtime is shutting down.
+    pub fn block_on<T: Send + 'static>(
+        &self,
+        timeout: std::time::Duration,
+        future: impl std::future::Future<Output = anyhow::Result<T>> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+
+        self.handle().spawn(async move {
+            // the receiver only stops waiting if the runtime is shutting down.
+            let _ = sender.send(tokio::time::timeout(timeout, future).await);
+        });
+
+        receiver
+            .recv()
+            .context("the runtime of the service has been shut down")?
+            .map_err(|_| anyhow::anyhow!("operation timed out after {timeout:?}"))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout() {
+        let runtime = Runtime::new("test").unwrap();
+
+        assert_eq!(
+            runtime
+                .block_on(std::time::Duration::from_secs(1), async { Ok(1) })
+                .unwrap(),
+            1
+        );
+        assert!(runtime
+            .block_on(std::time::Duration::from_millis(10), async {
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn dropped_in_async_context() {
+        let runtime = Runtime::new("test").unwrap();
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async move { drop(runtime) });
+    }
+}
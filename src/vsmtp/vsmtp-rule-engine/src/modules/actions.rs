@@ -15,9 +15,17 @@
  *
 */
 ///
+pub mod arc;
+///
 pub mod dkim;
 ///
+pub mod dmarc;
+///
+pub mod greylist;
+///
 pub mod logging;
+/// verification of the OAuth 2.0 bearer tokens.
+pub mod oauth;
 ///
 pub mod rule_state;
 ///
@@ -32,7 +40,7 @@ pub mod utils;
 pub mod write;
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use vsmtp_common::mail_context::{ConnectionContext, MailContext};
 
     pub fn get_default_context() -> MailContext {
@@ -40,6 +48,8 @@ mod test {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::now(),
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: "testserver.com".to_string(),
@@ -0,0 +1,162 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::modules::types::types::{Context, Message, Server};
+use crate::modules::EngineResult;
+use rhai::plugin::{
+    mem, Dynamic, FnAccess, FnNamespace, ImmutableString, Module, NativeCallContext,
+    PluginFunction, RhaiResult, TypeId,
+};
+use rhai::EvalAltResult;
+use vsmtp_common::re::tokio;
+use vsmtp_dkim::{ArcChain, ChainValidation, PublicKey};
+
+/// Get the valid public keys published at `query`, a failed query returns no key.
+fn get_public_keys(
+    resolver: &trust_dns_resolver::TokioAsyncResolver,
+    query: &str,
+) -> Vec<PublicKey> {
+    tokio::task::block_in_place(move || {
+        tokio::runtime::Handle::current().block_on(resolver.txt_lookup(query))
+    })
+    .map(|txt_record| {
+        txt_record
+            .into_iter()
+            .filter_map(|i| <PublicKey as std::str::FromStr>::from_str(&i.to_string()).ok())
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Authenticated Received Chain (RFC 8617), validation and sealing of the chain
+#[rhai::plugin::export_module]
+pub mod arc {
+
+    /// Validate the ARC chain of the message, return "none", "pass" or "fail".
+    ///
+    /// # Errors
+    /// * the message mutex is poisoned.
+    ///
+    /// # Panics
+    /// * the resolver of the server's domain is missing.
+    #[rhai_fn(global, pure, return_raw)]
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn arc_verify(server: &mut Server, message: Message) -> EngineResult<String> {
+        let guard = vsl_guard_ok!(message.read());
+        let resolver = server.resolvers.get(&server.config.server.domain).unwrap();
+
+        Ok(ArcChain::from_message(guard.inner())
+            .map_or(ChainValidation::Fail, |chain| {
+                chain.verify(guard.inner(), |query| get_public_keys(resolver, query))
+            })
+            .to_string())
+    }
+
+    /// Add a new ARC Set to the message, with the results recorded by this server in
+    /// the `Authentication-Results` headers and the state of the chain `chain_validation`.
+    ///
+    /// The key used is the dkim private key of the server, or of the virtual entry.
+    ///
+    /// # Errors
+    /// * a mutex is poisoned.
+    /// * `chain_validation` is not "none", "pass" or "fail".
+    /// * no private key is configured.
+    /// * the chain has already failed, or the signing failed.
+    #[rhai_fn(global, pure, return_raw)]
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn arc_seal(
+        message: &mut Message,
+        context: Context,
+        server: Server,
+        authserv_id: &str,
+        selector: &str,
+        headers_field: rhai::Array,
+        chain_validation: &str,
+    ) -> EngineResult<()> {
+        let chain_validation = <ChainValidation as std::str::FromStr>::from_str(chain_validation)
+            .map_err::<Box<EvalAltResult>, _>(|_| {
+            format!("`{chain_validation}` is not a valid chain validation status").into()
+        })?;
+
+        let sdid = vsl_guard_ok!(context.read()).connection.server_name.clone();
+        let dkim_params = server
+            .config
+            .server
+            .r#virtual
+            .get(&sdid)
+            .map_or_else(|| &server.config.server.dkim, |i| &i.dkim)
+            .as_ref()
+            .ok_or_else::<Box<EvalAltResult>, _>(|| {
+                format!("dkim params are empty for this `{sdid}`").into()
+            })?;
+
+        let private_key = dkim_params
+            .private_key
+            .as_ref()
+            .map(|private_key| &private_key.inner)
+            .or_else(|| {
+                dkim_params
+                    .keyset
+                    .as_ref()
+                    .and_then(|keyset| keyset.inner.get(selector))
+            })
+            .ok_or_else::<Box<EvalAltResult>, _>(|| {
+                format!("no dkim key with the selector `{selector}` for this `{sdid}`").into()
+            })?;
+
+        let mut msg_guard = vsl_guard_ok!(message.write());
+
+        // the results of this server, without the `authserv-id`
+        let mut results = msg_guard
+            .inner()
+            .headers()
+            .into_iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("Authentication-Results"))
+            .filter_map(|(_, value)| {
+                value
+                    .trim()
+                    .strip_prefix(authserv_id)
+                    .and_then(|value| value.trim_start().strip_prefix(';'))
+                    .map(|value| value.trim().trim_end_matches(';').to_string())
+            })
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>();
+
+        if !results.iter().any(|i| i.starts_with("arc=")) {
+            results.push(format!("arc={chain_validation}"));
+        }
+
+        let chain = ArcChain::from_message(msg_guard.inner()).unwrap_or_default();
+        let set = chain
+            .seal(
+                msg_guard.inner(),
+                chain_validation,
+                &format!("{authserv_id};\r\n\t{}", results.join(";\r\n\t")),
+                selector,
+                &sdid,
+                headers_field.iter().map(ToString::to_string).collect(),
+                private_key,
+            )
+            .map_err::<Box<EvalAltResult>, _>(|e| e.to_string().into())?;
+
+        for (name, value) in set.headers() {
+            msg_guard.prepend_header(name, &value);
+        }
+        drop(msg_guard);
+
+        Ok(())
+    }
+}
@@ -168,7 +168,11 @@ pub mod dkim {
             .map_err::<Box<EvalAltResult>, _>(|_| DkimErrors::SignatureMismatch.into())
     }
 
+    /// Produce a `DKIM-Signature` header with the private key of the server.
     ///
+    /// # Errors
+    /// * no dkim key is configured for the server name of the connection.
+    /// * the signature failed.
     #[rhai_fn(global, pure, return_raw)]
     #[allow(clippy::module_name_repetitions, clippy::needless_pass_by_value)]
     pub fn dkim_sign(
@@ -178,33 +182,231 @@ pub mod dkim {
         selector: &str,
         headers_field: rhai::Array,
     ) -> EngineResult<()> {
-        let mut msg_guard = vsl_guard_ok!(message.write());
-        let ctx_guard = vsl_guard_ok!(context.read());
-
-        let sdid = &ctx_guard.connection.server_name;
-        let dkim_params = server
-            .config
-            .server
-            .r#virtual
-            .get(sdid)
-            .map_or_else(|| &server.config.server.dkim, |i| &i.dkim);
-
-        match dkim_params {
-            None => Err(format!("dkim params are empty for this `{sdid}`").into()),
-            Some(dkim_params) => {
-                let signature = Signature::sign(
-                    msg_guard.inner(),
-                    selector,
-                    sdid,
-                    headers_field.iter().map(ToString::to_string).collect(),
-                    &dkim_params.private_key.inner,
-                )
-                .map_err::<Box<EvalAltResult>, _>(|e| e.to_string().into())?;
+        sign(
+            message,
+            &context,
+            &server,
+            Some(&[selector.to_string()]),
+            &headers_field,
+        )
+    }
 
-                msg_guard.add_header("DKIM-Signature", &signature.get_signature_value());
+    /// Produce two `DKIM-Signature` headers, one with the private key of the server
+    /// and the other with its dual key (RFC 8463), using the two selectors in order.
+    ///
+    /// # Errors
+    /// * more selectors than configured dkim keys.
+    /// * the signature failed.
+    #[rhai_fn(global, pure, return_raw, name = "dkim_sign")]
+    #[allow(clippy::module_name_repetitions, clippy::needless_pass_by_value)]
+    pub fn dkim_dual_sign(
+        message: &mut Message,
+        context: Context,
+        server: Server,
+        selectors: rhai::Array,
+        headers_field: rhai::Array,
+    ) -> EngineResult<()> {
+        sign(
+            message,
+            &context,
+            &server,
+            Some(
+                &selectors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            ),
+            &headers_field,
+        )
+    }
 
-                Ok(())
+    /// Produce a `DKIM-Signature` header with the active key of the keyset of the server,
+    /// the selector is the one of the key.
+    ///
+    /// # Errors
+    /// * no dkim keyset is configured for the server name of the connection.
+    /// * the keyset has no active key.
+    /// * the signature failed.
+    #[rhai_fn(global, pure, return_raw, name = "dkim_sign")]
+    #[allow(clippy::module_name_repetitions, clippy::needless_pass_by_value)]
+    pub fn dkim_keyset_sign(
+        message: &mut Message,
+        context: Context,
+        server: Server,
+        headers_field: rhai::Array,
+    ) -> EngineResult<()> {
+        sign(message, &context, &server, None, &headers_field)
+    }
+}
+
+fn sign(
+    message: &Message,
+    context: &Context,
+    server: &Server,
+    selectors: Option<&[String]>,
+    headers_field: &rhai::Array,
+) -> EngineResult<()> {
+    let mut msg_guard = vsl_guard_ok!(message.write());
+    let ctx_guard = vsl_guard_ok!(context.read());
+
+    let sdid = &ctx_guard.connection.server_name;
+    let dkim_params = server
+        .config
+        .server
+        .r#virtual
+        .get(sdid)
+        .map_or_else(|| &server.config.server.dkim, |i| &i.dkim)
+        .as_ref()
+        .ok_or_else::<Box<EvalAltResult>, _>(|| {
+            format!("dkim params are empty for this `{sdid}`").into()
+        })?;
+
+    let keys = match (selectors, &dkim_params.keyset) {
+        (Some(selectors), _) => {
+            let private_keys = dkim_params
+                .private_key
+                .iter()
+                .chain(dkim_params.dual_private_key.iter())
+                .collect::<Vec<_>>();
+
+            if selectors.len() > private_keys.len() {
+                return Err(format!(
+                    "{} selectors were given but only {} dkim keys are configured for `{sdid}`",
+                    selectors.len(),
+                    private_keys.len()
+                )
+                .into());
             }
+
+            selectors
+                .iter()
+                .zip(private_keys)
+                .map(|(selector, key)| (selector.as_str(), &key.inner))
+                .collect::<Vec<_>>()
+        }
+        (None, Some(keyset)) => {
+            let key = keyset
+                .inner
+                .active_key(std::time::SystemTime::now())
+                .ok_or_else::<Box<EvalAltResult>, _>(|| {
+                    format!(
+                        "no active key in the dkim keyset '{}'",
+                        keyset.path.display()
+                    )
+                    .into()
+                })?;
+
+            vec![key]
+        }
+        (None, None) => {
+            return Err(format!("dkim keyset is not configured for this `{sdid}`").into())
+        }
+    };
+
+    let headers_field = headers_field
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    for (selector, private_key) in keys {
+        let signature = Signature::sign(
+            msg_guard.inner(),
+            selector,
+            sdid,
+            headers_field.clone(),
+            private_key,
+        )
+        .map_err::<Box<EvalAltResult>, _>(|e| e.to_string().into())?;
+
+        msg_guard.add_header("DKIM-Signature", &signature.get_signature_value());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign;
+    use crate::{modules::actions::test::get_default_context, server_api::ServerAPI};
+    use vsmtp_common::MessageBody;
+    use vsmtp_config::field::{FieldDkim, SecretFile};
+    use vsmtp_dkim::{PrivateKey, PublicKey, Signature, SigningAlgorithm};
+
+    fn generate_key(name: &str, signing_algorithm: SigningAlgorithm) -> SecretFile<PrivateKey> {
+        std::fs::create_dir_all("./tmp/dkim").unwrap();
+        let path = std::path::PathBuf::from(format!("./tmp/dkim/{name}.pem"));
+        std::fs::write(&path, PrivateKey::generate_pem(signing_algorithm).unwrap()).unwrap();
+
+        SecretFile {
+            inner: PrivateKey::read_pem_file(&path).unwrap(),
+            path,
+        }
+    }
+
+    #[test]
+    fn dual_sign() {
+        let rsa = generate_key("dual_rsa", SigningAlgorithm::RsaSha256);
+        let ed25519 = generate_key("dual_ed25519", SigningAlgorithm::Ed25519Sha256);
+
+        let mut config = vsmtp_config::Config::default();
+        config.server.dkim = Some(FieldDkim {
+            private_key: Some(rsa.clone()),
+            dual_private_key: Some(ed25519.clone()),
+            keyset: None,
+        });
+        let server = std::sync::Arc::new(ServerAPI {
+            config,
+            resolvers: std::sync::Arc::new(std::collections::HashMap::new()),
+        });
+
+        let context = std::sync::Arc::new(std::sync::RwLock::new(get_default_context()));
+        let message = std::sync::Arc::new(std::sync::RwLock::new(
+            MessageBody::try_from(concat!(
+                "From: john.doe@testserver.com\r\n",
+                "To: jenny.doe@example.com\r\n",
+                "Subject: test\r\n",
+                "\r\n",
+                "test\r\n",
+            ))
+            .unwrap(),
+        ));
+
+        sign(
+            &message,
+            &context,
+            &server,
+            Some(&["rsa".to_string(), "ed".to_string()]),
+            &vec!["From".into(), "To".into(), "Subject".into()],
+        )
+        .unwrap();
+
+        let message = message.read().unwrap();
+        let signatures = message
+            .inner()
+            .headers()
+            .into_iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("DKIM-Signature"))
+            .map(|(_, value)| {
+                <Signature as std::str::FromStr>::from_str(&format!(
+                    "DKIM-Signature: {}",
+                    value.trim_start()
+                ))
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(signatures.len(), 2);
+
+        for (selector, signing_algorithm, key) in [
+            ("rsa", SigningAlgorithm::RsaSha256, &rsa),
+            ("ed", SigningAlgorithm::Ed25519Sha256, &ed25519),
+        ] {
+            let signature = signatures.iter().find(|i| i.selector == selector).unwrap();
+            assert_eq!(signature.signing_algorithm, signing_algorithm);
+
+            let public_key =
+                <PublicKey as std::str::FromStr>::from_str(&key.inner.dns_record().unwrap())
+                    .unwrap();
+            signature.verify(message.inner(), &public_key).unwrap();
         }
     }
 }
@@ -0,0 +1,269 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::modules::types::types::{Context, Message, Server};
+use crate::modules::EngineResult;
+use rhai::plugin::{
+    mem, Dynamic, FnAccess, FnNamespace, ImmutableString, Module, NativeCallContext,
+    PluginFunction, RhaiResult, TypeId,
+};
+use rhai::EvalAltResult;
+use vsmtp_common::re::{log, tokio};
+use vsmtp_dmarc::{is_aligned, organizational_domain, AuthResult, Evaluation, Record};
+
+/// Outcome of the lookup of the DMARC record of a domain.
+enum Lookup {
+    /// the record and the domain where it has been found.
+    Found(String, Record),
+    /// no policy is published.
+    None,
+    /// the dns query failed.
+    TempError,
+}
+
+/// Query the `_dmarc` TXT record of `domain`, a result containing zero or
+/// more than one valid record is treated as no policy, see RFC 7489 section 6.6.3
+fn query_record(resolver: &trust_dns_resolver::TokioAsyncResolver, domain: &str) -> Lookup {
+    let txt_record = tokio::task::block_in_place(move || {
+        tokio::runtime::Handle::current().block_on(resolver.txt_lookup(format!("_dmarc.{domain}.")))
+    });
+
+    match txt_record {
+        Ok(txt_record) => {
+            let mut records = txt_record
+                .into_iter()
+                .map(|i| i.to_string())
+                .filter(|i| i.starts_with("v=DMARC1"))
+                .filter_map(|i| <Record as std::str::FromStr>::from_str(&i).ok());
+
+            match (records.next(), records.next()) {
+                (Some(record), None) => Lookup::Found(domain.to_string(), record),
+                _ => Lookup::None,
+            }
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. }
+            ) =>
+        {
+            Lookup::None
+        }
+        Err(_) => Lookup::TempError,
+    }
+}
+
+/// Get the domain of the `From` header, which must contain a single address.
+fn from_domain(header: &str) -> Option<String> {
+    match vsmtp_mail_parser::get_addresses(header).as_slice() {
+        [address] => address
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.trim_end_matches('.').to_lowercase())
+            .filter(|domain| !domain.is_empty()),
+        _ => None,
+    }
+}
+
+/// Domain-based Message Authentication, Reporting & Conformance (RFC 7489)
+#[rhai::plugin::export_module]
+pub mod dmarc {
+
+    /// Evaluate the DMARC policy of the domain of the `From` header.
+    ///
+    /// `spf` is the result of the SPF evaluation of the `MAIL FROM` identity,
+    /// and `dkim` an array of map `#{ domain, result }` for each signature verified.
+    ///
+    /// The evaluation is stored to produce the aggregate reports if the
+    /// `server.dmarc` field is set in the configuration.
+    ///
+    /// # Errors
+    /// * a mutex is poisoned.
+    /// * the `dkim` array contains a value which is not a map.
+    ///
+    /// # Panics
+    /// * the resolver of the server's domain is missing.
+    #[allow(clippy::needless_pass_by_value, clippy::too_many_lines)]
+    #[rhai_fn(global, return_raw)]
+    pub fn check_dmarc(
+        ctx: Context,
+        srv: Server,
+        message: Message,
+        spf: &str,
+        dkim: rhai::Array,
+    ) -> EngineResult<rhai::Map> {
+        let from = vsl_guard_ok!(message.read())
+            .get_header("From")
+            .as_deref()
+            .and_then(from_domain);
+
+        let from = match from {
+            Some(from) => from,
+            None => {
+                return Ok(rhai::Map::from_iter([
+                    ("result".into(), "permerror".into()),
+                    ("disposition".into(), "none".into()),
+                ]))
+            }
+        };
+
+        let (spf_domain, ip) = {
+            let ctx = vsl_guard_ok!(ctx.read());
+            let mail_from = ctx.envelop.mail_from.domain();
+            (
+                if mail_from.is_empty() {
+                    ctx.envelop.helo.clone()
+                } else {
+                    mail_from.to_string()
+                },
+                ctx.client_addr.ip(),
+            )
+        };
+
+        let dkim = dkim
+            .into_iter()
+            .map(|i| {
+                let i = i
+                    .try_cast::<rhai::Map>()
+                    .ok_or_else::<Box<EvalAltResult>, _>(|| {
+                        "dkim results must be maps of `domain` and `result`".into()
+                    })?;
+                let get = |key: &str| i.get(key).map(ToString::to_string).unwrap_or_default();
+
+                Ok(AuthResult {
+                    domain: get("domain"),
+                    result: get("result"),
+                })
+            })
+            .collect::<EngineResult<Vec<_>>>()?;
+
+        let resolver = srv.resolvers.get(&srv.config.server.domain).unwrap();
+
+        let lookup = match query_record(resolver, &from) {
+            Lookup::None if organizational_domain(&from) != from => {
+                query_record(resolver, &organizational_domain(&from))
+            }
+            lookup => lookup,
+        };
+
+        let (policy_domain, record) = match lookup {
+            Lookup::Found(policy_domain, record) => (policy_domain, record),
+            Lookup::None | Lookup::TempError => {
+                return Ok(rhai::Map::from_iter([
+                    (
+                        "result".into(),
+                        if matches!(lookup, Lookup::None) {
+                            "none"
+                        } else {
+                            "temperror"
+                        }
+                        .into(),
+                    ),
+                    ("domain".into(), from.into()),
+                    ("disposition".into(), "none".into()),
+                ]));
+            }
+        };
+
+        let spf_aligned = spf == "pass" && is_aligned(&from, &spf_domain, record.spf_alignment);
+        let dkim_aligned = dkim
+            .iter()
+            .any(|i| i.result == "pass" && is_aligned(&from, &i.domain, record.dkim_alignment));
+
+        let is_subdomain = policy_domain != from;
+        let disposition = if spf_aligned || dkim_aligned {
+            vsmtp_dmarc::ReceiverPolicy::None
+        } else {
+            record.disposition(is_subdomain, fastrand::u8(0..100))
+        };
+
+        let result = rhai::Map::from_iter([
+            (
+                "result".into(),
+                if spf_aligned || dkim_aligned {
+                    "pass"
+                } else {
+                    "fail"
+                }
+                .into(),
+            ),
+            ("domain".into(), from.clone().into()),
+            ("policy_domain".into(), policy_domain.clone().into()),
+            (
+                "policy".into(),
+                record.policy(is_subdomain).to_string().into(),
+            ),
+            ("disposition".into(), disposition.to_string().into()),
+            ("spf_aligned".into(), spf_aligned.into()),
+            ("dkim_aligned".into(), dkim_aligned.into()),
+        ]);
+
+        if srv.config.server.dmarc.is_some() && !record.aggregate_report_uris.is_empty() {
+            let evaluation = Evaluation {
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |i| i.as_secs()),
+                source_ip: ip,
+                header_from: from,
+                policy_domain,
+                record,
+                disposition,
+                dkim_aligned,
+                spf_aligned,
+                dkim,
+                spf: AuthResult {
+                    domain: spf_domain,
+                    result: spf.to_string(),
+                },
+            };
+
+            if let Err(error) = evaluation.store(&srv.config.app.dirpath.join("dmarc")) {
+                log::warn!("failed to store the dmarc evaluation: {error}");
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::from_domain;
+
+    #[test]
+    fn from_header() {
+        assert_eq!(
+            from_domain(" John Doe <john.doe@Example.com>"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(
+            from_domain(" john.doe@example.com"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(
+            from_domain(" john.doe@example.com, jane.doe@example.org"),
+            None
+        );
+        assert_eq!(
+            from_domain(" \"John @ Home\" <john.doe@example.com>"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(
+            from_domain(" john@home <john.doe@example.com> (John Doe)"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(from_domain(" undisclosed"), None);
+    }
+}
@@ -0,0 +1,620 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::modules::types::types::{Context, Server};
+use crate::modules::EngineResult;
+use rhai::plugin::{
+    mem, Dynamic, FnAccess, FnNamespace, Module, NativeCallContext, PluginFunction, RhaiResult,
+    TypeId,
+};
+use vsmtp_common::re::{anyhow, log};
+use vsmtp_common::{status::Status, CodeID, ReplyOrCodeID};
+use vsmtp_config::field::FieldGreylist;
+
+/// The expired entries are removed at most once per period.
+const SWEEP_PERIOD: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Number of locks the entries are spread over.
+const LOCKS: u64 = 64;
+
+/// Outcome of the greylisting of a (client network, sender, recipient) triplet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// the client network passed the greylisting enough times to be trusted.
+    Whitelisted,
+    /// the triplet has been seen again after the delay.
+    Pass,
+    /// the triplet is unknown, or has been retried too soon.
+    Greylisted,
+}
+
+/// A record of the store, the timestamps are in seconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    first_seen: u64,
+    last_seen: u64,
+    /// number of passes, `0` while the triplet is greylisted.
+    count: u32,
+}
+
+impl Entry {
+    const fn new(now: u64, count: u32) -> Self {
+        Self {
+            first_seen: now,
+            last_seen: now,
+            count,
+        }
+    }
+
+    /// An entry which has not passed the greylisting expires at the end of the
+    /// retry window, the others when they are not seen during the lifetime.
+    const fn is_expired(&self, config: &FieldGreylist, now: u64) -> bool {
+        if self.count == 0 {
+            now.saturating_sub(self.first_seen) > config.retry_window.as_secs()
+        } else {
+            now.saturating_sub(self.last_seen) > config.lifetime.as_secs()
+        }
+    }
+
+    /// A missing or malformed file is treated as an unknown entry.
+    fn read(path: &std::path::Path) -> anyhow::Result<Option<Self>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(
+                    anyhow::Error::new(error).context(format!("cannot read '{}'", path.display()))
+                )
+            }
+        };
+
+        let mut fields = content.split_whitespace();
+        Ok(match (fields.next(), fields.next(), fields.next()) {
+            (Some(first_seen), Some(last_seen), Some(count)) => {
+                match (first_seen.parse(), last_seen.parse(), count.parse()) {
+                    (Ok(first_seen), Ok(last_seen), Ok(count)) => Some(Self {
+                        first_seen,
+                        last_seen,
+                        count,
+                    }),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// The entry is written in a temporary file renamed over the previous one,
+    /// a crash cannot leave a truncated entry behind.
+    fn write(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        let (dirpath, filename) = match (path.parent(), path.file_name()) {
+            (Some(dirpath), Some(filename)) => (dirpath, filename.to_string_lossy()),
+            _ => anyhow::bail!("invalid greylist entry path '{}'", path.display()),
+        };
+
+        std::fs::create_dir_all(dirpath)
+            .map_err(|error| anyhow::anyhow!("cannot create '{}': {error}", dirpath.display()))?;
+
+        let tmp = dirpath.join(format!(".{filename}.tmp"));
+        std::fs::write(
+            &tmp,
+            format!("{} {} {}\n", self.first_seen, self.last_seen, self.count),
+        )
+        .map_err(|error| anyhow::anyhow!("cannot write to '{}': {error}", tmp.display()))?;
+
+        std::fs::rename(&tmp, path)
+            .map_err(|error| anyhow::anyhow!("cannot write to '{}': {error}", path.display()))
+    }
+}
+
+fn epoch(time: std::time::SystemTime) -> anyhow::Result<u64> {
+    Ok(time.duration_since(std::time::UNIX_EPOCH)?.as_secs())
+}
+
+/// A greylist stored on disk, the triplets and the clients networks which
+/// passed the greylisting are kept in one file per entry.
+#[derive(Debug, Clone)]
+pub struct Greylist {
+    dirpath: std::path::PathBuf,
+    config: FieldGreylist,
+}
+
+impl Greylist {
+    /// Create a greylist stored in `dirpath`.
+    #[must_use]
+    pub const fn new(dirpath: std::path::PathBuf, config: FieldGreylist) -> Self {
+        Self { dirpath, config }
+    }
+
+    /// Serialize the accesses to the entries at `paths`, the server can handle several
+    /// transactions at once. The entries are spread over [`LOCKS`] lock files, the locks
+    /// are released when the files are dropped.
+    fn lock(&self, paths: &[&std::path::Path]) -> anyhow::Result<Vec<std::fs::File>> {
+        let dirpath = self.dirpath.join("locks");
+        std::fs::create_dir_all(&dirpath)
+            .map_err(|error| anyhow::anyhow!("cannot create '{}': {error}", dirpath.display()))?;
+
+        // the locks are always taken in the same order, and only once.
+        let mut locks = paths
+            .iter()
+            .map(|path| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                std::hash::Hash::hash(path, &mut hasher);
+                std::hash::Hasher::finish(&hasher) % LOCKS
+            })
+            .collect::<Vec<_>>();
+        locks.sort_unstable();
+        locks.dedup();
+
+        locks
+            .into_iter()
+            .map(|lock| {
+                let path = dirpath.join(lock.to_string());
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(&path)
+                    .map_err(|error| {
+                        anyhow::anyhow!("cannot open '{}': {error}", path.display())
+                    })?;
+                vsmtp_common::libc_abstraction::flock_exclusive(&file)?;
+                Ok(file)
+            })
+            .collect()
+    }
+
+    /// The addresses of a network share their entries, the mail exchangers of
+    /// a pool can retry from a different address.
+    fn network(&self, ip: std::net::IpAddr) -> anyhow::Result<ipnet::IpNet> {
+        let prefix = match ip {
+            std::net::IpAddr::V4(_) => self.config.ipv4_prefix,
+            std::net::IpAddr::V6(_) => self.config.ipv6_prefix,
+        };
+
+        Ok(ipnet::IpNet::new(ip, prefix)?.trunc())
+    }
+
+    fn client_path(&self, network: &ipnet::IpNet) -> std::path::PathBuf {
+        self.dirpath
+            .join("clients")
+            .join(network.to_string().replace('/', "_"))
+    }
+
+    fn triplet_path(
+        &self,
+        network: &ipnet::IpNet,
+        sender: &str,
+        recipient: &str,
+    ) -> std::path::PathBuf {
+        let digest = <sha2::Sha256 as sha2::Digest>::digest(
+            format!("{network}\0{sender}\0{recipient}")
+                .to_lowercase()
+                .as_bytes(),
+        );
+
+        self.dirpath.join("triplets").join(format!("{digest:x}"))
+    }
+
+    /// Greylist the delivery of a message from `sender` to `recipient` by the client `ip`.
+    ///
+    /// # Errors
+    ///
+    /// * the entries cannot be read or written
+    pub fn check(
+        &self,
+        ip: std::net::IpAddr,
+        sender: &str,
+        recipient: &str,
+        now: std::time::SystemTime,
+    ) -> anyhow::Result<Verdict> {
+        let now = epoch(now)?;
+        let network = self.network(ip)?;
+        let client_path = self.client_path(&network);
+        let triplet_path = self.triplet_path(&network, sender, recipient);
+
+        let guard = self.lock(&[&client_path, &triplet_path])?;
+
+        let client = Entry::read(&client_path)?.filter(|i| !i.is_expired(&self.config, now));
+
+        if let Some(mut client) = client {
+            if self.config.auto_whitelist != 0 && client.count >= self.config.auto_whitelist {
+                client.last_seen = now;
+                client.write(&client_path)?;
+                drop(guard);
+                return Ok(Verdict::Whitelisted);
+            }
+        }
+
+        let verdict = match Entry::read(&triplet_path)?.filter(|i| !i.is_expired(&self.config, now))
+        {
+            Some(mut triplet) if triplet.count != 0 => {
+                triplet.count = triplet.count.saturating_add(1);
+                triplet.last_seen = now;
+                triplet.write(&triplet_path)?;
+                Verdict::Pass
+            }
+            Some(mut triplet)
+                if now.saturating_sub(triplet.first_seen) < self.config.delay.as_secs() =>
+            {
+                triplet.last_seen = now;
+                triplet.write(&triplet_path)?;
+                Verdict::Greylisted
+            }
+            Some(mut triplet) => {
+                triplet.count = 1;
+                triplet.last_seen = now;
+                triplet.write(&triplet_path)?;
+
+                let client = client.map_or_else(
+                    || Entry::new(now, 1),
+                    |client| Entry {
+                        last_seen: now,
+                        count: client.count.saturating_add(1),
+                        ..client
+                    },
+                );
+                client.write(&client_path)?;
+                Verdict::Pass
+            }
+            None => {
+                Entry::new(now, 0).write(&triplet_path)?;
+                Verdict::Greylisted
+            }
+        };
+        drop(guard);
+
+        Ok(verdict)
+    }
+
+    /// Remove the expired entries of the store.
+    ///
+    /// # Errors
+    ///
+    /// * the store cannot be read, or an entry cannot be removed
+    pub fn sweep(&self, now: std::time::SystemTime) -> anyhow::Result<()> {
+        let now = epoch(now)?;
+
+        for dirpath in [self.dirpath.join("clients"), self.dirpath.join("triplets")] {
+            if !dirpath.exists() {
+                continue;
+            }
+
+            for entry in std::fs::read_dir(&dirpath)
+                .map_err(|error| anyhow::anyhow!("cannot read '{}': {error}", dirpath.display()))?
+            {
+                let path = entry?.path();
+                if path
+                    .file_name()
+                    .map_or(true, |i| i.to_string_lossy().starts_with('.'))
+                {
+                    continue;
+                }
+
+                let guard = self.lock(&[&path])?;
+                if Entry::read(&path)?.map_or(true, |i| i.is_expired(&self.config, now)) {
+                    std::fs::remove_file(&path).map_err(|error| {
+                        anyhow::anyhow!("cannot remove '{}': {error}", path.display())
+                    })?;
+                }
+                drop(guard);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the expired entries in the background, at most once per [`SWEEP_PERIOD`].
+    ///
+    /// The time of the last sweep is kept in the store, the greylists stored
+    /// in other directories are swept independently.
+    fn sweep_in_background(&self, now: std::time::SystemTime) {
+        let now_secs = match epoch(now) {
+            Ok(now_secs) => now_secs,
+            Err(_) => return,
+        };
+
+        let path = self.dirpath.join(".last_sweep");
+        let last_sweep = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        if now_secs.saturating_sub(last_sweep) < SWEEP_PERIOD.as_secs() {
+            return;
+        }
+
+        // NOTE: two transactions can sweep at the same time, removing an expired entry twice.
+        let tmp = self.dirpath.join(".last_sweep.tmp");
+        if let Err(error) = std::fs::create_dir_all(&self.dirpath)
+            .and_then(|()| std::fs::write(&tmp, format!("{now_secs}\n")))
+            .and_then(|()| std::fs::rename(&tmp, &path))
+        {
+            log::warn!("cannot write to '{}': {error}", path.display());
+            return;
+        }
+
+        let greylist = self.clone();
+        std::thread::spawn(move || {
+            if let Err(error) = greylist.sweep(now) {
+                log::warn!("failed to remove the expired greylist entries: {error}");
+            }
+        });
+    }
+}
+
+///
+#[rhai::plugin::export_module]
+pub mod greylist {
+
+    /// Greylist the current recipient.
+    ///
+    /// The entries are keyed on the network of the client, the sender and the recipient,
+    /// a greylisted recipient is removed from the envelop and a temporary failure is
+    /// sent back to the client.
+    ///
+    /// # Errors
+    ///
+    /// * the function is not called at the `rcpt` stage
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, return_raw, pure)]
+    pub fn greylist(ctx: &mut Context, srv: Server) -> EngineResult<Status> {
+        let now = std::time::SystemTime::now();
+        let (ip, sender, recipient) = {
+            let ctx = vsl_guard_ok!(ctx.read());
+            let recipient = ctx
+                .envelop
+                .rcpt
+                .last()
+                .ok_or_else::<Box<rhai::EvalAltResult>, _>(|| {
+                    "greylist() must be called at the rcpt stage".into()
+                })?
+                .address
+                .full()
+                .to_string();
+
+            (
+                ctx.client_addr.ip(),
+                ctx.envelop.mail_from.full().to_string(),
+                recipient,
+            )
+        };
+
+        let greylist = super::Greylist::new(
+            srv.config.app.dirpath.join("greylist"),
+            srv.config.server.greylist.clone(),
+        );
+        greylist.sweep_in_background(now);
+
+        match greylist.check(ip, &sender, &recipient, now) {
+            Ok(super::Verdict::Greylisted) => {
+                log::info!("greylisting '{recipient}' from '{sender}' sent by '{ip}'");
+
+                vsl_guard_ok!(ctx.write()).envelop.rcpt.pop();
+                Ok(Status::Info(ReplyOrCodeID::Left(CodeID::Greylisted)))
+            }
+            Ok(_) => Ok(Status::Next),
+            // the greylisting is a best effort, a broken store must not block the emails.
+            Err(error) => {
+                log::warn!("failed to greylist '{recipient}', the recipient is accepted: {error}");
+                Ok(Status::Next)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENDER: &str = "john.doe@example.com";
+    const RCPT: &str = "jenny.doe@example.com";
+
+    fn greylist(name: &str, auto_whitelist: u32) -> Greylist {
+        let dirpath = std::path::PathBuf::from(format!("./tmp/greylist/{name}"));
+        if dirpath.exists() {
+            std::fs::remove_dir_all(&dirpath).unwrap();
+        }
+
+        Greylist::new(
+            dirpath,
+            FieldGreylist {
+                auto_whitelist,
+                ..FieldGreylist::default()
+            },
+        )
+    }
+
+    fn at(secs: u64) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000 + secs)
+    }
+
+    fn ip(ip: &str) -> std::net::IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn retry_after_delay() {
+        let greylist = greylist("retry_after_delay", 0);
+        let client = ip("192.0.2.1");
+
+        for (time, verdict) in [
+            (0, Verdict::Greylisted),
+            (60, Verdict::Greylisted),
+            (5 * 60, Verdict::Pass),
+            (10 * 60, Verdict::Pass),
+        ] {
+            assert_eq!(
+                greylist.check(client, SENDER, RCPT, at(time)).unwrap(),
+                verdict
+            );
+        }
+
+        assert_eq!(
+            greylist
+                .check(client, SENDER, "other@example.com", at(11 * 60))
+                .unwrap(),
+            Verdict::Greylisted
+        );
+        assert_eq!(
+            greylist
+                .check(client, "other@example.com", RCPT, at(11 * 60))
+                .unwrap(),
+            Verdict::Greylisted
+        );
+    }
+
+    #[test]
+    fn retry_window_expired() {
+        let greylist = greylist("retry_window_expired", 0);
+        let client = ip("192.0.2.1");
+        let late = 2 * 24 * 60 * 60 + 1;
+
+        assert_eq!(
+            greylist.check(client, SENDER, RCPT, at(0)).unwrap(),
+            Verdict::Greylisted
+        );
+        assert_eq!(
+            greylist.check(client, SENDER, RCPT, at(late)).unwrap(),
+            Verdict::Greylisted
+        );
+        assert_eq!(
+            greylist
+                .check(client, SENDER, RCPT, at(late + 5 * 60))
+                .unwrap(),
+            Verdict::Pass
+        );
+    }
+
+    #[test]
+    fn lifetime_expired() {
+        let greylist = greylist("lifetime_expired", 0);
+        let client = ip("192.0.2.1");
+        let late = 5 * 60 + 35 * 24 * 60 * 60 + 1;
+
+        greylist.check(client, SENDER, RCPT, at(0)).unwrap();
+        assert_eq!(
+            greylist.check(client, SENDER, RCPT, at(5 * 60)).unwrap(),
+            Verdict::Pass
+        );
+        assert_eq!(
+            greylist.check(client, SENDER, RCPT, at(late)).unwrap(),
+            Verdict::Greylisted
+        );
+    }
+
+    #[test]
+    fn same_network() {
+        let greylist = greylist("same_network", 0);
+
+        for (client, verdict) in [
+            ("192.0.2.1", Verdict::Greylisted),
+            ("192.0.3.1", Verdict::Greylisted),
+            ("2001:db8::1", Verdict::Greylisted),
+        ] {
+            assert_eq!(
+                greylist.check(ip(client), SENDER, RCPT, at(0)).unwrap(),
+                verdict
+            );
+        }
+
+        for (client, verdict) in [
+            ("192.0.2.200", Verdict::Pass),
+            ("192.0.3.200", Verdict::Pass),
+            ("2001:db8::ffff:1", Verdict::Pass),
+            ("2001:db8:0:1::1", Verdict::Greylisted),
+        ] {
+            assert_eq!(
+                greylist
+                    .check(ip(client), SENDER, RCPT, at(5 * 60))
+                    .unwrap(),
+                verdict
+            );
+        }
+    }
+
+    #[test]
+    fn auto_whitelist() {
+        let greylist = greylist("auto_whitelist", 2);
+        let client = ip("192.0.2.1");
+
+        for rcpt in ["a@example.com", "b@example.com"] {
+            greylist.check(client, SENDER, rcpt, at(0)).unwrap();
+            assert_eq!(
+                greylist.check(client, SENDER, rcpt, at(5 * 60)).unwrap(),
+                Verdict::Pass
+            );
+        }
+
+        assert_eq!(
+            greylist
+                .check(client, SENDER, "c@example.com", at(6 * 60))
+                .unwrap(),
+            Verdict::Whitelisted
+        );
+        assert_eq!(
+            greylist
+                .check(ip("192.0.3.1"), SENDER, "c@example.com", at(6 * 60))
+                .unwrap(),
+            Verdict::Greylisted
+        );
+    }
+
+    #[test]
+    fn sweep() {
+        let greylist = greylist("sweep", 0);
+        let client = ip("192.0.2.1");
+
+        greylist.check(client, SENDER, RCPT, at(0)).unwrap();
+        greylist.check(client, SENDER, RCPT, at(5 * 60)).unwrap();
+        greylist
+            .check(client, SENDER, "other@example.com", at(0))
+            .unwrap();
+        std::fs::write(greylist.dirpath.join("triplets").join("malformed"), "").unwrap();
+
+        let count = |dirpath: &str| {
+            std::fs::read_dir(greylist.dirpath.join(dirpath))
+                .unwrap()
+                .count()
+        };
+        assert_eq!((count("clients"), count("triplets")), (1, 3));
+
+        greylist.sweep(at(3 * 24 * 60 * 60)).unwrap();
+        assert_eq!((count("clients"), count("triplets")), (1, 1));
+
+        greylist.sweep(at(36 * 24 * 60 * 60)).unwrap();
+        assert_eq!((count("clients"), count("triplets")), (0, 0));
+    }
+
+    #[test]
+    fn sweep_per_store() {
+        let first = greylist("sweep_per_store_first", 0);
+        let second = greylist("sweep_per_store_second", 0);
+        let last_sweep = |greylist: &Greylist| {
+            std::fs::read_to_string(greylist.dirpath.join(".last_sweep")).ok()
+        };
+
+        first.sweep_in_background(at(0));
+        second.sweep_in_background(at(60));
+        assert_eq!(last_sweep(&first).as_deref(), Some("1600000000\n"));
+        assert_eq!(last_sweep(&second).as_deref(), Some("1600000060\n"));
+
+        // at most once per period.
+        first.sweep_in_background(at(60));
+        assert_eq!(last_sweep(&first).as_deref(), Some("1600000000\n"));
+        first.sweep_in_background(at(60 * 60));
+        assert_eq!(last_sweep(&first).as_deref(), Some("1600003600\n"));
+    }
+}
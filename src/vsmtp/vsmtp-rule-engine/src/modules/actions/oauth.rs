@@ -0,0 +1,212 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::modules::types::types::Context;
+use crate::modules::EngineResult;
+use rhai::plugin::{
+    mem, Dynamic, FnAccess, FnNamespace, ImmutableString, Module, NativeCallContext,
+    PluginFunction, RhaiResult, TypeId,
+};
+use vsmtp_common::auth::Credentials;
+use vsmtp_common::re::{anyhow, log, serde_json};
+
+/// Verify an access token issued as a JWT (RFC 7519) with the keys of the authorization server.
+///
+/// The key is the one named by the `kid` of the token header, or the first of the set
+/// if the header has none. The token must be signed with the algorithm of the key,
+/// must not have expired, and its subject must be `authid`.
+///
+/// # Errors
+///
+/// * no key of the set can verify the token
+/// * the token is ill-formed, its signature is invalid or it has expired
+/// * the subject of the token is not `authid`
+pub fn verify_token(
+    token: &str,
+    authid: &str,
+    jwks: &jsonwebtoken::jwk::JwkSet,
+) -> anyhow::Result<()> {
+    let header = jsonwebtoken::decode_header(token)?;
+    let jwk = header
+        .kid
+        .as_ref()
+        .map_or_else(|| jwks.keys.first(), |kid| jwks.find(kid))
+        .ok_or_else(|| anyhow::anyhow!("no key to verify the token"))?;
+
+    if let Some(algorithm) = jwk.common.algorithm {
+        anyhow::ensure!(
+            algorithm == header.alg,
+            "the token is signed with '{:?}' instead of '{algorithm:?}'",
+            header.alg
+        );
+    }
+
+    let mut validation = jsonwebtoken::Validation::new(header.alg);
+    validation.set_required_spec_claims(&["exp", "sub"]);
+    validation.sub = Some(authid.to_string());
+
+    jsonwebtoken::decode::<serde_json::Value>(
+        token,
+        &jsonwebtoken::DecodingKey::from_jwk(jwk)?,
+        &validation,
+    )?;
+
+    Ok(())
+}
+
+/// vsl functions to authenticate the bearer tokens.
+#[rhai::plugin::export_module]
+pub mod oauth {
+
+    /// Verify the bearer token sent with the `OAUTHBEARER` or `XOAUTH2` mechanisms,
+    /// with the JSON Web Key Set (RFC 7517) stored at `jwks`.
+    ///
+    /// # Errors
+    ///
+    /// * the function is not called at the `authenticate` stage of a bearer token
+    /// * the key set cannot be read
+    #[rhai_fn(global, return_raw, pure)]
+    pub fn verify_bearer_token(ctx: &mut Context, jwks: &str) -> EngineResult<bool> {
+        let (authid, token) = match &vsl_guard_ok!(ctx.read()).connection.credentials {
+            Some(Credentials::BearerToken { authid, token }) => (authid.clone(), token.clone()),
+            _ => {
+                return Err(
+                    "verify_bearer_token() must be called to authenticate a bearer token".into(),
+                )
+            }
+        };
+
+        let jwks = std::fs::read(jwks)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(serde_json::from_slice(&content)?))
+            .map_err::<Box<rhai::EvalAltResult>, _>(|error| {
+                format!("failed to read the key set '{jwks}': {error}").into()
+            })?;
+
+        match super::verify_token(&token, &authid, &jwks) {
+            Ok(()) => Ok(true),
+            Err(error) => {
+                log::warn!("the bearer token of '{authid}' is rejected: {error}");
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use vsmtp_common::re::base64;
+
+    /// generate an Ed25519 key pair, and the key set holding its public key.
+    pub(crate) fn key_pair(kid: &str) -> (EncodingKey, jsonwebtoken::jwk::JwkSet) {
+        use ring::signature::KeyPair;
+
+        let pkcs8 =
+            ring::signature::Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+                .unwrap();
+        let public_key = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+            .unwrap()
+            .public_key()
+            .as_ref()
+            .to_vec();
+
+        let jwks = serde_json::json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "alg": "EdDSA",
+                "kid": kid,
+                "x": base64::encode_config(public_key, base64::URL_SAFE_NO_PAD),
+            }]
+        });
+
+        (
+            EncodingKey::from_ed_der(pkcs8.as_ref()),
+            serde_json::from_value(jwks).unwrap(),
+        )
+    }
+
+    pub(crate) fn token(
+        key: &EncodingKey,
+        kid: Option<&str>,
+        claims: &serde_json::Value,
+    ) -> String {
+        let header = Header {
+            kid: kid.map(str::to_string),
+            ..Header::new(Algorithm::EdDSA)
+        };
+        jsonwebtoken::encode(&header, claims, key).unwrap()
+    }
+
+    pub(crate) fn expires_in(secs: i64) -> i64 {
+        i64::try_from(jsonwebtoken::get_current_timestamp()).unwrap() + secs
+    }
+
+    #[test]
+    fn valid() {
+        let (key, jwks) = key_pair("2022-11");
+        let claims = serde_json::json!({ "sub": "john.doe", "exp": expires_in(3600) });
+
+        verify_token(&token(&key, Some("2022-11"), &claims), "john.doe", &jwks).unwrap();
+        verify_token(&token(&key, None, &claims), "john.doe", &jwks).unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let (key, jwks) = key_pair("2022-11");
+        let (other_key, _) = key_pair("2022-11");
+        let claims = serde_json::json!({ "sub": "john.doe", "exp": expires_in(3600) });
+
+        // signed by another key.
+        assert!(verify_token(&token(&other_key, None, &claims), "john.doe", &jwks).is_err());
+        // unknown key.
+        assert!(verify_token(&token(&key, Some("2021-01"), &claims), "john.doe", &jwks).is_err());
+        // issued for another user.
+        assert!(verify_token(&token(&key, None, &claims), "jenny.doe", &jwks).is_err());
+        // expired.
+        let expired = serde_json::json!({ "sub": "john.doe", "exp": expires_in(-3600) });
+        assert!(verify_token(&token(&key, None, &expired), "john.doe", &jwks).is_err());
+        // no expiration or subject.
+        let no_exp = serde_json::json!({ "sub": "john.doe" });
+        assert!(verify_token(&token(&key, None, &no_exp), "john.doe", &jwks).is_err());
+        let no_sub = serde_json::json!({ "exp": expires_in(3600) });
+        assert!(verify_token(&token(&key, None, &no_sub), "john.doe", &jwks).is_err());
+        // ill-formed.
+        assert!(verify_token(
+            "vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==",
+            "john.doe",
+            &jwks
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn algorithm_of_the_key() {
+        let (_, jwks) = key_pair("2022-11");
+        let claims = serde_json::json!({ "sub": "john.doe", "exp": expires_in(3600) });
+
+        // a token signed with a shared secret cannot be verified with the key.
+        let forged = jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(verify_token(&forged, "john.doe", &jwks).is_err());
+    }
+}
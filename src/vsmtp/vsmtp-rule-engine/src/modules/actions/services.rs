@@ -19,7 +19,18 @@ use rhai::{
     Dynamic, EvalAltResult, ImmutableString, NativeCallContext,
 };
 
-use crate::{modules::EngineResult, Service};
+use crate::{
+    dsl::service::{
+        clamav::{scan, ScanResult},
+        databases::{Failure, KeyValue},
+    },
+    modules::{
+        types::types::{Context, Message},
+        EngineResult,
+    },
+    Service,
+};
+use vsmtp_common::status::Status;
 
 ///
 #[rhai::plugin::export_module]
@@ -95,12 +106,7 @@ pub mod services {
         record: rhai::Array,
     ) -> EngineResult<()> {
         match &**service {
-            Service::CSVDatabase {
-                path,
-                delimiter,
-                fd,
-                ..
-            } => {
+            Service::CSVDatabase { database } => {
                 let record = record
                     .into_iter()
                     .map(rhai::Dynamic::try_cast)
@@ -109,9 +115,27 @@ pub mod services {
                         "all fields in a record must be strings".into()
                     })?;
 
-                crate::dsl::service::databases::csv::add_record(path, *delimiter, fd, &record[..])
+                database
+                    .add(&record[..])
                     .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())
             }
+            Service::SQLDatabase {
+                add: Some(add),
+                timeout,
+                pool,
+                ..
+            } => crate::dsl::service::databases::sql::execute(pool, add, *timeout, &record)
+                .map(|_| ())
+                .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+            Service::SQLDatabase { .. } => {
+                Err(format!("{service} does not define an 'add' statement.").into())
+            }
+            Service::RedisDatabase { .. } | Service::MemcachedDatabase { .. } => {
+                match record.as_slice() {
+                    [key, value] => super::database_set(service, key, value, None),
+                    _ => Err(format!("a record of {service} must be a key and a value").into()),
+                }
+            }
             _ => Err("'db_add' can only be used on a database service.".into()),
         }
     }
@@ -154,6 +178,153 @@ pub mod services {
         super::database_query_key(service, &key.to_string())
     }
 
+    /// set the value of a key in a key-value database, expiring after `ttl`.
+    ///
+    /// # Errors
+    ///
+    /// * `ttl` is not a valid duration.
+    /// * the database failed, and its failure behavior is closed.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "db_set", return_raw, pure)]
+    pub fn database_set_with_ttl(
+        service: &mut std::sync::Arc<Service>,
+        key: &str,
+        value: rhai::Dynamic,
+        ttl: rhai::Dynamic,
+    ) -> EngineResult<()> {
+        super::database_set(
+            service,
+            &rhai::Dynamic::from(key.to_string()),
+            &value,
+            Some(&ttl),
+        )
+    }
+
+    /// increment the value of a key in a key-value database, and return the new value.
+    ///
+    /// # Errors
+    ///
+    /// * the value of the key is not an integer.
+    /// * the database failed, and its failure behavior is closed.
+    #[rhai_fn(global, name = "db_incr", return_raw, pure)]
+    pub fn database_incr(
+        service: &mut std::sync::Arc<Service>,
+        key: &str,
+        amount: rhai::INT,
+    ) -> EngineResult<rhai::INT> {
+        let (database, failure) = super::key_value(service)?;
+        failure
+            .apply(&service.to_string(), database.incr(key, amount), 0)
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())
+    }
+
+    /// expire a key of a key-value database after `ttl`, return false if the key does not exist.
+    ///
+    /// # Errors
+    ///
+    /// * `ttl` is not a valid duration.
+    /// * the database failed, and its failure behavior is closed.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "db_expire", return_raw, pure)]
+    pub fn database_expire(
+        service: &mut std::sync::Arc<Service>,
+        key: &str,
+        ttl: rhai::Dynamic,
+    ) -> EngineResult<bool> {
+        let (database, failure) = super::key_value(service)?;
+        let ttl = crate::dsl::service::databases::parse_ttl(&ttl)
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+        failure
+            .apply(&service.to_string(), database.expire(key, &ttl), false)
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())
+    }
+
+    /// check the password of a user with a simple bind on a ldap service.
+    ///
+    /// # Errors
+    ///
+    /// * the service is not a ldap service.
+    /// * the directory could not be reached or replied with an error.
+    #[rhai_fn(global, name = "ldap_bind", return_raw, pure)]
+    pub fn ldap_bind(
+        service: &mut std::sync::Arc<Service>,
+        user: &str,
+        password: &str,
+    ) -> EngineResult<bool> {
+        match &**service {
+            Service::Ldap { client } => client
+                .bind(user, password)
+                .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+            _ => Err(format!("{service} is not a ldap service.").into()),
+        }
+    }
+
+    /// scan the whole message with a clamav service.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "clamav_scan", return_raw, pure)]
+    pub fn clamav_scan(
+        service: &mut std::sync::Arc<Service>,
+        message: Message,
+    ) -> EngineResult<rhai::Map> {
+        let content = vsl_guard_ok!(message.read()).inner().to_bytes();
+        super::clamav_verdict(service, "", &content)
+    }
+
+    /// scan each decoded attachment of the message with a clamav service,
+    /// stopping at the first infected one.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "clamav_scan_attachments", return_raw, pure)]
+    pub fn clamav_scan_attachments(
+        service: &mut std::sync::Arc<Service>,
+        message: Message,
+    ) -> EngineResult<rhai::Map> {
+        let attachments = {
+            let mut writer = vsl_guard_ok!(message.write());
+            vsmtp_mail_parser::get_attachments(vsl_parse_ok!(writer))
+                .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?
+        };
+
+        for attachment in attachments {
+            let verdict = super::clamav_verdict(
+                service,
+                attachment.filename.as_deref().unwrap_or_default(),
+                &attachment.content,
+            )?;
+
+            if verdict
+                .get("infected")
+                .and_then(|infected| infected.as_bool().ok())
+                .unwrap_or_default()
+            {
+                return Ok(verdict);
+            }
+        }
+
+        super::clamav_verdict(service, "", &[])
+    }
+
+    /// send the events of the transaction to a milter service and apply its response.
+    #[allow(clippy::needless_pass_by_value)]
+    #[rhai_fn(global, name = "milter_run", return_raw, pure)]
+    pub fn milter_run(
+        service: &mut std::sync::Arc<Service>,
+        ctx: Context,
+        message: Message,
+    ) -> EngineResult<Status> {
+        match &**service {
+            Service::Milter {
+                address,
+                timeout,
+                quarantine,
+                sessions,
+            } => crate::dsl::service::milter::run(
+                address, timeout, quarantine, sessions, &ctx, &message,
+            )
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+            _ => Err(format!("{service} is not a milter service.").into()),
+        }
+    }
+
     /// get the receiver address from a smtp service.
     #[rhai_fn(global, get = "receiver_address", return_raw, pure)]
     pub fn smtp_service_receiver_address(
@@ -166,13 +337,59 @@ pub mod services {
     }
 }
 
+/// scan `content` and build the map returned to vsl.
+fn clamav_verdict(
+    service: &std::sync::Arc<Service>,
+    filename: &str,
+    content: &[u8],
+) -> EngineResult<rhai::Map> {
+    match &**service {
+        Service::ClamAV { address, timeout } => {
+            let result = if content.is_empty() {
+                ScanResult::Clean
+            } else {
+                scan(address, timeout, content)
+                    .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?
+            };
+
+            let (infected, virus) = match result {
+                ScanResult::Clean => (false, String::new()),
+                ScanResult::Infected(virus) => (true, virus),
+            };
+
+            Ok(rhai::Map::from_iter([
+                ("infected".into(), infected.into()),
+                ("virus".into(), virus.into()),
+                ("filename".into(), filename.to_string().into()),
+            ]))
+        }
+        _ => Err(format!("{service} is not a clamav service.").into()),
+    }
+}
+
 fn database_remove(service: &mut std::sync::Arc<Service>, key: &str) -> EngineResult<()> {
     match &**service {
-        Service::CSVDatabase { path, .. } => {
-            crate::dsl::service::databases::csv::remove_record(path, key)
+        Service::CSVDatabase { database } => database
+            .remove(key)
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+        Service::SQLDatabase {
+            remove: Some(remove),
+            timeout,
+            pool,
+            ..
+        } => crate::dsl::service::databases::sql::execute(pool, remove, *timeout, &[key.into()])
+            .map(|_| ())
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+        Service::SQLDatabase { .. } => {
+            Err(format!("{service} does not define a 'remove' statement.").into())
+        }
+        Service::RedisDatabase { .. } | Service::MemcachedDatabase { .. } => {
+            let (database, failure) = key_value(service)?;
+            failure
+                .apply(&service.to_string(), database.remove(key), ())
                 .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())
         }
-        _ => Err("'db_add' can only be used on a database service.".into()),
+        _ => Err("'db_rm' can only be used on a database service.".into()),
     }
 }
 
@@ -181,13 +398,8 @@ fn database_query_key(
     key: &str,
 ) -> EngineResult<rhai::Array> {
     match &**service {
-        Service::CSVDatabase {
-            path,
-            delimiter,
-            refresh,
-            fd,
-            ..
-        } => crate::dsl::service::databases::csv::query_key(path, *delimiter, refresh, fd, key)
+        Service::CSVDatabase { database } => database
+            .query(key)
             .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?
             .map_or_else(
                 || Ok(rhai::Array::default()),
@@ -198,6 +410,55 @@ fn database_query_key(
                         .collect())
                 },
             ),
+        Service::SQLDatabase {
+            query,
+            timeout,
+            pool,
+            ..
+        } => crate::dsl::service::databases::sql::query(pool, query, *timeout, &[key.into()])
+            .map(|rows| rows.into_iter().map(rhai::Dynamic::from).collect())
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+        Service::Ldap { client } => client
+            .search(key)
+            .map(|entries| entries.into_iter().map(rhai::Dynamic::from).collect())
+            .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into()),
+        Service::RedisDatabase { .. } | Service::MemcachedDatabase { .. } => {
+            let (database, failure) = key_value(service)?;
+            failure
+                .apply(&service.to_string(), database.get(key), None)
+                .map(|value| value.into_iter().map(rhai::Dynamic::from).collect())
+                .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())
+        }
         _ => Err(format!("{service} cannot be run as a cmd script.").into()),
     }
 }
+
+/// get the client of a key-value database and its failure behavior.
+fn key_value(service: &Service) -> EngineResult<(&dyn KeyValue, Failure)> {
+    match service {
+        Service::RedisDatabase { failure, client } => Ok((client, *failure)),
+        Service::MemcachedDatabase { failure, client } => Ok((client, *failure)),
+        _ => Err(format!("{service} is not a key-value database.").into()),
+    }
+}
+
+fn database_set(
+    service: &Service,
+    key: &rhai::Dynamic,
+    value: &rhai::Dynamic,
+    ttl: Option<&rhai::Dynamic>,
+) -> EngineResult<()> {
+    let (database, failure) = key_value(service)?;
+    let ttl = ttl
+        .map(crate::dsl::service::databases::parse_ttl)
+        .transpose()
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())?;
+
+    failure
+        .apply(
+            &service.to_string(),
+            database.set(&key.to_string(), &value.to_string(), ttl.as_ref()),
+            (),
+        )
+        .map_err::<Box<EvalAltResult>, _>(|err| err.to_string().into())
+}
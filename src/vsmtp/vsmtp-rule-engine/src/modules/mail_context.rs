@@ -21,7 +21,11 @@ use rhai::plugin::{
     PluginFunction, RhaiResult, TypeId,
 };
 use vsmtp_common::rcpt::Rcpt;
-use vsmtp_common::{auth::Credentials, auth::Mechanism, state::StateSMTP, Address};
+use vsmtp_common::{
+    auth::{ClientCertificate, Credentials, Mechanism},
+    state::StateSMTP,
+    Address,
+};
 
 #[rhai::plugin::export_module]
 pub mod mail_context {
@@ -104,6 +108,40 @@ pub mod mail_context {
         .clone())
     }
 
+    #[rhai_fn(global, get = "verify_query", return_raw, pure)]
+    pub fn verify_query(context: &mut Context) -> EngineResult<String> {
+        Ok(vsl_missing_ok!(
+            vsl_guard_ok!(context.read()).connection.verify_query,
+            "verify_query",
+            StateSMTP::Vrfy
+        )
+        .clone())
+    }
+
+    #[rhai_fn(global, get = "client_certificate", return_raw, pure)]
+    pub fn client_certificate(context: &mut Context) -> EngineResult<Dynamic> {
+        Ok(vsl_guard_ok!(context.read())
+            .connection
+            .client_certificate
+            .clone()
+            .map_or(Dynamic::UNIT, Dynamic::from))
+    }
+
+    #[rhai_fn(global, get = "subject", pure)]
+    pub fn get_subject(certificate: &mut ClientCertificate) -> String {
+        certificate.subject.clone()
+    }
+
+    #[rhai_fn(global, get = "subject_alt_names", pure)]
+    pub fn get_subject_alt_names(certificate: &mut ClientCertificate) -> rhai::Array {
+        certificate
+            .subject_alt_names
+            .iter()
+            .cloned()
+            .map(Dynamic::from)
+            .collect()
+    }
+
     #[rhai_fn(global, get = "type", pure)]
     pub fn get_type(credentials: &mut Credentials) -> String {
         credentials.to_string()
@@ -112,9 +150,11 @@ pub mod mail_context {
     #[rhai_fn(global, get = "authid", return_raw, pure)]
     pub fn get_authid(credentials: &mut Credentials) -> EngineResult<String> {
         match credentials {
-            Credentials::Query { authid } | Credentials::Verify { authid, .. } => {
-                Ok(authid.clone())
-            }
+            Credentials::Query { authid }
+            | Credentials::Verify { authid, .. }
+            | Credentials::ScramQuery { authid, .. }
+            | Credentials::BearerToken { authid, .. }
+            | Credentials::External { authid } => Ok(authid.clone()),
             Credentials::AnonymousToken { .. } => {
                 Err(format!("no `authid` available in credentials of type `{credentials}`").into())
             }
@@ -142,6 +182,24 @@ pub mod mail_context {
         }
     }
 
+    #[rhai_fn(global, get = "token", return_raw, pure)]
+    pub fn get_token(credentials: &mut Credentials) -> EngineResult<String> {
+        match credentials {
+            Credentials::BearerToken { token, .. } => Ok(token.clone()),
+            _ => Err(format!("no `token` available in credentials of type `{credentials}`").into()),
+        }
+    }
+
+    #[rhai_fn(global, get = "mechanism", return_raw, pure)]
+    pub fn get_mechanism(credentials: &mut Credentials) -> EngineResult<String> {
+        match credentials {
+            Credentials::ScramQuery { mechanism, .. } => Ok(mechanism.to_string()),
+            _ => Err(
+                format!("no `mechanism` available in credentials of type `{credentials}`").into(),
+            ),
+        }
+    }
+
     #[rhai_fn(global, get = "helo", return_raw, pure)]
     pub fn helo(context: &mut Context) -> EngineResult<String> {
         Ok(vsl_guard_ok!(context.read()).envelop.helo.clone())
@@ -154,6 +212,16 @@ pub mod mail_context {
         )))
     }
 
+    #[rhai_fn(global, get = "declared_size", return_raw, pure)]
+    pub fn declared_size(context: &mut Context) -> EngineResult<Dynamic> {
+        Ok(vsl_guard_ok!(context.read())
+            .envelop
+            .declared_size
+            .map_or(Dynamic::UNIT, |size| {
+                Dynamic::from(i64::try_from(size).unwrap_or(i64::MAX))
+            }))
+    }
+
     #[rhai_fn(global, get = "rcpt_list", return_raw, pure)]
     pub fn rcpt_list(context: &mut Context) -> EngineResult<Vec<SharedObject>> {
         Ok(vsl_guard_ok!(context.read())
@@ -40,6 +40,16 @@ pub mod message {
             .unwrap_or_default())
     }
 
+    /// return the value of a header with its encoded-words (rfc2047) decoded to utf8 if it exists.
+    /// Otherwise, returns an empty string.
+    #[rhai_fn(global, name = "get_decoded_header", return_raw, pure)]
+    pub fn get_decoded_header(message: &mut Message, header: &str) -> EngineResult<String> {
+        Ok(vsl_guard_ok!(message.read())
+            .get_header(header)
+            .map(|value| vsmtp_mail_parser::decode_header_value(&value))
+            .unwrap_or_default())
+    }
+
     /// Return a list of headers bearing the `name` given as argument.
     /// The `count` parameter specify the number of headers with the same name
     /// to return.
@@ -143,6 +153,60 @@ pub mod message {
     pub fn mail(this: &mut Message) -> EngineResult<String> {
         Ok(vsl_guard_ok!(this.read()).inner().to_string())
     }
+
+    /// list the attachments of the message, with their `filename`, `content_type`,
+    /// `size` and the `sha256` hash of their decoded content.
+    #[rhai_fn(global, name = "attachments", return_raw, pure)]
+    pub fn attachments(this: &mut Message) -> EngineResult<rhai::Array> {
+        let mut writer = vsl_guard_ok!(this.write());
+
+        Ok(vsmtp_mail_parser::get_attachments(vsl_parse_ok!(writer))
+            .map_err::<Box<rhai::EvalAltResult>, _>(|e| e.to_string().into())?
+            .into_iter()
+            .map(|attachment| {
+                rhai::Map::from_iter([
+                    (
+                        "filename".into(),
+                        attachment.filename.clone().unwrap_or_default().into(),
+                    ),
+                    (
+                        "content_type".into(),
+                        attachment.content_type.clone().into(),
+                    ),
+                    (
+                        "size".into(),
+                        rhai::INT::try_from(attachment.size())
+                            .unwrap_or(rhai::INT::MAX)
+                            .into(),
+                    ),
+                    ("sha256".into(), attachment.sha256().into()),
+                ])
+                .into()
+            })
+            .collect())
+    }
+
+    /// list the regular mime parts of the message, with their `content_type`, `filename`,
+    /// `is_attachment`, their decoded `content` and the `text` of the `text/*` parts in utf8.
+    #[rhai_fn(global, name = "parts", return_raw, pure)]
+    pub fn parts(this: &mut Message) -> EngineResult<rhai::Array> {
+        let mut writer = vsl_guard_ok!(this.write());
+
+        Ok(vsmtp_mail_parser::get_parts(vsl_parse_ok!(writer))
+            .map_err::<Box<rhai::EvalAltResult>, _>(|e| e.to_string().into())?
+            .into_iter()
+            .map(|part| {
+                rhai::Map::from_iter([
+                    ("content_type".into(), part.content_type.into()),
+                    ("filename".into(), part.filename.unwrap_or_default().into()),
+                    ("is_attachment".into(), part.is_attachment.into()),
+                    ("text".into(), part.text.unwrap_or_default().into()),
+                    ("content".into(), rhai::Dynamic::from_blob(part.content)),
+                ])
+                .into()
+            })
+            .collect())
+    }
 }
 
 #[allow(dead_code)]
@@ -432,4 +496,92 @@ mod test {
             "VALUE-2"
         );
     }
+
+    #[test]
+    fn test_attachments() {
+        let mut message = std::sync::Arc::new(std::sync::RwLock::new(
+            MessageBody::try_from(concat!(
+                "From: a@example.com\r\n",
+                "Date: Tue, 30 Nov 2021 20:54:27 +0100\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: multipart/mixed; boundary=\"frontier\"\r\n",
+                "\r\n",
+                "--frontier\r\n",
+                "Content-Type: text/plain\r\n",
+                "\r\n",
+                "see attached\r\n",
+                "--frontier\r\n",
+                "Content-Type: application/x-msdownload\r\n",
+                "Content-Disposition: attachment; filename=\"invoice.exe\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "c3VtbWVy\r\n",
+                "--frontier--\r\n",
+            ))
+            .unwrap(),
+        ));
+
+        let attachments = message::attachments(&mut message).unwrap();
+        assert_eq!(attachments.len(), 1);
+
+        let attachment = attachments[0].clone_cast::<rhai::Map>();
+        assert_eq!(attachment["filename"].to_string(), "invoice.exe");
+        assert_eq!(
+            attachment["content_type"].to_string(),
+            "application/x-msdownload"
+        );
+        assert_eq!(attachment["size"].as_int().unwrap(), 6);
+        assert_eq!(
+            attachment["sha256"].to_string(),
+            "e83664255c6963e962bb20f9fcfaad1b570ddf5da69f5444ed37e5260f3ef689"
+        );
+    }
+
+    #[test]
+    fn test_parts() {
+        let mut message = std::sync::Arc::new(std::sync::RwLock::new(
+            MessageBody::try_from(concat!(
+                "From: a@example.com\r\n",
+                "Date: Tue, 30 Nov 2021 20:54:27 +0100\r\n",
+                "Subject: =?utf-8?B?w6l0w6k=?= report\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: multipart/alternative; boundary=\"frontier\"\r\n",
+                "\r\n",
+                "--frontier\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "w6l0w6k=\r\n",
+                "--frontier\r\n",
+                "Content-Type: image/png\r\n",
+                "Content-Disposition: inline\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "iVBO\r\n",
+                "--frontier--\r\n",
+            ))
+            .unwrap(),
+        ));
+
+        assert_eq!(
+            message::get_decoded_header(&mut message, "Subject").unwrap(),
+            "été report"
+        );
+
+        let parts = message::parts(&mut message).unwrap();
+        assert_eq!(parts.len(), 2);
+
+        let text = parts[0].clone_cast::<rhai::Map>();
+        assert_eq!(text["content_type"].to_string(), "text/plain");
+        assert_eq!(text["text"].to_string(), "été");
+        assert!(!text["is_attachment"].as_bool().unwrap());
+
+        let image = parts[1].clone_cast::<rhai::Map>();
+        assert_eq!(image["content_type"].to_string(), "image/png");
+        assert_eq!(image["text"].to_string(), "");
+        assert_eq!(
+            image["content"].clone_cast::<rhai::Blob>(),
+            vec![0x89, b'P', b'N']
+        );
+    }
 }
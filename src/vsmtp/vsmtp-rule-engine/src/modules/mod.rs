@@ -36,7 +36,11 @@ mod inner {
 
             module
                 .combine(rhai::exported_module!(actions::logging::logging))
+                .combine(rhai::exported_module!(actions::arc::arc))
                 .combine(rhai::exported_module!(actions::dkim::dkim))
+                .combine(rhai::exported_module!(actions::dmarc::dmarc))
+                .combine(rhai::exported_module!(actions::greylist::greylist))
+                .combine(rhai::exported_module!(actions::oauth::oauth))
                 .combine(rhai::exported_module!(actions::rule_state::rule_state))
                 .combine(rhai::exported_module!(actions::security::security))
                 .combine(rhai::exported_module!(actions::services::services))
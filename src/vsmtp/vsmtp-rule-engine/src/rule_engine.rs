@@ -42,11 +42,15 @@ use crate::rule_state::RuleState;
 /// a sharable rhai engine.
 /// contains an ast representation of the user's parsed .vsl script files,
 /// and modules / packages to create a cheap rhai runtime.
+///
+/// cloning the engine is cheap, the clone is a snapshot of the rules which is
+/// not affected if the original engine is replaced by a reload.
+#[derive(Clone)]
 pub struct RuleEngine {
     /// ast built from the user's .vsl files.
-    pub(super) ast: AST,
+    pub(super) ast: rhai::Shared<AST>,
     /// rules & actions registered by the user.
-    pub(super) directives: Directives,
+    pub(super) directives: rhai::Shared<Directives>,
     /// vsl's standard rust api.
     pub(super) vsl_native_module: rhai::Shared<rhai::Module>,
     /// vsl's standard rhai api.
@@ -121,8 +125,8 @@ impl RuleEngine {
         log::debug!("done.");
 
         Ok(Self {
-            ast,
-            directives,
+            ast: rhai::Shared::new(ast),
+            directives: rhai::Shared::new(directives),
             vsl_native_module,
             vsl_rhai_module,
             std_module,
@@ -156,8 +160,8 @@ impl RuleEngine {
         let directives = Self::extract_directives(&compiler, &ast)?;
 
         Ok(Self {
-            ast,
-            directives,
+            ast: rhai::Shared::new(ast),
+            directives: rhai::Shared::new(directives),
             vsl_native_module,
             vsl_rhai_module,
             std_module,
@@ -165,6 +169,37 @@ impl RuleEngine {
         })
     }
 
+    /// close the sessions opened by the milter services for the smtp session of
+    /// the client at `client_addr` connected at `timestamp`, once its transaction
+    /// or its connection is over.
+    pub fn close_milter_sessions(
+        &self,
+        client_addr: std::net::SocketAddr,
+        timestamp: std::time::SystemTime,
+    ) {
+        // NOTE: the services are the variables of the modules imported by the rules.
+        fn close_in(module: &rhai::Module, key: &crate::dsl::service::milter::SessionKey) {
+            for (_, value) in module.iter_var() {
+                if let Some(service) = value.clone().try_cast::<std::sync::Arc<Service>>() {
+                    if let Service::Milter { sessions, .. } = &*service {
+                        if let Err(error) = crate::dsl::service::milter::close(sessions, key) {
+                            log::warn!("failed to close the session of {service}: {error}");
+                        }
+                    }
+                }
+            }
+            for (_, sub_module) in module.iter_sub_modules() {
+                close_in(sub_module, key);
+            }
+        }
+
+        if let Some(resolver) = self.ast.resolver() {
+            for (_, module) in resolver.iter() {
+                close_in(module, &(client_addr, timestamp));
+            }
+        }
+    }
+
     // FIXME: delegation handling to refactor.
     /// runs all rules from a stage using the current transaction state.
     ///
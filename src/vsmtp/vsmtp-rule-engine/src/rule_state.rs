@@ -60,6 +60,8 @@ impl RuleState {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::now(),
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: config.server.domain.clone(),
@@ -248,6 +250,10 @@ impl RuleState {
         let (mail_context, mail_message, skipped) = rule_state
             .take()
             .expect("should not have strong reference here");
+
+        // NOTE: the stages run outside of the smtp session end the transaction.
+        rule_engine
+            .close_milter_sessions(mail_context.client_addr, mail_context.connection.timestamp);
         Ok((mail_context, mail_message, result, skipped))
     }
 
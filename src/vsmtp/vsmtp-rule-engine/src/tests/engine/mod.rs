@@ -102,6 +102,8 @@ fn test_rule_state() {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::now(),
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: "testserver.com".to_string(),
@@ -112,6 +114,11 @@ fn test_rule_state() {
                 helo: "test".to_string(),
                 mail_from: vsmtp_common::addr!("a@a.a"),
                 rcpt: vec![],
+                declared_size: None,
+                dsn_return: None,
+                envelop_id: None,
+                smtp_utf8: false,
+                null_reverse_path: false,
             },
             metadata: None,
         },
@@ -50,6 +50,245 @@ fn test_greylist() {
     std::fs::remove_file(root_example!["greylist/greylist.csv"]).unwrap();
 }
 
+#[test]
+fn test_sql_database() {
+    use crate::dsl::service::databases::sql;
+
+    let database = root_example!["sql/users.db"];
+    std::fs::File::create(&database).unwrap();
+
+    let timeout = std::time::Duration::from_secs(5);
+    let pool = sql::Pool::new(
+        "users",
+        &format!("sqlite://{}", database.display()),
+        1,
+        timeout,
+    )
+    .unwrap();
+    sql::execute(
+        &pool,
+        "CREATE TABLE users (address TEXT PRIMARY KEY, quota INTEGER)",
+        timeout,
+        &[],
+    )
+    .unwrap();
+
+    let config = get_default_config("./tmp/app");
+    let resolvers = std::sync::Arc::new(std::collections::HashMap::new());
+    let run = || {
+        let re = RuleEngine::new(&config, &Some(root_example!["sql/main.vsl"])).unwrap();
+        let mut state = RuleState::new(&config, resolvers.clone(), &re);
+        re.run_when(&mut state, &StateSMTP::MailFrom)
+    };
+
+    // unknown sender, registered by the rule.
+    assert_eq!(run(), Status::Deny(ReplyOrCodeID::Left(CodeID::Denied)));
+    assert_eq!(run(), Status::Accept(ReplyOrCodeID::Left(CodeID::Ok)));
+
+    // no quota left, removed by the rule.
+    sql::execute(&pool, "UPDATE users SET quota = 0", timeout, &[]).unwrap();
+    assert_eq!(run(), Status::Deny(ReplyOrCodeID::Left(CodeID::Denied)));
+    assert!(sql::query(&pool, "SELECT * FROM users", timeout, &[])
+        .unwrap()
+        .is_empty());
+
+    std::fs::remove_file(database).unwrap();
+}
+
+#[test]
+fn test_key_value_databases() {
+    use crate::dsl::service::databases::{memcached, redis};
+
+    let (redis, redis_store, _) = redis::stand_in::serve(None);
+    let (memcached, _) = memcached::stand_in::serve();
+    let unreachable = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+
+    let dirpath = std::path::PathBuf::from("./tmp/key_value");
+    std::fs::create_dir_all(&dirpath).unwrap();
+    std::fs::write(
+        dirpath.join("db.vsl"),
+        format!(
+            r#"
+service redis db:redis = #{{ address: "{redis}", timeout: "5s", connections: 2 }};
+service memcached db:memcached = #{{ address: "{memcached}", timeout: "5s" }};
+service fail_open db:redis = #{{ address: "{unreachable}", failure: "open" }};
+service fail_closed db:memcached = #{{ address: "{unreachable}", failure: "closed" }};
+"#
+        ),
+    )
+    .unwrap();
+    std::fs::write(
+        dirpath.join("main.vsl"),
+        r#"
+import "db" as db;
+
+#{
+    mail: [
+        rule "key-value" || {
+            for kv in [db::redis, db::memcached] {
+                if kv.get("greylist").len() != 0 { return deny(); }
+                kv.set("greylist", "john.doe@example.com", "1h");
+                if kv.get("greylist")[0] != "john.doe@example.com" { return deny(); }
+
+                if kv.incr("counter") != 1 || kv.incr("counter", 4) != 5 { return deny(); }
+                if !kv.expire("counter", 60) || kv.expire("unknown", "1m") { return deny(); }
+
+                kv.rm("counter");
+                if kv.get("counter").len() != 0 { return deny(); }
+            }
+
+            if db::fail_open.get("greylist").len() != 0 || db::fail_open.incr("counter") != 0 {
+                return deny();
+            }
+            db::fail_open.set("greylist", "john.doe@example.com", 60);
+
+            let failed = false;
+            try { db::fail_closed.get("greylist"); } catch { failed = true; }
+
+            if failed { accept() } else { deny() }
+        },
+    ],
+}
+"#,
+    )
+    .unwrap();
+
+    let config = get_default_config("./tmp/app");
+    let re = RuleEngine::new(&config, &Some(dirpath.join("main.vsl"))).unwrap();
+    let resolvers = std::sync::Arc::new(std::collections::HashMap::new());
+    let mut state = RuleState::new(&config, resolvers, &re);
+
+    assert_eq!(
+        re.run_when(&mut state, &StateSMTP::MailFrom),
+        Status::Accept(ReplyOrCodeID::Left(CodeID::Ok)),
+    );
+    assert_eq!(redis_store.lock().unwrap()["greylist"].1, Some(3_600_000));
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
+
+#[test]
+fn test_ldap_service() {
+    use crate::dsl::service::ldap::stand_in;
+
+    let url = stand_in::serve();
+
+    let dirpath = std::path::PathBuf::from("./tmp/ldap");
+    std::fs::create_dir_all(&dirpath).unwrap();
+    std::fs::write(
+        dirpath.join("services.vsl"),
+        format!(
+            r#"
+service directory ldap = #{{
+    url: "{url}",
+    timeout: "5s",
+    bind: #{{ dn: "{}", password: "{}" }},
+    search: #{{
+        base: "ou=people,dc=example,dc=com",
+        filter: "(&(objectClass=inetOrgPerson)(mail=%u@%d))",
+        attributes: ["mail", "mailAlias"],
+    }},
+}};
+"#,
+            stand_in::SERVICE.0,
+            stand_in::SERVICE.1
+        ),
+    )
+    .unwrap();
+    std::fs::write(
+        dirpath.join("main.vsl"),
+        r#"
+import "services" as svc;
+
+#{
+    mail: [
+        rule "ldap" || {
+            let entries = svc::directory.get("john.doe@example.com");
+            if entries.len() != 1 || !("jdoe@example.com" in entries[0].mailAlias) {
+                return deny();
+            }
+            if svc::directory.get("jenny.doe@example.com").len() != 0 { return deny(); }
+
+            if svc::directory.bind("john.doe@example.com", "secret")
+                && !svc::directory.bind("john.doe@example.com", "wrong") {
+                accept()
+            } else {
+                deny()
+            }
+        },
+    ],
+}
+"#,
+    )
+    .unwrap();
+
+    let config = get_default_config("./tmp/app");
+    let re = RuleEngine::new(&config, &Some(dirpath.join("main.vsl"))).unwrap();
+    let resolvers = std::sync::Arc::new(std::collections::HashMap::new());
+    let mut state = RuleState::new(&config, resolvers, &re);
+
+    assert_eq!(
+        re.run_when(&mut state, &StateSMTP::MailFrom),
+        Status::Accept(ReplyOrCodeID::Left(CodeID::Ok)),
+    );
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
+
+#[test]
+fn test_greylisting() {
+    let dirpath = std::path::PathBuf::from("./tmp/greylisting");
+    std::fs::create_dir_all(&dirpath).unwrap();
+    std::fs::write(
+        dirpath.join("main.vsl"),
+        r#"
+#{
+    rcpt: [
+        rule "greylist" || greylist(),
+        rule "not greylisted" || accept(),
+    ],
+}
+"#,
+    )
+    .unwrap();
+
+    let config = get_default_config(dirpath.join("app"));
+    let re = RuleEngine::new(&config, &Some(dirpath.join("main.vsl"))).unwrap();
+    let resolvers = std::sync::Arc::new(std::collections::HashMap::new());
+
+    // the client retries too soon, the recipient is greylisted both times.
+    for _ in 0..2 {
+        let mut state = RuleState::new(&config, resolvers.clone(), &re);
+        {
+            let ctx = state.context();
+            let mut ctx = ctx.write().unwrap();
+            ctx.client_addr = "192.0.2.1:25".parse().unwrap();
+            ctx.envelop.mail_from = addr!("john.doe@example.com");
+            ctx.envelop
+                .rcpt
+                .push(Rcpt::new(addr!("jenny.doe@testserver.com")));
+        }
+
+        assert_eq!(
+            re.run_when(&mut state, &StateSMTP::RcptTo),
+            Status::Info(ReplyOrCodeID::Left(CodeID::Greylisted))
+        );
+        assert!(state.context().read().unwrap().envelop.rcpt.is_empty());
+    }
+
+    // the greylisting can only be done at the rcpt stage.
+    let mut state = RuleState::new(&config, resolvers, &re);
+    assert!(matches!(
+        re.run_when(&mut state, &StateSMTP::RcptTo),
+        Status::Deny(_)
+    ));
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
+
 // TODO: add more test cases for this example.
 #[test]
 fn test_check_relay() {
@@ -106,3 +345,108 @@ fn test_check_relay() {
 
     assert_eq!(re.run_when(&mut state, &StateSMTP::RcptTo), Status::Next);
 }
+
+#[test]
+fn test_verify() {
+    let config = get_default_config("./tmp/app");
+    let resolvers = std::sync::Arc::new(std::collections::HashMap::new());
+
+    for (stage, query, expected) in [
+        (
+            StateSMTP::Vrfy,
+            "john",
+            Some("250 2.1.5 <john.doe@example.com>\r\n"),
+        ),
+        (StateSMTP::Vrfy, "staff", None),
+        (
+            StateSMTP::Expn,
+            "staff",
+            Some("250-2.1.5 <john.doe@example.com>\r\n250 2.1.5 <jenny.doe@example.com>\r\n"),
+        ),
+        (
+            StateSMTP::Expn,
+            "nobody",
+            Some("550 5.1.1 Bad destination mailbox address\r\n"),
+        ),
+    ] {
+        let re = RuleEngine::new(&config, &Some(root_example!["verify/main.vsl"])).unwrap();
+        let mut state = RuleState::new(&config, resolvers.clone(), &re);
+        state.context().write().unwrap().connection.verify_query = Some(query.to_string());
+
+        match (re.run_when(&mut state, &stage), expected) {
+            (Status::Info(ReplyOrCodeID::Right(reply)), Some(expected)) => {
+                assert_eq!(reply.fold(), expected);
+            }
+            (Status::Next, None) => {}
+            (status, expected) => panic!("{stage} '{query}': {status:?}, expected {expected:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_oauth() {
+    use crate::modules::actions::oauth::tests::{expires_in, key_pair, token};
+    use vsmtp_common::{auth::Credentials, auth::Mechanism, re::serde_json};
+
+    let dirpath = std::path::PathBuf::from("./tmp/oauth");
+    std::fs::create_dir_all(&dirpath).unwrap();
+
+    // the example is run with a key generated for the test.
+    let (key, jwks) = key_pair("2022-11");
+    std::fs::write(
+        dirpath.join("jwks.json"),
+        serde_json::to_string(&jwks).unwrap(),
+    )
+    .unwrap();
+    std::fs::write(
+        dirpath.join("main.vsl"),
+        std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("../../../examples/config/oauth/main.vsl"),
+        )
+        .unwrap()
+        .replace(
+            "/etc/vsmtp/oauth/jwks.json",
+            dirpath.join("jwks.json").to_str().unwrap(),
+        ),
+    )
+    .unwrap();
+
+    let config = get_default_config(dirpath.join("app"));
+    let re = RuleEngine::new(&config, &Some(dirpath.join("main.vsl"))).unwrap();
+    let resolvers = std::sync::Arc::new(std::collections::HashMap::new());
+
+    let valid = token(
+        &key,
+        Some("2022-11"),
+        &serde_json::json!({ "sub": "john.doe", "exp": expires_in(3600) }),
+    );
+
+    for (authid, token, accepted) in [
+        ("john.doe", valid.as_str(), true),
+        ("jenny.doe", valid.as_str(), false),
+        (
+            "john.doe",
+            "vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==",
+            false,
+        ),
+    ] {
+        let mut state = RuleState::new(&config, resolvers.clone(), &re);
+        state.context().write().unwrap().connection.credentials = Some(Credentials::BearerToken {
+            authid: authid.to_string(),
+            token: token.to_string(),
+        });
+
+        let status = re.run_when(
+            &mut state,
+            &StateSMTP::Authenticate(Mechanism::OAuthBearer, None),
+        );
+        assert_eq!(
+            matches!(status, Status::Accept(_)),
+            accepted,
+            "{authid}: {status:?}"
+        );
+    }
+
+    std::fs::remove_dir_all(dirpath).unwrap();
+}
@@ -0,0 +1,59 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+
+//! Enable the SASL properties defined by the installed gsasl.
+//!
+//! The bindings of gsasl are generated from its headers with the `gsasl_bindgen`
+//! feature, the properties added in recent versions are used only if the library
+//! defines them. The stale bindings are those of gsasl 1.8.
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    println!("cargo:rustc-check-cfg=cfg(gsasl_scram_keys)");
+    println!("cargo:rustc-check-cfg=cfg(gsasl_tls_exporter)");
+
+    if std::env::var_os("CARGO_FEATURE_GSASL_BINDGEN").is_none() {
+        return;
+    }
+
+    let library = match pkg_config::Config::new()
+        .cargo_metadata(false)
+        .probe("libgsasl")
+    {
+        Ok(library) => library,
+        Err(error) => {
+            println!("cargo:warning=cannot find the version of gsasl: {error}");
+            return;
+        }
+    };
+
+    let version = library
+        .version
+        .split('.')
+        .map(|i| i.parse::<u32>().unwrap_or_default())
+        .chain(std::iter::repeat(0))
+        .take(2)
+        .collect::<Vec<_>>();
+
+    if version[..] >= [1, 10][..] {
+        println!("cargo:rustc-cfg=gsasl_scram_keys");
+    }
+    if version[..] >= [2, 2][..] {
+        println!("cargo:rustc-cfg=gsasl_tls_exporter");
+    }
+}
@@ -15,53 +15,125 @@
  *
 */
 use vsmtp_common::{
-    auth::Credentials, auth::Mechanism, mail_context::ConnectionContext, re::vsmtp_rsasl,
-    state::StateSMTP, status::Status,
+    auth::{Credentials, Mechanism, ScramSecrets},
+    mail_context::ConnectionContext,
+    re::vsmtp_rsasl,
+    state::StateSMTP,
+    status::Status,
 };
 use vsmtp_config::{Config, Resolvers};
 use vsmtp_rule_engine::{rule_engine::RuleEngine, rule_state::RuleState};
 
 /// Backend of SASL implementation
-pub type Backend = vsmtp_rsasl::DiscardOnDrop<
-    vsmtp_rsasl::SASL<
-        std::sync::Arc<Config>,
-        (
-            std::sync::Arc<std::sync::RwLock<RuleEngine>>,
-            std::sync::Arc<Resolvers>,
-            ConnectionContext,
-        ),
-    >,
->;
+pub type Backend =
+    vsmtp_rsasl::DiscardOnDrop<vsmtp_rsasl::SASL<std::sync::Arc<Config>, SessionData>>;
 
 /// SASL session data.
-pub type Session = vsmtp_rsasl::Session<(
-    std::sync::Arc<std::sync::RwLock<RuleEngine>>,
-    std::sync::Arc<Resolvers>,
-    ConnectionContext,
-)>;
+pub type Session = vsmtp_rsasl::Session<SessionData>;
+
+/// Data of the connection available while processing a SASL exchange.
+pub struct SessionData {
+    /// rule engine running the `authenticate` stage
+    pub rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
+    /// resolvers used by the rules
+    pub resolvers: std::sync::Arc<Resolvers>,
+    /// connection of the client
+    pub conn: ConnectionContext,
+    /// mechanism requested by the client
+    pub mechanism: Mechanism,
+    /// `tls-exporter` channel binding of the connection
+    /// see <https://datatracker.ietf.org/doc/html/rfc9266>
+    pub channel_binding: Option<Vec<u8>>,
+    /// keys returned by the `authenticate` stage for a SCRAM mechanism,
+    /// `None` if the rules have not been queried yet.
+    pub scram_secrets: Option<Option<ScramSecrets>>,
+}
+
+fn get_authid(session: &mut Session) -> Result<String, vsmtp_rsasl::ReturnCode> {
+    Ok(session
+        .get_property(vsmtp_rsasl::Property::GSASL_AUTHID)
+        .ok_or(vsmtp_rsasl::ReturnCode::GSASL_NO_AUTHID)?
+        .to_str()
+        .unwrap()
+        .to_string())
+}
+
+/// Run the `authenticate` stage with the credentials of the client.
+pub(crate) fn run_authenticate(
+    config: &Config,
+    data: &SessionData,
+    credentials: Credentials,
+) -> Result<Status, vsmtp_rsasl::ReturnCode> {
+    let mut conn = data.conn.clone();
+    conn.credentials = Some(credentials);
+
+    let re = data
+        .rule_engine
+        .read()
+        .map_err(|_| vsmtp_rsasl::ReturnCode::GSASL_INTEGRITY_ERROR)?;
+
+    let mut rule_state = RuleState::with_connection(config, data.resolvers.clone(), &re, conn);
+
+    Ok(re.run_when(
+        &mut rule_state,
+        &StateSMTP::Authenticate(Mechanism::default(), None),
+    ))
+}
+
+/// Query the salted keys of the user with the `authenticate` stage, only once per exchange.
+///
+/// If the rules do not produce valid keys, the properties are not provided and
+/// gsasl falls back on the `GSASL_PASSWORD` property.
+fn scram_property(
+    config: &Config,
+    session: &mut Session,
+    prop: vsmtp_rsasl::Property,
+) -> Result<(), vsmtp_rsasl::ReturnCode> {
+    let authid = get_authid(session)?;
+
+    let data = session
+        .retrieve_mut()
+        .ok_or(vsmtp_rsasl::ReturnCode::GSASL_INTEGRITY_ERROR)?;
+
+    if data.scram_secrets.is_none() {
+        let mechanism = data.mechanism;
+        let result = run_authenticate(config, data, Credentials::ScramQuery { authid, mechanism })?;
+
+        data.scram_secrets = Some(match result {
+            Status::Packet(secrets) => secrets
+                .parse::<ScramSecrets>()
+                .ok()
+                .filter(|secrets| secrets.can_be_used_with(mechanism)),
+            _ => None,
+        });
+    }
+
+    let secrets = data
+        .scram_secrets
+        .clone()
+        .flatten()
+        .ok_or(vsmtp_rsasl::ReturnCode::GSASL_NO_CALLBACK)?;
+
+    let value = match prop {
+        vsmtp_rsasl::Property::GSASL_SCRAM_ITER => secrets.iterations.to_string(),
+        vsmtp_rsasl::Property::GSASL_SCRAM_SALT => secrets.salt,
+        #[cfg(gsasl_scram_keys)]
+        vsmtp_rsasl::Property::GSASL_SCRAM_SERVERKEY => secrets.server_key,
+        #[cfg(gsasl_scram_keys)]
+        vsmtp_rsasl::Property::GSASL_SCRAM_STOREDKEY => secrets.stored_key,
+        _ => return Err(vsmtp_rsasl::ReturnCode::GSASL_NO_CALLBACK),
+    };
+
+    session.set_property(prop, value.as_bytes());
+    Ok(())
+}
 
 /// Function called by the SASL backend
 pub struct Callback;
 
-impl
-    vsmtp_rsasl::Callback<
-        std::sync::Arc<Config>,
-        (
-            std::sync::Arc<std::sync::RwLock<RuleEngine>>,
-            std::sync::Arc<Resolvers>,
-            ConnectionContext,
-        ),
-    > for Callback
-{
+impl vsmtp_rsasl::Callback<std::sync::Arc<Config>, SessionData> for Callback {
     fn callback(
-        sasl: &mut vsmtp_rsasl::SASL<
-            std::sync::Arc<Config>,
-            (
-                std::sync::Arc<std::sync::RwLock<RuleEngine>>,
-                std::sync::Arc<Resolvers>,
-                ConnectionContext,
-            ),
-        >,
+        sasl: &mut vsmtp_rsasl::SASL<std::sync::Arc<Config>, SessionData>,
         session: &mut Session,
         prop: vsmtp_rsasl::Property,
     ) -> Result<(), vsmtp_rsasl::ReturnCode> {
@@ -72,20 +144,10 @@ impl
 
         let credentials = match prop {
             vsmtp_rsasl::Property::GSASL_PASSWORD => Credentials::Query {
-                authid: session
-                    .get_property(vsmtp_rsasl::Property::GSASL_AUTHID)
-                    .ok_or(vsmtp_rsasl::ReturnCode::GSASL_NO_AUTHID)?
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
+                authid: get_authid(session)?,
             },
             vsmtp_rsasl::Property::GSASL_VALIDATE_SIMPLE => Credentials::Verify {
-                authid: session
-                    .get_property(vsmtp_rsasl::Property::GSASL_AUTHID)
-                    .ok_or(vsmtp_rsasl::ReturnCode::GSASL_NO_AUTHID)?
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
+                authid: get_authid(session)?,
                 authpass: session
                     .get_property(vsmtp_rsasl::Property::GSASL_PASSWORD)
                     .ok_or(vsmtp_rsasl::ReturnCode::GSASL_NO_PASSWORD)?
@@ -101,28 +163,39 @@ impl
                     .unwrap()
                     .to_string(),
             },
+            vsmtp_rsasl::Property::GSASL_SCRAM_ITER | vsmtp_rsasl::Property::GSASL_SCRAM_SALT => {
+                return scram_property(&config, session, prop);
+            }
+            // NOTE: these properties were added in gsasl 1.10 & 2.2, the version of the
+            //       installed library is checked by the build script.
+            #[cfg(gsasl_scram_keys)]
+            vsmtp_rsasl::Property::GSASL_SCRAM_SERVERKEY
+            | vsmtp_rsasl::Property::GSASL_SCRAM_STOREDKEY => {
+                return scram_property(&config, session, prop);
+            }
+            #[cfg(gsasl_tls_exporter)]
+            vsmtp_rsasl::Property::GSASL_CB_TLS_EXPORTER => {
+                let channel_binding = session
+                    .retrieve_mut()
+                    .ok_or(vsmtp_rsasl::ReturnCode::GSASL_INTEGRITY_ERROR)?
+                    .channel_binding
+                    .as_ref()
+                    .map(vsmtp_common::re::base64::encode)
+                    .ok_or(vsmtp_rsasl::ReturnCode::GSASL_NO_CB_TLS_UNIQUE)?;
+
+                session.set_property(prop, channel_binding.as_bytes());
+                return Ok(());
+            }
             _ => return Err(vsmtp_rsasl::ReturnCode::GSASL_NO_CALLBACK),
         };
 
-        let (rule_engine, resolvers, conn) = session
-            .retrieve_mut()
-            .ok_or(vsmtp_rsasl::ReturnCode::GSASL_INTEGRITY_ERROR)?;
-
-        let mut conn = conn.clone();
-        conn.credentials = Some(credentials);
-
-        let result = {
-            let re = rule_engine
-                .read()
-                .map_err(|_| vsmtp_rsasl::ReturnCode::GSASL_INTEGRITY_ERROR)?;
-
-            let mut rule_state = RuleState::with_connection(&config, resolvers.clone(), &re, conn);
-
-            re.run_when(
-                &mut rule_state,
-                &StateSMTP::Authenticate(Mechanism::default(), None),
-            )
-        };
+        let result = run_authenticate(
+            &config,
+            session
+                .retrieve_mut()
+                .ok_or(vsmtp_rsasl::ReturnCode::GSASL_INTEGRITY_ERROR)?,
+            credentials,
+        )?;
 
         match prop {
             vsmtp_rsasl::Property::GSASL_VALIDATE_SIMPLE
@@ -0,0 +1,751 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+ */
+use std::fmt::Write;
+use time::format_description::well_known::Rfc2822;
+use vsmtp_common::{
+    dsn::{encode_xtext, DsnReturn},
+    envelop::Envelop,
+    mail_context::{ConnectionContext, MailContext, MessageMetadata},
+    queue::Queue,
+    rcpt::Rcpt,
+    re::{anyhow, log},
+    transfer::{EmailTransferStatus, Transfer, TransferErrors},
+    Address, MessageBody,
+};
+use vsmtp_config::Config;
+
+/// The action performed by the server for a recipient, see RFC 3464 section 2.3.3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Failed,
+    Delayed,
+    Delivered,
+    Relayed,
+}
+
+impl Action {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Failed => "failed",
+            Self::Delayed => "delayed",
+            Self::Delivered => "delivered",
+            Self::Relayed => "relayed",
+        }
+    }
+}
+
+/// Get the subject and detail of the enhanced status code found in a reason, see RFC 3463,
+/// as in the reply "550 5.1.1 <bob@example.com>: user unknown" of a remote server.
+fn status_detail(reason: &str) -> Option<&str> {
+    reason.split_whitespace().find_map(|word| {
+        let code = word.trim_matches(|c: char| !c.is_ascii_digit());
+        let parts = code.split('.').collect::<Vec<_>>();
+
+        match parts.as_slice() {
+            [class, subject, detail]
+                if matches!(*class, "2" | "4" | "5")
+                    && [subject, detail].iter().all(|i| {
+                        (1..=3).contains(&i.len()) && i.bytes().all(|c| c.is_ascii_digit())
+                    }) =>
+            {
+                Some(&code[2..])
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Get the enhanced status code of a report, the class being given by the action.
+fn enhanced_status(class: u8, reason: Option<&str>) -> String {
+    format!(
+        "{class}.{}",
+        reason.and_then(status_detail).unwrap_or("0.0")
+    )
+}
+
+fn transfer_error_to_string(error: &TransferErrors) -> String {
+    match error {
+        TransferErrors::NoSuchMailbox { name } => format!("mailbox `{name}` does not exist"),
+        TransferErrors::Other(reason) => reason.clone(),
+    }
+}
+
+/// What is reported for a recipient, see RFC 3464 section 2.3
+struct Report<'a> {
+    rcpt: &'a Rcpt,
+    action: Action,
+    status: String,
+    reason: Option<String>,
+}
+
+/// Get the report of a recipient, with the reason of the failure / delay if any.
+///
+/// Only the statuses set since `attempt` are reported, and a delay is reported only once.
+fn recipient_report(rcpt: &Rcpt, attempt: std::time::SystemTime) -> Option<Report<'_>> {
+    let notify = rcpt.notify.unwrap_or_default();
+
+    let (action, status, reason) = match &rcpt.email_status {
+        EmailTransferStatus::Failed { timestamp, reason }
+            if *timestamp >= attempt && notify.on_failure() =>
+        {
+            (
+                Action::Failed,
+                enhanced_status(5, Some(reason)),
+                Some(reason.clone()),
+            )
+        }
+        EmailTransferStatus::HeldBack { errors }
+            if matches!(errors.as_slice(), [(timestamp, _)] if *timestamp >= attempt)
+                && notify.on_delay() =>
+        {
+            let error = errors.last().map(|(_, error)| error);
+            (
+                Action::Delayed,
+                match error {
+                    Some(TransferErrors::NoSuchMailbox { .. }) => "4.1.1".to_string(),
+                    Some(TransferErrors::Other(reason)) => enhanced_status(4, Some(reason)),
+                    None => enhanced_status(4, None),
+                },
+                error.map(transfer_error_to_string),
+            )
+        }
+        EmailTransferStatus::Sent { timestamp } if *timestamp >= attempt && notify.on_success() => {
+            (
+                match rcpt.transfer_method {
+                    Transfer::Mbox | Transfer::Maildir => Action::Delivered,
+                    _ => Action::Relayed,
+                },
+                enhanced_status(2, None),
+                None,
+            )
+        }
+        _ => return None,
+    };
+
+    Some(Report {
+        rcpt,
+        action,
+        status,
+        reason,
+    })
+}
+
+pub(super) fn format_date(date: std::time::SystemTime) -> anyhow::Result<String> {
+    Ok(time::OffsetDateTime::from(date).format(&Rfc2822)?)
+}
+
+pub(super) fn generate_id(now: std::time::SystemTime) -> String {
+    format!(
+        "{}{}{}",
+        now.duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .expect("did went back in time")
+            .as_micros(),
+        std::iter::repeat_with(fastrand::alphanumeric)
+            .take(36)
+            .collect::<String>(),
+        std::process::id()
+    )
+}
+
+/// Create the machine readable part of the notification, see RFC 3464 section 2
+fn create_delivery_status(
+    config: &Config,
+    ctx: &MailContext,
+    reports: &[Report<'_>],
+) -> anyhow::Result<String> {
+    let mut delivery_status = format!("Reporting-MTA: dns; {}\r\n", config.server.domain);
+    if let Some(envelop_id) = &ctx.envelop.envelop_id {
+        write!(
+            delivery_status,
+            "Original-Envelope-Id: {}\r\n",
+            encode_xtext(envelop_id)
+        )?;
+    }
+    if let Some(metadata) = &ctx.metadata {
+        write!(
+            delivery_status,
+            "Arrival-Date: {}\r\n",
+            format_date(metadata.timestamp)?
+        )?;
+    }
+
+    for Report {
+        rcpt,
+        action,
+        status,
+        reason,
+    } in reports
+    {
+        delivery_status.push_str("\r\n");
+        if let Some(original_recipient) = &rcpt.original_recipient {
+            write!(
+                delivery_status,
+                "Original-Recipient: {original_recipient}\r\n"
+            )?;
+        }
+        write!(
+            delivery_status,
+            "Final-Recipient: rfc822; {}\r\nAction: {}\r\nStatus: {}\r\n",
+            rcpt.address,
+            action.as_str(),
+            status
+        )?;
+        if let Some(reason) = reason {
+            write!(delivery_status, "Diagnostic-Code: X-VSMTP; {reason}\r\n")?;
+        }
+    }
+
+    Ok(delivery_status)
+}
+
+/// Create the human readable part of the notification.
+fn create_explanation(
+    config: &Config,
+    has_action: &impl Fn(Action) -> bool,
+) -> anyhow::Result<String> {
+    let mut explanation = format!(
+        "This is the mail system at host {}.\r\n",
+        config.server.domain
+    );
+    for (action, text) in [
+        (
+            Action::Failed,
+            "Your message could not be delivered to one or more recipients.",
+        ),
+        (
+            Action::Delayed,
+            "Delivery of your message has been delayed to one or more recipients, the system will keep trying.",
+        ),
+        (
+            Action::Delivered,
+            "Your message has been delivered to one or more recipients.",
+        ),
+        (
+            Action::Relayed,
+            "Your message has been relayed to one or more recipients, no further notification will be sent.",
+        ),
+    ] {
+        if has_action(action) {
+            write!(explanation, "\r\n{text}\r\n")?;
+        }
+    }
+
+    Ok(explanation)
+}
+
+/// Get the content type and the content of the message returned to the sender.
+fn create_returned_content(
+    ctx: &MailContext,
+    message: &MessageBody,
+    has_failure: bool,
+) -> (&'static str, String) {
+    // NOTE: the whole message is returned only on failure, see RFC 3461 section 4.3,
+    // and only the headers of a binary message (`BODY=BINARYMIME`) are returned.
+    if has_failure
+        && ctx.envelop.dsn_return == Some(DsnReturn::Full)
+        && message.inner().body().is_some()
+    {
+        ("message/rfc822", message.inner().to_string())
+    } else {
+        (
+            "text/rfc822-headers",
+            message
+                .inner()
+                .headers_lines()
+                .fold(String::new(), |acc, line| acc + line + "\r\n"),
+        )
+    }
+}
+
+/// Create the notification for the delivery attempt of a message started at `attempt`,
+/// or `None` if the sender did not ask for it.
+fn create_notification(
+    config: &Config,
+    ctx: &MailContext,
+    message: &MessageBody,
+    attempt: std::time::SystemTime,
+) -> anyhow::Result<Option<(MailContext, MessageBody)>> {
+    let mailer_daemon = Address::new_unchecked(format!("mailer-daemon@{}", config.server.domain));
+
+    // NOTE: notifications are never sent about a notification, to prevent loops.
+    if ctx.envelop.null_reverse_path
+        || ctx
+            .envelop
+            .mail_from
+            .full()
+            .eq_ignore_ascii_case(mailer_daemon.full())
+    {
+        return Ok(None);
+    }
+
+    let reports = ctx
+        .envelop
+        .rcpt
+        .iter()
+        .filter_map(|rcpt| recipient_report(rcpt, attempt))
+        .collect::<Vec<_>>();
+
+    if reports.is_empty() {
+        return Ok(None);
+    }
+
+    let now = std::time::SystemTime::now();
+    let has_action = |action| reports.iter().any(|report| report.action == action);
+    let has_failure = has_action(Action::Failed);
+
+    let delivery_status = create_delivery_status(config, ctx, &reports)?;
+    let explanation = create_explanation(config, &has_action)?;
+
+    let returned_content = create_returned_content(ctx, message, has_failure);
+
+    let boundary = format!("{}/{}", generate_id(now), config.server.domain);
+    let body = format!(
+        "This is a MIME-encapsulated message.\r\n\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        {explanation}\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: message/delivery-status\r\n\
+        \r\n\
+        {delivery_status}\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: {}\r\n\
+        \r\n\
+        {}\
+        \r\n\
+        --{boundary}--\r\n",
+        returned_content.0, returned_content.1
+    );
+
+    let message_id = generate_id(now);
+    let headers = vec![
+        format!("From: Mail Delivery System <{mailer_daemon}>"),
+        format!("To: {}", ctx.envelop.mail_from),
+        format!(
+            "Subject: {}",
+            if has_failure {
+                "Undelivered Mail Returned to Sender"
+            } else if has_action(Action::Delayed) {
+                "Delayed Mail (still being retried)"
+            } else {
+                "Successful Mail Delivery Report"
+            }
+        ),
+        format!("Date: {}", format_date(now)?),
+        format!("Message-ID: <{message_id}@{}>", config.server.domain),
+        "Auto-Submitted: auto-replied".to_string(),
+        "MIME-Version: 1.0".to_string(),
+        format!(
+            "Content-Type: multipart/report; report-type=delivery-status; boundary=\"{boundary}\""
+        ),
+    ];
+
+    Ok(Some((
+        MailContext {
+            connection: ConnectionContext {
+                timestamp: now,
+                credentials: None,
+                verify_query: None,
+                client_certificate: None,
+                is_authenticated: false,
+                is_secured: false,
+                server_name: config.server.domain.clone(),
+                server_address: ctx.connection.server_address,
+            },
+            client_addr: ctx.connection.server_address,
+            envelop: Envelop {
+                helo: config.server.domain.clone(),
+                mail_from: mailer_daemon,
+                rcpt: vec![Rcpt::new(ctx.envelop.mail_from.clone())],
+                null_reverse_path: true,
+                ..Envelop::default()
+            },
+            metadata: Some(MessageMetadata {
+                timestamp: now,
+                message_id,
+                skipped: None,
+            }),
+        },
+        MessageBody::new(headers, body),
+    )))
+}
+
+/// Send a delivery status notification to the sender of a message, if requested,
+/// about the result of the delivery attempt started at `attempt`.
+///
+/// The notification is written in the deferred queue, to be sent with the next flush.
+///
+/// # Errors
+///
+/// * failed to create the notification.
+/// * failed to write the notification in the deferred queue.
+pub fn notify_sender(
+    config: &Config,
+    ctx: &MailContext,
+    message: &MessageBody,
+    attempt: std::time::SystemTime,
+) -> anyhow::Result<()> {
+    if let Some((notification_ctx, notification)) =
+        create_notification(config, ctx, message, attempt)?
+    {
+        let message_id = &notification_ctx.metadata.as_ref().unwrap().message_id;
+
+        notification.write_to_mails(&config.server.queues.dirpath, message_id)?;
+        Queue::Deferred.write_to_queue(&config.server.queues.dirpath, &notification_ctx)?;
+
+        log::info!(
+            "delivery status notification '{message_id}' sent to '{}'",
+            ctx.envelop.mail_from
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_common::{
+        addr,
+        dsn::{NotifyOn, OriginalRecipient},
+    };
+    use vsmtp_test::config;
+
+    fn get_context(envelop: Envelop) -> MailContext {
+        MailContext {
+            connection: ConnectionContext {
+                timestamp: std::time::SystemTime::now(),
+                credentials: None,
+                verify_query: None,
+                client_certificate: None,
+                is_authenticated: false,
+                is_secured: false,
+                server_name: "testserver.com".to_string(),
+                server_address: "127.0.0.1:25".parse().unwrap(),
+            },
+            client_addr: "127.0.0.1:5977".parse().unwrap(),
+            envelop,
+            metadata: Some(MessageMetadata {
+                timestamp: std::time::SystemTime::UNIX_EPOCH,
+                message_id: "dsn_test".to_string(),
+                skipped: None,
+            }),
+        }
+    }
+
+    fn get_message() -> MessageBody {
+        MessageBody::try_from(concat!(
+            "From: john@doe.com\r\n",
+            "Subject: hello\r\n",
+            "\r\n",
+            "Hello world\r\n"
+        ))
+        .unwrap()
+    }
+
+    fn get_envelop(rcpt: Vec<Rcpt>) -> Envelop {
+        Envelop {
+            helo: "client.com".to_string(),
+            mail_from: addr!("john@doe.com"),
+            rcpt,
+            ..Envelop::default()
+        }
+    }
+
+    fn failed(address: Address, notify: Option<NotifyOn>) -> Rcpt {
+        Rcpt {
+            email_status: EmailTransferStatus::Failed {
+                timestamp: std::time::SystemTime::now(),
+                reason: "mailbox unavailable".to_string(),
+            },
+            notify,
+            ..Rcpt::new(address)
+        }
+    }
+
+    #[test]
+    fn failure() {
+        let config = config::local_test();
+        let attempt = std::time::SystemTime::now();
+
+        let ctx = get_context(Envelop {
+            envelop_id: Some("QQ 314159".to_string()),
+            ..get_envelop(vec![
+                Rcpt {
+                    original_recipient: Some(OriginalRecipient {
+                        addr_type: "rfc822".to_string(),
+                        mailbox: "Bob@example.com".to_string(),
+                    }),
+                    ..failed(addr!("bob@example.com"), None)
+                },
+                Rcpt {
+                    email_status: EmailTransferStatus::Sent {
+                        timestamp: std::time::SystemTime::now(),
+                    },
+                    ..Rcpt::new(addr!("alice@example.com"))
+                },
+            ])
+        });
+
+        let (notification_ctx, notification) =
+            create_notification(&config, &ctx, &get_message(), attempt)
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(
+            notification_ctx.envelop.mail_from,
+            addr!("mailer-daemon@testserver.com")
+        );
+        assert!(notification_ctx.envelop.null_reverse_path);
+        assert_eq!(notification_ctx.envelop.rcpt.len(), 1);
+        assert_eq!(
+            notification_ctx.envelop.rcpt[0].address,
+            addr!("john@doe.com")
+        );
+
+        let notification = notification.inner().to_string();
+        assert!(notification.contains("Subject: Undelivered Mail Returned to Sender\r\n"));
+        assert!(notification.contains("Auto-Submitted: auto-replied\r\n"));
+        assert!(notification.contains(concat!(
+            "Reporting-MTA: dns; testserver.com\r\n",
+            "Original-Envelope-Id: QQ+20314159\r\n",
+            "Arrival-Date: Thu, 01 Jan 1970 00:00:00 +0000\r\n",
+            "\r\n",
+            "Original-Recipient: rfc822; Bob@example.com\r\n",
+            "Final-Recipient: rfc822; bob@example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.0.0\r\n",
+            "Diagnostic-Code: X-VSMTP; mailbox unavailable\r\n",
+        )));
+        assert!(!notification.contains("alice@example.com"));
+        assert!(notification.contains(concat!(
+            "Content-Type: text/rfc822-headers\r\n",
+            "\r\n",
+            "From: john@doe.com\r\n",
+            "Subject: hello\r\n",
+        )));
+        assert!(!notification.contains("Hello world"));
+    }
+
+    #[test]
+    fn failure_return_full() {
+        let config = config::local_test();
+        let attempt = std::time::SystemTime::now();
+
+        let ctx = get_context(Envelop {
+            dsn_return: Some(DsnReturn::Full),
+            ..get_envelop(vec![failed(addr!("bob@example.com"), None)])
+        });
+
+        let (_, notification) = create_notification(&config, &ctx, &get_message(), attempt)
+            .unwrap()
+            .unwrap();
+
+        let notification = notification.inner().to_string();
+        assert!(notification.contains("Content-Type: message/rfc822\r\n"));
+        assert!(notification.contains("Hello world\r\n"));
+    }
+
+    #[test]
+    fn not_requested() {
+        let config = config::local_test();
+        let attempt = std::time::SystemTime::now();
+
+        for rcpt in [
+            failed(addr!("bob@example.com"), Some(NotifyOn::Never)),
+            failed(
+                addr!("bob@example.com"),
+                Some(NotifyOn::Some {
+                    success: true,
+                    failure: false,
+                    delay: true,
+                }),
+            ),
+            Rcpt {
+                email_status: EmailTransferStatus::Sent {
+                    timestamp: std::time::SystemTime::now(),
+                },
+                ..Rcpt::new(addr!("bob@example.com"))
+            },
+            Rcpt {
+                email_status: EmailTransferStatus::HeldBack {
+                    errors: vec![(
+                        std::time::SystemTime::now(),
+                        TransferErrors::Other("connection refused".to_string()),
+                    )],
+                },
+                ..Rcpt::new(addr!("bob@example.com"))
+            },
+        ] {
+            let ctx = get_context(get_envelop(vec![rcpt]));
+            assert!(create_notification(&config, &ctx, &get_message(), attempt)
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn previous_attempt() {
+        let config = config::local_test();
+
+        let ctx = get_context(get_envelop(vec![failed(addr!("bob@example.com"), None)]));
+        let attempt = std::time::SystemTime::now() + std::time::Duration::from_secs(1);
+
+        assert!(create_notification(&config, &ctx, &get_message(), attempt)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn delay_and_success() {
+        let config = config::local_test();
+        let attempt = std::time::SystemTime::now();
+        let notify = Some(NotifyOn::Some {
+            success: true,
+            failure: true,
+            delay: true,
+        });
+
+        let ctx = get_context(get_envelop(vec![
+            Rcpt {
+                email_status: EmailTransferStatus::HeldBack {
+                    errors: vec![(
+                        std::time::SystemTime::now(),
+                        TransferErrors::NoSuchMailbox {
+                            name: "bob".to_string(),
+                        },
+                    )],
+                },
+                notify,
+                ..Rcpt::new(addr!("bob@example.com"))
+            },
+            Rcpt {
+                email_status: EmailTransferStatus::Sent {
+                    timestamp: std::time::SystemTime::now(),
+                },
+                notify,
+                ..Rcpt::with_transfer_method(addr!("alice@example.com"), Transfer::Maildir)
+            },
+            Rcpt {
+                email_status: EmailTransferStatus::Sent {
+                    timestamp: std::time::SystemTime::now(),
+                },
+                notify,
+                ..Rcpt::new(addr!("carol@example.com"))
+            },
+        ]));
+
+        let (_, notification) = create_notification(&config, &ctx, &get_message(), attempt)
+            .unwrap()
+            .unwrap();
+
+        let notification = notification.inner().to_string();
+        assert!(notification.contains("Subject: Delayed Mail (still being retried)\r\n"));
+        assert!(notification.contains(concat!(
+            "Final-Recipient: rfc822; bob@example.com\r\n",
+            "Action: delayed\r\n",
+            "Status: 4.1.1\r\n",
+            "Diagnostic-Code: X-VSMTP; mailbox `bob` does not exist\r\n",
+        )));
+        assert!(notification.contains(concat!(
+            "Final-Recipient: rfc822; alice@example.com\r\n",
+            "Action: delivered\r\n",
+            "Status: 2.0.0\r\n",
+        )));
+        assert!(notification.contains(concat!(
+            "Final-Recipient: rfc822; carol@example.com\r\n",
+            "Action: relayed\r\n",
+            "Status: 2.0.0\r\n",
+        )));
+    }
+
+    #[test]
+    fn no_loop() {
+        let config = config::local_test();
+        let attempt = std::time::SystemTime::now();
+
+        for ctx in [
+            get_context(Envelop {
+                mail_from: addr!("MAILER-DAEMON@testserver.com"),
+                ..get_envelop(vec![failed(addr!("bob@example.com"), None)])
+            }),
+            get_context(Envelop {
+                null_reverse_path: true,
+                ..get_envelop(vec![failed(addr!("bob@example.com"), None)])
+            }),
+        ] {
+            assert!(create_notification(&config, &ctx, &get_message(), attempt)
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn status_of_remote_reply() {
+        let config = config::local_test();
+        let attempt = std::time::SystemTime::now();
+
+        let ctx = get_context(get_envelop(vec![
+            Rcpt {
+                email_status: EmailTransferStatus::Failed {
+                    timestamp: std::time::SystemTime::now(),
+                    reason: "maximum retry count of '5' reached, last error: no valid mail exchanger found for 'example.com': permanent error (550): 5.1.1 <bob@example.com>: user unknown".to_string(),
+                },
+                ..Rcpt::new(addr!("bob@example.com"))
+            },
+            Rcpt {
+                email_status: EmailTransferStatus::Failed {
+                    timestamp: std::time::SystemTime::now(),
+                    reason: "5.1.10 null record found for this domain".to_string(),
+                },
+                ..Rcpt::new(addr!("alice@null.example.com"))
+            },
+        ]));
+
+        let (_, notification) = create_notification(&config, &ctx, &get_message(), attempt)
+            .unwrap()
+            .unwrap();
+
+        let notification = notification.inner().to_string();
+        assert!(notification.contains(concat!(
+            "Final-Recipient: rfc822; bob@example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.1.1\r\n",
+        )));
+        assert!(notification.contains(concat!(
+            "Final-Recipient: rfc822; alice@null.example.com\r\n",
+            "Action: failed\r\n",
+            "Status: 5.1.10\r\n",
+        )));
+    }
+
+    #[test]
+    fn status_detail_of_reason() {
+        assert_eq!(
+            status_detail("transient error (451): 4.7.1 try again later"),
+            Some("7.1")
+        );
+        assert_eq!(status_detail("unexpected reply: 550 5.7.26."), Some("7.26"));
+        assert_eq!(status_detail("connection refused to 192.168.1.1"), None);
+        assert_eq!(status_detail("version 1.2.3"), None);
+        assert_eq!(status_detail("mailbox unavailable"), None);
+    }
+}
@@ -15,7 +15,7 @@
  *
 */
 use crate::{
-    delivery::{send_mail, SenderOutcome},
+    delivery::{bounce, send_mail, SenderOutcome},
     ProcessMessage,
 };
 use vsmtp_common::{
@@ -64,7 +64,14 @@ async fn handle_one_in_deferred_queue(
         .read(&config.server.queues.dirpath, &process_message.message_id)
         .await?;
 
-    match send_mail(&config, &mut mail_context, &mail_message, &resolvers).await {
+    let attempt = std::time::SystemTime::now();
+    let outcome = send_mail(&config, &mut mail_context, &mail_message, &resolvers).await;
+
+    if let Err(e) = bounce::notify_sender(&config, &mail_context, &mail_message, attempt) {
+        log::warn!("failed to send a delivery status notification: {e}");
+    }
+
+    match outcome {
         SenderOutcome::MoveToDead => {
             Queue::Deferred
                 .move_to(&Queue::Dead, &config.server.queues.dirpath, &mail_context)
@@ -120,6 +127,8 @@ mod tests {
                     connection: ConnectionContext {
                         timestamp: now,
                         credentials: None,
+                        verify_query: None,
+                        client_certificate: None,
                         is_authenticated: false,
                         is_secured: false,
                         server_name: "testserver.com".to_string(),
@@ -136,6 +145,8 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                             Rcpt {
                                 address: addr!("to+2@client.com"),
@@ -143,8 +154,15 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                         ],
+                        declared_size: None,
+                        dsn_return: None,
+                        envelop_id: None,
+                        smtp_utf8: false,
+                        null_reverse_path: false,
                     },
                     metadata: Some(MessageMetadata {
                         timestamp: now,
@@ -187,6 +205,8 @@ mod tests {
                 connection: ConnectionContext {
                     timestamp: now,
                     credentials: None,
+                    verify_query: None,
+                    client_certificate: None,
                     is_authenticated: false,
                     is_secured: false,
                     server_name: "testserver.com".to_string(),
@@ -208,6 +228,8 @@ mod tests {
                                     }
                                 )]
                             },
+                            notify: None,
+                            original_recipient: None,
                         },
                         Rcpt {
                             address: addr!("to+2@client.com"),
@@ -220,8 +242,15 @@ mod tests {
                                     }
                                 )]
                             },
+                            notify: None,
+                            original_recipient: None,
                         },
                     ],
+                    declared_size: None,
+                    dsn_return: None,
+                    envelop_id: None,
+                    smtp_utf8: false,
+                    null_reverse_path: false,
                 },
                 metadata: Some(MessageMetadata {
                     timestamp: now,
@@ -16,7 +16,7 @@
 */
 use crate::{
     delegate,
-    delivery::{add_trace_information, send_mail, SenderOutcome},
+    delivery::{add_trace_information, bounce, send_mail, SenderOutcome},
     receiver::MailHandlerError,
     ProcessMessage,
 };
@@ -164,6 +164,7 @@ async fn handle_one_in_delivery_queue_inner(
             "delivery is the last stage, delegation results cannot travel down any further."
         ),
         Some(Status::Deny(code)) => {
+            let attempt = std::time::SystemTime::now();
             for rcpt in &mut mail_context.envelop.rcpt {
                 rcpt.email_status = EmailTransferStatus::Failed {
                     timestamp: std::time::SystemTime::now(),
@@ -171,6 +172,10 @@ async fn handle_one_in_delivery_queue_inner(
                 };
             }
 
+            if let Err(e) = bounce::notify_sender(&config, &mail_context, &mail_message, attempt) {
+                log::warn!("failed to send a delivery status notification: {e}");
+            }
+
             queue.move_to(&Queue::Dead, &config.server.queues.dirpath, &mail_context)?;
 
             mail_message
@@ -189,7 +194,14 @@ async fn handle_one_in_delivery_queue_inner(
 
     add_trace_information(&config, &mail_context, &mut mail_message, &result)?;
 
-    match send_mail(&config, &mut mail_context, &mail_message, &resolvers).await {
+    let attempt = std::time::SystemTime::now();
+    let outcome = send_mail(&config, &mut mail_context, &mail_message, &resolvers).await;
+
+    if let Err(e) = bounce::notify_sender(&config, &mail_context, &mail_message, attempt) {
+        log::warn!("failed to send a delivery status notification: {e}");
+    }
+
+    match outcome {
         SenderOutcome::MoveToDead => {
             queue
                 .move_to(&Queue::Dead, &config.server.queues.dirpath, &mail_context)
@@ -255,6 +267,8 @@ mod tests {
                     connection: ConnectionContext {
                         timestamp: now,
                         credentials: None,
+                        verify_query: None,
+                        client_certificate: None,
                         is_authenticated: false,
                         is_secured: false,
                         server_name: "testserver.com".to_string(),
@@ -271,6 +285,8 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                             Rcpt {
                                 address: addr!("to+2@client.com"),
@@ -278,8 +294,15 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                         ],
+                        declared_size: None,
+                        dsn_return: None,
+                        envelop_id: None,
+                        smtp_utf8: false,
+                        null_reverse_path: false,
                     },
                     metadata: Some(MessageMetadata {
                         timestamp: now,
@@ -0,0 +1,326 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::bounce::{format_date, generate_id};
+use std::io::Write;
+use trust_dns_resolver::TokioAsyncResolver;
+use vsmtp_common::{
+    envelop::Envelop,
+    mail_context::{ConnectionContext, MailContext, MessageMetadata},
+    queue::Queue,
+    rcpt::Rcpt,
+    re::{anyhow, base64, log},
+    Address, MessageBody,
+};
+use vsmtp_config::{Config, Resolvers};
+use vsmtp_dmarc::{organizational_domain, AggregateReport, Evaluation};
+
+/// Does the domain of `address` accept the reports of `policy_domain` ?
+/// A destination outside of the organization of the policy domain must publish
+/// a `<policy-domain>._report._dmarc.<destination>` record, see RFC 7489 section 7.1
+async fn is_authorized(
+    resolver: &TokioAsyncResolver,
+    policy_domain: &str,
+    address: &Address,
+) -> bool {
+    if organizational_domain(address.domain()) == organizational_domain(policy_domain) {
+        return true;
+    }
+
+    resolver
+        .txt_lookup(format!(
+            "{policy_domain}._report._dmarc.{}.",
+            address.domain()
+        ))
+        .await
+        .map_or(false, |record| {
+            record
+                .into_iter()
+                .any(|i| i.to_string().starts_with("v=DMARC1"))
+        })
+}
+
+/// Compress the report with gzip, and encode it in base64 with lines of 76 characters.
+pub(super) fn encode_report(report: &impl std::fmt::Display) -> anyhow::Result<String> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(report.to_string().as_bytes())?;
+
+    let content = base64::encode(encoder.finish()?);
+    let mut lines = String::with_capacity(content.len() + content.len() / 38);
+    for line in content.as_bytes().chunks(76) {
+        lines.push_str(std::str::from_utf8(line)?);
+        lines.push_str("\r\n");
+    }
+
+    Ok(lines)
+}
+
+fn create_report_message(
+    config: &Config,
+    report: &AggregateReport,
+    rcpt: Vec<Rcpt>,
+) -> anyhow::Result<(MailContext, MessageBody)> {
+    let now = std::time::SystemTime::now();
+    let sender = Address::try_from(report.email.clone())?;
+
+    let boundary = format!("{}/{}", generate_id(now), config.server.domain);
+    let filename = format!("{}.gz", report.filename());
+    let body = format!(
+        "This is a MIME-encapsulated message.\r\n\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        This is an aggregate report from {} about the messages\r\n\
+        claiming to be from the domain {}.\r\n\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: application/gzip; name=\"{filename}\"\r\n\
+        Content-Disposition: attachment; filename=\"{filename}\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        {}\
+        \r\n\
+        --{boundary}--\r\n",
+        report.org_name,
+        report.policy_domain,
+        encode_report(report)?,
+    );
+
+    let message_id = generate_id(now);
+    let headers = vec![
+        format!("From: {sender}"),
+        format!(
+            "To: {}",
+            rcpt.iter()
+                .map(|i| i.address.full())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        format!(
+            "Subject: Report Domain: {} Submitter: {} Report-ID: <{}>",
+            report.policy_domain, report.org_name, report.report_id
+        ),
+        format!("Date: {}", format_date(now)?),
+        format!("Message-ID: <{message_id}@{}>", config.server.domain),
+        "Auto-Submitted: auto-generated".to_string(),
+        "MIME-Version: 1.0".to_string(),
+        format!("Content-Type: multipart/mixed; boundary=\"{boundary}\""),
+    ];
+
+    Ok((
+        report_context(config, sender, rcpt, now, message_id),
+        MessageBody::new(headers, body),
+    ))
+}
+
+/// The context of a report generated by the server itself.
+pub(super) fn report_context(
+    config: &Config,
+    sender: Address,
+    rcpt: Vec<Rcpt>,
+    now: std::time::SystemTime,
+    message_id: String,
+) -> MailContext {
+    let server_address = config
+        .server
+        .interfaces
+        .addr
+        .first()
+        .copied()
+        .unwrap_or_else(|| std::net::SocketAddr::from(([127, 0, 0, 1], 25)));
+
+    MailContext {
+        connection: ConnectionContext {
+            timestamp: now,
+            credentials: None,
+            verify_query: None,
+            client_certificate: None,
+            is_authenticated: false,
+            is_secured: false,
+            server_name: config.server.domain.clone(),
+            server_address,
+        },
+        client_addr: server_address,
+        envelop: Envelop {
+            helo: config.server.domain.clone(),
+            mail_from: sender,
+            rcpt,
+            ..Envelop::default()
+        },
+        metadata: Some(MessageMetadata {
+            timestamp: now,
+            message_id,
+            skipped: None,
+        }),
+    }
+}
+
+/// Write the aggregate report of the evaluations of `policy_domain` in the deferred queue.
+async fn queue_aggregate_report(
+    config: &Config,
+    resolver: &TokioAsyncResolver,
+    policy_domain: &str,
+    evaluations: Vec<Evaluation>,
+) -> anyhow::Result<()> {
+    let report = match AggregateReport::new(
+        config.server.domain.clone(),
+        format!("noreply-dmarc@{}", config.server.domain),
+        generate_id(std::time::SystemTime::now()),
+        evaluations,
+    ) {
+        Some(report) => report,
+        None => return Ok(()),
+    };
+
+    let mut rcpt = vec![];
+    for address in report.record.aggregate_report_addresses() {
+        match Address::try_from(address) {
+            Ok(address) if is_authorized(resolver, policy_domain, &address).await => {
+                rcpt.push(Rcpt::new(address));
+            }
+            Ok(address) => {
+                log::warn!(
+                    "dmarc report destination '{address}' does not accept the reports of '{policy_domain}'"
+                );
+            }
+            Err(error) => log::warn!("invalid dmarc report destination: {error}"),
+        }
+    }
+
+    if rcpt.is_empty() {
+        return Ok(());
+    }
+
+    let (ctx, message) = create_report_message(config, &report, rcpt)?;
+    let message_id = &ctx.metadata.as_ref().unwrap().message_id;
+
+    message.write_to_mails(&config.server.queues.dirpath, message_id)?;
+    Queue::Deferred.write_to_queue(&config.server.queues.dirpath, &ctx)?;
+
+    log::info!("dmarc aggregate report '{message_id}' sent for '{policy_domain}'");
+
+    Ok(())
+}
+
+/// Send the aggregate reports of the DMARC evaluations collected since the last call,
+/// to the addresses requested by the policy of each domain.
+///
+/// The reports are written in the deferred queue, to be sent with the next flush.
+/// The evaluations of a domain whose report could not be written are kept for the next call.
+///
+/// # Errors
+///
+/// * failed to read the evaluations.
+pub async fn send_aggregate_reports(
+    config: std::sync::Arc<Config>,
+    resolvers: std::sync::Arc<Resolvers>,
+) -> anyhow::Result<()> {
+    let resolver = resolvers
+        .get(&config.server.domain)
+        .expect("root server's resolver is missing");
+
+    let dirpath = config.app.dirpath.join("dmarc");
+    for (policy_domain, evaluations) in Evaluation::take_all(&dirpath)? {
+        if let Err(error) =
+            queue_aggregate_report(&config, resolver, &policy_domain, evaluations).await
+        {
+            log::error!("failed to send the dmarc aggregate report of '{policy_domain}': {error}");
+            continue;
+        }
+
+        if let Err(error) = Evaluation::remove_taken(&dirpath, &policy_domain) {
+            log::error!("{error}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use vsmtp_common::addr;
+    use vsmtp_dmarc::{AuthResult, ReceiverPolicy};
+    use vsmtp_test::config;
+
+    fn get_report() -> AggregateReport {
+        AggregateReport::new(
+            "testserver.com",
+            "noreply-dmarc@testserver.com",
+            "report_test",
+            vec![Evaluation {
+                timestamp: 1_000,
+                source_ip: "192.0.2.1".parse().unwrap(),
+                header_from: "example.com".to_string(),
+                policy_domain: "example.com".to_string(),
+                record: "v=DMARC1; p=reject; rua=mailto:dmarc@example.com"
+                    .parse()
+                    .unwrap(),
+                disposition: ReceiverPolicy::Reject,
+                dkim_aligned: false,
+                spf_aligned: false,
+                dkim: vec![],
+                spf: AuthResult {
+                    domain: "example.net".to_string(),
+                    result: "pass".to_string(),
+                },
+            }],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn report_message() {
+        let config = config::local_test();
+        let report = get_report();
+
+        let (ctx, message) = create_report_message(
+            &config,
+            &report,
+            vec![Rcpt::new(addr!("dmarc@example.com"))],
+        )
+        .unwrap();
+
+        assert_eq!(ctx.envelop.mail_from, addr!("noreply-dmarc@testserver.com"));
+        assert_eq!(ctx.envelop.rcpt.len(), 1);
+        assert_eq!(
+            message.get_header("Subject").unwrap().trim(),
+            "Report Domain: example.com Submitter: testserver.com Report-ID: <report_test>"
+        );
+        assert!(message
+            .inner()
+            .to_string()
+            .contains("filename=\"testserver.com!example.com!1000!1000.xml.gz\""));
+    }
+
+    #[test]
+    fn report_encoding() {
+        let report = get_report();
+        let encoded = encode_report(&report).unwrap();
+
+        assert!(encoded.lines().all(|line| line.len() <= 77));
+
+        let compressed = base64::decode(encoded.replace("\r\n", "")).unwrap();
+        let mut xml = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut xml)
+            .unwrap();
+
+        assert_eq!(xml, report.to_string());
+    }
+}
@@ -20,6 +20,7 @@ use crate::{
         deferred::flush_deferred_queue,
         deliver::{flush_deliver_queue, handle_one_in_delivery_queue},
     },
+    server::Reload,
 };
 use anyhow::Context;
 use time::format_description::well_known::Rfc2822;
@@ -29,7 +30,7 @@ use vsmtp_common::{
     rcpt::Rcpt,
     re::{anyhow, log},
     status::Status,
-    transfer::{ForwardTarget, Transfer},
+    transfer::{ForwardTarget, Transfer, TransferErrors},
     MessageBody,
 };
 use vsmtp_common::{re::tokio, transfer::EmailTransferStatus};
@@ -37,16 +38,29 @@ use vsmtp_config::{Config, Resolvers};
 use vsmtp_delivery::transport::{deliver as smtp_deliver, forward, maildir, mbox, Transport};
 use vsmtp_rule_engine::rule_engine::RuleEngine;
 
+/// delivery status notifications sent back to the sender of a message.
+/// see "An Extensible Message Format for Delivery Status Notifications"
+/// <https://datatracker.ietf.org/doc/html/rfc3464>
+mod bounce;
 mod deferred;
 mod deliver;
+/// aggregate reports of the DMARC evaluations.
+/// see "Domain-based Message Authentication, Reporting, and Conformance (DMARC)"
+/// <https://datatracker.ietf.org/doc/html/rfc7489#section-7.2>
+mod dmarc;
+/// reports of the outgoing TLS sessions.
+/// see "SMTP TLS Reporting"
+/// <https://datatracker.ietf.org/doc/html/rfc8460>
+mod tls_rpt;
 
 /// process used to deliver incoming emails force accepted by the smtp process
 /// or parsed by the vMime process.
 pub async fn start(
-    config: std::sync::Arc<Config>,
-    rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
-    resolvers: std::sync::Arc<Resolvers>,
+    mut config: std::sync::Arc<Config>,
+    mut rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
+    mut resolvers: std::sync::Arc<Resolvers>,
     mut delivery_receiver: tokio::sync::mpsc::Receiver<ProcessMessage>,
+    mut reload_receiver: tokio::sync::watch::Receiver<Reload>,
 ) {
     if let Err(e) =
         flush_deliver_queue(config.clone(), resolvers.clone(), rule_engine.clone()).await
@@ -56,9 +70,33 @@ pub async fn start(
 
     let mut flush_deferred_interval =
         tokio::time::interval(config.server.queues.delivery.deferred_retry_period);
+    let mut dmarc_report_interval = tokio::time::interval(
+        config
+            .server
+            .dmarc
+            .as_ref()
+            .map_or(std::time::Duration::from_secs(24 * 60 * 60), |dmarc| {
+                dmarc.report_interval
+            }),
+    );
+    let mut tls_report_interval = tokio::time::interval(
+        config
+            .server
+            .tls_rpt
+            .as_ref()
+            .map_or(std::time::Duration::from_secs(24 * 60 * 60), |tls_rpt| {
+                tls_rpt.report_interval
+            }),
+    );
 
     loop {
         tokio::select! {
+            Ok(()) = reload_receiver.changed() => {
+                let reload = reload_receiver.borrow().clone();
+                config = reload.config;
+                rule_engine = reload.rule_engine;
+                resolvers = reload.resolvers;
+            }
             Some(pm) = delivery_receiver.recv() => {
                 tokio::spawn(
                     handle_one_in_delivery_queue(
@@ -73,6 +111,22 @@ pub async fn start(
                 log::info!("cronjob delay elapsed, flushing queue.");
                 tokio::spawn(flush_deferred_queue(config.clone(), resolvers.clone()));
             }
+            _ = dmarc_report_interval.tick(), if config.server.dmarc.is_some() => {
+                let (config, resolvers) = (config.clone(), resolvers.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = dmarc::send_aggregate_reports(config, resolvers).await {
+                        log::error!("sending dmarc aggregate reports failed: {e}");
+                    }
+                });
+            }
+            _ = tls_report_interval.tick(), if config.server.tls_rpt.is_some() => {
+                let (config, resolvers) = (config.clone(), resolvers.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = tls_rpt::send_reports(config, resolvers).await {
+                        log::error!("sending tls reports failed: {e}");
+                    }
+                });
+            }
         };
     }
 }
@@ -107,7 +161,8 @@ pub async fn send_mail(
         return SenderOutcome::MoveToDead;
     }
 
-    let message_content = message_body.inner().to_string();
+    // NOTE: the octets are sent as is, a body received with `BODY=BINARYMIME` is not converted.
+    let message_content = message_body.inner().to_bytes();
 
     let root_server_resolver = resolvers
         .get(&config.server.domain)
@@ -115,6 +170,7 @@ pub async fn send_mail(
 
     let metadata = &message_ctx.metadata.as_ref().unwrap();
     let from = &message_ctx.envelop.mail_from;
+    let null_reverse_path = message_ctx.envelop.null_reverse_path;
 
     let futures = acc
         .into_iter()
@@ -127,13 +183,9 @@ pub async fn send_mail(
                 }
                 .unwrap_or(root_server_resolver);
 
-                forward::Forward::new(forward_target.clone(), resolver).deliver(
-                    config,
-                    metadata,
-                    from,
-                    to,
-                    &message_content,
-                )
+                forward::Forward::new(forward_target.clone(), resolver)
+                    .with_null_reverse_path(null_reverse_path)
+                    .deliver(config, metadata, from, to, &message_content)
             }
             Transfer::Deliver => smtp_deliver::Deliver::new({
                 resolvers
@@ -145,6 +197,7 @@ pub async fn send_mail(
                     )
                     .unwrap_or(root_server_resolver)
             })
+            .with_null_reverse_path(null_reverse_path)
             .deliver(config, metadata, from, to, &message_content),
             Transfer::Mbox => mbox::MBox.deliver(config, metadata, from, to, &message_content),
             Transfer::Maildir => {
@@ -162,16 +215,28 @@ pub async fn send_mail(
 
     // updating retry count, set status to Failed if threshold reached.
     for rcpt in &mut message_ctx.envelop.rcpt {
-        if matches!(&rcpt.email_status, EmailTransferStatus::HeldBack{errors}
-            if errors.len() >= config.server.queues.delivery.deferred_retry_max)
-        {
-            rcpt.email_status = EmailTransferStatus::Failed {
-                timestamp: std::time::SystemTime::now(),
-                reason: format!(
+        if let EmailTransferStatus::HeldBack { errors } = &rcpt.email_status {
+            if errors.len() >= config.server.queues.delivery.deferred_retry_max {
+                // NOTE: the last error is kept, it carries the status reported to the sender.
+                let mut reason = format!(
                     "maximum retry count of '{}' reached",
                     config.server.queues.delivery.deferred_retry_max
-                ),
-            };
+                );
+                match errors.last() {
+                    Some((_, TransferErrors::NoSuchMailbox { name })) => {
+                        reason =
+                            format!("{reason}, last error: 5.1.1 mailbox `{name}` does not exist");
+                    }
+                    Some((_, TransferErrors::Other(error))) => {
+                        reason = format!("{reason}, last error: {error}");
+                    }
+                    None => {}
+                }
+                rcpt.email_status = EmailTransferStatus::Failed {
+                    timestamp: std::time::SystemTime::now(),
+                    reason,
+                };
+            }
         }
     }
 
@@ -314,6 +379,8 @@ mod test {
             connection: ConnectionContext {
                 timestamp: std::time::SystemTime::UNIX_EPOCH,
                 credentials: None,
+                verify_query: None,
+                client_certificate: None,
                 is_authenticated: false,
                 is_secured: false,
                 server_name: "testserver.com".to_string(),
@@ -327,6 +394,11 @@ mod test {
                 helo: "localhost".to_string(),
                 mail_from: vsmtp_common::addr!("a@a.a"),
                 rcpt: vec![],
+                declared_size: None,
+                dsn_return: None,
+                envelop_id: None,
+                smtp_utf8: false,
+                null_reverse_path: false,
             },
             metadata: Some(vsmtp_common::mail_context::MessageMetadata {
                 timestamp: std::time::SystemTime::UNIX_EPOCH,
@@ -0,0 +1,308 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::{
+    bounce::{format_date, generate_id},
+    dmarc::{encode_report, report_context},
+};
+use trust_dns_resolver::TokioAsyncResolver;
+use vsmtp_common::{
+    mail_context::MailContext,
+    queue::Queue,
+    rcpt::Rcpt,
+    re::{anyhow, log},
+    Address, MessageBody,
+};
+use vsmtp_config::{Config, Resolvers};
+use vsmtp_delivery::tls_rpt::{parse_txt_record, Report, SessionResult};
+
+/// The destinations of the reports of `policy_domain`, published in a
+/// `_smtp._tls.<policy-domain>` record, see RFC 8460 section 3
+async fn report_destinations(resolver: &TokioAsyncResolver, policy_domain: &str) -> Vec<String> {
+    resolver
+        .txt_lookup(format!("_smtp._tls.{policy_domain}."))
+        .await
+        .map_or_else(
+            |_| vec![],
+            |record| {
+                record
+                    .into_iter()
+                    .find_map(|i| parse_txt_record(&i.to_string()))
+                    .unwrap_or_default()
+            },
+        )
+}
+
+fn create_report_message(
+    config: &Config,
+    report: &Report,
+    rcpt: Vec<Rcpt>,
+) -> anyhow::Result<(MailContext, MessageBody)> {
+    let now = std::time::SystemTime::now();
+    let sender = Address::try_from(report.contact_info.clone())?;
+
+    let boundary = format!("{}/{}", generate_id(now), config.server.domain);
+    let filename = format!("{}.gz", report.filename());
+    let body = format!(
+        "This is a MIME-encapsulated message.\r\n\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        This is an aggregate TLS report from {}\r\n\
+        about the sessions to the domain {}.\r\n\
+        \r\n\
+        --{boundary}\r\n\
+        Content-Type: application/tlsrpt+gzip; name=\"{filename}\"\r\n\
+        Content-Disposition: attachment; filename=\"{filename}\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        {}\
+        \r\n\
+        --{boundary}--\r\n",
+        report.organization_name,
+        report.policy_domain(),
+        encode_report(report)?,
+    );
+
+    let message_id = generate_id(now);
+    let headers = vec![
+        format!("From: {sender}"),
+        format!(
+            "To: {}",
+            rcpt.iter()
+                .map(|i| i.address.full())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        format!(
+            "Subject: Report Domain: {} Submitter: {} Report-ID: <{}>",
+            report.policy_domain(),
+            report.organization_name,
+            report.report_id
+        ),
+        format!("TLS-Report-Domain: {}", report.policy_domain()),
+        format!("TLS-Report-Submitter: {}", report.organization_name),
+        format!("Date: {}", format_date(now)?),
+        format!("Message-ID: <{message_id}@{}>", config.server.domain),
+        "Auto-Submitted: auto-generated".to_string(),
+        "MIME-Version: 1.0".to_string(),
+        format!("Content-Type: multipart/report; report-type=\"tlsrpt\"; boundary=\"{boundary}\""),
+    ];
+
+    Ok((
+        report_context(config, sender, rcpt, now, message_id),
+        MessageBody::new(headers, body),
+    ))
+}
+
+/// Write the report of `policy_domain` in `report_dirpath` if set, or queue it
+/// for the `mailto:` destinations published by the domain.
+async fn queue_report(
+    config: &Config,
+    resolver: &TokioAsyncResolver,
+    report_dirpath: Option<&std::path::Path>,
+    policy_domain: &str,
+    results: Vec<SessionResult>,
+) -> anyhow::Result<()> {
+    let report = match Report::new(
+        config.server.domain.clone(),
+        format!("noreply-tlsrpt@{}", config.server.domain),
+        generate_id(std::time::SystemTime::now()),
+        results,
+    ) {
+        Some(report) => report,
+        None => return Ok(()),
+    };
+
+    if let Some(dirpath) = report_dirpath {
+        std::fs::create_dir_all(dirpath)?;
+        std::fs::write(dirpath.join(report.filename()), report.to_string())?;
+
+        log::info!(
+            "tls report '{}' written for '{policy_domain}'",
+            report.report_id
+        );
+        return Ok(());
+    }
+
+    let mut rcpt = vec![];
+    for destination in report_destinations(resolver, policy_domain).await {
+        // NOTE: only the `mailto:` destinations are supported, not the `https:` ones.
+        if let Some(address) = destination.strip_prefix("mailto:") {
+            match Address::try_from(address.split('?').next().unwrap_or_default().to_string()) {
+                Ok(address) => rcpt.push(Rcpt::new(address)),
+                Err(error) => log::warn!("invalid tls report destination: {error}"),
+            }
+        } else {
+            log::warn!(
+                "tls report destination '{destination}' of '{policy_domain}' is not supported"
+            );
+        }
+    }
+
+    if rcpt.is_empty() {
+        return Ok(());
+    }
+
+    let (ctx, message) = create_report_message(config, &report, rcpt)?;
+    let message_id = &ctx.metadata.as_ref().unwrap().message_id;
+
+    message.write_to_mails(&config.server.queues.dirpath, message_id)?;
+    Queue::Deferred.write_to_queue(&config.server.queues.dirpath, &ctx)?;
+
+    log::info!("tls report '{message_id}' sent for '{policy_domain}'");
+    Ok(())
+}
+
+/// Send the reports of the outgoing TLS sessions recorded since the last call,
+/// to the `mailto:` destinations published by each domain, or write them in
+/// the directory configured with `server.tls_rpt.dirpath`.
+///
+/// The reports are written in the deferred queue, to be sent with the next flush.
+/// The results of a domain are kept for the next call if its report could not be written.
+///
+/// # Errors
+///
+/// * failed to read the results of the sessions.
+pub async fn send_reports(
+    config: std::sync::Arc<Config>,
+    resolvers: std::sync::Arc<Resolvers>,
+) -> anyhow::Result<()> {
+    let resolver = resolvers
+        .get(&config.server.domain)
+        .expect("root server's resolver is missing");
+    let report_dirpath = config
+        .server
+        .tls_rpt
+        .as_ref()
+        .and_then(|tls_rpt| tls_rpt.dirpath.as_deref());
+
+    let dirpath = config.app.dirpath.join("tls-rpt");
+    for (policy_domain, results) in SessionResult::take_all(&dirpath)? {
+        if let Err(error) =
+            queue_report(&config, resolver, report_dirpath, &policy_domain, results).await
+        {
+            log::error!("failed to send the tls report of '{policy_domain}': {error}");
+            continue;
+        }
+
+        if let Err(error) = SessionResult::remove_taken(&dirpath, &policy_domain) {
+            log::error!("{error}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsmtp_common::addr;
+    use vsmtp_config::{build_resolvers, field::FieldTlsRpt};
+    use vsmtp_delivery::tls_rpt::{Policy, ResultType};
+    use vsmtp_test::config;
+
+    fn session_result(policy_domain: &str) -> SessionResult {
+        SessionResult {
+            timestamp: 1_000,
+            policy_domain: policy_domain.to_string(),
+            policy: Policy::none(),
+            receiving_mx_hostname: format!("mx.{policy_domain}"),
+            result: Some(ResultType::StartTlsNotSupported),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_reports_to_dirpath() {
+        let dirpath = std::path::PathBuf::from("./tmp/tls_rpt_send_reports");
+        let _ = std::fs::remove_dir_all(&dirpath);
+        let report_dirpath = dirpath.join("reports");
+
+        let mut config = config::local_test();
+        config.app.dirpath = dirpath.clone();
+        config.server.tls_rpt = Some(FieldTlsRpt {
+            report_interval: std::time::Duration::from_secs(60 * 60),
+            dirpath: Some(report_dirpath.clone()),
+        });
+        let resolvers = std::sync::Arc::new(build_resolvers(&config).unwrap());
+        let config = std::sync::Arc::new(config);
+
+        for policy_domain in ["example.com", "example.net"] {
+            session_result(policy_domain)
+                .store(&dirpath.join("tls-rpt"))
+                .unwrap();
+        }
+
+        // the reports cannot be written, the results are kept for the next call.
+        std::fs::write(&report_dirpath, "").unwrap();
+        send_reports(config.clone(), resolvers.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            SessionResult::take_all(&dirpath.join("tls-rpt"))
+                .unwrap()
+                .len(),
+            2
+        );
+
+        std::fs::remove_file(&report_dirpath).unwrap();
+        send_reports(config, resolvers).await.unwrap();
+        assert_eq!(std::fs::read_dir(&report_dirpath).unwrap().count(), 2);
+        assert!(SessionResult::take_all(&dirpath.join("tls-rpt"))
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(dirpath).unwrap();
+    }
+
+    #[test]
+    fn report_message() {
+        let config = config::local_test();
+        let report = Report::new(
+            "testserver.com",
+            "noreply-tlsrpt@testserver.com",
+            "report_test",
+            vec![session_result("example.com")],
+        )
+        .unwrap();
+
+        let (ctx, message) = create_report_message(
+            &config,
+            &report,
+            vec![Rcpt::new(addr!("tlsrpt@example.com"))],
+        )
+        .unwrap();
+
+        assert_eq!(
+            ctx.envelop.mail_from,
+            addr!("noreply-tlsrpt@testserver.com")
+        );
+        assert_eq!(ctx.envelop.rcpt.len(), 1);
+        assert_eq!(
+            message.get_header("Subject").unwrap().trim(),
+            "Report Domain: example.com Submitter: testserver.com Report-ID: <report_test>"
+        );
+        assert_eq!(
+            message.get_header("TLS-Report-Domain").unwrap().trim(),
+            "example.com"
+        );
+        assert!(message
+            .inner()
+            .to_string()
+            .contains("filename=\"testserver.com!example.com!1000!1000.json.gz\""));
+    }
+}
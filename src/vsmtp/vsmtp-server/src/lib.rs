@@ -77,7 +77,11 @@ pub(crate) fn delegate(
     use lettre::Transport;
 
     let envelope = lettre::address::Envelope::new(
-        Some(context.envelop.mail_from.full().parse()?),
+        if context.envelop.null_reverse_path {
+            None
+        } else {
+            Some(context.envelop.mail_from.full().parse()?)
+        },
         context
             .envelop
             .rcpt
@@ -95,6 +99,6 @@ pub(crate) fn delegate(
         .0
         .lock()
         .unwrap()
-        .send_raw(&envelope, message.inner().to_string().as_bytes())
+        .send_raw(&envelope, &message.inner().to_bytes())
         .context("failed to delegate email")
 }
@@ -14,7 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::{delegate, receiver::MailHandlerError, Process, ProcessMessage};
+use crate::{delegate, receiver::MailHandlerError, server::Reload, Process, ProcessMessage};
 use vsmtp_common::{
     queue::Queue,
     re::{anyhow, log, tokio},
@@ -26,21 +26,31 @@ use vsmtp_config::{create_app_folder, Config, Resolvers};
 use vsmtp_rule_engine::{rule_engine::RuleEngine, rule_state::RuleState};
 
 pub async fn start(
-    config: std::sync::Arc<Config>,
-    rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
-    resolvers: std::sync::Arc<Resolvers>,
+    mut config: std::sync::Arc<Config>,
+    mut rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
+    mut resolvers: std::sync::Arc<Resolvers>,
     mut working_receiver: tokio::sync::mpsc::Receiver<ProcessMessage>,
     delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+    mut reload_receiver: tokio::sync::watch::Receiver<Reload>,
 ) {
     loop {
-        if let Some(pm) = working_receiver.recv().await {
-            tokio::spawn(handle_one_in_working_queue(
-                config.clone(),
-                rule_engine.clone(),
-                resolvers.clone(),
-                pm,
-                delivery_sender.clone(),
-            ));
+        tokio::select! {
+            Ok(()) = reload_receiver.changed() => {
+                let reload = reload_receiver.borrow().clone();
+                config = reload.config;
+                rule_engine = reload.rule_engine;
+                resolvers = reload.resolvers;
+            }
+            Some(pm) = working_receiver.recv() => {
+                tokio::spawn(handle_one_in_working_queue(
+                    config.clone(),
+                    rule_engine.clone(),
+                    resolvers.clone(),
+                    pm,
+                    delivery_sender.clone(),
+                ));
+            }
+            else => break,
         }
     }
 }
@@ -253,6 +263,8 @@ mod tests {
                     connection: ConnectionContext {
                         timestamp: std::time::SystemTime::now(),
                         credentials: None,
+                        verify_query: None,
+                        client_certificate: None,
                         is_authenticated: false,
                         is_secured: false,
                         server_name: "testserver.com".to_string(),
@@ -269,6 +281,8 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                             Rcpt {
                                 address: addr!("to+2@client.com"),
@@ -276,8 +290,15 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                         ],
+                        declared_size: None,
+                        dsn_return: None,
+                        envelop_id: None,
+                        smtp_utf8: false,
+                        null_reverse_path: false,
                     },
                     metadata: Some(MessageMetadata {
                         timestamp: std::time::SystemTime::now(),
@@ -341,6 +362,8 @@ mod tests {
                     connection: ConnectionContext {
                         timestamp: std::time::SystemTime::now(),
                         credentials: None,
+                        verify_query: None,
+                        client_certificate: None,
                         is_authenticated: false,
                         is_secured: false,
                         server_name: "testserver.com".to_string(),
@@ -357,6 +380,8 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                             Rcpt {
                                 address: addr!("to+2@client.com"),
@@ -364,8 +389,15 @@ mod tests {
                                 email_status: EmailTransferStatus::Waiting {
                                     timestamp: std::time::SystemTime::now(),
                                 },
+                                notify: None,
+                                original_recipient: None,
                             },
                         ],
+                        declared_size: None,
+                        dsn_return: None,
+                        envelop_id: None,
+                        smtp_utf8: false,
+                        null_reverse_path: false,
                     },
                     metadata: Some(MessageMetadata {
                         timestamp: std::time::SystemTime::now(),
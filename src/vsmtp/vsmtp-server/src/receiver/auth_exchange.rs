@@ -14,13 +14,14 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::auth::{self, Session};
+use crate::auth::{self, Session, SessionData};
 
 use super::Connection;
 use vsmtp_common::{
-    auth::Mechanism,
+    auth::{Credentials, Mechanism},
     mail_context::ConnectionContext,
     re::{anyhow, base64, log, tokio, vsmtp_rsasl},
+    status::Status,
     CodeID,
 };
 use vsmtp_config::Resolvers;
@@ -66,11 +67,17 @@ where
 
     match session.step(&bytes64decoded) {
         Ok(vsmtp_rsasl::Step::Done(buffer)) => {
+            // NOTE: the additional data of the server (ex: SCRAM's server signature)
+            // is sent in a last challenge, the client response must be empty.
+            // see https://datatracker.ietf.org/doc/html/rfc4954#section-4
             if !buffer.is_empty() {
-                todo!(
-                    "Authentication successful, bytes to return to client: {:?}",
-                    std::str::from_utf8(&*buffer)
-                );
+                conn.send(&format!("334 {}\r\n", base64::encode::<&[u8]>(&buffer)))
+                    .await
+                    .map_err(AuthExchangeError::SendingResponse)?;
+
+                if read_response(conn).await? == "*" {
+                    return Err(AuthExchangeError::Canceled);
+                }
             }
 
             conn.send_code(CodeID::AuthSucceeded)
@@ -81,7 +88,7 @@ where
         Ok(vsmtp_rsasl::Step::NeedsMore(buffer)) => {
             let reply = format!(
                 "334 {}\r\n",
-                base64::encode(std::str::from_utf8(&*buffer).unwrap())
+                base64::encode(std::str::from_utf8(&buffer).unwrap())
             );
 
             conn.send(&reply)
@@ -98,6 +105,137 @@ where
 
 const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
+async fn read_response<S>(conn: &mut Connection<S>) -> Result<String, AuthExchangeError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+{
+    match conn.read(READ_TIMEOUT).await {
+        Ok(Some(buffer)) => {
+            log::trace!("{buffer}");
+            Ok(buffer)
+        }
+        Ok(None) => Err(AuthExchangeError::ReadingMessage(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "unexpected EOF during SASL exchange",
+        ))),
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => Err(AuthExchangeError::Timeout(e)),
+        Err(e) => Err(AuthExchangeError::ReadingMessage(e)),
+    }
+}
+
+/// `OAUTHBEARER` and `XOAUTH2` are not supported by the SASL backend, the
+/// token is sent in a single response and verified by the `authenticate` stage.
+async fn on_bearer_token<S>(
+    conn: &mut Connection<S>,
+    session_data: &SessionData,
+    initial_response: Option<Vec<u8>>,
+) -> Result<(), AuthExchangeError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+{
+    let response = if let Some(initial_response) = initial_response {
+        initial_response
+    } else {
+        conn.send("334 \r\n")
+            .await
+            .map_err(AuthExchangeError::SendingResponse)?;
+        read_response(conn).await?.into_bytes()
+    };
+
+    if response == [b'*'] {
+        return Err(AuthExchangeError::Canceled);
+    }
+
+    let payload = base64::decode(response).map_err(|_| AuthExchangeError::InvalidBase64)?;
+
+    let accepted = match Credentials::from_bearer_payload(session_data.mechanism, &payload) {
+        Ok(credentials) => matches!(
+            auth::run_authenticate(&conn.config, session_data, credentials),
+            Ok(Status::Accept(..))
+        ),
+        Err(e) => {
+            log::warn!("invalid {} payload: {e}", session_data.mechanism);
+            false
+        }
+    };
+
+    if accepted {
+        conn.send_code(CodeID::AuthSucceeded)
+            .await
+            .map_err(AuthExchangeError::SendingResponse)?;
+        return Ok(());
+    }
+
+    // NOTE: the error is sent in a challenge, the client must send a dummy response.
+    // see https://datatracker.ietf.org/doc/html/rfc7628#section-3.2.2
+    conn.send(&format!(
+        "334 {}\r\n",
+        base64::encode(r#"{"status":"invalid_token"}"#)
+    ))
+    .await
+    .map_err(AuthExchangeError::SendingResponse)?;
+
+    if read_response(conn).await? == "*" {
+        return Err(AuthExchangeError::Canceled);
+    }
+
+    Err(AuthExchangeError::Failed)
+}
+
+/// `EXTERNAL` is not supported by the SASL backend, the client is identified by
+/// the certificate of the TLS handshake and the (optional) authorization identity
+/// it requests is verified by the `authenticate` stage.
+async fn on_external<S>(
+    conn: &mut Connection<S>,
+    session_data: &SessionData,
+    initial_response: Option<Vec<u8>>,
+) -> Result<(), AuthExchangeError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+{
+    let response = if let Some(initial_response) = initial_response {
+        initial_response
+    } else {
+        conn.send("334 \r\n")
+            .await
+            .map_err(AuthExchangeError::SendingResponse)?;
+        read_response(conn).await?.into_bytes()
+    };
+
+    if response == [b'*'] {
+        return Err(AuthExchangeError::Canceled);
+    }
+
+    // NOTE: an empty initial response is sent as "="
+    // see https://datatracker.ietf.org/doc/html/rfc4954#section-4
+    let authid = if response == [b'='] {
+        String::new()
+    } else {
+        String::from_utf8(base64::decode(response).map_err(|_| AuthExchangeError::InvalidBase64)?)
+            .map_err(|_| AuthExchangeError::InvalidBase64)?
+    };
+
+    if session_data.conn.client_certificate.is_none() {
+        log::warn!(
+            "{} requested without a trusted client certificate",
+            Mechanism::External
+        );
+        return Err(AuthExchangeError::Failed);
+    }
+
+    if !matches!(
+        auth::run_authenticate(&conn.config, session_data, Credentials::External { authid }),
+        Ok(Status::Accept(..))
+    ) {
+        return Err(AuthExchangeError::Failed);
+    }
+
+    conn.send_code(CodeID::AuthSucceeded)
+        .await
+        .map_err(AuthExchangeError::SendingResponse)?;
+    Ok(())
+}
+
 pub async fn on_authentication<S>(
     conn: &mut Connection<S>,
     rsasl: std::sync::Arc<tokio::sync::Mutex<auth::Backend>>,
@@ -111,6 +249,16 @@ where
 {
     // TODO: if initial data == "=" ; it mean empty ""
 
+    // NOTE: channel binding and client certificates are impossible without tls,
+    // whatever the configuration.
+    if mechanism.requires_tls_session() && !conn.is_secured {
+        conn.send_code(CodeID::AuthMechanismMustBeEncrypted)
+            .await
+            .map_err(AuthExchangeError::SendingResponse)?;
+
+        return Err(AuthExchangeError::AuthMechanismMustBeEncrypted(mechanism));
+    }
+
     if mechanism.must_be_under_tls() && !conn.is_secured {
         if conn
             .config
@@ -140,39 +288,40 @@ where
         return Err(AuthExchangeError::AuthClientMustNotStart(mechanism));
     }
 
-    let mut guard = rsasl.lock().await;
-    let mut session = guard.server_start(&format!("{mechanism}")).unwrap();
-    session.store(Box::new((
+    let session_data = SessionData {
         rule_engine,
         resolvers,
-        ConnectionContext {
+        conn: ConnectionContext {
             timestamp: conn.timestamp,
             credentials: None,
+            verify_query: None,
+            client_certificate: conn.client_certificate.clone(),
             is_authenticated: conn.is_authenticated,
             is_secured: conn.is_secured,
             server_name: conn.server_name.clone(),
             server_address: conn.server_addr,
         },
-    )));
+        mechanism,
+        channel_binding: conn.channel_binding.clone(),
+        scram_secrets: None,
+    };
+
+    if mechanism == Mechanism::External {
+        return on_external(conn, &session_data, initial_response).await;
+    } else if mechanism.is_native() {
+        return on_bearer_token(conn, &session_data, initial_response).await;
+    }
+
+    let mut guard = rsasl.lock().await;
+    let mut session = guard.server_start(&format!("{mechanism}")).unwrap();
+    session.store(Box::new(session_data));
 
     let mut succeeded =
         auth_step(conn, &mut session, &initial_response.unwrap_or_default()).await?;
 
     while !succeeded {
-        succeeded = match conn.read(READ_TIMEOUT).await {
-            Ok(Some(buffer)) => {
-                log::trace!("{buffer}");
-                auth_step(conn, &mut session, buffer.as_bytes()).await
-            }
-            Ok(None) => Err(AuthExchangeError::ReadingMessage(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "unexpected EOF during SASL exchange",
-            ))),
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                Err(AuthExchangeError::Timeout(e))
-            }
-            Err(e) => Err(AuthExchangeError::ReadingMessage(e)),
-        }?;
+        let buffer = read_response(conn).await?;
+        succeeded = auth_step(conn, &mut session, buffer.as_bytes()).await?;
     }
 
     // TODO: if success get session property
@@ -16,6 +16,7 @@
 */
 use crate::AbstractIO;
 use vsmtp_common::{
+    auth::ClientCertificate,
     re::{anyhow, log, tokio},
     CodeID, ConnectionKind, Reply, ReplyOrCodeID,
 };
@@ -51,6 +52,12 @@ where
     pub authentication_attempt: i64,
     /// inner stream
     pub inner: AbstractIO<S>,
+    /// `tls-exporter` channel binding data, see <https://datatracker.ietf.org/doc/html/rfc9266>
+    pub channel_binding: Option<Vec<u8>>,
+    /// trusted certificate presented by the client during the TLS handshake
+    pub client_certificate: Option<ClientCertificate>,
+    /// replies not sent yet, waiting for the end of a pipelined group of commands
+    pending_replies: Vec<u8>,
 }
 
 impl<S> std::fmt::Debug for Connection<S>
@@ -99,6 +106,9 @@ where
             inner: AbstractIO::new(inner),
             is_authenticated: false,
             authentication_attempt: 0,
+            channel_binding: None,
+            client_certificate: None,
+            pending_replies: Vec::new(),
         }
     }
 
@@ -130,6 +140,9 @@ where
             is_authenticated,
             authentication_attempt,
             inner: AbstractIO::new(inner),
+            channel_binding: None,
+            client_certificate: None,
+            pending_replies: Vec::new(),
         }
     }
 }
@@ -192,7 +205,7 @@ where
                 .fold(),
             )
             .await?;
-            tokio::io::AsyncWriteExt::flush(&mut self.inner.inner).await?;
+            self.flush().await?;
 
             anyhow::bail!("{:?}", CodeID::TooManyError)
         }
@@ -208,15 +221,35 @@ where
 
     /// Send a buffer
     ///
+    /// If the client has pipelined commands (RFC 2920), the buffer is kept until
+    /// the whole group of commands has been processed.
+    ///
     /// # Errors
     ///
     /// * internal connection writer error
     pub async fn send(&mut self, reply: &str) -> anyhow::Result<()> {
         log::trace!("sending=`{reply:?}`");
-        tokio::io::AsyncWriteExt::write_all(&mut self.inner.inner, reply.as_bytes()).await?;
+        self.pending_replies.extend_from_slice(reply.as_bytes());
+        if !self.inner.has_pending_input() {
+            self.flush().await?;
+        }
         Ok(())
     }
 
+    /// Write the pending replies to the client.
+    ///
+    /// # Errors
+    ///
+    /// * internal connection writer error
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        if self.pending_replies.is_empty() {
+            return Ok(());
+        }
+        let replies = std::mem::take(&mut self.pending_replies);
+        tokio::io::AsyncWriteExt::write_all(&mut self.inner.inner, &replies).await?;
+        tokio::io::AsyncWriteExt::flush(&mut self.inner.inner).await
+    }
+
     /// Read a line from the client
     ///
     /// # Errors
@@ -227,6 +260,45 @@ where
         &mut self,
         timeout: std::time::Duration,
     ) -> std::io::Result<Option<std::string::String>> {
+        // NOTE: synchronization point, the client is waiting for the replies
+        // of the commands it has sent before sending anything else.
+        if !self.inner.has_pending_input() {
+            self.flush().await?;
+        }
         self.inner.next_line(Some(timeout)).await
     }
+
+    /// Read a chunk of `size` octets sent with the BDAT command
+    ///
+    /// # Errors
+    ///
+    /// * timed-out
+    /// * internal connection reader error
+    pub async fn read_chunk(
+        &mut self,
+        size: usize,
+        timeout: std::time::Duration,
+    ) -> std::io::Result<Vec<u8>> {
+        if !self.inner.has_pending_input() {
+            self.flush().await?;
+        }
+        self.inner.next_chunk(size, Some(timeout)).await
+    }
+
+    /// Read and discard a chunk of `size` octets sent with the BDAT command
+    ///
+    /// # Errors
+    ///
+    /// * timed-out
+    /// * internal connection reader error
+    pub async fn skip_chunk(
+        &mut self,
+        size: usize,
+        timeout: std::time::Duration,
+    ) -> std::io::Result<()> {
+        if !self.inner.has_pending_input() {
+            self.flush().await?;
+        }
+        self.inner.skip_chunk(size, Some(timeout)).await
+    }
 }
@@ -25,6 +25,7 @@ where
     ///
     pub inner: S,
     buf: Vec<u8>,
+    consumed: usize,
 }
 
 macro_rules! ready {
@@ -64,9 +65,28 @@ where
         Self {
             inner: stream,
             buf: Vec::new(),
+            consumed: 0,
         }
     }
 
+    /// Returns the number of bytes consumed since the last call to [`AbstractIO::reset_consumed`].
+    #[must_use]
+    pub const fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Reset the counter of bytes consumed.
+    pub fn reset_consumed(&mut self) {
+        self.consumed = 0;
+    }
+
+    /// Returns `true` if data sent by the client have been received but not consumed yet,
+    /// which is the case when commands are pipelined (RFC 2920).
+    #[must_use]
+    pub fn has_pending_input(&self) -> bool {
+        !self.buf.is_empty()
+    }
+
     /// Returns the next line from the inner stream. Or [`None`] if stream is closed.
     ///
     /// # Errors
@@ -84,6 +104,67 @@ where
         .await
         .map_err(|t| std::io::Error::new(std::io::ErrorKind::TimedOut, t))?
     }
+
+    /// Returns the next `size` octets of the inner stream, as is (no line splitting, no dot-stuffing).
+    ///
+    /// # Errors
+    ///
+    /// * timed-out
+    /// * failed to read
+    /// * stream closed before `size` octets were read
+    pub async fn next_chunk(
+        &mut self,
+        size: usize,
+        timeout: Option<std::time::Duration>,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut chunk = vec![];
+        self.read_chunk(size, timeout, |octets| chunk.extend_from_slice(octets))
+            .await?;
+        Ok(chunk)
+    }
+
+    /// Read and discard the next `size` octets of the inner stream.
+    ///
+    /// # Errors
+    ///
+    /// * see [`AbstractIO::next_chunk`]
+    pub async fn skip_chunk(
+        &mut self,
+        size: usize,
+        timeout: Option<std::time::Duration>,
+    ) -> std::io::Result<()> {
+        self.read_chunk(size, timeout, |_| ()).await
+    }
+
+    async fn read_chunk(
+        &mut self,
+        size: usize,
+        timeout: Option<std::time::Duration>,
+        mut on_octets: impl FnMut(&[u8]) + Send,
+    ) -> std::io::Result<()> {
+        tokio::time::timeout(
+            timeout.unwrap_or(std::time::Duration::from_millis(500)),
+            async {
+                let mut remaining = size;
+                while remaining != 0 {
+                    let available = tokio::io::AsyncBufReadExt::fill_buf(self).await?;
+                    if available.is_empty() {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            "stream closed in the middle of a chunk",
+                        ));
+                    }
+                    let len = std::cmp::min(available.len(), remaining);
+                    on_octets(&available[..len]);
+                    tokio::io::AsyncBufReadExt::consume(self, len);
+                    remaining -= len;
+                }
+                Ok(())
+            },
+        )
+        .await
+        .map_err(|t| std::io::Error::new(std::io::ErrorKind::TimedOut, t))?
+    }
 }
 
 impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin> tokio::io::AsyncBufRead
@@ -104,6 +185,7 @@ impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin> tokio::io::
 
     fn consume(mut self: std::pin::Pin<&mut Self>, amt: usize) {
         self.buf = self.buf[amt..].to_vec();
+        self.consumed += amt;
     }
 }
 
@@ -239,4 +321,40 @@ mod tests {
             std::io::ErrorKind::InvalidData
         );
     }
+
+    #[tokio::test]
+    async fn read_chunk() {
+        let input = b"BDAT 5\r\n\x00\r\n.\xffBDAT 3 LAST\r\nabc".to_vec();
+        let mut written = Vec::new();
+        let mut io = AbstractIO::new(Mock::new(input, &mut written));
+
+        assert_eq!(
+            io.next_line(None).await.unwrap(),
+            Some("BDAT 5".to_string())
+        );
+        assert_eq!(io.next_chunk(5, None).await.unwrap(), b"\x00\r\n.\xff");
+        assert_eq!(
+            io.next_line(None).await.unwrap(),
+            Some("BDAT 3 LAST".to_string())
+        );
+        io.skip_chunk(3, None).await.unwrap();
+        assert!(!io.has_pending_input());
+        assert_eq!(
+            io.next_chunk(1, None).await.unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[tokio::test]
+    async fn pending_input() {
+        let input = ["a\r\n", "b\r\n"].concat().as_bytes().to_vec();
+        let mut written = Vec::new();
+        let mut io = AbstractIO::new(Mock::new(input, &mut written));
+
+        assert!(!io.has_pending_input());
+        assert_eq!(io.next_line(None).await.unwrap(), Some("a".to_string()));
+        assert!(io.has_pending_input());
+        assert_eq!(io.next_line(None).await.unwrap(), Some("b".to_string()));
+        assert!(!io.has_pending_input());
+    }
 }
@@ -20,14 +20,14 @@ use crate::{
     receiver::auth_exchange::{on_authentication, AuthExchangeError},
 };
 use vsmtp_common::{
-    auth::Mechanism,
+    auth::{ClientCertificate, Mechanism},
     mail_context::MAIL_CAPACITY,
     re::{anyhow, log, tokio},
     state::StateSMTP,
     status::Status,
     CodeID, ConnectionKind, Either, MailParserOnFly, MessageBody, ParserOutcome, RawBody,
 };
-use vsmtp_config::{re::rustls, Resolvers};
+use vsmtp_config::{re::rustls, verify_client_certificate, Resolvers};
 use vsmtp_rule_engine::rule_engine::RuleEngine;
 
 mod auth_exchange;
@@ -88,6 +88,37 @@ where
         resolvers: std::sync::Arc<Resolvers>,
         mail_handler: &mut M,
     ) -> anyhow::Result<()>
+    where
+        M: OnMail + Send,
+    {
+        let result = self
+            .receive_plain(
+                tls_config,
+                rsasl,
+                rule_engine.clone(),
+                resolvers,
+                mail_handler,
+            )
+            .await;
+
+        rule_engine
+            .read()
+            .unwrap()
+            .close_milter_sessions(self.client_addr, self.timestamp);
+
+        // NOTE: the connection can be closed in the middle of a pipelined group of commands.
+        self.flush().await?;
+        result
+    }
+
+    async fn receive_plain<M>(
+        &mut self,
+        tls_config: Option<std::sync::Arc<rustls::ServerConfig>>,
+        rsasl: Option<std::sync::Arc<tokio::sync::Mutex<auth::Backend>>>,
+        rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
+        resolvers: std::sync::Arc<Resolvers>,
+        mail_handler: &mut M,
+    ) -> anyhow::Result<()>
     where
         M: OnMail + Send,
     {
@@ -238,6 +269,13 @@ where
     where
         M: OnMail + Send,
     {
+        self.flush().await?;
+        if self.inner.has_pending_input() {
+            // NOTE: commands pipelined after STARTTLS must not be processed (RFC 3207 section 4),
+            // they are discarded with the plain text stream.
+            log::warn!("client sent data before the TLS handshake, discarding it");
+        }
+
         let mut secured_conn = {
             let smtps_config = self.config.server.tls.as_ref().ok_or_else(|| {
                 anyhow::anyhow!(
@@ -252,7 +290,31 @@ where
             )
             .await??;
 
-            Connection::new_with(
+            // see https://datatracker.ietf.org/doc/html/rfc9266#section-2
+            let mut keying_material = vec![0; 32];
+            let channel_binding = stream
+                .get_ref()
+                .1
+                .export_keying_material(&mut keying_material, b"EXPORTER-Channel-Binding", None)
+                .ok()
+                .map(|()| keying_material);
+
+            let client_certificate = stream.get_ref().1.peer_certificates().and_then(|chain| {
+                verify_client_certificate(
+                    smtps_config,
+                    &self.config.server.r#virtual,
+                    stream.get_ref().1.sni_hostname(),
+                    chain,
+                )
+                .map_err(|e| log::warn!("client certificate is not trusted: {e}"))
+                .ok()?;
+
+                ClientCertificate::from_der(&chain.first()?.0)
+                    .map_err(|e| log::warn!("client certificate cannot be parsed: {e}"))
+                    .ok()
+            });
+
+            let mut secured_conn = Connection::new_with(
                 self.kind,
                 stream
                     .get_ref()
@@ -269,12 +331,18 @@ where
                 self.is_authenticated,
                 self.authentication_attempt,
                 stream,
-            )
+            );
+            secured_conn.channel_binding = channel_binding;
+            secured_conn.client_certificate = client_certificate;
+            secured_conn
         };
 
-        secured_conn
+        let result = secured_conn
             .receive_secured(rsasl, rule_engine, resolvers, mail_handler)
-            .await
+            .await;
+
+        secured_conn.flush().await?;
+        result
     }
 
     async fn handle_stream<M>(
@@ -286,10 +354,36 @@ where
     where
         M: OnMail + Send,
     {
-        // fetching the email using the transaction's stream.
+        let chunks = transaction.take_chunks();
+        let is_chunked = chunks.is_some();
+
+        // fetching the email using the transaction's stream, or the chunks received with BDAT.
         {
             log::info!("SMTP handshake completed, fetching email");
-            let mut body = {
+            let mut body = if let Some(chunks) = chunks {
+                // NOTE: the body is stored as is (`BODY=BINARYMIME`), but the headers
+                // must be text to be read and modified by the rules.
+                if let Ok(raw) = RawBody::from_bytes(chunks) {
+                    Either::Left(raw)
+                } else {
+                    log::warn!(
+                        "header section received with BDAT is not valid utf8, discarding it"
+                    );
+
+                    *helo_domain = Some(
+                        transaction
+                            .rule_state
+                            .context()
+                            .read()
+                            .unwrap()
+                            .envelop
+                            .helo
+                            .clone(),
+                    );
+                    self.send_code(CodeID::InvalidMessageContent).await?;
+                    return Ok(true);
+                }
+            } else {
                 let stream = Transaction::stream(self);
                 tokio::pin!(stream);
                 NoParsing::default().parse(stream).await?
@@ -313,6 +407,31 @@ where
             *message = MessageBody::from(body);
         }
 
+        // NOTE: the terminating <CRLF>.<CRLF> is not part of the message,
+        // and the size of the chunks has already been checked.
+        if !is_chunked
+            && self.inner.consumed().saturating_sub(".\r\n".len())
+                > self.config.server.smtp.message_size_max
+        {
+            log::warn!(
+                "message size exceeded the limit of `{}` bytes, discarding it",
+                self.config.server.smtp.message_size_max
+            );
+
+            *helo_domain = Some(
+                transaction
+                    .rule_state
+                    .context()
+                    .read()
+                    .unwrap()
+                    .envelop
+                    .helo
+                    .clone(),
+            );
+            self.send_code(CodeID::MessageSizeExceeded).await?;
+            return Ok(true);
+        }
+
         let status = transaction
             .rule_engine
             .read()
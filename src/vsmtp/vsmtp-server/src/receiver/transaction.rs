@@ -18,8 +18,9 @@ use super::connection::Connection;
 use vsmtp_common::{
     addr,
     auth::Mechanism,
+    dsn::{DsnReturn, NotifyOn, OriginalRecipient},
     envelop::Envelop,
-    event::Event,
+    event::{Event, MimeBodyType},
     mail_context::{ConnectionContext, MessageMetadata},
     rcpt::Rcpt,
     re::{anyhow, log, tokio},
@@ -34,12 +35,19 @@ enum ProcessedEvent {
     Reply(ReplyOrCodeID),
     ChangeState(StateSMTP),
     ReplyChangeState(StateSMTP, ReplyOrCodeID),
+    ReceiveChunk(usize, bool),
+    DiscardChunk(usize, ReplyOrCodeID),
+    DiscardChunkChangeState(usize, StateSMTP, ReplyOrCodeID),
 }
 
 pub struct Transaction {
     state: StateSMTP,
     pub rule_state: RuleState,
     pub rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
+    /// octets of the message received with BDAT, see RFC 3030
+    chunks: Option<Vec<u8>>,
+    /// the client declared `BODY=BINARYMIME`, the message must be sent with BDAT
+    binary_mime: bool,
 }
 
 impl std::fmt::Debug for Transaction {
@@ -86,11 +94,19 @@ impl Transaction {
         connection: &Connection<S>,
     ) -> ProcessedEvent {
         match (&self.state, event) {
+            // NOTE: only BDAT and RSET are accepted until the last chunk (RFC 3030 section 2).
+            (StateSMTP::Bdat, event) if !matches!(event, Event::BdatCmd(..) | Event::RsetCmd) => {
+                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::BadSequence))
+            }
+
             (_, Event::NoopCmd) => ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::Ok)),
 
             (_, Event::HelpCmd(_)) => ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::Help)),
 
             (_, Event::RsetCmd) => {
+                self.chunks = None;
+                self.binary_mime = false;
+                self.close_milter_sessions();
                 {
                     let state = self.rule_state.context();
                     let mut ctx = state.write().unwrap();
@@ -106,9 +122,9 @@ impl Transaction {
                 ProcessedEvent::ReplyChangeState(StateSMTP::Helo, ReplyOrCodeID::Left(CodeID::Ok))
             }
 
-            (_, Event::ExpnCmd(_) | Event::VrfyCmd(_) /*| Event::PrivCmd*/) => {
-                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::Unimplemented))
-            }
+            (_, Event::VrfyCmd(query)) => self.verify(query, &StateSMTP::Vrfy),
+
+            (_, Event::ExpnCmd(query)) => self.verify(query, &StateSMTP::Expn),
 
             (_, Event::QuitCmd) => ProcessedEvent::ReplyChangeState(
                 StateSMTP::Stop,
@@ -210,10 +226,43 @@ impl Transaction {
                 ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::AuthRequired))
             }
 
-            (StateSMTP::Helo, Event::MailCmd(mail_from, _body_bit_mime, _auth_mailbox)) => {
-                // TODO: store in envelop _body_bit_mime & _auth_mailbox
+            (StateSMTP::Helo, Event::MailCmd(_, _, _, Some(size), ..))
+                if size > connection.config.server.smtp.message_size_max =>
+            {
+                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::MessageSizeExceeded))
+            }
+
+            // NOTE: a non-ascii address can only be used with SMTPUTF8 (RFC 6531 section 3.4)
+            (StateSMTP::Helo, Event::MailCmd(Some(mail_from), .., false))
+                if !mail_from.is_ascii() =>
+            {
+                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::Utf8AddressNotPermitted))
+            }
+
+            (
+                StateSMTP::Helo,
+                Event::MailCmd(
+                    mail_from,
+                    body_bit_mime,
+                    _auth_mailbox,
+                    size,
+                    dsn_return,
+                    envelop_id,
+                    smtp_utf8,
+                ),
+            ) => {
+                // TODO: store in envelop _auth_mailbox
                 // TODO: handle : mail_from can be "<>""
-                self.set_mail_from(mail_from.unwrap(), connection);
+                self.chunks = None;
+                self.binary_mime = body_bit_mime == Some(MimeBodyType::Binary);
+                self.set_mail_from(
+                    mail_from.unwrap(),
+                    size,
+                    dsn_return,
+                    envelop_id,
+                    smtp_utf8,
+                    connection,
+                );
 
                 match self
                     .rule_engine
@@ -239,8 +288,18 @@ impl Transaction {
                 }
             }
 
-            (StateSMTP::MailFrom | StateSMTP::RcptTo, Event::RcptCmd(rcpt_to)) => {
-                self.set_rcpt_to(rcpt_to);
+            (StateSMTP::MailFrom | StateSMTP::RcptTo, Event::RcptCmd(rcpt_to, ..))
+                if !rcpt_to.is_ascii()
+                    && !self.rule_state.context().read().unwrap().envelop.smtp_utf8 =>
+            {
+                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::Utf8AddressNotPermitted))
+            }
+
+            (
+                StateSMTP::MailFrom | StateSMTP::RcptTo,
+                Event::RcptCmd(rcpt_to, notify, original_recipient),
+            ) => {
+                self.set_rcpt_to(rcpt_to, notify, original_recipient);
 
                 match self
                     .rule_engine
@@ -271,15 +330,87 @@ impl Transaction {
                 }
             }
 
+            // NOTE: a BINARYMIME message must be sent with BDAT (RFC 3030 section 3).
+            (StateSMTP::RcptTo, Event::DataCmd) if self.binary_mime => {
+                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::BadSequence))
+            }
+
             (StateSMTP::RcptTo, Event::DataCmd) => ProcessedEvent::ReplyChangeState(
                 StateSMTP::Data,
                 ReplyOrCodeID::Left(CodeID::DataStart),
             ),
 
+            (StateSMTP::RcptTo | StateSMTP::Bdat, Event::BdatCmd(size, _))
+                if self
+                    .chunks
+                    .as_ref()
+                    .map_or(0, Vec::len)
+                    .saturating_add(size)
+                    > connection.config.server.smtp.message_size_max =>
+            {
+                self.chunks = None;
+                ProcessedEvent::DiscardChunkChangeState(
+                    size,
+                    StateSMTP::Helo,
+                    ReplyOrCodeID::Left(CodeID::MessageSizeExceeded),
+                )
+            }
+
+            (StateSMTP::RcptTo | StateSMTP::Bdat, Event::BdatCmd(size, last)) => {
+                ProcessedEvent::ReceiveChunk(size, last)
+            }
+
+            // NOTE: the chunk must be read even if the command is rejected,
+            // otherwise it would be interpreted as commands.
+            (_, Event::BdatCmd(size, _)) => {
+                ProcessedEvent::DiscardChunk(size, ReplyOrCodeID::Left(CodeID::BadSequence))
+            }
+
             _ => ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::BadSequence)),
         }
     }
 
+    /// answer a VRFY or EXPN command with the `vrfy` or `expn` stage,
+    /// a "252" reply is sent if the rules do not provide one (RFC 5321 section 3.5.3).
+    fn verify(&mut self, query: String, stage: &StateSMTP) -> ProcessedEvent {
+        self.rule_state
+            .context()
+            .write()
+            .unwrap()
+            .connection
+            .verify_query = Some(query);
+
+        // NOTE: VRFY and EXPN are not part of the mail transaction, the status
+        // of those stages must not skip the rules of the transaction (and vice versa).
+        let skipped = self.rule_state.skipped().cloned();
+        self.rule_state.resume();
+
+        let status = self
+            .rule_engine
+            .read()
+            .unwrap()
+            .run_when(&mut self.rule_state, stage);
+
+        match skipped {
+            Some(skipped) => self.rule_state.skipping(skipped),
+            None => self.rule_state.resume(),
+        }
+
+        match status {
+            Status::Info(packet) | Status::Accept(packet) | Status::Faccept(packet) => {
+                ProcessedEvent::Reply(packet)
+            }
+            Status::Deny(packet) => ProcessedEvent::ReplyChangeState(StateSMTP::Stop, packet),
+            Status::Delegated(_)
+            | Status::DelegationResult
+            | Status::Next
+            | Status::Quarantine(_)
+            | Status::Packet(_) => {
+                ProcessedEvent::Reply(ReplyOrCodeID::Left(CodeID::CannotVerifyUser))
+            }
+        }
+    }
+
     fn set_connect<
         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
     >(
@@ -293,7 +424,21 @@ impl Transaction {
         ctx.connection.timestamp = connection.timestamp;
     }
 
+    /// the milter sessions of the previous transaction must not leak into the next one.
+    fn close_milter_sessions(&self) {
+        let (client_addr, timestamp) = {
+            let state = self.rule_state.context();
+            let ctx = state.read().unwrap();
+            (ctx.client_addr, ctx.connection.timestamp)
+        };
+        self.rule_engine
+            .read()
+            .unwrap()
+            .close_milter_sessions(client_addr, timestamp);
+    }
+
     fn set_helo(&mut self, helo: String) {
+        self.close_milter_sessions();
         {
             let state = self.rule_state.context();
             let mut ctx = state.write().unwrap();
@@ -303,6 +448,11 @@ impl Transaction {
                 helo,
                 mail_from: addr!("no@address.net"),
                 rcpt: vec![],
+                declared_size: None,
+                dsn_return: None,
+                envelop_id: None,
+                smtp_utf8: false,
+                null_reverse_path: false,
             };
         }
         {
@@ -316,6 +466,10 @@ impl Transaction {
     >(
         &mut self,
         mail_from: Address,
+        declared_size: Option<usize>,
+        dsn_return: Option<DsnReturn>,
+        envelop_id: Option<String>,
+        smtp_utf8: bool,
         connection: &Connection<S>,
     ) {
         let now = std::time::SystemTime::now();
@@ -325,6 +479,10 @@ impl Transaction {
             let mut ctx = state.write().unwrap();
             ctx.envelop.rcpt.clear();
             ctx.envelop.mail_from = mail_from;
+            ctx.envelop.declared_size = declared_size;
+            ctx.envelop.dsn_return = dsn_return;
+            ctx.envelop.envelop_id = envelop_id;
+            ctx.envelop.smtp_utf8 = smtp_utf8;
             ctx.metadata = Some(MessageMetadata {
                 timestamp: now,
                 message_id: format!(
@@ -352,14 +510,23 @@ impl Transaction {
         }
     }
 
-    fn set_rcpt_to(&mut self, rcpt_to: Address) {
+    fn set_rcpt_to(
+        &mut self,
+        rcpt_to: Address,
+        notify: Option<NotifyOn>,
+        original_recipient: Option<OriginalRecipient>,
+    ) {
         self.rule_state
             .context()
             .write()
             .unwrap()
             .envelop
             .rcpt
-            .push(Rcpt::new(rcpt_to));
+            .push(Rcpt {
+                notify,
+                original_recipient,
+                ..Rcpt::new(rcpt_to)
+            });
     }
 
     pub async fn new<
@@ -379,6 +546,8 @@ impl Transaction {
             ConnectionContext {
                 timestamp: conn.timestamp,
                 credentials: None,
+                verify_query: None,
+                client_certificate: conn.client_certificate.clone(),
                 server_name: conn.server_name.clone(),
                 server_address: conn.server_addr,
                 is_authenticated: conn.is_authenticated,
@@ -394,15 +563,25 @@ impl Transaction {
             },
             rule_state,
             rule_engine,
+            chunks: None,
+            binary_mime: false,
         })
     }
 
+    /// Take the octets of the message if it has been received with BDAT.
+    pub fn take_chunks(&mut self) -> Option<Vec<u8>> {
+        self.chunks.take()
+    }
+
     pub fn stream<
         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
     >(
         connection: &mut Connection<S>,
     ) -> impl tokio_stream::Stream<Item = String> + '_ {
         let read_timeout = get_timeout_for_state(&connection.config, &StateSMTP::Data);
+        let message_size_max = connection.config.server.smtp.message_size_max;
+        connection.inner.reset_consumed();
+
         async_stream::stream! {
             loop {
                 match connection.read(read_timeout).await {
@@ -411,6 +590,9 @@ impl Transaction {
                         log::trace!("parsed=`{command_or_code:?}`");
 
                         match command_or_code {
+                            // NOTE: the data are still read until <CRLF>.<CRLF> but discarded,
+                            // the error is sent to the client once the transaction is over.
+                            Ok(Some(_)) if connection.inner.consumed() > message_size_max => {}
                             Ok(Some(line)) => yield line,
                             Ok(None) => break,
                             Err(code) => {
@@ -427,6 +609,7 @@ impl Transaction {
         }
     }
 
+    #[allow(clippy::too_many_lines)]
     pub async fn receive<
         S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Sync + Send + Unpin + std::fmt::Debug,
     >(
@@ -511,6 +694,62 @@ impl Transaction {
                                     get_timeout_for_state(&connection.config, &self.state);
                                 connection.send_reply_or_code(reply_to_send).await?;
                             }
+                            ProcessedEvent::ReceiveChunk(size, last) => {
+                                let chunk = connection
+                                    .read_chunk(
+                                        size,
+                                        get_timeout_for_state(&connection.config, &StateSMTP::Data),
+                                    )
+                                    .await?;
+                                self.chunks.get_or_insert_with(Vec::new).extend(chunk);
+
+                                let new_state = if last {
+                                    StateSMTP::Data
+                                } else {
+                                    StateSMTP::Bdat
+                                };
+                                if self.state != new_state {
+                                    log::info!(
+                                        "STATE: {old_state:?} => {new_state:?}",
+                                        old_state = self.state,
+                                    );
+                                    self.state = new_state;
+                                    read_timeout =
+                                        get_timeout_for_state(&connection.config, &self.state);
+                                }
+                                if !last {
+                                    connection.send_code(CodeID::Ok).await?;
+                                }
+                            }
+                            ProcessedEvent::DiscardChunk(size, reply_to_send) => {
+                                connection
+                                    .skip_chunk(
+                                        size,
+                                        get_timeout_for_state(&connection.config, &StateSMTP::Data),
+                                    )
+                                    .await?;
+                                connection.send_reply_or_code(reply_to_send).await?;
+                            }
+                            ProcessedEvent::DiscardChunkChangeState(
+                                size,
+                                new_state,
+                                reply_to_send,
+                            ) => {
+                                connection
+                                    .skip_chunk(
+                                        size,
+                                        get_timeout_for_state(&connection.config, &StateSMTP::Data),
+                                    )
+                                    .await?;
+                                log::info!(
+                                    "STATE: {old_state:?} => {new_state:?}",
+                                    old_state = self.state,
+                                );
+                                self.state = new_state;
+                                read_timeout =
+                                    get_timeout_for_state(&connection.config, &self.state);
+                                connection.send_reply_or_code(reply_to_send).await?;
+                            }
                         }
                     }
                     Ok(None) => {
@@ -541,7 +780,7 @@ fn get_timeout_for_state(
         StateSMTP::Helo => config.server.smtp.timeout_client.helo,
         StateSMTP::MailFrom => config.server.smtp.timeout_client.mail_from,
         StateSMTP::RcptTo => config.server.smtp.timeout_client.rcpt_to,
-        StateSMTP::Data => config.server.smtp.timeout_client.data,
+        StateSMTP::Data | StateSMTP::Bdat => config.server.smtp.timeout_client.data,
         _ => std::time::Duration::from_millis(TIMEOUT_DEFAULT),
     }
 }
@@ -14,7 +14,7 @@
  * this program. If not, see https://www.gnu.org/licenses/.
  *
 */
-use crate::{delivery, processing, ProcessMessage, Server};
+use crate::{delivery, processing, server::Reload, ProcessMessage, Server};
 use vsmtp_common::{
     queue::Queue,
     re::{
@@ -23,7 +23,6 @@ use vsmtp_common::{
     },
 };
 use vsmtp_config::Config;
-use vsmtp_rule_engine::rule_engine::RuleEngine;
 
 fn init_runtime<F>(
     sender: tokio::sync::mpsc::Sender<()>,
@@ -63,13 +62,51 @@ where
         .map_err(anyhow::Error::new)
 }
 
+/// read the configuration file, or the default configuration if no path is given.
+fn read_config(config_path: Option<&std::path::Path>) -> anyhow::Result<Config> {
+    config_path.map_or_else(
+        || Ok(Config::default()),
+        |path| {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Cannot read file '{}'", path.display()))
+                .and_then(|f| Config::from_toml(&f).context("File contains format error"))
+        },
+    )
+}
+
+/// build a new configuration, rule engine, resolvers, tls and sasl parameters from the files on disk.
+///
+/// nothing is swapped if one of them cannot be built, otherwise they are sent
+/// at once to the receiver, the processing and the delivery.
+fn reload(
+    config_path: Option<&std::path::Path>,
+    reload_sender: &tokio::sync::watch::Sender<Reload>,
+) -> anyhow::Result<()> {
+    let config = read_config(config_path).context("Cannot parse the configuration")?;
+    let reload = Reload::new(config)?;
+
+    reload_sender
+        .send(reload)
+        .map_err(|_| anyhow::anyhow!("runtimes are not running"))
+}
+
 /// Start the `vSMTP` server's runtime
 ///
+/// Sending `SIGHUP` to the process reload the vsl rules and the configuration
+/// file at `config_path`. Only the rules, the resolvers, the tls and the virtual entries are
+/// reloaded, changing the queues, the interfaces or the thread pools requires a restart.
+///
+/// The pid file is removed when the server stops.
+///
 /// # Errors
 ///
+/// * the queues or the pid file cannot be created
+/// * the rule engine, the resolvers, the tls or sasl parameters cannot be built
+/// * the pid file cannot be removed
 #[allow(clippy::module_name_repetitions)]
 pub fn start_runtime(
     config: Config,
+    config_path: Option<std::path::PathBuf>,
     sockets: (
         Vec<std::net::TcpListener>,
         Vec<std::net::TcpListener>,
@@ -81,6 +118,9 @@ pub fn start_runtime(
         .map(|q| vsmtp_common::queue_path!(create_if_missing => &config.server.queues.dirpath, q))
         .collect::<std::io::Result<Vec<_>>>()?;
 
+    std::fs::write(config.pid_filepath(), std::process::id().to_string())
+        .with_context(|| format!("cannot write '{}'", config.pid_filepath().display()))?;
+
     let mut error_handler = tokio::sync::mpsc::channel::<()>(3);
 
     let (delivery_channel, working_channel) = (
@@ -88,14 +128,13 @@ pub fn start_runtime(
         tokio::sync::mpsc::channel::<ProcessMessage>(config.server.queues.working.channel_size),
     );
 
-    let rule_engine = RuleEngine::new(&config, &config.app.vsl.filepath.clone())?;
+    let pid_filepath = config.pid_filepath();
+    let initial = Reload::new(config)?;
 
-    let resolvers = std::sync::Arc::new(
-        vsmtp_config::build_resolvers(&config).context("could not initialize dns")?,
-    );
-
-    let config_arc = std::sync::Arc::new(config);
-    let rule_engine_arc = std::sync::Arc::new(std::sync::RwLock::new(rule_engine));
+    let config_arc = initial.config.clone();
+    let rule_engine_arc = initial.rule_engine.clone();
+    let resolvers = initial.resolvers.clone();
+    let (reload_sender, reload_receiver) = tokio::sync::watch::channel(initial);
 
     let _tasks_delivery = init_runtime(
         error_handler.0.clone(),
@@ -106,6 +145,7 @@ pub fn start_runtime(
             rule_engine_arc.clone(),
             resolvers.clone(),
             delivery_channel.1,
+            reload_receiver.clone(),
         ),
         timeout,
     )?;
@@ -120,10 +160,12 @@ pub fn start_runtime(
             resolvers.clone(),
             working_channel.1,
             delivery_channel.0.clone(),
+            reload_receiver.clone(),
         ),
         timeout,
     )?;
 
+    let receiver_rule_engine = rule_engine_arc;
     let _tasks_receiver = init_runtime(
         error_handler.0.clone(),
         "receiver",
@@ -131,12 +173,12 @@ pub fn start_runtime(
         async move {
             let server = match Server::new(
                 config_arc.clone(),
-                rule_engine_arc.clone(),
+                receiver_rule_engine,
                 resolvers.clone(),
                 working_channel.0.clone(),
                 delivery_channel.0.clone(),
             ) {
-                Ok(server) => server,
+                Ok(server) => server.with_reload_receiver(reload_receiver),
                 Err(error) => {
                     log::error!("{}", error);
                     return;
@@ -155,10 +197,19 @@ pub fn start_runtime(
         signal_hook::consts::SIGTERM,
         // Ctrl+C on a terminal
         signal_hook::consts::SIGINT,
+        // Send by `systemctl reload` or `vqueue reload`
+        signal_hook::consts::SIGHUP,
     ])?;
     let _signal_handler = std::thread::spawn(move || {
         for sig in signals.forever() {
             log::info!("Received signal '{}'", sig);
+            if sig == signal_hook::consts::SIGHUP {
+                log::warn!("Reloading vSMTP configuration and rules");
+                if let Err(e) = reload(config_path.as_deref(), &reload_sender) {
+                    log::error!("reload failed, keeping the previous configuration: {e:?}");
+                }
+                continue;
+            }
             log::warn!("Stopping vSMTP server");
             error_handler_sig
                 .blocking_send(())
@@ -168,7 +219,8 @@ pub fn start_runtime(
 
     error_handler.1.blocking_recv();
 
-    Ok(())
+    std::fs::remove_file(&pid_filepath)
+        .with_context(|| format!("cannot remove '{}'", pid_filepath.display()))
 
     // if the runtime panicked (receiver/processing/delivery)
     // .join() would return an error,
@@ -188,6 +240,7 @@ mod tests {
     fn basic() -> anyhow::Result<()> {
         start_runtime(
             config::local_test(),
+            None,
             (
                 vec![std::net::TcpListener::bind("0.0.0.0:22001").unwrap()],
                 vec![std::net::TcpListener::bind("0.0.0.0:22002").unwrap()],
@@ -26,7 +26,7 @@ use vsmtp_common::{
     },
     CodeID, ConnectionKind,
 };
-use vsmtp_config::{get_rustls_config, re::rustls, Config, Resolvers};
+use vsmtp_config::{build_resolvers, get_rustls_config, re::rustls, Config, Resolvers};
 use vsmtp_rule_engine::rule_engine::RuleEngine;
 
 /// TCP/IP server
@@ -38,6 +38,45 @@ pub struct Server {
     resolvers: std::sync::Arc<Resolvers>,
     working_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
     delivery_sender: tokio::sync::mpsc::Sender<ProcessMessage>,
+    reload_receiver: Option<tokio::sync::watch::Receiver<Reload>>,
+}
+
+/// The configuration, the rules and the resolvers used by every runtime,
+/// with the TLS and SASL parameters of the server.
+///
+/// Everything is built before being swapped, a reload being either complete or discarded.
+#[derive(Clone)]
+pub struct Reload {
+    pub(crate) config: std::sync::Arc<Config>,
+    pub(crate) rule_engine: std::sync::Arc<std::sync::RwLock<RuleEngine>>,
+    pub(crate) resolvers: std::sync::Arc<Resolvers>,
+    tls_config: Option<std::sync::Arc<rustls::ServerConfig>>,
+    rsasl: Option<std::sync::Arc<tokio::sync::Mutex<auth::Backend>>>,
+}
+
+impl Reload {
+    /// Build the rules, the resolvers, the TLS and SASL parameters of `config`.
+    ///
+    /// # Errors
+    ///
+    /// * the rule engine cannot be built
+    /// * cannot build the resolvers
+    /// * cannot initialize [rustls] config
+    /// * cannot initialize the SASL backend
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        let config = std::sync::Arc::new(config);
+        let rule_engine = RuleEngine::new(&config, &config.app.vsl.filepath.clone())
+            .context("Cannot compile the vsl rules")?;
+        let resolvers = build_resolvers(&config).context("could not initialize dns")?;
+
+        Ok(Self {
+            tls_config: build_tls_config(&config)?,
+            rsasl: build_rsasl(&config)?,
+            rule_engine: std::sync::Arc::new(std::sync::RwLock::new(rule_engine)),
+            resolvers: std::sync::Arc::new(resolvers),
+            config,
+        })
+    }
 }
 
 /// Create a `TCPListener` ready to be listened to
@@ -72,6 +111,30 @@ fn listener_to_stream(
     }
 }
 
+fn build_tls_config(
+    config: &Config,
+) -> anyhow::Result<Option<std::sync::Arc<rustls::ServerConfig>>> {
+    config
+        .server
+        .tls
+        .as_ref()
+        .map(|smtps| get_rustls_config(smtps, &config.server.r#virtual).map(std::sync::Arc::new))
+        .transpose()
+}
+
+fn build_rsasl(
+    config: &std::sync::Arc<Config>,
+) -> anyhow::Result<Option<std::sync::Arc<tokio::sync::Mutex<auth::Backend>>>> {
+    if config.server.smtp.auth.is_none() {
+        return Ok(None);
+    }
+
+    let mut rsasl = vsmtp_rsasl::SASL::new().map_err(|e| anyhow::anyhow!("{}", e))?;
+    rsasl.install_callback::<auth::Callback>();
+    rsasl.store(Box::new(config.clone()));
+    Ok(Some(std::sync::Arc::new(tokio::sync::Mutex::new(rsasl))))
+}
+
 impl Server {
     /// Create a server with the configuration provided, and the sockets already bound
     ///
@@ -94,33 +157,40 @@ impl Server {
         }
 
         Ok(Self {
-            tls_config: if let Some(smtps) = &config.server.tls {
-                Some(std::sync::Arc::new(get_rustls_config(
-                    smtps,
-                    &config.server.r#virtual,
-                )?))
-            } else {
-                None
-            },
-            rsasl: if config.server.smtp.auth.is_some() {
-                Some(std::sync::Arc::new(tokio::sync::Mutex::new({
-                    let mut rsasl =
-                        vsmtp_rsasl::SASL::new().map_err(|e| anyhow::anyhow!("{}", e))?;
-                    rsasl.install_callback::<auth::Callback>();
-                    rsasl.store(Box::new(config.clone()));
-                    rsasl
-                })))
-            } else {
-                None
-            },
+            tls_config: build_tls_config(&config)?,
+            rsasl: build_rsasl(&config)?,
             config,
             rule_engine,
             resolvers,
             working_sender,
             delivery_sender,
+            reload_receiver: None,
         })
     }
 
+    /// Reload the configuration of the server each time a new one is sent,
+    /// see [`Server::reload`].
+    #[must_use]
+    pub fn with_reload_receiver(
+        mut self,
+        reload_receiver: tokio::sync::watch::Receiver<Reload>,
+    ) -> Self {
+        self.reload_receiver = Some(reload_receiver);
+        self
+    }
+
+    /// Replace the configuration, the rules, the TLS parameters (certificates and virtual entries)
+    /// and the resolvers used for the next connections.
+    ///
+    /// The connections already accepted keep the previous ones.
+    pub fn reload(&mut self, reload: Reload) {
+        self.tls_config = reload.tls_config;
+        self.rsasl = reload.rsasl;
+        self.rule_engine = reload.rule_engine;
+        self.resolvers = reload.resolvers;
+        self.config = reload.config;
+    }
+
     #[tracing::instrument(skip(self, stream))]
     async fn handle_client(
         &self,
@@ -165,6 +235,16 @@ impl Server {
 
         client_counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
+        // NOTE: the session runs with a snapshot of the rules, not affected by a reload.
+        let rule_engine = match self.rule_engine.read() {
+            Ok(rule_engine) => std::sync::Arc::new(std::sync::RwLock::new(rule_engine.clone())),
+            Err(e) => {
+                log::error!("rule engine mutex poisoned: {e}");
+                client_counter.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                return;
+            }
+        };
+
         let session = Self::run_session(
             Connection::new(
                 kind,
@@ -175,7 +255,7 @@ impl Server {
             ),
             self.tls_config.clone(),
             self.rsasl.clone(),
-            self.rule_engine.clone(),
+            rule_engine,
             self.resolvers.clone(),
             self.working_sender.clone(),
             self.delivery_sender.clone(),
@@ -197,7 +277,7 @@ impl Server {
     /// * failed to convert sockets to `[tokio::net::TcpListener]`
     #[tracing::instrument(skip(self, sockets))]
     pub async fn listen_and_serve(
-        self,
+        mut self,
         sockets: (
             Vec<std::net::TcpListener>,
             Vec<std::net::TcpListener>,
@@ -248,19 +328,36 @@ impl Server {
             map.keys().collect::<Vec<_>>()
         );
 
-        while let Some((server_addr, (kind, client))) =
-            tokio_stream::StreamExt::next(&mut map).await
-        {
-            let (stream, client_addr) = client?;
+        let mut reload_receiver = self.reload_receiver.take();
 
-            self.handle_client(
-                client_counter.clone(),
-                kind,
-                stream,
-                client_addr,
-                server_addr,
-            )
-            .await;
+        loop {
+            tokio::select! {
+                Some((server_addr, (kind, client))) = tokio_stream::StreamExt::next(&mut map) => {
+                    let (stream, client_addr) = client?;
+
+                    self.handle_client(
+                        client_counter.clone(),
+                        kind,
+                        stream,
+                        client_addr,
+                        server_addr,
+                    )
+                    .await;
+                }
+                Some(reload) = async {
+                    match &mut reload_receiver {
+                        Some(receiver) => match receiver.changed().await {
+                            Ok(()) => Some(receiver.borrow().clone()),
+                            Err(_) => None,
+                        },
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    self.reload(reload);
+                    log::info!("configuration of the server reloaded");
+                }
+                else => break,
+            }
         }
         Ok(())
     }
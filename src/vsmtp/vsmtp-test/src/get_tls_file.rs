@@ -37,3 +37,21 @@ pub const fn get_pkcs8_key() -> &'static str {
 pub const fn get_ec256_key() -> &'static str {
     include_str!("./template/certs/private_key.ec256.key")
 }
+
+///
+#[must_use]
+pub const fn get_client_ca() -> &'static str {
+    include_str!("./template/certs/client/ca.crt")
+}
+
+///
+#[must_use]
+pub const fn get_client_certificate() -> &'static str {
+    include_str!("./template/certs/client/client.crt")
+}
+
+///
+#[must_use]
+pub const fn get_client_key() -> &'static str {
+    include_str!("./template/certs/client/client.key")
+}
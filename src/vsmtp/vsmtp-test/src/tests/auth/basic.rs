@@ -47,6 +47,11 @@ async fn plain_in_clair_secured() {
             "250-AUTH \r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "538 5.7.11 Encryption required for requested authentication mechanism\r\n",
         ].concat()
@@ -97,9 +102,14 @@ async fn plain_in_clair_unsecured() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "235 2.7.0 Authentication succeeded\r\n",
             "250 Ok\r\n",
@@ -157,9 +167,14 @@ async fn login_in_clair_unsecured() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             &format!("334 {}\r\n", base64::encode("User Name")),
             &format!("334 {}\r\n", base64::encode("Password")),
@@ -217,9 +232,14 @@ async fn anonymous_in_clair_unsecured() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "235 2.7.0 Authentication succeeded\r\n",
             "250 Ok\r\n",
@@ -275,9 +295,14 @@ async fn plain_in_clair_unsecured_utf8() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "235 2.7.0 Authentication succeeded\r\n",
             "250 Ok\r\n",
@@ -313,9 +338,14 @@ async fn plain_in_clair_invalid_credentials() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "535 5.7.8 Authentication credentials invalid\r\n"
         ].concat()
@@ -350,9 +380,14 @@ async fn plain_in_clair_unsecured_cancel() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "334 \r\n",
             "501 Authentication canceled by client\r\n",
@@ -386,9 +421,14 @@ async fn plain_in_clair_unsecured_bad_base64() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "501 5.5.2 Invalid, not base64\r\n",
             "503 Bad sequence of commands\r\n",
@@ -441,9 +481,14 @@ async fn plain_in_clair_unsecured_without_initial_response() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             // See https://datatracker.ietf.org/doc/html/rfc4422#section-5 2.a
             "334 \r\n",
@@ -478,9 +523,14 @@ async fn no_auth_with_authenticated_policy() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "530 5.7.0 Authentication required\r\n",
         ].concat()
@@ -507,9 +557,14 @@ async fn client_must_not_start() {
         [
             "220 testserver.com Service ready\r\n",
             "250-testserver.com\r\n",
-            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "501 5.7.0 Client must not start with this mechanism\r\n"
         ].concat()
@@ -71,3 +71,5 @@ pub fn unsafe_auth_config() -> Config {
 }
 
 mod basic;
+mod oauth;
+mod scram;
@@ -0,0 +1,146 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{test_receiver, tests::auth::unsafe_auth_config};
+use vsmtp_common::re::{base64, tokio, vsmtp_rsasl};
+use vsmtp_server::auth;
+
+const EHLO: &str = concat!(
+    "220 testserver.com Service ready\r\n",
+    "250-testserver.com\r\n",
+    "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
+    "250-STARTTLS\r\n",
+    "250-8BITMIME\r\n",
+    "250-SIZE 20000000\r\n",
+    "250-PIPELINING\r\n",
+    "250-CHUNKING\r\n",
+    "250-BINARYMIME\r\n",
+    "250-DSN\r\n",
+    "250 SMTPUTF8\r\n",
+);
+
+const TOKEN: &str = "vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==";
+
+#[tokio::test]
+async fn oauthbearer() {
+    let config = unsafe_auth_config();
+    assert!(test_receiver! {
+        with_auth => {
+            let mut rsasl = vsmtp_rsasl::SASL::new().unwrap();
+            rsasl.install_callback::<auth::Callback>();
+            rsasl.store(Box::new(std::sync::Arc::new(config.clone())));
+            rsasl
+        },
+        with_config => config.clone(),
+        [
+            "EHLO client.com\r\n",
+            &format!(
+                "AUTH OAUTHBEARER {}\r\n",
+                base64::encode(format!("n,a=hello,\x01host=testserver.com\x01port=25\x01auth=Bearer {TOKEN}\x01\x01"))
+            ),
+            "QUIT\r\n"
+        ].concat(),
+        [
+            EHLO,
+            "235 2.7.0 Authentication succeeded\r\n",
+            "221 Service closing transmission channel\r\n"
+        ].concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn xoauth2_without_initial_response() {
+    let config = unsafe_auth_config();
+    assert!(test_receiver! {
+        with_auth => {
+            let mut rsasl = vsmtp_rsasl::SASL::new().unwrap();
+            rsasl.install_callback::<auth::Callback>();
+            rsasl.store(Box::new(std::sync::Arc::new(config.clone())));
+            rsasl
+        },
+        with_config => config.clone(),
+        [
+            "EHLO client.com\r\n",
+            "AUTH XOAUTH2\r\n",
+            &format!(
+                "{}\r\n",
+                base64::encode(format!("user=hello\x01auth=Bearer {TOKEN}\x01\x01"))
+            ),
+            "QUIT\r\n"
+        ].concat(),
+        [
+            EHLO,
+            "334 \r\n",
+            "235 2.7.0 Authentication succeeded\r\n",
+            "221 Service closing transmission channel\r\n"
+        ].concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn oauthbearer_invalid_token() {
+    let config = unsafe_auth_config();
+    assert!(test_receiver! {
+        with_auth => {
+            let mut rsasl = vsmtp_rsasl::SASL::new().unwrap();
+            rsasl.install_callback::<auth::Callback>();
+            rsasl.store(Box::new(std::sync::Arc::new(config.clone())));
+            rsasl
+        },
+        with_config => config.clone(),
+        [
+            "EHLO client.com\r\n",
+            &format!(
+                "AUTH OAUTHBEARER {}\r\n",
+                base64::encode("n,a=hello,\x01auth=Bearer expired\x01\x01")
+            ),
+            "AQ==\r\n",
+        ].concat(),
+        [
+            EHLO,
+            &format!("334 {}\r\n", base64::encode(r#"{"status":"invalid_token"}"#)),
+            "535 5.7.8 Authentication credentials invalid\r\n"
+        ].concat()
+    }
+    .is_err());
+}
+
+#[tokio::test]
+async fn xoauth2_ill_formed() {
+    let config = unsafe_auth_config();
+    assert!(test_receiver! {
+        with_auth => {
+            let mut rsasl = vsmtp_rsasl::SASL::new().unwrap();
+            rsasl.install_callback::<auth::Callback>();
+            rsasl.store(Box::new(std::sync::Arc::new(config.clone())));
+            rsasl
+        },
+        with_config => config.clone(),
+        [
+            "EHLO client.com\r\n",
+            &format!("AUTH XOAUTH2 {}\r\n", base64::encode(format!("\0hello\0{TOKEN}"))),
+            "\r\n",
+        ].concat(),
+        [
+            EHLO,
+            &format!("334 {}\r\n", base64::encode(r#"{"status":"invalid_token"}"#)),
+            "535 5.7.8 Authentication credentials invalid\r\n"
+        ].concat()
+    }
+    .is_err());
+}
@@ -0,0 +1,141 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{test_receiver, tests::auth::unsafe_auth_config};
+use vsmtp_common::{
+    auth::Mechanism,
+    mail_context::ConnectionContext,
+    re::{base64, tokio, vsmtp_rsasl},
+};
+use vsmtp_config::Config;
+use vsmtp_rule_engine::rule_engine::RuleEngine;
+use vsmtp_server::auth::{self, SessionData};
+
+/// run a complete SASL exchange between a gsasl client and the server's callback.
+fn exchange(
+    config: &Config,
+    mechanism: Mechanism,
+    authid: &str,
+    password: &str,
+) -> Result<(), vsmtp_rsasl::SaslError> {
+    let mut server = vsmtp_rsasl::SASL::new().unwrap();
+    server.install_callback::<auth::Callback>();
+    server.store(Box::new(std::sync::Arc::new(config.clone())));
+
+    let mut server_session = server.server_start(&mechanism.to_string()).unwrap();
+    server_session.store(Box::new(SessionData {
+        rule_engine: std::sync::Arc::new(std::sync::RwLock::new(
+            RuleEngine::new(config, &config.app.vsl.filepath.clone()).unwrap(),
+        )),
+        resolvers: std::sync::Arc::new(std::collections::HashMap::new()),
+        conn: ConnectionContext {
+            timestamp: std::time::SystemTime::now(),
+            credentials: None,
+            verify_query: None,
+            client_certificate: None,
+            is_authenticated: false,
+            is_secured: false,
+            server_name: "testserver.com".to_string(),
+            server_address: "127.0.0.1:25".parse().unwrap(),
+        },
+        mechanism,
+        channel_binding: None,
+        scram_secrets: None,
+    }));
+
+    let mut client = vsmtp_rsasl::SASL::<(), ()>::new().unwrap();
+    let mut client_session = client.client_start(&mechanism.to_string()).unwrap();
+    client_session.set_property(vsmtp_rsasl::Property::GSASL_AUTHID, authid.as_bytes());
+    client_session.set_property(vsmtp_rsasl::Property::GSASL_PASSWORD, password.as_bytes());
+
+    let mut client_message = match client_session.step(&[])? {
+        vsmtp_rsasl::Step::NeedsMore(buffer) => buffer.to_vec(),
+        vsmtp_rsasl::Step::Done(_) => panic!("client-first mechanism"),
+    };
+
+    loop {
+        match server_session.step(&client_message)? {
+            vsmtp_rsasl::Step::NeedsMore(buffer) => match client_session.step(&buffer)? {
+                vsmtp_rsasl::Step::NeedsMore(buffer) | vsmtp_rsasl::Step::Done(buffer) => {
+                    client_message = buffer.to_vec();
+                }
+            },
+            // the client verifies the server signature.
+            vsmtp_rsasl::Step::Done(buffer) => {
+                return match client_session.step(&buffer)? {
+                    vsmtp_rsasl::Step::Done(_) => Ok(()),
+                    vsmtp_rsasl::Step::NeedsMore(_) => panic!("the exchange should be over"),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn scram_sha_256() {
+    let config = unsafe_auth_config();
+    assert!(exchange(&config, Mechanism::ScramSha256, "hello", "world").is_ok());
+    assert!(exchange(&config, Mechanism::ScramSha256, "hello", "foo").is_err());
+    assert!(exchange(&config, Mechanism::ScramSha256, "foo", "world").is_err());
+}
+
+#[test]
+fn scram_sha_1() {
+    let config = unsafe_auth_config();
+    assert!(exchange(&config, Mechanism::ScramSha1, "hello", "world").is_ok());
+    assert!(exchange(&config, Mechanism::ScramSha1, "hello", "foo").is_err());
+}
+
+#[test]
+fn scram_with_password() {
+    // NOTE: no salted keys for this user, the password is queried instead.
+    let config = unsafe_auth_config();
+    assert!(exchange(&config, Mechanism::ScramSha256, "john", "doe").is_ok());
+    assert!(exchange(&config, Mechanism::ScramSha256, "john", "foo").is_err());
+}
+
+#[tokio::test]
+async fn scram_plus_in_clair() {
+    let config = unsafe_auth_config();
+    assert!(test_receiver! {
+        with_auth => {
+            let mut rsasl = vsmtp_rsasl::SASL::new().unwrap();
+            rsasl.install_callback::<auth::Callback>();
+            rsasl.store(Box::new(std::sync::Arc::new(config.clone())));
+            rsasl
+        },
+        with_config => config.clone(),
+        [
+            "EHLO client.com\r\n",
+            &format!("AUTH SCRAM-SHA-256-PLUS {}\r\n", base64::encode("p=tls-exporter,,n=hello,r=rOprNGfwEbeRWgbNEkqO")),
+        ].concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-AUTH PLAIN LOGIN CRAM-MD5 ANONYMOUS SCRAM-SHA-1 SCRAM-SHA-256 OAUTHBEARER XOAUTH2\r\n",
+            "250-STARTTLS\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "538 5.7.11 Encryption required for requested authentication mechanism\r\n",
+        ].concat()
+    }
+    .is_err());
+}
@@ -0,0 +1,306 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{config, receiver::test_receiver_inner, test_receiver};
+use vsmtp_common::{mail_context::MailContext, re::tokio, CodeID, MessageBody};
+use vsmtp_server::{Connection, OnMail};
+
+struct ExpectBody(&'static [u8]);
+
+#[async_trait::async_trait]
+impl OnMail for ExpectBody {
+    async fn on_mail<
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+    >(
+        &mut self,
+        _: &mut Connection<S>,
+        mail: Box<MailContext>,
+        message: MessageBody,
+    ) -> CodeID {
+        assert_eq!(mail.envelop.mail_from.full(), "a@b");
+        assert_eq!(mail.envelop.rcpt.len(), 1);
+        pretty_assertions::assert_eq!(message.inner().to_bytes(), self.0);
+
+        CodeID::Ok
+    }
+}
+
+fn bdat(chunk: &str, last: bool) -> String {
+    format!(
+        "BDAT {}{}\r\n{chunk}",
+        chunk.len(),
+        if last { " LAST" } else { "" }
+    )
+}
+
+#[tokio::test]
+async fn chunks() {
+    assert!(test_receiver! {
+        on_mail => &mut ExpectBody(b"From: a@b\r\n\r\nhello\r\n.\r\n..world\r\n"),
+        [
+            "EHLO client.com\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            bdat("From: a@b\r\n\r\nhel", false).as_str(),
+            bdat("lo\r\n.\r\n..world\r\n", true).as_str(),
+            "QUIT\r\n"
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-STARTTLS\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn empty_last_chunk() {
+    assert!(test_receiver! {
+        on_mail => &mut ExpectBody(b"From: a@b\r\n\r\nhello\r\n"),
+        [
+            "HELO client.com\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            bdat("From: a@b\r\n\r\nhello\r\n", false).as_str(),
+            "BDAT 0 LAST\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn binarymime() {
+    let mut input = [
+        "EHLO client.com\r\n",
+        "MAIL FROM:<a@b> BODY=BINARYMIME\r\n",
+        "RCPT TO:<b@c>\r\n",
+        "DATA\r\n",
+        "BDAT 37 LAST\r\n",
+        "Content-Type: image/png\r\n\r\n",
+    ]
+    .concat()
+    .into_bytes();
+    input.extend_from_slice(b"\x89PNG\r\n\x1a\n\x00\xff");
+    input.extend_from_slice(b"QUIT\r\n");
+
+    assert!(test_receiver_inner(
+        &mut ExpectBody(b"Content-Type: image/png\r\n\r\n\x89PNG\r\n\x1a\n\x00\xff"),
+        &input,
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-STARTTLS\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+        .as_bytes(),
+        std::sync::Arc::new(config::local_test()),
+        None,
+    )
+    .await
+    .is_ok());
+}
+
+#[tokio::test]
+async fn invalid_header_section() {
+    let mut input = [
+        "HELO client.com\r\n",
+        "MAIL FROM:<a@b> BODY=BINARYMIME\r\n",
+        "RCPT TO:<b@c>\r\n",
+        "BDAT 15 LAST\r\n",
+    ]
+    .concat()
+    .into_bytes();
+    input.extend_from_slice(b"From: \xff\r\n\r\nbody");
+    input.extend_from_slice(b"QUIT\r\n");
+
+    assert!(test_receiver_inner(
+        &mut ExpectBody(b""),
+        &input,
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "554 5.6.0 Message header section is not valid utf8\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+        .as_bytes(),
+        std::sync::Arc::new(config::local_test()),
+        None,
+    )
+    .await
+    .is_ok());
+}
+
+#[tokio::test]
+async fn chunks_in_progress() {
+    assert!(test_receiver! {
+        on_mail => &mut ExpectBody(b"From: a@b\r\n\r\nhello\r\n"),
+        [
+            "HELO client.com\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            bdat("From: a@b\r\n\r\n", false).as_str(),
+            "RCPT TO:<c@d>\r\n",
+            "MAIL FROM:<c@d>\r\n",
+            "DATA\r\n",
+            "NOOP\r\n",
+            bdat("hello\r\n", true).as_str(),
+            "QUIT\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "503 Bad sequence of commands\r\n",
+            "503 Bad sequence of commands\r\n",
+            "503 Bad sequence of commands\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn chunks_reset() {
+    assert!(test_receiver! {
+        on_mail => &mut ExpectBody(b"From: a@b\r\n\r\nworld\r\n"),
+        [
+            "HELO client.com\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            bdat("From: a@b\r\n\r\nhello\r\n", false).as_str(),
+            "RSET\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            bdat("From: a@b\r\n\r\nworld\r\n", true).as_str(),
+            "QUIT\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn chunk_bad_sequence() {
+    assert!(test_receiver! {
+        [
+            "HELO client.com\r\n",
+            bdat("MAIL FROM:<a@b>\r\n", true).as_str(),
+            "MAIL FROM:<a@b>\r\n",
+            bdat("RCPT TO:<b@c>\r\n", false).as_str(),
+            "QUIT\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn chunks_size_exceeded() {
+    let mut config = config::local_test();
+    config.server.smtp.message_size_max = 20;
+
+    assert!(test_receiver! {
+        with_config => config,
+        [
+            "HELO client.com\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            bdat("From: a@b\r\n\r\n", false).as_str(),
+            bdat("hello world\r\n", true).as_str(),
+            "QUIT\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
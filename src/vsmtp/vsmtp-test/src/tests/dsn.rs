@@ -0,0 +1,133 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::test_receiver;
+use vsmtp_common::{
+    dsn::{DsnReturn, NotifyOn, OriginalRecipient},
+    mail_context::MailContext,
+    re::tokio,
+    CodeID, MessageBody,
+};
+use vsmtp_server::Connection;
+use vsmtp_server::OnMail;
+
+#[tokio::test]
+async fn dsn_parameters() {
+    struct T;
+
+    #[async_trait::async_trait]
+    impl OnMail for T {
+        async fn on_mail<
+            S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+        >(
+            &mut self,
+            _: &mut Connection<S>,
+            mail: Box<MailContext>,
+            _: MessageBody,
+        ) -> CodeID {
+            assert_eq!(mail.envelop.dsn_return, Some(DsnReturn::Headers));
+            assert_eq!(mail.envelop.envelop_id, Some("QQ314159".to_string()));
+
+            assert_eq!(mail.envelop.rcpt.len(), 2);
+            assert_eq!(
+                mail.envelop.rcpt[0].notify,
+                Some(NotifyOn::Some {
+                    success: true,
+                    failure: true,
+                    delay: false
+                })
+            );
+            assert_eq!(
+                mail.envelop.rcpt[0].original_recipient,
+                Some(OriginalRecipient {
+                    addr_type: "rfc822".to_string(),
+                    mailbox: "Bob@example.com".to_string()
+                })
+            );
+            assert_eq!(mail.envelop.rcpt[1].notify, None);
+            assert_eq!(mail.envelop.rcpt[1].original_recipient, None);
+
+            CodeID::Ok
+        }
+    }
+
+    assert!(test_receiver! {
+        on_mail => &mut T,
+        [
+            "EHLO foo\r\n",
+            "MAIL FROM:<john@doe.com> RET=HDRS ENVID=QQ314159\r\n",
+            "RCPT TO:<bob@example.com> NOTIFY=SUCCESS,FAILURE ORCPT=rfc822;Bob+40example.com\r\n",
+            "RCPT TO:<alice@example.com>\r\n",
+            "DATA\r\n",
+            "from: john doe <john@doe.com>\r\n",
+            "\r\n",
+            "mail content wow\r\n",
+            ".\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn dsn_invalid_parameters() {
+    assert!(test_receiver! {
+        [
+            "EHLO foo\r\n",
+            "MAIL FROM:<john@doe.com> RET=BODY\r\n",
+            "MAIL FROM:<john@doe.com> RET=FULL\r\n",
+            "RCPT TO:<bob@example.com> NOTIFY=NEVER,FAILURE\r\n",
+            "RCPT TO:<bob@example.com> ORCPT=Bob@example.com\r\n",
+            "RCPT TO:<bob@example.com> NOTIFY=NEVER\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "501 Syntax error in parameters or arguments\r\n",
+            "250 Ok\r\n",
+            "501 Syntax error in parameters or arguments\r\n",
+            "501 Syntax error in parameters or arguments\r\n",
+            "250 Ok\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
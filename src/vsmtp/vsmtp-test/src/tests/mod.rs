@@ -15,9 +15,14 @@
  *
 */
 mod auth;
+mod chunking;
 mod clair;
+mod dsn;
 mod examples;
+mod pipelining;
 mod rset;
 mod rules;
+mod size;
 mod tls;
 mod utf8;
+mod verify;
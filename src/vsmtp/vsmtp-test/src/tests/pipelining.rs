@@ -0,0 +1,147 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::test_receiver;
+use vsmtp_common::{mail_context::MailContext, re::tokio, CodeID, MessageBody};
+use vsmtp_server::{Connection, OnMail};
+
+#[tokio::test]
+async fn pipelined_transaction() {
+    struct T;
+
+    #[async_trait::async_trait]
+    impl OnMail for T {
+        async fn on_mail<
+            S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+        >(
+            &mut self,
+            _: &mut Connection<S>,
+            mail: Box<MailContext>,
+            _: MessageBody,
+        ) -> CodeID {
+            assert_eq!(mail.envelop.helo, "client.com");
+            assert_eq!(mail.envelop.mail_from.full(), "a@b");
+            assert_eq!(mail.envelop.rcpt.len(), 2);
+
+            CodeID::Ok
+        }
+    }
+
+    assert!(test_receiver! {
+        on_mail => &mut T,
+        [
+            "EHLO client.com\r\n",
+            "MAIL FROM:<a@b>\r\nRCPT TO:<b@c>\r\nRCPT TO:<c@d>\r\nDATA\r\n",
+            "from: a b <a@b>\r\n",
+            "\r\n",
+            "mail content wow\r\n",
+            ".\r\n",
+            "QUIT\r\n"
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-STARTTLS\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn pipelined_errors_keep_order() {
+    assert!(test_receiver! {
+        [
+            "EHLO client.com\r\n",
+            "MAIL FROM:<a@b>\r\nMAIL FROM:<a@b>\r\nRCPT TO:<b@c>\r\nFOO\r\nRCPT TO:<c@d>\r\n",
+            "RSET\r\nRCPT TO:<b@c>\r\nMAIL FROM:<a@b>\r\nRCPT TO:<b@c>\r\n",
+            "QUIT\r\n"
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-STARTTLS\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "250 Ok\r\n",
+            "501 Syntax error in parameters or arguments\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "503 Bad sequence of commands\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn pipelined_transactions() {
+    assert!(test_receiver! {
+        [
+            "HELO client.com\r\n",
+            "MAIL FROM:<a@b>\r\nRCPT TO:<b@c>\r\nDATA\r\n",
+            "\r\n",
+            "first\r\n",
+            ".\r\n",
+            "MAIL FROM:<a@b>\r\nRCPT TO:<b@c>\r\nDATA\r\n",
+            "\r\n",
+            "second\r\n",
+            ".\r\n",
+            "QUIT\r\n"
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n"
+        ]
+        .concat()
+    }
+    .is_ok());
+}
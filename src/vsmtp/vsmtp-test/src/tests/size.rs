@@ -0,0 +1,98 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{config, test_receiver};
+use vsmtp_common::re::tokio;
+
+#[tokio::test]
+async fn declared_size_exceeded() {
+    let mut config = config::local_test();
+    config.server.smtp.message_size_max = 1000;
+
+    assert!(test_receiver! {
+        with_config => config,
+        [
+            "HELO foo\r\n",
+            "MAIL FROM:<a@b> SIZE=1001\r\n",
+            "MAIL FROM:<a@b> size=99999999999999999999\r\n",
+            "MAIL FROM:<a@b> SIZE=1000\r\n",
+            "RCPT TO:<b@c>\r\n",
+            "DATA\r\n",
+            "from: a b <a@b>\r\n",
+            "\r\n",
+            "mail content wow\r\n",
+            ".\r\n"
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+            "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n"
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn message_size_exceeded() {
+    let mut config = config::local_test();
+    config.server.smtp.message_size_max = 100;
+
+    assert!(test_receiver! {
+        with_config => config,
+        [
+            "HELO foo\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            "DATA\r\n",
+            "from: a b <a@b>\r\n",
+            "\r\n",
+            "a".repeat(78).as_str(),
+            "\r\n",
+            "b".repeat(78).as_str(),
+            "\r\n",
+            ".\r\n",
+            "MAIL FROM:<a@b>\r\n",
+            "RCPT TO:<b@c>\r\n",
+            "DATA\r\n",
+            "from: a b <a@b>\r\n",
+            "\r\n",
+            "mail content wow\r\n",
+            ".\r\n"
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "552 5.3.4 Message size exceeds fixed maximum message size\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n"
+        ]
+        .concat()
+    }
+    .is_ok());
+}
@@ -0,0 +1,314 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use super::{TEST_SERVER_CERT, TEST_SERVER_KEY};
+use crate::get_tls_file;
+use vsmtp_common::{
+    auth::Mechanism,
+    re::{anyhow, base64, tokio, vsmtp_rsasl},
+    ConnectionKind,
+};
+use vsmtp_config::{
+    field::SecretFile,
+    get_rustls_config,
+    re::{rustls, rustls_pemfile},
+    Config,
+};
+use vsmtp_rule_engine::rule_engine::RuleEngine;
+use vsmtp_server::{auth, Connection, ProcessMessage, Server};
+
+fn get_mutual_tls_config() -> Config {
+    let mut config = Config::builder()
+        .with_version_str("<1.0.0")
+        .unwrap()
+        .with_server_name("testserver.com")
+        .with_user_group_and_default_system("root", "root")
+        .unwrap()
+        .with_ipv4_localhost()
+        .with_default_logs_settings()
+        .with_spool_dir_and_default_queues("./tmp/delivery")
+        .with_safe_tls_config(TEST_SERVER_CERT, TEST_SERVER_KEY)
+        .unwrap()
+        .with_default_smtp_options()
+        .with_default_smtp_error_handler()
+        .with_default_smtp_codes()
+        .with_auth(
+            false,
+            false,
+            vec![Mechanism::External, Mechanism::Plain],
+            -1,
+        )
+        .with_default_app()
+        .with_vsl("./src/tests/auth.vsl")
+        .with_default_app_logs()
+        .with_system_dns()
+        .without_virtual_entries()
+        .validate()
+        .unwrap();
+
+    config.server.tls.as_mut().unwrap().client_ca = Some(SecretFile {
+        inner: rustls_pemfile::certs(&mut get_tls_file::get_client_ca().as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect(),
+        path: "./src/template/certs/client/ca.crt".into(),
+    });
+
+    config
+}
+
+// same as `super::test_tls_tunneled`, but the client can present a certificate.
+async fn test_mutual_tls(
+    smtp_input: Vec<String>,
+    expected_output: Vec<String>,
+    port: u32,
+    with_client_certificate: bool,
+) -> anyhow::Result<(anyhow::Result<()>, anyhow::Result<()>)> {
+    let server_config = std::sync::Arc::new(get_mutual_tls_config());
+
+    let socket_server = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
+        .await
+        .unwrap();
+
+    let (working_sender, _working_receiver) = tokio::sync::mpsc::channel::<ProcessMessage>(10);
+    let (delivery_sender, _delivery_receiver) = tokio::sync::mpsc::channel::<ProcessMessage>(10);
+
+    let server = tokio::spawn(async move {
+        let (client_stream, client_addr) = socket_server.accept().await.unwrap();
+
+        Server::run_session(
+            Connection::new(
+                ConnectionKind::Tunneled,
+                client_addr,
+                socket_server.local_addr().expect("retrieve local address"),
+                server_config.clone(),
+                client_stream,
+            ),
+            Some(std::sync::Arc::new(
+                get_rustls_config(
+                    server_config.server.tls.as_ref().unwrap(),
+                    &server_config.server.r#virtual,
+                )
+                .unwrap(),
+            )),
+            Some({
+                let mut rsasl = vsmtp_rsasl::SASL::new().unwrap();
+                rsasl.install_callback::<auth::Callback>();
+                rsasl.store(Box::new(server_config.clone()));
+                std::sync::Arc::new(tokio::sync::Mutex::new(rsasl))
+            }),
+            std::sync::Arc::new(std::sync::RwLock::new(
+                RuleEngine::new(&server_config, &server_config.app.vsl.filepath.clone()).unwrap(),
+            )),
+            std::sync::Arc::new(std::collections::HashMap::new()),
+            working_sender,
+            delivery_sender,
+        )
+        .await
+    });
+
+    let mut root_store = rustls::RootCertStore::empty();
+    for i in rustls_pemfile::certs(&mut get_tls_file::get_certificate().as_bytes()).unwrap() {
+        root_store.add(&rustls::Certificate(i)).unwrap();
+    }
+
+    let client_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store);
+
+    let client_config = if with_client_certificate {
+        client_config
+            .with_single_cert(
+                rustls_pemfile::certs(&mut get_tls_file::get_client_certificate().as_bytes())
+                    .unwrap()
+                    .into_iter()
+                    .map(rustls::Certificate)
+                    .collect(),
+                rustls::PrivateKey(
+                    rustls_pemfile::pkcs8_private_keys(
+                        &mut get_tls_file::get_client_key().as_bytes(),
+                    )
+                    .unwrap()
+                    .remove(0),
+                ),
+            )
+            .unwrap()
+    } else {
+        client_config.with_no_client_auth()
+    };
+
+    let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client_config));
+
+    let client = tokio::spawn(async move {
+        let stream = tokio::net::TcpStream::connect(format!("0.0.0.0:{port}"))
+            .await
+            .unwrap();
+        let mut stream = vsmtp_server::AbstractIO::new(
+            connector
+                .connect(
+                    rustls::ServerName::try_from("testserver.com").unwrap(),
+                    stream,
+                )
+                .await?,
+        );
+
+        let mut output = vec![];
+        let mut input = smtp_input.into_iter();
+        loop {
+            let line = stream.next_line(None).await.unwrap().unwrap();
+            output.push(line);
+            if output.last().unwrap().chars().nth(3) == Some('-') {
+                continue;
+            }
+            match input.next() {
+                Some(line) => {
+                    tokio::io::AsyncWriteExt::write_all(&mut stream.inner, line.as_bytes())
+                        .await
+                        .unwrap();
+                }
+                None => break,
+            }
+        }
+
+        while let Ok(Some(last)) = stream.next_line(None).await {
+            output.push(last);
+        }
+
+        pretty_assertions::assert_eq!(expected_output, output);
+
+        anyhow::Ok(())
+    });
+
+    let (client, server) = tokio::join!(client, server);
+    Ok((client.unwrap(), server.unwrap()))
+}
+
+const EHLO: [&str; 10] = [
+    "220 testserver.com Service ready",
+    "250-testserver.com",
+    "250-AUTH EXTERNAL PLAIN",
+    "250-8BITMIME",
+    "250-SIZE 20000000",
+    "250-PIPELINING",
+    "250-CHUNKING",
+    "250-BINARYMIME",
+    "250-DSN",
+    "250 SMTPUTF8",
+];
+
+fn to_lines(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(ToString::to_string).collect()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn external() {
+    let (client, server) = test_mutual_tls(
+        to_lines(&["EHLO client.com\r\n", "AUTH EXTERNAL =\r\n", "QUIT\r\n"]),
+        to_lines(
+            &[
+                EHLO.as_slice(),
+                &[
+                    "235 2.7.0 Authentication succeeded",
+                    "221 Service closing transmission channel",
+                ],
+            ]
+            .concat(),
+        ),
+        20470,
+        true,
+    )
+    .await
+    .unwrap();
+
+    client.unwrap();
+    server.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn external_with_authzid() {
+    let (client, server) = test_mutual_tls(
+        vec![
+            "EHLO client.com\r\n".to_string(),
+            "AUTH EXTERNAL\r\n".to_string(),
+            format!("{}\r\n", base64::encode("relay.example.com")),
+            "QUIT\r\n".to_string(),
+        ],
+        to_lines(
+            &[
+                EHLO.as_slice(),
+                &[
+                    "334 ",
+                    "235 2.7.0 Authentication succeeded",
+                    "221 Service closing transmission channel",
+                ],
+            ]
+            .concat(),
+        ),
+        20471,
+        true,
+    )
+    .await
+    .unwrap();
+
+    client.unwrap();
+    server.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn external_wrong_authzid() {
+    let (client, server) = test_mutual_tls(
+        vec![
+            "EHLO client.com\r\n".to_string(),
+            format!("AUTH EXTERNAL {}\r\n", base64::encode("postmaster")),
+        ],
+        to_lines(
+            &[
+                EHLO.as_slice(),
+                &["535 5.7.8 Authentication credentials invalid"],
+            ]
+            .concat(),
+        ),
+        20472,
+        true,
+    )
+    .await
+    .unwrap();
+
+    client.unwrap();
+    server.unwrap_err();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+async fn external_without_certificate() {
+    let (client, server) = test_mutual_tls(
+        to_lines(&["EHLO client.com\r\n", "AUTH EXTERNAL =\r\n"]),
+        to_lines(
+            &[
+                EHLO.as_slice(),
+                &["535 5.7.8 Authentication credentials invalid"],
+            ]
+            .concat(),
+        ),
+        20473,
+        false,
+    )
+    .await
+    .unwrap();
+
+    client.unwrap();
+    server.unwrap_err();
+}
@@ -15,6 +15,7 @@
  *
 */
 mod cipher_suite;
+mod client_certificate;
 mod starttls;
 mod tunneled;
 mod tunneled_with_auth;
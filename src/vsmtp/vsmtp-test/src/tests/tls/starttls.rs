@@ -38,10 +38,20 @@ async fn simple() {
             "250-testserver.com",
             "250-STARTTLS",
             "250-8BITMIME",
+            "250-SIZE 20000000",
+            "250-PIPELINING",
+            "250-CHUNKING",
+            "250-BINARYMIME",
+            "250-DSN",
             "250 SMTPUTF8",
             "220 testserver.com Service ready",
             "250-testserver.com",
             "250-8BITMIME",
+            "250-SIZE 20000000",
+            "250-PIPELINING",
+            "250-CHUNKING",
+            "250-BINARYMIME",
+            "250-DSN",
             "250 SMTPUTF8",
             "250 Ok",
             "250 Ok",
@@ -71,10 +81,20 @@ async fn double_starttls() {
             "250-testserver.com",
             "250-STARTTLS",
             "250-8BITMIME",
+            "250-SIZE 20000000",
+            "250-PIPELINING",
+            "250-CHUNKING",
+            "250-BINARYMIME",
+            "250-DSN",
             "250 SMTPUTF8",
             "220 testserver.com Service ready",
             "250-testserver.com",
             "250-8BITMIME",
+            "250-SIZE 20000000",
+            "250-PIPELINING",
+            "250-CHUNKING",
+            "250-BINARYMIME",
+            "250-DSN",
             "250 SMTPUTF8",
             "220 testserver.com Service ready",
             "554 5.5.1 Error: TLS already active",
@@ -104,6 +124,11 @@ async fn test_receiver_7() {
             "250-testserver.com\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "454 TLS not available due to temporary reason\r\n",
             "221 Service closing transmission channel\r\n",
@@ -127,6 +152,11 @@ async fn test_receiver_8() -> anyhow::Result<()> {
             "250-testserver.com\r\n",
             "250-STARTTLS\r\n",
             "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
             "250 SMTPUTF8\r\n",
             "530 Must issue a STARTTLS command first\r\n",
             "221 Service closing transmission channel\r\n",
@@ -150,6 +180,11 @@ async fn config_ill_formed() {
             "250-testserver.com",
             "250-STARTTLS",
             "250-8BITMIME",
+            "250-SIZE 20000000",
+            "250-PIPELINING",
+            "250-CHUNKING",
+            "250-BINARYMIME",
+            "250-DSN",
             "250 SMTPUTF8",
             "220 testserver.com Service ready",
         ],
@@ -71,6 +71,11 @@ async fn simple() {
             "250-testserver.com",
             "250-AUTH PLAIN LOGIN CRAM-MD5",
             "250-8BITMIME",
+            "250-SIZE 20000000",
+            "250-PIPELINING",
+            "250-CHUNKING",
+            "250-BINARYMIME",
+            "250-DSN",
             "250 SMTPUTF8",
             "334 ",
             "235 2.7.0 Authentication succeeded",
@@ -127,3 +127,75 @@ async fn test_receiver_utf8_ar() {
 async fn test_receiver_utf8_ko() {
     assert!(test_lang!("mail/ko.txt").is_ok());
 }
+
+#[tokio::test]
+async fn test_receiver_international_address() {
+    struct T;
+
+    #[async_trait::async_trait]
+    impl OnMail for T {
+        async fn on_mail<
+            S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + std::fmt::Debug,
+        >(
+            &mut self,
+            _: &mut Connection<S>,
+            mail: Box<MailContext>,
+            _: MessageBody,
+        ) -> CodeID {
+            assert!(mail.envelop.smtp_utf8);
+            assert_eq!(mail.envelop.mail_from.full(), "用户@例子.广告");
+            assert_eq!(
+                mail.envelop.rcpt,
+                vec![
+                    addr!("θσερ@παράδειγμα.δοκιμή").into(),
+                    addr!("aa@bb").into()
+                ]
+            );
+            CodeID::Ok
+        }
+    }
+
+    assert!(test_receiver! {
+        on_mail => &mut T,
+        [
+            "EHLO foobar\r\n",
+            "MAIL FROM:<用户@例子.广告>\r\n",
+            "MAIL FROM:<用户@例子.广告> SMTPUTF8\r\n",
+            "RCPT TO:<θσερ@παράδειγμα.δοκιμή>\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            "DATA\r\n",
+            "from: 用户 <用户@例子.广告>\r\n",
+            "\r\n",
+            "mail content wow\r\n",
+            ".\r\n",
+            "MAIL FROM:<john@doe>\r\n",
+            "RCPT TO:<θσερ@παράδειγμα.δοκιμή>\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            "QUIT\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250-testserver.com\r\n",
+            "250-8BITMIME\r\n",
+            "250-SIZE 20000000\r\n",
+            "250-PIPELINING\r\n",
+            "250-CHUNKING\r\n",
+            "250-BINARYMIME\r\n",
+            "250-DSN\r\n",
+            "250 SMTPUTF8\r\n",
+            "553 5.6.7 Non-ASCII addresses require the SMTPUTF8 parameter\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "354 Start mail input; end with <CRLF>.<CRLF>\r\n",
+            "250 Ok\r\n",
+            "250 Ok\r\n",
+            "553 5.6.7 Non-ASCII addresses require the SMTPUTF8 parameter\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
@@ -0,0 +1,82 @@
+/*
+ * vSMTP mail transfer agent
+ * Copyright (C) 2022 viridIT SAS
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, either version 3 of the License, or any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see https://www.gnu.org/licenses/.
+ *
+*/
+use crate::{config, test_receiver};
+use vsmtp_common::re::tokio;
+
+#[tokio::test]
+async fn verify_default() {
+    assert!(test_receiver! {
+        [
+            "HELO foobar\r\n",
+            "VRFY john\r\n",
+            "EXPN staff\r\n",
+            "MAIL FROM:<john@doe>\r\n",
+            "VRFY jenny\r\n",
+            "RCPT TO:<aa@bb>\r\n",
+            "QUIT\r\n",
+        ]
+        .concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "252 2.1.5 Cannot VRFY user, but will accept message and attempt delivery\r\n",
+            "252 2.1.5 Cannot VRFY user, but will accept message and attempt delivery\r\n",
+            "250 Ok\r\n",
+            "252 2.1.5 Cannot VRFY user, but will accept message and attempt delivery\r\n",
+            "250 Ok\r\n",
+            "221 Service closing transmission channel\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}
+
+#[tokio::test]
+async fn verify_with_rules() {
+    let mut config = config::local_test();
+    config.app.vsl.filepath = Some(
+        <std::path::PathBuf as std::str::FromStr>::from_str(
+            "../../../examples/vsl/verify/main.vsl",
+        )
+        .unwrap(),
+    );
+
+    assert!(test_receiver! {
+        with_config => config.clone(),
+        ["HELO foobar\r\n", "VRFY john\r\n"].concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250 2.1.5 <john.doe@example.com>\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+
+    assert!(test_receiver! {
+        with_config => config,
+        ["HELO foobar\r\n", "EXPN staff\r\n"].concat(),
+        [
+            "220 testserver.com Service ready\r\n",
+            "250 Ok\r\n",
+            "250-2.1.5 <john.doe@example.com>\r\n",
+            "250 2.1.5 <jenny.doe@example.com>\r\n",
+        ]
+        .concat()
+    }
+    .is_ok());
+}